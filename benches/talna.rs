@@ -197,6 +197,71 @@ fn avg(c: &mut Criterion) {
     });
 }
 
+fn repeated_query(c: &mut Criterion) {
+    let metric_name = MetricName::try_from("cpu").unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = talna::Database::builder().open(&dir).unwrap();
+
+    for host in 0..1_000 {
+        let host = format!("host-{host}");
+        let env = if host.ends_with('0') { "prod" } else { "dev" };
+
+        let tags = tagset!(
+            "service" => "db",
+            "env" => env,
+            "host" => host.as_str(),
+        );
+
+        db.write(metric_name, 10.0, tags).unwrap();
+    }
+
+    // NOTE: The postings lists of `service:db` and `env:prod` are cached after the first query
+    c.bench_function("avg (repeated query, 1000 series)", |b| {
+        b.iter(|| {
+            db.avg(metric_name, "host")
+                .filter("service:db AND env:prod")
+                .build()
+                .unwrap()
+                .collect()
+                .unwrap();
+        });
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = talna::Database::builder()
+        .postings_cache_size_mib(0)
+        .open(&dir)
+        .unwrap();
+
+    for host in 0..1_000 {
+        let host = format!("host-{host}");
+        let env = if host.ends_with('0') { "prod" } else { "dev" };
+
+        let tags = tagset!(
+            "service" => "db",
+            "env" => env,
+            "host" => host.as_str(),
+        );
+
+        db.write(metric_name, 10.0, tags).unwrap();
+    }
+
+    c.bench_function(
+        "avg (repeated query, 1000 series, no postings cache)",
+        |b| {
+            b.iter(|| {
+                db.avg(metric_name, "host")
+                    .filter("service:db AND env:prod")
+                    .build()
+                    .unwrap()
+                    .collect()
+                    .unwrap();
+            });
+        },
+    );
+}
+
 criterion_group!(
     benches,
     intersection,
@@ -206,5 +271,6 @@ criterion_group!(
     parse_filter_query,
     insert_timestamp,
     avg,
+    repeated_query,
 );
 criterion_main!(benches);
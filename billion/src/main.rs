@@ -72,17 +72,16 @@ fn main() -> talna::Result<()> {
             .into_iter()
             .enumerate()
             {
-                let tagset = talna::tagset!(
+                let tagset: &talna::TagSet = talna::tagset!(
                     "env" => "prod",
                     "service" => "db",
                     "host" => host,
                 );
 
-                for idx in 0..100_000_000 {
+                let points = (0..100_000_000).map(|idx| {
                     let items_written = (hidx * 100_000_000 + idx) as u128;
 
                     let value = rng.gen_range(0.0..100.0);
-                    db.write_at(metric_name, items_written, value, tagset)?;
 
                     if idx > 0 && idx % 5_000_000 == 0 {
                         let elapsed = start.elapsed();
@@ -99,7 +98,11 @@ fn main() -> talna::Result<()> {
                             max_memory / 1_024 / 1_024
                         );
                     }
-                }
+
+                    (metric_name, tagset, items_written, value)
+                });
+
+                db.ingest_sorted(points)?;
             }
         }
 
@@ -0,0 +1,333 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use talna::query::filter::{parse_filter_query, Node};
+use talna::{
+    Aggregation, AggregationBuilder, Bucket, Database, Duration, ExportCursor, MetricName,
+    Timestamp,
+};
+
+/// Inspect and query talna databases
+#[derive(Parser)]
+#[command(name = "talna", version)]
+struct Cli {
+    /// Path to the database folder
+    #[arg(short, long)]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs an aggregation and prints the buckets of every group
+    Query {
+        /// Metric to query
+        #[arg(short, long)]
+        metric: String,
+
+        /// Tag to group by
+        #[arg(short, long)]
+        group_by: String,
+
+        /// Filter expression
+        #[arg(short, long, default_value = "*")]
+        filter: String,
+
+        /// Aggregation function
+        #[arg(short, long, value_enum, default_value_t = Agg::Avg)]
+        agg: Agg,
+
+        /// Only query the last time frame (e.g. 30s, 15m, 1h, 7d)
+        #[arg(short, long, value_parser = parse_duration)]
+        last: Option<Timestamp>,
+
+        /// Bucket width (e.g. 30s, 15m, 1h, 7d)
+        #[arg(long, value_parser = parse_duration)]
+        granularity: Option<Timestamp>,
+    },
+
+    /// Prints database statistics
+    Stats,
+
+    /// Exports all data points in line protocol
+    Export {
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Checkpoint file, so an interrupted export continues where it left off
+        /// (requires --output)
+        #[arg(long, requires = "output")]
+        checkpoint: Option<PathBuf>,
+    },
+
+    /// Imports data points in line protocol
+    Import {
+        /// Input file (default: stdin)
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Agg {
+    Avg,
+    Sum,
+    Min,
+    Max,
+    Count,
+    Distinct,
+}
+
+/// Parses a duration like `1h` into nanoseconds
+fn parse_duration(s: &str) -> Result<Timestamp, String> {
+    let idx = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+
+    let (n, unit) = s.split_at(idx);
+
+    let n = n
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration {s:?}"))?;
+
+    Ok(match unit {
+        "ns" => Duration::nanos(n),
+        "us" => Duration::micros(n),
+        "ms" => Duration::millis(n),
+        "s" => Duration::seconds(n),
+        "m" => Duration::minutes(n),
+        "h" => Duration::hours(n),
+        "d" => Duration::days(n),
+        "w" => Duration::weeks(n),
+        _ => {
+            return Err(format!(
+                "invalid duration unit {unit:?} (expected ns, us, ms, s, m, h, d or w)"
+            ))
+        }
+    })
+}
+
+/// Collects the tag keys and `key:value` tags a filter references
+fn filter_terms(node: &Node, terms: &mut Vec<String>) {
+    match node {
+        Node::Eq(tag) => terms.push(format!("{}:{}", tag.key, tag.value)),
+        Node::Wildcard(tag) => terms.push(tag.key.to_string()),
+        Node::Has(key) | Node::Missing(key) => terms.push(key.to_string()),
+        Node::Not(child) => filter_terms(child, terms),
+        Node::And(children) | Node::Or(children) => {
+            for child in children {
+                filter_terms(child, terms);
+            }
+        }
+        Node::AllStar => {}
+    }
+}
+
+/// Prints close matches for tag keys & tags of the query that do not exist
+fn print_hints(
+    db: &Database,
+    metric: MetricName,
+    group_by: &str,
+    filter: &str,
+) -> talna::Result<()> {
+    let mut terms = vec![group_by.to_owned()];
+
+    if let Ok(node) = parse_filter_query(filter) {
+        filter_terms(&node, &mut terms);
+    }
+
+    for term in terms {
+        let suggestions = db.suggest(metric, &term)?;
+
+        if suggestions.first() != Some(&term) && !suggestions.is_empty() {
+            eprintln!("unknown {term:?}, did you mean {}?", suggestions.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Data points exported between checkpoints
+const CHECKPOINT_INTERVAL: u64 = 1_000_000;
+
+/// Exports into the output file, storing the output length & export cursor in the
+/// checkpoint file after every chunk
+///
+/// If the checkpoint file exists, the output is truncated to the checkpointed length
+/// (dropping lines written after the last checkpoint), and the export is resumed.
+fn export_resumable(db: &Database, output: &Path, checkpoint: &Path) -> talna::Result<u64> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid checkpoint");
+
+    let (mut cursor, len) = match std::fs::read_to_string(checkpoint) {
+        Ok(s) => {
+            let (len, token) = s.split_once('\n').ok_or_else(invalid)?;
+            let len = len.parse::<u64>().map_err(|_| invalid())?;
+            (Some(token.parse::<ExportCursor>()?), len)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (None, 0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(output)?;
+    file.set_len(len)?;
+
+    let mut writer = BufWriter::new(file);
+    writer.seek(std::io::SeekFrom::End(0))?;
+
+    let tmp = checkpoint.with_extension("tmp");
+    let mut total = 0;
+
+    loop {
+        let (count, next) = db.export_from(&mut writer, cursor.as_ref(), CHECKPOINT_INTERVAL)?;
+        total += count;
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        let Some(next) = next else {
+            break;
+        };
+
+        // NOTE: Replace the checkpoint atomically
+        let len = writer.stream_position()?;
+        std::fs::write(&tmp, format!("{len}\n{next}"))?;
+        std::fs::rename(&tmp, checkpoint)?;
+
+        cursor = Some(next);
+    }
+
+    match std::fs::remove_file(checkpoint) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    Ok(total)
+}
+
+fn run_query<'a, A: Aggregation>(
+    mut builder: AggregationBuilder<'a, A>,
+    filter: &'a str,
+    last: Option<Timestamp>,
+    granularity: Option<Timestamp>,
+) -> talna::Result<Vec<(String, Vec<Bucket>)>> {
+    builder = builder.filter(filter);
+
+    if let Some(last) = last {
+        builder = builder.start_relative(last);
+    }
+    if let Some(granularity) = granularity {
+        builder = builder.granularity(granularity);
+    }
+
+    let mut groups = builder.build()?.collect()?.into_iter().collect::<Vec<_>>();
+    groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(groups)
+}
+
+fn main() -> talna::Result<()> {
+    env_logger::builder()
+        .filter_module("lsm_tree", log::LevelFilter::Warn)
+        .filter_module("fjall", log::LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+
+    let cli = Cli::parse();
+
+    let db = Database::builder().open(&cli.db)?;
+
+    match cli.command {
+        Command::Query {
+            metric,
+            group_by,
+            filter,
+            agg,
+            last,
+            granularity,
+        } => {
+            let metric = match MetricName::try_from(metric.as_str()) {
+                Ok(name) => name,
+                Err(e) => {
+                    eprintln!("{e}: {metric:?}");
+                    std::process::exit(1);
+                }
+            };
+
+            let group_by = group_by.as_str();
+
+            let result = match agg {
+                Agg::Avg => run_query(db.avg(metric, group_by), &filter, last, granularity),
+                Agg::Sum => run_query(db.sum(metric, group_by), &filter, last, granularity),
+                Agg::Min => run_query(db.min(metric, group_by), &filter, last, granularity),
+                Agg::Max => run_query(db.max(metric, group_by), &filter, last, granularity),
+                Agg::Count => run_query(db.count(metric, group_by), &filter, last, granularity),
+                Agg::Distinct => {
+                    run_query(db.distinct(metric, group_by), &filter, last, granularity)
+                }
+            };
+
+            let groups = match result {
+                Ok(groups) => groups,
+                Err(talna::Error::InvalidQuery) => {
+                    eprintln!("invalid filter {filter:?}");
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e),
+            };
+
+            if groups.is_empty() {
+                print_hints(&db, metric, group_by, &filter)?;
+            }
+
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            writeln!(out, "group\tstart\tend\tlen\tvalue")?;
+
+            for (group, buckets) in groups {
+                for bucket in buckets {
+                    writeln!(
+                        out,
+                        "{group}\t{}\t{}\t{}\t{}",
+                        bucket.start, bucket.end, bucket.len, bucket.value,
+                    )?;
+                }
+            }
+
+            out.flush()?;
+        }
+        Command::Stats => {
+            println!("series: {}", db.series_count()?);
+            println!("disk space: {} bytes", db.disk_space());
+        }
+        Command::Export { output, checkpoint } => {
+            let count = if let (Some(path), Some(checkpoint)) = (&output, &checkpoint) {
+                export_resumable(&db, path, checkpoint)?
+            } else if let Some(path) = output {
+                db.export(&mut BufWriter::new(std::fs::File::create(path)?))?
+            } else {
+                db.export(&mut BufWriter::new(std::io::stdout().lock()))?
+            };
+
+            eprintln!("exported {count} data points");
+        }
+        Command::Import { input } => {
+            let count = if let Some(path) = input {
+                db.import(BufReader::new(std::fs::File::open(path)?))?
+            } else {
+                db.import(std::io::stdin().lock())?
+            };
+
+            db.flush(true)?;
+
+            eprintln!("imported {count} data points");
+        }
+    }
+
+    Ok(())
+}
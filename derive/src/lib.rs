@@ -0,0 +1,237 @@
+//! Derive macros for [talna](https://github.com/marvin-j97/talna).
+//!
+//! Use the `derive` feature flag of `talna` instead of depending on this crate directly.
+
+#![forbid(unsafe_code)]
+#![deny(clippy::all, missing_docs)]
+#![warn(clippy::pedantic, clippy::nursery)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Fields, FieldsNamed, LitStr};
+
+// NOTE: Same rules as `talna::TagSetBuf`
+fn is_valid_tag_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains([':', ';', '#'])
+}
+
+// NOTE: Same rules as `talna::MetricName`
+fn is_valid_metric_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<&FieldsNamed> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields),
+            _ => Err(syn::Error::new(
+                input.span(),
+                "only structs with named fields are supported",
+            )),
+        },
+        _ => Err(syn::Error::new(input.span(), "only structs are supported")),
+    }
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    tag: bool,
+    skip: bool,
+    name: Option<LitStr>,
+}
+
+fn field_options(field: &syn::Field, attr_name: &str) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident(attr_name))
+    {
+        attr.parse_nested_meta(|meta| {
+            if attr_name == "metric" && meta.path.is_ident("tag") {
+                options.tag = true;
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+            } else if meta.path.is_ident("name") {
+                options.name = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown attribute"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(options)
+}
+
+/// Derives `talna::Metric` for a struct with named fields.
+///
+/// Every field is written as a data point of the metric named like the field,
+/// fields need to be numbers that can be cast to `talna::Value` using `as`.
+///
+/// # Attributes
+///
+/// - `#[metric(prefix = "cpu")]` on the struct prefixes all metric names (`cpu.<field>`)
+/// - `#[metric(tag)]` uses the field as a tag instead, it needs to implement `AsRef<str>`
+/// - `#[metric(name = "...")]` renames the metric or tag
+/// - `#[metric(skip)]` ignores the field
+#[proc_macro_derive(Metric, attributes(metric))]
+pub fn derive_metric(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_metric(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_metric(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut prefix: Option<LitStr> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("metric"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `prefix = \"...\"`"))
+            }
+        })?;
+    }
+
+    let mut tags = vec![];
+    let mut values = vec![];
+
+    for field in &named_fields(input)?.named {
+        let options = field_options(field, "metric")?;
+
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+
+        if options.skip {
+            continue;
+        }
+
+        let name = options
+            .name
+            .as_ref()
+            .map_or_else(|| ident.to_string(), LitStr::value);
+
+        if options.tag {
+            tags.push(quote! {
+                (#name, ::core::convert::AsRef::<str>::as_ref(&self.#ident))
+            });
+        } else {
+            let name = match &prefix {
+                Some(prefix) => format!("{}.{name}", prefix.value()),
+                None => name,
+            };
+
+            if !is_valid_metric_name(&name) {
+                return Err(syn::Error::new(
+                    field.span(),
+                    format!("invalid metric name {name:?} (allowed: a-z A-Z 0-9 . _)"),
+                ));
+            }
+
+            values.push(quote! {
+                (#name, self.#ident as ::talna::Value)
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::talna::Metric for #ident #ty_generics #where_clause {
+            fn tags(&self) -> ::std::vec::Vec<(&str, &str)> {
+                ::std::vec![#(#tags),*]
+            }
+
+            fn values(&self) -> ::std::vec::Vec<(&'static str, ::talna::Value)> {
+                ::std::vec![#(#values),*]
+            }
+        }
+    })
+}
+
+/// Derives `talna::ToTagSet` for a struct with named fields.
+///
+/// Every field is used as a tag named like the field, fields need to implement `AsRef<str>`.
+/// Tags are sorted by key at compile time.
+///
+/// # Attributes
+///
+/// - `#[tag(name = "...")]` renames the tag
+/// - `#[tag(skip)]` ignores the field
+#[proc_macro_derive(TagSet, attributes(tag))]
+pub fn derive_tag_set(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_tag_set(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_tag_set(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut tags = vec![];
+
+    for field in &named_fields(input)?.named {
+        let options = field_options(field, "tag")?;
+
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+
+        if options.skip {
+            continue;
+        }
+
+        let key = options
+            .name
+            .as_ref()
+            .map_or_else(|| ident.to_string(), LitStr::value);
+
+        if !is_valid_tag_key(&key) {
+            return Err(syn::Error::new(
+                field.span(),
+                format!("invalid tag key {key:?}"),
+            ));
+        }
+
+        if tags.iter().any(|(other, _)| *other == key) {
+            return Err(syn::Error::new(
+                field.span(),
+                format!("duplicate tag key {key:?}"),
+            ));
+        }
+
+        tags.push((key, ident));
+    }
+
+    tags.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let tags = tags.iter().map(|(key, ident)| {
+        quote! {
+            (#key, ::core::convert::AsRef::<str>::as_ref(&self.#ident))
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::talna::ToTagSet for #ident #ty_generics #where_clause {
+            fn to_tag_set(&self) -> ::std::vec::Vec<(&str, &str)> {
+                ::std::vec![#(#tags),*]
+            }
+        }
+    })
+}
@@ -0,0 +1,69 @@
+#ifndef TALNA_H
+#define TALNA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque database handle */
+typedef struct TalnaDb TalnaDb;
+
+/* Opaque query result */
+typedef struct TalnaResult TalnaResult;
+
+/* A single bucket of a query result, the group string is owned by the result */
+typedef struct TalnaBucket {
+    const char *group;
+    uint64_t start;
+    uint64_t end;
+    uint64_t len;
+    double value;
+    double sum;
+} TalnaBucket;
+
+#define TALNA_OK 0
+#define TALNA_ERROR -1
+
+/* Returns the last error of the current thread, or NULL; valid until the next call */
+const char *talna_last_error(void);
+
+/* Opens (or creates) a database in the given folder, returns NULL on error */
+TalnaDb *talna_open(const char *path);
+
+/* Closes the database, NULL is a no-op */
+void talna_close(TalnaDb *db);
+
+/* Writes a data point; ts is a nanosecond timestamp, 0 = current time */
+int talna_write(TalnaDb *db, const char *metric, double value, const char *const *tag_keys,
+                const char *const *tag_values, size_t tag_count, uint64_t ts);
+
+/* Flushes writes, if sync is non-zero the writes are guaranteed to be on disk */
+int talna_flush(TalnaDb *db, int sync);
+
+/*
+ * Runs an aggregation (avg, sum, min, max, count, distinct)
+ *
+ * start, end and granularity are nanoseconds, 0 = unbounded/default.
+ * The result needs to be freed using talna_result_free.
+ */
+int talna_query(TalnaDb *db, const char *metric, const char *group_by, const char *agg,
+                const char *filter, uint64_t start, uint64_t end, uint64_t granularity,
+                TalnaResult **out);
+
+/* Returns the amount of buckets in the result */
+size_t talna_result_len(const TalnaResult *result);
+
+/* Reads the bucket at the given index */
+int talna_result_get(const TalnaResult *result, size_t idx, TalnaBucket *out);
+
+/* Frees the query result, NULL is a no-op */
+void talna_result_free(TalnaResult *result);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TALNA_H */
@@ -0,0 +1,401 @@
+//! C API for talna, see `include/talna.h`
+//!
+//! All functions are `unsafe` to call: pointers need to be valid (or NULL where allowed),
+//! and strings need to be NUL-terminated UTF-8.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use talna::{Aggregation, AggregationBuilder, Database, MetricName, Timestamp};
+
+pub const TALNA_OK: c_int = 0;
+pub const TALNA_ERROR: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: impl Into<String>) -> c_int {
+    let msg = msg.into().replace('\0', "");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(msg).ok());
+    TALNA_ERROR
+}
+
+/// Opaque database handle
+pub struct TalnaDb(Database);
+
+/// A single bucket of a query result
+#[repr(C)]
+pub struct TalnaBucket {
+    pub group: *const c_char,
+    pub start: u64,
+    pub end: u64,
+    pub len: u64,
+    pub value: f64,
+    pub sum: f64,
+}
+
+/// Opaque query result
+pub struct TalnaResult {
+    groups: Vec<CString>,
+
+    /// Group index + bucket
+    buckets: Vec<(usize, talna::Bucket)>,
+}
+
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, c_int> {
+    if s.is_null() {
+        return Err(set_last_error(format!("{name} is NULL")));
+    }
+
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| set_last_error(format!("{name} is not valid UTF-8")))
+}
+
+fn to_u64(ts: Timestamp) -> u64 {
+    u64::try_from(ts).unwrap_or(u64::MAX)
+}
+
+/// Returns the last error of the current thread, or NULL.
+#[no_mangle]
+pub extern "C" fn talna_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |e| e.as_ptr()))
+}
+
+/// Opens (or creates) a database in the given folder, returns NULL on error.
+#[no_mangle]
+pub unsafe extern "C" fn talna_open(path: *const c_char) -> *mut TalnaDb {
+    let Ok(path) = to_str(path, "path") else {
+        return std::ptr::null_mut();
+    };
+
+    match Database::builder().open(path) {
+        Ok(db) => Box::into_raw(Box::new(TalnaDb(db))),
+        Err(e) => {
+            set_last_error(e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Closes the database.
+#[no_mangle]
+pub unsafe extern "C" fn talna_close(db: *mut TalnaDb) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Writes a data point; `ts` = 0 uses the current time.
+#[no_mangle]
+pub unsafe extern "C" fn talna_write(
+    db: *mut TalnaDb,
+    metric: *const c_char,
+    value: f64,
+    tag_keys: *const *const c_char,
+    tag_values: *const *const c_char,
+    tag_count: usize,
+    ts: u64,
+) -> c_int {
+    let Some(db) = db.as_ref() else {
+        return set_last_error("db is NULL");
+    };
+
+    let metric = match to_str(metric, "metric") {
+        Ok(metric) => metric,
+        Err(e) => return e,
+    };
+    let metric = match MetricName::try_from(metric) {
+        Ok(name) => name,
+        Err(e) => return set_last_error(format!("{e}: {metric:?}")),
+    };
+
+    if tag_count > 0 && (tag_keys.is_null() || tag_values.is_null()) {
+        return set_last_error("tags are NULL");
+    }
+
+    let mut tags = Vec::with_capacity(tag_count);
+
+    for idx in 0..tag_count {
+        let key = match to_str(*tag_keys.add(idx), "tag key") {
+            Ok(key) => key,
+            Err(e) => return e,
+        };
+        let value = match to_str(*tag_values.add(idx), "tag value") {
+            Ok(value) => value,
+            Err(e) => return e,
+        };
+        tags.push((key, value));
+    }
+
+    let ts = if ts == 0 {
+        talna::timestamp()
+    } else {
+        Timestamp::from(ts)
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let value = value as talna::Value;
+
+    match db.0.write_at(metric, ts, value, &tags) {
+        Ok(()) => TALNA_OK,
+        Err(e) => set_last_error(e.to_string()),
+    }
+}
+
+/// Flushes writes.
+#[no_mangle]
+pub unsafe extern "C" fn talna_flush(db: *mut TalnaDb, sync: c_int) -> c_int {
+    let Some(db) = db.as_ref() else {
+        return set_last_error("db is NULL");
+    };
+
+    match db.0.flush(sync != 0) {
+        Ok(()) => TALNA_OK,
+        Err(e) => set_last_error(e.to_string()),
+    }
+}
+
+struct Query<'a> {
+    filter: &'a str,
+    start: u64,
+    end: u64,
+    granularity: u64,
+}
+
+impl<'a> Query<'a> {
+    fn run<A: Aggregation>(
+        &self,
+        mut builder: AggregationBuilder<'a, A>,
+    ) -> talna::Result<TalnaResult> {
+        builder = builder.filter(self.filter);
+
+        if self.start > 0 {
+            builder = builder.start(self.start.into());
+        }
+        if self.end > 0 {
+            builder = builder.end(self.end.into());
+        }
+        if self.granularity > 0 {
+            builder = builder.granularity(self.granularity.into());
+        }
+
+        let mut groups = builder.build()?.collect()?.into_iter().collect::<Vec<_>>();
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut result = TalnaResult {
+            groups: Vec::with_capacity(groups.len()),
+            buckets: vec![],
+        };
+
+        for (group, buckets) in groups {
+            let idx = result.groups.len();
+            result
+                .groups
+                .push(CString::new(group.replace('\0', "")).unwrap_or_default());
+            result
+                .buckets
+                .extend(buckets.into_iter().map(|bucket| (idx, bucket)));
+        }
+
+        Ok(result)
+    }
+}
+
+/// Runs an aggregation; `start`, `end` and `granularity` = 0 use the defaults.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn talna_query(
+    db: *mut TalnaDb,
+    metric: *const c_char,
+    group_by: *const c_char,
+    agg: *const c_char,
+    filter: *const c_char,
+    start: u64,
+    end: u64,
+    granularity: u64,
+    out: *mut *mut TalnaResult,
+) -> c_int {
+    let Some(db) = db.as_ref() else {
+        return set_last_error("db is NULL");
+    };
+
+    if out.is_null() {
+        return set_last_error("out is NULL");
+    }
+
+    let (metric, group_by, agg, filter) = match (
+        to_str(metric, "metric"),
+        to_str(group_by, "group_by"),
+        to_str(agg, "agg"),
+        to_str(filter, "filter"),
+    ) {
+        (Ok(metric), Ok(group_by), Ok(agg), Ok(filter)) => (metric, group_by, agg, filter),
+        (Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => return e,
+    };
+
+    let metric = match MetricName::try_from(metric) {
+        Ok(name) => name,
+        Err(e) => return set_last_error(format!("{e}: {metric:?}")),
+    };
+
+    let db = &db.0;
+
+    let query = Query {
+        filter,
+        start,
+        end,
+        granularity,
+    };
+
+    let result = match agg {
+        "avg" => query.run(db.avg(metric, group_by)),
+        "sum" => query.run(db.sum(metric, group_by)),
+        "min" => query.run(db.min(metric, group_by)),
+        "max" => query.run(db.max(metric, group_by)),
+        "count" => query.run(db.count(metric, group_by)),
+        "distinct" => query.run(db.distinct(metric, group_by)),
+        agg => return set_last_error(format!("invalid aggregation {agg:?}")),
+    };
+
+    match result {
+        Ok(result) => {
+            *out = Box::into_raw(Box::new(result));
+            TALNA_OK
+        }
+        Err(talna::Error::InvalidQuery) => set_last_error(format!("invalid filter {filter:?}")),
+        Err(e) => set_last_error(e.to_string()),
+    }
+}
+
+/// Returns the amount of buckets in the result.
+#[no_mangle]
+pub unsafe extern "C" fn talna_result_len(result: *const TalnaResult) -> usize {
+    result.as_ref().map_or(0, |result| result.buckets.len())
+}
+
+/// Reads the bucket at the given index.
+#[no_mangle]
+pub unsafe extern "C" fn talna_result_get(
+    result: *const TalnaResult,
+    idx: usize,
+    out: *mut TalnaBucket,
+) -> c_int {
+    let Some(result) = result.as_ref() else {
+        return set_last_error("result is NULL");
+    };
+
+    if out.is_null() {
+        return set_last_error("out is NULL");
+    }
+
+    let Some((group_idx, bucket)) = result.buckets.get(idx) else {
+        return set_last_error(format!("bucket index {idx} out of bounds"));
+    };
+
+    let Some(group) = result.groups.get(*group_idx) else {
+        return set_last_error("group index out of bounds");
+    };
+
+    *out = TalnaBucket {
+        group: group.as_ptr(),
+        start: to_u64(bucket.start),
+        end: to_u64(bucket.end),
+        len: bucket.len,
+        value: f64::from(bucket.value),
+        sum: f64::from(bucket.sum),
+    };
+
+    TALNA_OK
+}
+
+/// Frees the query result.
+#[no_mangle]
+pub unsafe extern "C" fn talna_result_free(result: *mut TalnaResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_write_query() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = CString::new(folder.path().to_str().unwrap()).unwrap();
+
+        unsafe {
+            let db = talna_open(path.as_ptr());
+            assert!(!db.is_null());
+
+            let metric = c"cpu.total";
+            let keys = [c"host".as_ptr()];
+
+            for (ts, value, host) in [(1, 4.0, c"h-1"), (2, 6.0, c"h-1"), (3, 1.0, c"h-2")] {
+                let values = [host.as_ptr()];
+                assert_eq!(
+                    TALNA_OK,
+                    talna_write(
+                        db,
+                        metric.as_ptr(),
+                        value,
+                        keys.as_ptr(),
+                        values.as_ptr(),
+                        1,
+                        ts
+                    ),
+                );
+            }
+
+            let mut result = std::ptr::null_mut();
+            assert_eq!(
+                TALNA_OK,
+                talna_query(
+                    db,
+                    metric.as_ptr(),
+                    c"host".as_ptr(),
+                    c"avg".as_ptr(),
+                    c"*".as_ptr(),
+                    0,
+                    0,
+                    0,
+                    &mut result,
+                ),
+            );
+            assert_eq!(2, talna_result_len(result));
+
+            let mut bucket = std::mem::zeroed::<TalnaBucket>();
+            assert_eq!(TALNA_OK, talna_result_get(result, 0, &mut bucket));
+            assert_eq!(c"h-1", CStr::from_ptr(bucket.group));
+            assert_eq!((1, 2, 2), (bucket.start, bucket.end, bucket.len));
+            assert!((bucket.value - 5.0).abs() < f64::EPSILON);
+            assert!((bucket.sum - 10.0).abs() < f64::EPSILON);
+
+            assert_eq!(TALNA_ERROR, talna_result_get(result, 2, &mut bucket));
+            talna_result_free(result);
+
+            assert_eq!(
+                TALNA_ERROR,
+                talna_query(
+                    db,
+                    metric.as_ptr(),
+                    c"host".as_ptr(),
+                    c"avg".as_ptr(),
+                    c"((".as_ptr(),
+                    0,
+                    0,
+                    0,
+                    &mut result,
+                ),
+            );
+            assert!(!talna_last_error().is_null());
+
+            talna_close(db);
+        }
+    }
+}
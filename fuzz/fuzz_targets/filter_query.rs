@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use talna::query::filter::parse_filter_query;
+
+// Run using `cargo +nightly fuzz run filter_query`
+fuzz_target!(|s: &str| {
+    if let Ok(node) = parse_filter_query(s) {
+        // NOTE: Display prints a fully parenthesized expression, which parses to the same node
+        let printed = node.to_string();
+        let reparsed = parse_filter_query(&printed).expect("printed node should parse");
+        assert_eq!(node, reparsed, "{s:?} was printed as {printed:?}");
+    }
+});
@@ -0,0 +1,173 @@
+use pyo3::{
+    exceptions::{PyIOError, PyTimeoutError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use std::collections::HashMap;
+use talna::{Aggregation, AggregationBuilder, MetricName, Timestamp, Value};
+
+fn to_py_err(e: talna::Error) -> PyErr {
+    match e {
+        talna::Error::InvalidQuery => PyValueError::new_err("invalid filter query"),
+        talna::Error::Timeout => PyTimeoutError::new_err("query timed out"),
+        talna::Error::InvalidTagSet(e) => PyValueError::new_err(e.to_string()),
+        e => PyIOError::new_err(e.to_string()),
+    }
+}
+
+fn metric_name(metric: &str) -> PyResult<MetricName<'_>> {
+    MetricName::try_from(metric)
+        .map_err(|e| PyValueError::new_err(format!("{e}: {metric:?}")))
+}
+
+/// Columnar query result, one row per bucket
+#[derive(Default)]
+struct Columns {
+    group: Vec<String>,
+    start: Vec<Timestamp>,
+    end: Vec<Timestamp>,
+    len: Vec<u64>,
+    sum: Vec<Value>,
+    value: Vec<Value>,
+}
+
+struct Query<'a> {
+    filter: &'a str,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+    granularity: Option<Timestamp>,
+}
+
+impl<'a> Query<'a> {
+    fn run<A: Aggregation>(&self, mut builder: AggregationBuilder<'a, A>) -> talna::Result<Columns> {
+        builder = builder.filter(self.filter);
+
+        if let Some(ts) = self.start {
+            builder = builder.start(ts);
+        }
+        if let Some(ts) = self.end {
+            builder = builder.end(ts);
+        }
+        if let Some(granularity) = self.granularity {
+            builder = builder.granularity(granularity);
+        }
+
+        let mut groups = builder.build()?.collect()?.into_iter().collect::<Vec<_>>();
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut columns = Columns::default();
+
+        for (group, buckets) in groups {
+            for bucket in buckets {
+                columns.group.push(group.clone());
+                columns.start.push(bucket.start);
+                columns.end.push(bucket.end);
+                columns.len.push(bucket.len);
+                columns.sum.push(bucket.sum);
+                columns.value.push(bucket.value);
+            }
+        }
+
+        Ok(columns)
+    }
+}
+
+/// An embeddable time series database
+#[pyclass(frozen)]
+struct Database(talna::Database);
+
+#[pymethods]
+impl Database {
+    /// Opens (or creates) a database in the given folder.
+    #[staticmethod]
+    #[pyo3(signature = (path, cache_size_mib=None, hyper_mode=false))]
+    fn open(path: &str, cache_size_mib: Option<u64>, hyper_mode: bool) -> PyResult<Self> {
+        let mut builder = talna::Database::builder().hyper_mode(hyper_mode);
+
+        if let Some(mib) = cache_size_mib {
+            builder = builder.cache_size_mib(mib);
+        }
+
+        builder.open(path).map(Self).map_err(to_py_err)
+    }
+
+    /// Writes a data point; if `ts` (nanoseconds) is not set, the current time is used.
+    #[pyo3(signature = (metric, value, tags=None, ts=None))]
+    fn write(
+        &self,
+        metric: &str,
+        value: Value,
+        tags: Option<HashMap<String, String>>,
+        ts: Option<Timestamp>,
+    ) -> PyResult<()> {
+        let metric = metric_name(metric)?;
+
+        let tags = tags.unwrap_or_default();
+        let tags = tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+
+        self.0
+            .write_at(metric, ts.unwrap_or_else(talna::timestamp), value, &tags)
+            .map_err(to_py_err)
+    }
+
+    /// Flushes writes; if `sync` is set, the writes are guaranteed to be on disk.
+    #[pyo3(signature = (sync=false))]
+    fn flush(&self, sync: bool) -> PyResult<()> {
+        self.0.flush(sync).map_err(to_py_err)
+    }
+
+    /// Runs an aggregation, returning a dict of columns (`group`, `start`, `end`, `len`, `sum`, `value`).
+    #[pyo3(signature = (metric, group_by, agg="avg", filter="*", start=None, end=None, granularity=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn query<'py>(
+        &self,
+        py: Python<'py>,
+        metric: &str,
+        group_by: &str,
+        agg: &str,
+        filter: &str,
+        start: Option<Timestamp>,
+        end: Option<Timestamp>,
+        granularity: Option<Timestamp>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let metric = metric_name(metric)?;
+        let db = &self.0;
+
+        let query = Query {
+            filter,
+            start,
+            end,
+            granularity,
+        };
+
+        let columns = py.allow_threads(|| match agg {
+            "avg" => Ok(query.run(db.avg(metric, group_by))),
+            "sum" => Ok(query.run(db.sum(metric, group_by))),
+            "min" => Ok(query.run(db.min(metric, group_by))),
+            "max" => Ok(query.run(db.max(metric, group_by))),
+            "count" => Ok(query.run(db.count(metric, group_by))),
+            "distinct" => Ok(query.run(db.distinct(metric, group_by))),
+            agg => Err(PyValueError::new_err(format!("invalid aggregation {agg:?}"))),
+        })?;
+
+        let columns = columns.map_err(to_py_err)?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("group", columns.group)?;
+        dict.set_item("start", columns.start)?;
+        dict.set_item("end", columns.end)?;
+        dict.set_item("len", columns.len)?;
+        dict.set_item("sum", columns.sum)?;
+        dict.set_item("value", columns.value)?;
+        Ok(dict)
+    }
+}
+
+#[pymodule]
+fn _talna(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Database>()?;
+    Ok(())
+}
@@ -0,0 +1,29 @@
+"""Python bindings for talna, a simple, embeddable time series database."""
+
+from ._talna import Database
+
+__all__ = ["Database", "to_numpy", "to_pandas"]
+
+
+def to_numpy(columns):
+    """Converts a query result into a dict of numpy arrays.
+
+    Timestamps are converted to ``datetime64[ns]``.
+    """
+    import numpy as np
+
+    return {
+        "group": np.asarray(columns["group"], dtype=object),
+        "start": np.asarray(columns["start"], dtype="datetime64[ns]"),
+        "end": np.asarray(columns["end"], dtype="datetime64[ns]"),
+        "len": np.asarray(columns["len"], dtype=np.uint64),
+        "sum": np.asarray(columns["sum"], dtype=np.float64),
+        "value": np.asarray(columns["value"], dtype=np.float64),
+    }
+
+
+def to_pandas(columns):
+    """Converts a query result into a pandas DataFrame, one row per bucket."""
+    import pandas as pd
+
+    return pd.DataFrame(to_numpy(columns))
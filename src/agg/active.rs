@@ -0,0 +1,32 @@
+use crate::{SeriesId, Value};
+
+/// Counts the distinct series that have at least one data point per bucket
+/// (e.g. active hosts per hour)
+///
+/// Values are ignored, and the series are counted exactly.
+#[derive(Clone, Default)]
+pub struct ActiveSeries(rustc_hash::FxHashSet<SeriesId>);
+
+impl super::stream::Aggregation for ActiveSeries {
+    fn init(&mut self, _: Value) -> Value {
+        self.0.clear();
+        0.0
+    }
+
+    fn transform(&mut self, accu: Value, _: Value) -> Value {
+        accu
+    }
+
+    fn transform_batch(&mut self, accu: Value, _: &[Value]) -> Value {
+        accu
+    }
+
+    fn observe_series(&mut self, series_id: SeriesId) {
+        self.0.insert(series_id);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&mut self, _: &super::Bucket) -> Value {
+        self.0.len() as Value
+    }
+}
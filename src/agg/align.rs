@@ -0,0 +1,359 @@
+use crate::Timestamp;
+
+const SECOND_IN_NS: i128 = 1_000_000_000;
+
+const DAY_IN_NS: i128 = 24 * 3_600 * SECOND_IN_NS;
+
+const WEEK_IN_NS: i128 = 7 * DAY_IN_NS;
+
+/// The Unix epoch was a Thursday, so weeks are shifted to start on Mondays (in days)
+const MONDAY_OFFSET: i128 = 4;
+
+/// Time zone buckets are aligned to, see [`AggregationBuilder::align_timezone`](crate::AggregationBuilder::align_timezone)
+///
+/// Fixed offsets can be passed as seconds (e.g. `align_timezone(3_600)`).
+/// Using the `tz` feature, time zones of the tz database can be passed as [`jiff::tz::TimeZone`]
+/// (e.g. `align_timezone(jiff::tz::TimeZone::get("Europe/Berlin")?)`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TimeZone {
+    /// Fixed offset to UTC in seconds (e.g. `3_600` for CET)
+    ///
+    /// Daylight saving time transitions are not taken into account.
+    Fixed(i32),
+
+    /// Time zone whose offset changes with daylight saving time (e.g. `Europe/Berlin`)
+    #[cfg(feature = "tz")]
+    Zoned(jiff::tz::TimeZone),
+}
+
+impl From<i32> for TimeZone {
+    fn from(utc_offset_secs: i32) -> Self {
+        Self::Fixed(utc_offset_secs)
+    }
+}
+
+#[cfg(feature = "tz")]
+impl From<jiff::tz::TimeZone> for TimeZone {
+    fn from(tz: jiff::tz::TimeZone) -> Self {
+        Self::Zoned(tz)
+    }
+}
+
+impl TimeZone {
+    /// Identifies the time zone in query cache keys
+    pub(crate) fn cache_key(&self) -> String {
+        match self {
+            Self::Fixed(utc_offset_secs) => utc_offset_secs.to_string(),
+
+            #[cfg(feature = "tz")]
+            Self::Zoned(tz) => tz
+                .iana_name()
+                .map_or_else(|| format!("{tz:?}"), String::from),
+        }
+    }
+
+    /// Returns the offset to UTC (in seconds) at the given time
+    #[cfg_attr(not(feature = "tz"), allow(unused_variables))]
+    fn utc_offset_secs(&self, ts: i128) -> i32 {
+        match self {
+            Self::Fixed(utc_offset_secs) => *utc_offset_secs,
+
+            #[cfg(feature = "tz")]
+            Self::Zoned(tz) => {
+                jiff::Timestamp::from_nanosecond(ts).map_or(0, |ts| tz.to_offset(ts).seconds())
+            }
+        }
+    }
+
+    /// Returns the local date (in days since the Unix epoch) at the given time
+    fn local_day(&self, ts: i128) -> i128 {
+        let offset = i128::from(self.utc_offset_secs(ts)) * SECOND_IN_NS;
+        ts.saturating_add(offset).div_euclid(DAY_IN_NS)
+    }
+
+    /// Returns the time the local date (in days since the Unix epoch) starts at
+    ///
+    /// If midnight is skipped by a daylight saving time transition, the day starts at the transition.
+    fn midnight(&self, day: i128) -> i128 {
+        let utc_midnight = day.saturating_mul(DAY_IN_NS);
+
+        match self {
+            Self::Fixed(utc_offset_secs) => {
+                utc_midnight.saturating_sub(i128::from(*utc_offset_secs) * SECOND_IN_NS)
+            }
+
+            // NOTE: Dates outside of the range of the tz database fall back to UTC
+            #[cfg(feature = "tz")]
+            Self::Zoned(tz) => zoned_midnight(tz, day).unwrap_or(utc_midnight),
+        }
+    }
+}
+
+/// Returns the time the local date (in days since the Unix epoch) starts at in the time zone
+#[cfg(feature = "tz")]
+fn zoned_midnight(tz: &jiff::tz::TimeZone, day: i128) -> Option<i128> {
+    let (year, month, day) = civil_from_days(day);
+
+    let date = jiff::civil::Date::new(
+        i16::try_from(year).ok()?,
+        i8::try_from(month).ok()?,
+        i8::try_from(day).ok()?,
+    )
+    .ok()?;
+
+    tz.to_timestamp(date.at(0, 0, 0, 0))
+        .ok()
+        .map(jiff::Timestamp::as_nanosecond)
+}
+
+/// Returns the civil date `(year, month, day)` of the given day since the Unix epoch
+///
+/// NOTE: See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i128) -> (i128, i128, i128) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i128::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Returns the day since the Unix epoch of the given civil date
+///
+/// NOTE: See <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i128, month: i128, day: i128) -> i128 {
+    let year = year - i128::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Converts the bounds back to timestamps, clamping them to the timestamp range
+fn to_timestamps(start: i128, end: i128) -> (Timestamp, Timestamp) {
+    (
+        Timestamp::try_from(start).unwrap_or_default(),
+        Timestamp::try_from(end).unwrap_or(Timestamp::MAX),
+    )
+}
+
+/// Returns the bounds `[start, end)` of the aligned bucket containing the timestamp
+///
+/// Buckets that are a multiple of a day start at local midnight (weeks start on Mondays),
+/// so they are shorter or longer than their width on days of daylight saving time transitions.
+pub fn aligned_bounds(ts: Timestamp, width: Timestamp, tz: &TimeZone) -> (Timestamp, Timestamp) {
+    let width = i128::try_from(width).unwrap_or(i128::MAX).max(1);
+    let ts = i128::try_from(ts).unwrap_or(i128::MAX);
+
+    if width % DAY_IN_NS == 0 {
+        let days = width / DAY_IN_NS;
+        let origin = if width % WEEK_IN_NS == 0 {
+            MONDAY_OFFSET
+        } else {
+            0
+        };
+
+        let day = tz.local_day(ts);
+        let start = day - (day - origin).rem_euclid(days);
+
+        return to_timestamps(tz.midnight(start), tz.midnight(start.saturating_add(days)));
+    }
+
+    let origin = -i128::from(tz.utc_offset_secs(ts)) * SECOND_IN_NS;
+    let start = ts - (ts - origin).rem_euclid(width);
+
+    to_timestamps(start, start.saturating_add(width))
+}
+
+/// Returns the bounds `[start, end)` of the bucket of calendar months containing the timestamp,
+/// see [`AggregationBuilder::granularity_months`](crate::AggregationBuilder::granularity_months)
+///
+/// Buckets of multiple months start in January (e.g. quarters for 3 months).
+pub fn month_bounds(ts: Timestamp, months: u32, tz: &TimeZone) -> (Timestamp, Timestamp) {
+    let months = i128::from(months.max(1));
+    let ts = i128::try_from(ts).unwrap_or(i128::MAX);
+
+    let (year, month, _) = civil_from_days(tz.local_day(ts));
+
+    // NOTE: Months since January of year 0
+    let month = year * 12 + month - 1;
+    let start = month - month.rem_euclid(months);
+    let end = start + months;
+
+    let first_day =
+        |month: i128| days_from_civil(month.div_euclid(12), month.rem_euclid(12) + 1, 1);
+
+    to_timestamps(tz.midnight(first_day(start)), tz.midnight(first_day(end)))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const HOUR: Timestamp = 3_600 * 1_000_000_000;
+    const DAY: Timestamp = 24 * HOUR;
+
+    const UTC: TimeZone = TimeZone::Fixed(0);
+
+    #[test_log::test]
+    fn aligned_bounds_utc() {
+        assert_eq!((0, DAY), aligned_bounds(0, DAY, &UTC));
+        assert_eq!((0, DAY), aligned_bounds(DAY - 1, DAY, &UTC));
+        assert_eq!((DAY, 2 * DAY), aligned_bounds(DAY + 5 * HOUR, DAY, &UTC));
+        assert_eq!(
+            (DAY + 5 * HOUR, DAY + 6 * HOUR),
+            aligned_bounds(DAY + 5 * HOUR, HOUR, &UTC)
+        );
+    }
+
+    #[test_log::test]
+    fn aligned_bounds_timezone() {
+        let cet = TimeZone::Fixed(3_600);
+        let est = TimeZone::Fixed(-5 * 3_600);
+
+        // NOTE: 23:00 UTC is midnight in UTC+1
+        assert_eq!(
+            (DAY - HOUR, 2 * DAY - HOUR),
+            aligned_bounds(DAY + 5 * HOUR, DAY, &cet),
+        );
+        assert_eq!(
+            (DAY - HOUR, 2 * DAY - HOUR),
+            aligned_bounds(DAY - HOUR, DAY, &cet),
+        );
+
+        // NOTE: Midnight in UTC-5 is 05:00 UTC
+        assert_eq!(
+            (DAY + 5 * HOUR, 2 * DAY + 5 * HOUR),
+            aligned_bounds(DAY + 5 * HOUR, DAY, &est),
+        );
+        assert_eq!((5 * HOUR, DAY + 5 * HOUR), aligned_bounds(DAY, DAY, &est));
+    }
+
+    #[test_log::test]
+    fn aligned_bounds_week() {
+        // NOTE: 1970-01-05 was a Monday
+        let monday = 4 * DAY;
+        assert_eq!(
+            (monday, monday + 7 * DAY),
+            aligned_bounds(monday, 7 * DAY, &UTC)
+        );
+        assert_eq!(
+            (monday, monday + 7 * DAY),
+            aligned_bounds(monday + 6 * DAY, 7 * DAY, &UTC),
+        );
+        assert_eq!((0, monday), aligned_bounds(DAY, 7 * DAY, &UTC));
+    }
+
+    #[test_log::test]
+    fn civil_days_roundtrip() {
+        assert_eq!((1970, 1, 1), civil_from_days(0));
+        assert_eq!((2000, 2, 29), civil_from_days(11_016));
+        assert_eq!((1969, 12, 31), civil_from_days(-1));
+        assert_eq!(11_016, days_from_civil(2000, 2, 29));
+
+        for days in -1_000_000..1_000_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days, days_from_civil(year, month, day));
+        }
+    }
+
+    #[test_log::test]
+    fn month_bounds_utc() {
+        let day = |year, month, day| {
+            Timestamp::try_from(days_from_civil(year, month, day)).unwrap() * DAY
+        };
+
+        assert_eq!(
+            (day(2024, 2, 1), day(2024, 3, 1)),
+            month_bounds(day(2024, 2, 29) + 5 * HOUR, 1, &UTC)
+        );
+        assert_eq!(
+            (day(2024, 12, 1), day(2025, 1, 1)),
+            month_bounds(day(2024, 12, 31), 1, &UTC)
+        );
+
+        // NOTE: Quarters
+        assert_eq!(
+            (day(2024, 4, 1), day(2024, 7, 1)),
+            month_bounds(day(2024, 5, 17), 3, &UTC)
+        );
+        assert_eq!(
+            (day(2024, 1, 1), day(2025, 1, 1)),
+            month_bounds(day(2024, 5, 17), 12, &UTC)
+        );
+    }
+
+    #[test_log::test]
+    fn month_bounds_timezone() {
+        let cet = TimeZone::Fixed(3_600);
+        let march = Timestamp::try_from(days_from_civil(2024, 3, 1)).unwrap() * DAY;
+        let april = Timestamp::try_from(days_from_civil(2024, 4, 1)).unwrap() * DAY;
+
+        // NOTE: 23:30 UTC on the last day of February is already March in UTC+1
+        assert_eq!(
+            (march - HOUR, april - HOUR),
+            month_bounds(march - HOUR / 2, 1, &cet)
+        );
+    }
+
+    #[cfg(feature = "tz")]
+    #[test_log::test]
+    fn aligned_bounds_daylight_saving_time() {
+        let berlin = TimeZone::Zoned(jiff::tz::TimeZone::get("Europe/Berlin").unwrap());
+
+        let utc = |date: &str| {
+            Timestamp::try_from(date.parse::<jiff::Timestamp>().unwrap().as_nanosecond()).unwrap()
+        };
+
+        // NOTE: 2024-03-31 has 23 hours, midnight is 23:00 UTC before and 22:00 UTC after the transition
+        assert_eq!(
+            (utc("2024-03-30T23:00:00Z"), utc("2024-03-31T22:00:00Z")),
+            aligned_bounds(utc("2024-03-31T12:00:00Z"), DAY, &berlin)
+        );
+        assert_eq!(
+            (utc("2024-03-29T23:00:00Z"), utc("2024-03-30T23:00:00Z")),
+            aligned_bounds(utc("2024-03-30T12:00:00Z"), DAY, &berlin)
+        );
+
+        // NOTE: 2024-10-27 has 25 hours
+        assert_eq!(
+            (utc("2024-10-26T22:00:00Z"), utc("2024-10-27T23:00:00Z")),
+            aligned_bounds(utc("2024-10-27T22:30:00Z"), DAY, &berlin)
+        );
+
+        // NOTE: The week of the transition starts on Monday 2024-03-25
+        assert_eq!(
+            (utc("2024-03-24T23:00:00Z"), utc("2024-03-31T22:00:00Z")),
+            aligned_bounds(utc("2024-03-31T21:59:59Z"), 7 * DAY, &berlin)
+        );
+
+        // NOTE: Hourly buckets follow the offset of the timestamp
+        assert_eq!(
+            (utc("2024-03-31T01:00:00Z"), utc("2024-03-31T02:00:00Z")),
+            aligned_bounds(utc("2024-03-31T01:30:00Z"), HOUR, &berlin)
+        );
+
+        assert_eq!(
+            (utc("2024-02-29T23:00:00Z"), utc("2024-03-31T22:00:00Z")),
+            month_bounds(utc("2024-03-15T00:00:00Z"), 1, &berlin)
+        );
+        assert_eq!(
+            (utc("2024-09-30T22:00:00Z"), utc("2024-10-31T23:00:00Z")),
+            month_bounds(utc("2024-10-31T22:59:59Z"), 1, &berlin)
+        );
+    }
+}
@@ -1,8 +1,13 @@
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Average;
 
 impl super::stream::Aggregation for Average {
-    fn finish(bucket: &super::Bucket) -> crate::Value {
+    fn transform_batch(&mut self, accu: crate::Value, values: &[crate::Value]) -> crate::Value {
+        accu + super::sum::sum_chunked(values)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&mut self, bucket: &super::Bucket) -> crate::Value {
         bucket.value / bucket.len as crate::Value
     }
 }
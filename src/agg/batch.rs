@@ -0,0 +1,141 @@
+use super::{Agg, Aggregation, Bucket, Builder};
+use crate::{tier::DataSnapshot, Database, MetricGlob, MetricName, MetricSelector, Timestamp};
+use std::sync::Arc;
+
+/// One query of a batch, see [`Database::query_batch`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuerySpec {
+    /// Key the result is returned under (e.g. the dashboard panel)
+    pub id: String,
+
+    /// Metric name, or pattern matching multiple metrics (e.g. `cpu.*`)
+    pub metric: String,
+
+    /// Tag to group by
+    pub group_by: String,
+
+    /// Filter expression (default: `*`)
+    pub filter: String,
+
+    /// Aggregation function
+    pub agg: Agg,
+
+    /// Minimum timestamp
+    pub start: Option<Timestamp>,
+
+    /// Maximum timestamp
+    pub end: Option<Timestamp>,
+
+    /// Bucket width in nanoseconds
+    pub granularity: Option<Timestamp>,
+}
+
+impl QuerySpec {
+    /// Creates a query of all series of the metric.
+    #[must_use]
+    pub fn new(
+        id: impl Into<String>,
+        metric: impl Into<String>,
+        group_by: impl Into<String>,
+        agg: Agg,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            metric: metric.into(),
+            group_by: group_by.into(),
+            filter: "*".into(),
+            agg,
+            start: None,
+            end: None,
+            granularity: None,
+        }
+    }
+
+    /// Sets the filter expression.
+    #[must_use]
+    pub fn filter(mut self, filter_expr: impl Into<String>) -> Self {
+        self.filter = filter_expr.into();
+        self
+    }
+
+    /// Sets the minimum timestamp.
+    #[must_use]
+    pub fn start(mut self, ts: Timestamp) -> Self {
+        self.start = Some(ts);
+        self
+    }
+
+    /// Sets the maximum timestamp.
+    #[must_use]
+    pub fn end(mut self, ts: Timestamp) -> Self {
+        self.end = Some(ts);
+        self
+    }
+
+    /// Sets the bucket width in nanoseconds.
+    #[must_use]
+    pub fn granularity(mut self, bucket: Timestamp) -> Self {
+        self.granularity = Some(bucket);
+        self
+    }
+
+    /// Runs the query against the given snapshot.
+    pub(crate) fn run(
+        &self,
+        db: &Database,
+        snapshot: &Arc<DataSnapshot>,
+    ) -> crate::Result<crate::HashMap<String, Vec<Bucket>>> {
+        let metric: MetricSelector = if self.metric.contains('*') {
+            MetricGlob::try_from(self.metric.as_str())?.into()
+        } else {
+            MetricName::try_from(self.metric.as_str())?.into()
+        };
+
+        let group_by = self.group_by.as_str();
+
+        match self.agg {
+            Agg::Sum => self.collect(db.sum(metric, group_by), snapshot),
+            Agg::Avg => self.collect(db.avg(metric, group_by), snapshot),
+            Agg::Min => self.collect(db.min(metric, group_by), snapshot),
+            Agg::Max => self.collect(db.max(metric, group_by), snapshot),
+            Agg::Count => self.collect(db.count(metric, group_by), snapshot),
+            Agg::Distinct => self.collect(db.distinct(metric, group_by), snapshot),
+            Agg::ActiveSeries => self.collect(db.active_series(metric, group_by), snapshot),
+            Agg::Quantile(_) => {
+                let builder = self.configure(db.aggregate_many(metric, group_by, &[self.agg]));
+                let name = self.agg.name();
+
+                Ok(builder
+                    .build_at(snapshot)?
+                    .collect_many()?
+                    .into_iter()
+                    .map(|(group, mut aggs)| (group, aggs.remove(&name).unwrap_or_default()))
+                    .collect())
+            }
+        }
+    }
+
+    fn configure<'a, A: Aggregation>(&'a self, mut builder: Builder<'a, A>) -> Builder<'a, A> {
+        builder = builder.filter(self.filter.as_str());
+
+        if let Some(ts) = self.start {
+            builder = builder.start(ts);
+        }
+        if let Some(ts) = self.end {
+            builder = builder.end(ts);
+        }
+        if let Some(granularity) = self.granularity {
+            builder = builder.granularity(granularity);
+        }
+
+        builder
+    }
+
+    fn collect<A: Aggregation>(
+        &self,
+        builder: Builder<'_, A>,
+        snapshot: &Arc<DataSnapshot>,
+    ) -> crate::Result<crate::HashMap<String, Vec<Bucket>>> {
+        self.configure(builder).build_at(snapshot)?.collect()
+    }
+}
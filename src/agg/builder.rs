@@ -1,26 +1,131 @@
-use super::{stream::Aggregation, GroupedAggregation};
+use super::{stream::Aggregation, Bucket, GroupedAggregation, TimeZone, ValueFilter};
 use crate::{
-    agg::stream::Aggregator,
-    db::{SeriesStream, StreamItem},
+    agg::stream::{Aggregator, ScanBudget},
+    db::SeriesReader,
     merge::Merger,
-    timestamp, Database, Timestamp,
+    query::filter::Filter,
+    query_cache::{CacheTicket, QueryCacheKey, TimeBound},
+    tier::DataSnapshot,
+    timestamp, Database, Error, MetricGlob, SeriesId, Timestamp,
 };
-use std::marker::PhantomData;
+use std::{borrow::Cow, collections::BTreeMap, marker::PhantomData, ops::Bound, sync::Arc};
 
+/// What to do with series that do not have the `group_by` tag
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum MissingTagPolicy {
+    /// Series are skipped
+    #[default]
+    Skip,
+
+    /// Series are grouped under `"<none>"`
+    Group,
+
+    /// The query fails with [`crate::Error::MissingTag`]
+    Error,
+}
+
+/// Group of series that do not have the `group_by` tag, see [`MissingTagPolicy::Group`]
+const NONE_GROUP: &str = "<none>";
+
+/// Function creating the aggregation of each group
+pub type AggregationFactory<'a, A> = Arc<dyn Fn() -> A + Send + Sync + 'a>;
+
+/// Function mapping a tag value to its group
+pub type GroupMapFn<'a> = Arc<dyn Fn(&str) -> Option<String> + Send + Sync + 'a>;
+
+/// Function renaming a group, see [`Builder::group_by_label`]
+pub type GroupLabelFn<'a> = Arc<dyn Fn(&str) -> String + Send + Sync + 'a>;
+
+/// Predicate deciding whether a group is kept, given its buckets, see [`Builder::having`]
+pub type HavingFn<'a> = Arc<dyn Fn(&[Bucket]) -> bool + Send + Sync + 'a>;
+
+/// Series IDs of each group, ordered by group name
+type SeriesGroups = BTreeMap<String, Vec<SeriesId>>;
+
+/// Transformation applied to the `group_by` tag value to get the group
+#[derive(Clone, Default)]
+pub enum GroupMapping<'a> {
+    /// Each tag value is its own group
+    #[default]
+    Identity,
+
+    /// Group by the first N characters of the tag value
+    Prefix(usize),
+
+    /// Group by a user-provided mapping, series mapped to `None` are skipped
+    Map(GroupMapFn<'a>),
+
+    /// Rename each group before it becomes a key of the result
+    ///
+    /// Unlike the other mappings, the function is applied to the final group name,
+    /// so it also renames the `"<none>"` group & the group names of `split_by_metric`.
+    Label(GroupLabelFn<'a>),
+}
+
+impl GroupMapping<'_> {
+    /// Maps the `group_by` tag value to its group, or `None` if the series is skipped
+    fn apply(&self, value: &str) -> Option<String> {
+        match self {
+            Self::Identity | Self::Label(_) => Some(value.to_string()),
+            Self::Prefix(len) => Some(value.chars().take(*len).collect()),
+            Self::Map(f) => f(value),
+        }
+    }
+
+    /// Maps the final group name to the key of the result
+    fn label(&self, group: String) -> String {
+        match self {
+            Self::Label(f) => f(&group),
+            _ => group,
+        }
+    }
+}
+
+/// Builder for an aggregation query, see [`Database::aggregate`]
+///
+/// The builder is `Send + Sync`, so it can be moved into another thread before calling [`Builder::build`].
 pub struct Builder<'a, A: Aggregation> {
-    pub(crate) phantom: PhantomData<A>,
+    // NOTE: fn() -> A, so the builder is Send + Sync regardless of A
+    pub(crate) phantom: PhantomData<fn() -> A>,
 
     /// The database to access
     pub(crate) database: &'a Database,
 
     /// Name of metric to scan (e.g. `cpu_usage`)
-    pub(crate) metric_name: &'a str,
+    pub(crate) metric_name: Cow<'a, str>,
+
+    /// If `true`, the metric name is a pattern matching multiple metrics (e.g. `cpu.*`)
+    pub(crate) metric_glob: bool,
+
+    /// If `true`, groups of different metrics are not merged
+    pub(crate) split_by_metric: bool,
 
     /// Filter expression to filter out data points
-    pub(crate) filter_expr: &'a str,
+    pub(crate) filter_expr: Cow<'a, str>,
+
+    /// Pre-parsed filter, takes precedence over `filter_expr`
+    pub(crate) compiled_filter: Option<&'a Filter>,
 
     /// Group time series by tag (`host`)
-    pub(crate) group_by: &'a str,
+    pub(crate) group_by: Cow<'a, str>,
+
+    /// Group time series by multiple tags, takes precedence over `group_by`
+    ///
+    /// The group is the tag values joined by `;`, used by joins (see [`Database::join`]).
+    /// Without any tags, all series are in the same group.
+    pub(crate) group_by_tags: Option<Vec<Cow<'a, str>>>,
+
+    /// Transformation of the `group_by` tag value
+    pub(crate) group_mapping: GroupMapping<'a>,
+
+    /// What to do with series that do not have the `group_by` tag
+    pub(crate) missing_tag: MissingTagPolicy,
+
+    /// Maximum amount of groups, see `max_groups`
+    pub(crate) max_groups: Option<usize>,
+
+    /// Only groups after this group name are returned, see `groups_after`
+    pub(crate) groups_after: Option<Cow<'a, str>>,
 
     /// Bucket "width" in nanoseconds
     pub(crate) bucket_width: Timestamp,
@@ -30,112 +135,847 @@ pub struct Builder<'a, A: Aggregation> {
 
     /// Maximum timestamp to scan
     pub(crate) max_ts: Option<Timestamp>,
+
+    /// Relative window of the lower time bound, if set using `start_relative`
+    pub(crate) min_window: Option<u128>,
+
+    /// Relative window of the upper time bound, if set using `end_relative`
+    pub(crate) max_window: Option<u128>,
+
+    /// Factor the aggregated values are multiplied with
+    pub(crate) scale: f64,
+
+    /// Offset added to the aggregated values (after scaling)
+    pub(crate) offset: f64,
+
+    /// Maximum duration of the query, measured from `build()`
+    pub(crate) timeout: Option<std::time::Duration>,
+
+    /// Maximum amount of stored data points the query scans, see `max_scanned_points`
+    pub(crate) max_scanned_points: Option<u64>,
+
+    /// Maximum amount of buckets per group, see `downsample_lttb`
+    pub(crate) max_points: Option<usize>,
+
+    /// Bucket "width" in calendar months, takes precedence over `bucket_width`, see `granularity_months`
+    pub(crate) bucket_months: Option<u32>,
+
+    /// Time zone buckets are aligned to, see `align_timezone`
+    pub(crate) time_zone: Option<TimeZone>,
+
+    /// Data points whose value does not match are skipped
+    pub(crate) value_filter: Option<ValueFilter>,
+
+    /// Fraction of data points that are aggregated, see `sample`
+    pub(crate) sample_rate: Option<f64>,
+
+    /// Groups whose buckets do not match are dropped from the result, see `having`
+    pub(crate) having: Option<HavingFn<'a>>,
+
+    /// Creates the aggregation of each group, if it needs configuration (default: `A::default`)
+    pub(crate) aggregation_factory: Option<AggregationFactory<'a, A>>,
+
+    /// If `true`, deleted data points that were not compacted yet are aggregated, see `include_deleted`
+    pub(crate) include_deleted: bool,
 }
 
-impl<'a, A: Aggregation> Clone for Builder<'a, A> {
+impl<A: Aggregation> Clone for Builder<'_, A> {
     fn clone(&self) -> Self {
         Self {
             phantom: PhantomData,
             database: self.database,
-            metric_name: self.metric_name,
-            filter_expr: self.filter_expr,
-            group_by: self.group_by,
+            metric_name: self.metric_name.clone(),
+            metric_glob: self.metric_glob,
+            split_by_metric: self.split_by_metric,
+            filter_expr: self.filter_expr.clone(),
+            compiled_filter: self.compiled_filter,
+            group_by: self.group_by.clone(),
+            group_by_tags: self.group_by_tags.clone(),
+            group_mapping: self.group_mapping.clone(),
+            missing_tag: self.missing_tag,
+            max_groups: self.max_groups,
+            groups_after: self.groups_after.clone(),
             bucket_width: self.bucket_width,
             min_ts: self.min_ts,
             max_ts: self.max_ts,
+            min_window: self.min_window,
+            max_window: self.max_window,
+            scale: self.scale,
+            offset: self.offset,
+            timeout: self.timeout,
+            max_scanned_points: self.max_scanned_points,
+            max_points: self.max_points,
+            bucket_months: self.bucket_months,
+            time_zone: self.time_zone.clone(),
+            value_filter: self.value_filter,
+            sample_rate: self.sample_rate,
+            having: self.having.clone(),
+            aggregation_factory: self.aggregation_factory.clone(),
+            include_deleted: self.include_deleted,
         }
     }
 }
 
 impl<'a, A: Aggregation> Builder<'a, A> {
     /// Bucket "width" in nanoseconds
+    #[must_use]
     pub fn granularity(mut self, bucket: u128) -> Self {
         self.bucket_width = bucket;
+        self.bucket_months = None;
+        self
+    }
+
+    /// Bucket "width" in calendar months (e.g. `3` for quarters)
+    ///
+    /// Buckets start on the first day of the month, at midnight of the time zone
+    /// set using `align_timezone` (default = UTC).
+    #[must_use]
+    pub fn granularity_months(mut self, months: u32) -> Self {
+        self.bucket_months = Some(months);
         self
     }
 
     /// Sets the filter expression to filter out data points
     ///
     /// e.g. `env:prod AND service:db`
-    pub fn filter(mut self, filter_expr: &'a str) -> Self {
-        self.filter_expr = filter_expr;
+    ///
+    /// Accepts owned strings, so filters can be built dynamically.
+    #[must_use]
+    pub fn filter(mut self, filter_expr: impl Into<Cow<'a, str>>) -> Self {
+        self.filter_expr = filter_expr.into();
+        self.compiled_filter = None;
+        self
+    }
+
+    /// Sets a filter that was parsed using [`Database::parse_filter`].
+    ///
+    /// Reusing a parsed filter avoids parsing the same expression for every query.
+    #[must_use]
+    pub fn filter_compiled(mut self, filter: &'a Filter) -> Self {
+        self.filter_expr = Cow::Borrowed(filter.as_str());
+        self.compiled_filter = Some(filter);
+        self
+    }
+
+    /// If the query matches multiple metrics (see [`MetricGlob`](crate::MetricGlob)),
+    /// groups are returned per metric (`<metric>#<group>`, e.g. `cpu.user#h-1`)
+    /// instead of merging the series of all metrics.
+    #[must_use]
+    pub fn split_by_metric(mut self) -> Self {
+        self.split_by_metric = true;
+        self
+    }
+
+    /// Groups time series by the first `len` characters of the given tag's value,
+    /// e.g. hosts `r1-h01`, `r1-h02`, `r2-h01` grouped into racks using `group_by_prefix("host", 2)`.
+    #[must_use]
+    pub fn group_by_prefix(mut self, tag: impl Into<Cow<'a, str>>, len: usize) -> Self {
+        self.group_by = tag.into();
+        self.group_mapping = GroupMapping::Prefix(len);
+        self
+    }
+
+    /// Groups time series by mapping the given tag's value to a group.
+    ///
+    /// Series that are mapped to `None` are skipped.
+    ///
+    /// Results of queries using a mapping function are not cached.
+    #[must_use]
+    pub fn group_by_map(
+        mut self,
+        tag: impl Into<Cow<'a, str>>,
+        f: impl Fn(&str) -> Option<String> + Send + Sync + 'a,
+    ) -> Self {
+        self.group_by = tag.into();
+        self.group_mapping = GroupMapping::Map(Arc::new(f));
+        self
+    }
+
+    /// Groups time series by the given tag, and renames each group before it becomes
+    /// a key of the result, e.g. to lowercase names, strip instance suffixes or rename
+    /// groups using a lookup table.
+    ///
+    /// Unlike `group_by_map`, the function is also applied to the `"<none>"` group
+    /// & the group names of `split_by_metric`.
+    /// Groups that are renamed to the same name are aggregated together,
+    /// as if their series had the same tag value.
+    ///
+    /// A query has a single group mapping, so this replaces `group_by_prefix`/`group_by_map`.
+    ///
+    /// Results of queries using a label mapping are not cached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// db.write_at(metric_name, 0, 1.0, tagset!("host" => "H-1"))?;
+    /// db.write_at(metric_name, 0, 2.0, tagset!("host" => "h-1"))?;
+    /// db.write_at(metric_name, 0, 4.0, tagset!("host" => "h-2"))?;
+    ///
+    /// let buckets = db
+    ///     .sum(metric_name, "host")
+    ///     .group_by_label("host", |host| host.to_lowercase())
+    ///     .build()?
+    ///     .collect()?;
+    ///
+    /// assert_eq!(2, buckets.len());
+    /// assert_eq!(3.0, buckets["h-1"][0].value);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn group_by_label(
+        mut self,
+        tag: impl Into<Cow<'a, str>>,
+        f: impl Fn(&str) -> String + Send + Sync + 'a,
+    ) -> Self {
+        self.group_by = tag.into();
+        self.group_mapping = GroupMapping::Label(Arc::new(f));
+        self
+    }
+
+    /// Limits the result to the first `n` groups, ordered by group name, so queries
+    /// grouping by a high-cardinality tag do not build millions of groups.
+    ///
+    /// Use [`GroupedAggregation::next_page`] to check if groups were left out,
+    /// and [`Builder::groups_after`] to query the next page of groups.
+    ///
+    /// The limit applies before `having`, so fewer than `n` groups may be returned.
+    /// `collect_parallel` applies the limit as well, but can not report the next page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// for host in ["h-1", "h-2", "h-3"] {
+    ///     db.write_at(metric_name, 0, 1.0, tagset!("host" => host))?;
+    /// }
+    ///
+    /// let page = db.avg(metric_name, "host").max_groups(2).build()?;
+    /// let next_page = page.next_page().map(String::from);
+    ///
+    /// let buckets = page.collect()?;
+    /// assert!(buckets.contains_key("h-1") && buckets.contains_key("h-2"));
+    ///
+    /// let page = db
+    ///     .avg(metric_name, "host")
+    ///     .max_groups(2)
+    ///     .groups_after(next_page.unwrap())
+    ///     .build()?;
+    /// assert!(page.next_page().is_none());
+    ///
+    /// let buckets = page.collect()?;
+    /// assert_eq!(1, buckets.len());
+    /// assert!(buckets.contains_key("h-3"));
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn max_groups(mut self, n: usize) -> Self {
+        self.max_groups = Some(n);
+        self
+    }
+
+    /// Only returns groups whose name is ordered after the given group name,
+    /// see [`Builder::max_groups`].
+    #[must_use]
+    pub fn groups_after(mut self, group: impl Into<Cow<'a, str>>) -> Self {
+        self.groups_after = Some(group.into());
+        self
+    }
+
+    /// Sets what to do with series that do not have the `group_by` tag.
+    ///
+    /// Default = [`MissingTagPolicy::Skip`]
+    #[must_use]
+    pub fn missing_tag(mut self, policy: MissingTagPolicy) -> Self {
+        self.missing_tag = policy;
         self
     }
 
     /// Sets the lower time bound.
+    #[must_use]
     pub fn start(mut self, ts: Timestamp) -> Self {
         self.min_ts = Some(ts);
+        self.min_window = None;
         self
     }
 
     /// Sets the lower time bound relative to the current time.
     ///
     /// It is equivalent to `.start(timestamp() - window)`.
+    #[must_use]
     pub fn start_relative(mut self, window: u128) -> Self {
         self.min_ts = Some(timestamp() - window);
+        self.min_window = Some(window);
         self
     }
 
     /// Sets the upper time bound.
+    #[must_use]
     pub fn end(mut self, ts: Timestamp) -> Self {
         self.max_ts = Some(ts);
+        self.max_window = None;
         self
     }
 
     /// Sets the upper time bound relative to the current time.
     ///
     /// It is equivalent to `.end(timestamp() - window)`.
+    #[must_use]
     pub fn end_relative(mut self, window: u128) -> Self {
         self.max_ts = Some(timestamp() - window);
+        self.max_window = Some(window);
         self
     }
 
-    #[allow(clippy::option_if_let_else)]
-    #[allow(clippy::type_complexity)]
-    pub fn build(
-        self,
-    ) -> crate::Result<
-        GroupedAggregation<'a, A, Merger<Box<dyn Iterator<Item = crate::Result<StreamItem>>>>>,
-    > {
-        use std::ops::Bound;
-
-        let eligible_series = self.database.start_query(
-            self.metric_name,
-            self.filter_expr,
-            (
-                match self.min_ts {
-                    Some(ts) => Bound::Included(ts),
-                    None => Bound::Unbounded,
-                },
-                match self.max_ts {
-                    Some(ts) => Bound::Included(ts),
-                    None => Bound::Unbounded,
-                },
-            ),
-        )?;
-
-        let mut map: crate::HashMap<String, Vec<SeriesStream>> = crate::HashMap::default();
-
-        for series in eligible_series {
-            let Some(group) = series.tags.get(self.group_by) else {
-                continue;
-            };
-
-            if let Some(vec) = map.get_mut(group) {
-                vec.push(series);
-            } else {
-                map.insert(group.to_string(), vec![series]);
+    /// Multiplies each bucket's aggregated value by the given factor (e.g. bytes -> MiB).
+    ///
+    /// Scaling is applied before the offset.
+    #[must_use]
+    pub fn scale(mut self, factor: f64) -> Self {
+        self.scale = factor;
+        self
+    }
+
+    /// Adds the given offset to each bucket's aggregated value (e.g. Kelvin -> Celsius).
+    ///
+    /// The offset is applied after scaling.
+    #[must_use]
+    pub fn offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Converts durations stored in nanoseconds (e.g. written by [`crate::Timer`])
+    /// into the given unit.
+    ///
+    /// Equivalent to `.scale(1.0 / unit.nanos())`, so it replaces any previous scale.
+    #[must_use]
+    pub fn unit(self, unit: crate::DurationUnit) -> Self {
+        self.scale(unit.nanos().recip())
+    }
+
+    /// Aborts the query with [`crate::Error::Timeout`] if consuming it
+    /// takes longer than the given duration (measured from `build()`).
+    ///
+    /// This protects interactive callers from accidentally running huge scans.
+    #[must_use]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops scanning once the query has read `n` stored data points (across all groups),
+    /// returning a partial result instead of the full aggregation.
+    ///
+    /// Data points are scanned from newest to oldest, so truncated groups are missing their
+    /// oldest buckets. Groups are consumed one after another, so groups consumed after the
+    /// limit was reached may be missing entirely.
+    ///
+    /// Truncated groups are flagged in [`GroupMetadata::truncated`](super::GroupMetadata::truncated),
+    /// see [`GroupedAggregation::collect_with_metadata`]. Partial results are not cached.
+    ///
+    /// Default = [`DatabaseBuilder::max_scanned_points`](crate::DatabaseBuilder::max_scanned_points), or unlimited
+    #[must_use]
+    pub fn max_scanned_points(mut self, n: u64) -> Self {
+        self.max_scanned_points = Some(n);
+        self
+    }
+
+    /// Downsamples each group to at most `max_points` buckets when collecting, using the
+    /// Largest-Triangle-Three-Buckets algorithm, so the result can be plotted directly
+    /// while keeping the visual shape (e.g. spikes) of the series.
+    #[must_use]
+    pub fn downsample_lttb(mut self, max_points: usize) -> Self {
+        self.max_points = Some(max_points);
+        self
+    }
+
+    /// Only aggregates data points whose value matches the filter
+    /// (e.g. `ValueFilter::Gt(100.0)` in combination with `count` counts
+    /// how many data points exceeded a threshold).
+    ///
+    /// The filter is applied while scanning, so buckets without any
+    /// matching data points are not returned.
+    #[must_use]
+    pub fn value_filter(mut self, filter: ValueFilter) -> Self {
+        self.value_filter = Some(filter);
+        self
+    }
+
+    /// Only aggregates a deterministic sample of the data points (e.g. `0.1` = ~10%),
+    /// for fast approximate answers over large time ranges.
+    ///
+    /// Sums and counts are extrapolated to the full data, other aggregations are
+    /// computed over the sample. `Bucket::len` is the amount of sampled data points.
+    ///
+    /// Data points are sampled after they are read, so sampling saves the CPU time
+    /// of aggregating, but not the I/O of scanning the time range.
+    ///
+    /// The rate is clamped to `(0.0, 1.0]`.
+    #[must_use]
+    pub fn sample(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate.clamp(f64::MIN_POSITIVE, 1.0));
+        self
+    }
+
+    /// Only keeps groups whose buckets match the predicate (like SQL's `HAVING`),
+    /// e.g. to only return hosts whose average over the queried range exceeds a threshold.
+    ///
+    /// The predicate receives all buckets of a group once the group is aggregated, before
+    /// `downsample_lttb` is applied. Queries of multiple aggregations (see [`Database::aggregate_many`])
+    /// pass the buckets of the first aggregation.
+    ///
+    /// The query cache is bypassed, because predicates can not be compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, Value, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// db.write_at(metric_name, 0, 20.0, tagset!("host" => "h-1"))?;
+    /// db.write_at(metric_name, 1, 90.0, tagset!("host" => "h-2"))?;
+    ///
+    /// let busy_hosts = db
+    ///     .avg(metric_name, "host")
+    ///     .having(|buckets| {
+    ///         // NOTE: Buckets contain their raw sum & count, so they can be re-combined
+    ///         let sum = buckets.iter().map(|bucket| bucket.sum).sum::<Value>();
+    ///         let len = buckets.iter().map(|bucket| bucket.len).sum::<u64>();
+    ///         sum / len as Value > 50.0
+    ///     })
+    ///     .build()?
+    ///     .collect()?;
+    ///
+    /// assert_eq!(1, busy_hosts.len());
+    /// assert!(busy_hosts.contains_key("h-2"));
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn having(mut self, predicate: impl Fn(&[Bucket]) -> bool + Send + Sync + 'a) -> Self {
+        self.having = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Returns `true` if the group of the given buckets is kept, see `having`.
+    pub(crate) fn keeps_group(&self, buckets: &[Bucket]) -> bool {
+        self.having
+            .as_ref()
+            .map_or(true, |predicate| predicate(buckets))
+    }
+
+    /// Aligns buckets to the local time of the given time zone,
+    /// so e.g. daily buckets start at local midnight instead of containing the last 24 hours
+    /// before the newest data point.
+    ///
+    /// Buckets that are a multiple of a week start on Mondays.
+    ///
+    /// Accepts a fixed UTC offset in seconds (e.g. `3_600` for CET), which does not take
+    /// daylight saving time transitions into account. Using the `tz` feature, a time zone of
+    /// the tz database can be passed instead (e.g. `jiff::tz::TimeZone::get("Europe/Berlin")?`),
+    /// so daily buckets are 23 or 25 hours long on days of daylight saving time transitions.
+    ///
+    /// Use `align_timezone(0)` to align buckets to UTC.
+    #[must_use]
+    pub fn align_timezone(mut self, tz: impl Into<TimeZone>) -> Self {
+        self.time_zone = Some(tz.into());
+        self
+    }
+
+    /// Returns `true` if buckets are aligned to the calendar, instead of the data points.
+    pub(crate) fn is_aligned(&self) -> bool {
+        self.time_zone.is_some() || self.bucket_months.is_some()
+    }
+
+    /// Also aggregates data points that were deleted, but not compacted yet
+    /// (see [`Database::delete`]), e.g. to inspect an accidental delete before undoing it.
+    ///
+    /// Default = `false`
+    #[must_use]
+    pub fn include_deleted(mut self, include: bool) -> Self {
+        self.include_deleted = include;
+        self
+    }
+
+    fn cache_key(&self) -> QueryCacheKey {
+        // NOTE: Destructured, so every new option has to be added to the key (or skipped explicitly)
+        let Self {
+            phantom: _,
+            database: _,
+            metric_name,
+            metric_glob: _,
+            split_by_metric,
+            filter_expr,
+            // NOTE: Compiled filters set the filter expression
+            compiled_filter: _,
+            group_by,
+            group_by_tags,
+            group_mapping,
+            missing_tag,
+            // NOTE: Functions can not be compared, so these queries are not cached, see `cache_ticket`
+            having: _,
+            aggregation_factory: _,
+            max_groups,
+            groups_after,
+            bucket_width,
+            min_ts,
+            max_ts,
+            min_window,
+            max_window,
+            scale,
+            offset,
+            // NOTE: Queries that time out are not cached
+            timeout: _,
+            max_scanned_points,
+            max_points,
+            bucket_months,
+            time_zone,
+            value_filter,
+            sample_rate,
+            include_deleted,
+        } = self;
+
+        let bound = |ts: Option<Timestamp>, window: Option<u128>| match (ts, window) {
+            (_, Some(window)) => TimeBound::Relative(window),
+            (Some(ts), None) => TimeBound::Absolute(ts),
+            (None, None) => TimeBound::Unbounded,
+        };
+
+        QueryCacheKey {
+            metric: metric_name.to_string(),
+            filter: filter_expr.to_string(),
+            group_by: match group_mapping {
+                GroupMapping::Prefix(len) => format!("{group_by}[..{len}]"),
+                _ => group_by.to_string(),
+            },
+            group_by_tags: group_by_tags
+                .as_ref()
+                .map(|tags| tags.iter().map(ToString::to_string).collect()),
+            split_by_metric: *split_by_metric,
+            aggregation: std::any::type_name::<A>(),
+            start: bound(*min_ts, *min_window),
+            end: bound(*max_ts, *max_window),
+            granularity: *bucket_width,
+            scale: scale.to_bits(),
+            offset: offset.to_bits(),
+            max_points: *max_points,
+            max_scanned_points: *max_scanned_points,
+            granularity_months: *bucket_months,
+            time_zone: time_zone.as_ref().map(TimeZone::cache_key),
+            value_filter: value_filter.map(|filter| format!("{filter:?}")),
+            sample_rate: sample_rate.map(f64::to_bits),
+            missing_tag: *missing_tag,
+            include_deleted: *include_deleted,
+            max_groups: *max_groups,
+            groups_after: groups_after.as_deref().map(String::from),
+        }
+    }
+
+    fn cache_ticket(&self) -> Option<CacheTicket<'a>> {
+        // NOTE: Cached results are invalidated per metric, which does not work for patterns
+        if self.metric_glob {
+            return None;
+        }
+
+        // NOTE: Mapping functions can not be compared, so they can not be part of the cache key
+        if matches!(
+            self.group_mapping,
+            GroupMapping::Map(_) | GroupMapping::Label(_)
+        ) {
+            return None;
+        }
+
+        // NOTE: Same for predicates & configured aggregations
+        if self.having.is_some() || self.aggregation_factory.is_some() {
+            return None;
+        }
+
+        self.database
+            .query_cache()
+            .map(|cache| cache.ticket(self.cache_key()))
+    }
+
+    fn deadline(&self) -> Option<std::time::Instant> {
+        self.timeout
+            .map(|timeout| std::time::Instant::now() + timeout)
+    }
+
+    fn scan_budget(&self) -> Option<Arc<ScanBudget>> {
+        self.max_scanned_points
+            .map(|n| Arc::new(ScanBudget::new(n)))
+    }
+
+    fn bounds(&self) -> (Bound<Timestamp>, Bound<Timestamp>) {
+        (
+            self.min_ts.map_or(Bound::Unbounded, Bound::Included),
+            self.max_ts.map_or(Bound::Unbounded, Bound::Included),
+        )
+    }
+
+    /// Returns the matching series, grouped by the `group_by` tag, ordered by group name
+    ///
+    /// If groups were left out because of `max_groups`, the name of the
+    /// group to continue after is returned as well.
+    fn group_series(&self) -> crate::Result<(SeriesGroups, Option<String>)> {
+        let metrics = if self.metric_glob {
+            let glob = MetricGlob::try_from(&*self.metric_name).map_err(|_| Error::InvalidQuery)?;
+            self.database.list_metrics(glob)?
+        } else {
+            vec![self.metric_name.to_string()]
+        };
+
+        let parsed_filter;
+
+        let filter = if let Some(filter) = self.compiled_filter {
+            &filter.node
+        } else {
+            parsed_filter = self.database.parse_filter_expr(&self.filter_expr)?;
+            &parsed_filter
+        };
+
+        let mut map = SeriesGroups::new();
+        let mut source_count = 0;
+
+        for metric in &metrics {
+            for source in self.database.resolve_metric(metric) {
+                source_count += 1;
+
+                let series_ids = self.database.query_series_compiled(&source, filter)?;
+
+                for series_id in series_ids {
+                    let tags = self.database.tag_set(series_id)?;
+
+                    let value = self.group_by_tags.as_ref().map_or_else(
+                        || {
+                            tags.get(&*self.group_by)
+                                .map(|value| Cow::Borrowed(value.as_str()))
+                        },
+                        |keys| {
+                            keys.iter()
+                                .map(|key| tags.get(&**key).map(String::as_str))
+                                .collect::<Option<Vec<_>>>()
+                                .map(|values| Cow::Owned(values.join(";")))
+                        },
+                    );
+
+                    let group = match (value, self.missing_tag) {
+                        (Some(value), _) => self.group_mapping.apply(&value),
+                        (None, MissingTagPolicy::Skip) => None,
+                        (None, MissingTagPolicy::Group) => Some(NONE_GROUP.to_string()),
+                        (None, MissingTagPolicy::Error) => {
+                            return Err(Error::MissingTag(self.group_by.to_string()));
+                        }
+                    };
+
+                    let Some(group) = group else {
+                        continue;
+                    };
+
+                    let group = if self.split_by_metric && self.metric_glob {
+                        format!("{metric}#{group}")
+                    } else {
+                        group.clone()
+                    };
+
+                    let group = self.group_mapping.label(group);
+
+                    if self
+                        .groups_after
+                        .as_deref()
+                        .is_some_and(|after| *group <= *after)
+                    {
+                        continue;
+                    }
+
+                    // NOTE: Only the first N + 1 groups are kept, so the map stays small,
+                    // the additional group signals that groups were left out
+                    if let Some(max_groups) = self.max_groups {
+                        if map.len() > max_groups && !map.contains_key(&group) {
+                            if map.last_key_value().is_some_and(|(last, _)| *last < group) {
+                                continue;
+                            }
+                            map.pop_last();
+                        }
+                    }
+
+                    map.entry(group).or_default().push(series_id);
+                }
+            }
+        }
+
+        if source_count > 1 {
+            for series_ids in map.values_mut() {
+                series_ids.sort_unstable();
+                series_ids.dedup();
             }
         }
 
-        let map = map
+        let next_page = match self.max_groups {
+            Some(max_groups) if map.len() > max_groups => {
+                map.pop_last();
+
+                let last = map.last_key_value().map(|(group, _)| group.as_str());
+                Some(
+                    last.or(self.groups_after.as_deref())
+                        .unwrap_or_default()
+                        .into(),
+                )
+            }
+            _ => None,
+        };
+
+        Ok((map, next_page))
+    }
+
+    /// Runs the query, returning the aggregation of the matching series.
+    ///
+    /// The query reads from a snapshot taken when calling `build`,
+    /// so data points written afterwards are not observed.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    pub fn build(self) -> crate::Result<GroupedAggregation<'a, A, Merger<SeriesReader>>> {
+        let snapshot = self.database.snapshot();
+        self.build_at(&snapshot)
+    }
+
+    /// Runs the query against the given snapshot, see [`Builder::build`].
+    pub(crate) fn build_at(
+        self,
+        snapshot: &Arc<DataSnapshot>,
+    ) -> crate::Result<GroupedAggregation<'a, A, Merger<SeriesReader>>> {
+        let deadline = self.deadline();
+        let budget = self.scan_budget();
+        let cache_ticket = self.cache_ticket();
+        let bounds = self.bounds();
+
+        let span = span!(
+            "query",
+            metric = %self.metric_name,
+            filter = %self.filter_expr,
+            groups = tracing::field::Empty,
+            series = tracing::field::Empty,
+        );
+
+        let (groups, next_page) = self.group_series()?;
+        span.record("groups", groups.len());
+        span.record("series", groups.values().map(Vec::len).sum::<usize>());
+
+        let map = groups
             .into_iter()
-            .map(|(group, serieses)| {
-                let merger = Merger::new(serieses.into_iter().map(|x| x.reader).collect());
-                (group, Aggregator::new(self.clone(), merger))
+            .map(|(group, series_ids)| {
+                let in_bounds = self.database.series_in_bounds(&series_ids, bounds);
+                let readers = self.database.prepare_visible_query(
+                    snapshot,
+                    &in_bounds,
+                    bounds,
+                    self.include_deleted,
+                )?;
+                let merger = Merger::new(readers);
+                let aggregator = Aggregator::new(
+                    self.clone(),
+                    merger,
+                    deadline,
+                    budget.clone(),
+                    series_ids.len(),
+                );
+                Ok((group, aggregator))
+            })
+            .collect::<crate::Result<_>>()?;
+
+        Ok(GroupedAggregation(map, cache_ticket, next_page))
+    }
+
+    /// Runs the query, aggregating groups in parallel, and collects the result.
+    ///
+    /// This is equivalent to `.build()?.collect()`, but uses all cores for queries
+    /// with many groups. The series of a single group are still merged sequentially.
+    ///
+    /// Only available using the `rayon` feature flag.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    #[cfg(feature = "rayon")]
+    pub fn collect_parallel(self) -> crate::Result<crate::HashMap<String, Vec<super::Bucket>>> {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+        use std::sync::Arc;
+
+        let deadline = self.deadline();
+        let budget = self.scan_budget();
+        let cache_ticket = self.cache_ticket();
+
+        if let Some(ticket) = &cache_ticket {
+            if let Some(result) = ticket.cache.get(ticket) {
+                log::trace!("Query cache hit for {:?}", ticket.key);
+                return Ok((*result).clone());
+            }
+        }
+
+        let bounds = self.bounds();
+        let snapshot = self.database.snapshot();
+
+        // NOTE: Each worker opens the readers of its group, so only the groups
+        // that are currently aggregated hold buffered data points
+        let (groups, _) = self.group_series()?;
+
+        let map = groups
+            .into_par_iter()
+            .map(|(group, series_ids)| {
+                let in_bounds = self.database.series_in_bounds(&series_ids, bounds);
+                let readers = self.database.prepare_visible_query(
+                    &snapshot,
+                    &in_bounds,
+                    bounds,
+                    self.include_deleted,
+                )?;
+                let aggregator = Aggregator::new(
+                    self.clone(),
+                    Merger::new(readers),
+                    deadline,
+                    budget.clone(),
+                    series_ids.len(),
+                );
+                let buckets = aggregator.collect::<crate::Result<Vec<_>>>()?;
+
+                if !self.keeps_group(&buckets) {
+                    return Ok(None);
+                }
+
+                let buckets = match self.max_points {
+                    Some(max_points) => super::lttb::downsample(&buckets, max_points),
+                    None => buckets,
+                };
+
+                Ok(Some((group, buckets)))
             })
-            .collect();
+            .filter_map(Result::transpose)
+            .collect::<crate::Result<crate::HashMap<_, _>>>()?;
+
+        // NOTE: Partial results are not cached
+        let truncated = budget.as_ref().is_some_and(|budget| budget.is_truncated());
+
+        if let (Some(ticket), false) = (cache_ticket, truncated) {
+            ticket.cache.insert(ticket, Arc::new(map.clone()));
+        }
 
-        Ok(GroupedAggregation(map))
+        Ok(map)
     }
 }
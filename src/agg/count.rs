@@ -1,12 +1,38 @@
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Count;
 
+impl Count {
+    #[allow(clippy::cast_precision_loss)]
+    fn count_as_value(stat: &crate::Stat) -> crate::Value {
+        stat.count as crate::Value
+    }
+}
+
 impl super::stream::Aggregation for Count {
-    fn init(_: crate::Value) -> crate::Value {
+    fn init(&mut self, _: crate::Value) -> crate::Value {
         1.0
     }
 
-    fn transform(accu: crate::Value, _: crate::Value) -> crate::Value {
+    fn transform(&mut self, accu: crate::Value, _: crate::Value) -> crate::Value {
         accu + 1.0
     }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn transform_batch(&mut self, accu: crate::Value, values: &[crate::Value]) -> crate::Value {
+        accu + values.len() as crate::Value
+    }
+
+    fn init_stat(&mut self, stat: &crate::Stat) -> crate::Value {
+        Self::count_as_value(stat)
+    }
+
+    fn transform_stat(&mut self, accu: crate::Value, stat: &crate::Stat) -> crate::Value {
+        accu + Self::count_as_value(stat)
+    }
+
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::cast_possible_truncation, clippy::useless_conversion)]
+    fn extrapolate(&mut self, value: crate::Value, factor: f64) -> crate::Value {
+        (f64::from(value) * factor).round() as crate::Value
+    }
 }
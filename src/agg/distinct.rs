@@ -0,0 +1,157 @@
+use crate::Value;
+
+/// Number of index bits, results in 2^12 registers (4 KiB per sketch)
+///
+/// Standard error is around 1.04 / sqrt(2^12) = ~1.6%
+const PRECISION: u32 = 12;
+
+const REGISTER_COUNT: usize = 1 << PRECISION;
+
+/// `HyperLogLog` sketch used to approximate the number of distinct values
+#[derive(Clone)]
+pub struct HyperLogLog {
+    registers: Box<[u8]>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; REGISTER_COUNT].into_boxed_slice(),
+        }
+    }
+}
+
+impl HyperLogLog {
+    /// `SplitMix64` finalizer, scrambles the value's bits so similar values
+    /// are spread across all registers
+    fn hash(value: Value) -> u64 {
+        // NOTE: -0.0 and 0.0 are the same identifier
+        let value = if value == 0.0 { 0.0 } else { value };
+
+        // NOTE: Value is f64 when using the `high_precision` feature
+        #[allow(clippy::useless_conversion)]
+        let mut x = u64::from(value.to_bits());
+
+        x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^ (x >> 31)
+    }
+
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+
+    pub fn insert(&mut self, value: Value) {
+        let hash = Self::hash(value);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let idx = (hash >> (64 - PRECISION)) as usize;
+
+        // NOTE: Remaining bits, with a sentinel bit so the rank is bounded
+        let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+
+        #[allow(clippy::cast_possible_truncation)]
+        let rank = rest.leading_zeros() as u8 + 1;
+
+        if let Some(register) = self.registers.get_mut(idx) {
+            *register = (*register).max(rank);
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn estimate(&self) -> f64 {
+        let m = REGISTER_COUNT as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+        let sum = self
+            .registers
+            .iter()
+            .map(|&r| 2.0_f64.powi(-i32::from(r)))
+            .sum::<f64>();
+
+        let estimate = alpha * m * m / sum;
+
+        // NOTE: Small range correction (linear counting)
+        #[allow(clippy::naive_bytecount)]
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+/// Approximates the number of distinct values per bucket
+///
+/// Each value is treated as an identifier (e.g. a user ID).
+#[derive(Clone, Default)]
+pub struct Distinct(HyperLogLog);
+
+impl super::stream::Aggregation for Distinct {
+    fn init(&mut self, value: Value) -> Value {
+        self.0.clear();
+        self.0.insert(value);
+        0.0
+    }
+
+    fn transform(&mut self, accu: Value, x: Value) -> Value {
+        self.0.insert(x);
+        accu
+    }
+
+    // NOTE: Only the minimum and maximum value of a pre-aggregated sample are known
+    fn init_stat(&mut self, stat: &crate::Stat) -> Value {
+        self.0.clear();
+        self.0.insert(stat.min);
+        self.0.insert(stat.max);
+        0.0
+    }
+
+    fn transform_stat(&mut self, accu: Value, stat: &crate::Stat) -> Value {
+        self.0.insert(stat.min);
+        self.0.insert(stat.max);
+        accu
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn finish(&mut self, _: &super::Bucket) -> Value {
+        self.0.estimate().round() as Value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::cast_precision_loss, clippy::cast_lossless, clippy::float_cmp)]
+    fn hll_estimate() {
+        let mut hll = HyperLogLog::default();
+        assert_eq!(0.0, hll.estimate());
+
+        for _ in 0..10 {
+            for i in 0..10_000 {
+                hll.insert(i as Value);
+            }
+        }
+
+        let estimate = hll.estimate();
+        assert!((9_500.0..10_500.0).contains(&estimate), "{estimate}");
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn hll_small_cardinality() {
+        let mut hll = HyperLogLog::default();
+
+        for i in [1.0, 2.0, 3.0, 3.0, 2.0, 1.0, -0.0, 0.0] {
+            hll.insert(i);
+        }
+
+        assert_eq!(4.0, hll.estimate().round());
+    }
+}
@@ -1,10 +1,18 @@
-use super::{stream::Aggregation, Bucket};
-use crate::{agg::stream::Aggregator, db::StreamItem};
+use super::{stream::Aggregation, Bucket, GroupMetadata};
+use crate::{agg::stream::Aggregator, db::StreamItem, query_cache::CacheTicket};
+use std::sync::Arc;
 
 /// A dictionary of aggregators that can individually be advanced on demand.
 ///
 /// Call `.collect()` to read all aggregators into one result.
-pub struct GroupedAggregation<'a, A, I>(pub(crate) crate::HashMap<String, Aggregator<'a, A, I>>)
+///
+/// A grouped aggregation is `Send`, so it can be built on one thread and collected on another.
+pub struct GroupedAggregation<'a, A, I>(
+    pub(crate) crate::HashMap<String, Aggregator<'a, A, I>>,
+    pub(crate) Option<CacheTicket<'a>>,
+    /// Group to continue after, if groups were left out because of `max_groups`
+    pub(crate) Option<String>,
+)
 where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>;
@@ -21,7 +29,7 @@ where
     }
 }
 
-impl<'a, A, I> std::ops::DerefMut for GroupedAggregation<'a, A, I>
+impl<A, I> std::ops::DerefMut for GroupedAggregation<'_, A, I>
 where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>,
@@ -49,26 +57,138 @@ where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>,
 {
+    /// Returns the group name to pass to [`groups_after`](crate::AggregationBuilder::groups_after)
+    /// to get the next page of groups, or `None` if no groups were left out,
+    /// see [`max_groups`](crate::AggregationBuilder::max_groups).
+    #[must_use]
+    pub fn next_page(&self) -> Option<&str> {
+        self.2.as_deref()
+    }
+
     /// Consumes all groups, returning a dictionary of time series data,
     /// mapping each group to a list of data points (`Bucket`).
     ///
+    /// If the query cache is enabled, the result is cached, and
+    /// served from the cache if the same query is collected again.
+    ///
+    /// If the query reached its [`max_scanned_points`](crate::AggregationBuilder::max_scanned_points),
+    /// the result is partial; use [`GroupedAggregation::collect_with_metadata`] to detect truncated groups.
+    ///
     /// # Errors
     ///
     /// Returns an error if an I/O error occurred.
     pub fn collect(self) -> crate::Result<crate::HashMap<String, Vec<Bucket>>> {
+        let span = span!(
+            "collect",
+            groups = self.0.len(),
+            cache_hit = tracing::field::Empty,
+        );
+
+        if let Some(ticket) = &self.1 {
+            if let Some(result) = ticket.cache.get(ticket) {
+                log::trace!("Query cache hit for {:?}", ticket.key);
+                span.record("cache_hit", true);
+                return Ok((*result).clone());
+            }
+        }
+
+        let groups = Self::collect_groups(self.0)?;
+        let truncated = groups.values().any(|(_, metadata)| metadata.truncated);
+
+        let map = groups
+            .into_iter()
+            .map(|(group, (buckets, _))| (group, buckets))
+            .collect::<crate::HashMap<_, _>>();
+
+        // NOTE: Partial results are not cached
+        if let (Some(ticket), false) = (self.1, truncated) {
+            ticket.cache.insert(ticket, Arc::new(map.clone()));
+        }
+
+        Ok(map)
+    }
+
+    /// Consumes all groups like [`GroupedAggregation::collect`], additionally
+    /// returning the scan statistics of each group.
+    ///
+    /// The query cache is bypassed, because it does not store statistics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    pub fn collect_with_metadata(
+        self,
+    ) -> crate::Result<crate::HashMap<String, (Vec<Bucket>, GroupMetadata)>> {
+        Self::collect_groups(self.0)
+    }
+
+    fn collect_groups(
+        groups: crate::HashMap<String, Aggregator<'a, A, I>>,
+    ) -> crate::Result<crate::HashMap<String, (Vec<Bucket>, GroupMetadata)>> {
         let mut map =
-            crate::HashMap::with_capacity_and_hasher(self.0.len(), rustc_hash::FxBuildHasher);
+            crate::HashMap::with_capacity_and_hasher(groups.len(), rustc_hash::FxBuildHasher);
 
-        for (group, aggregator) in self.0 {
+        for (group, mut aggregator) in groups {
+            let max_points = aggregator.config.max_points;
             let mut buckets = vec![];
 
-            for bucket in aggregator {
+            for bucket in aggregator.by_ref() {
                 buckets.push(bucket?);
             }
 
-            map.insert(group, buckets);
+            if !aggregator.config.keeps_group(&buckets) {
+                continue;
+            }
+
+            if let Some(max_points) = max_points {
+                buckets = super::lttb::downsample(&buckets, max_points);
+            }
+
+            map.insert(group, (buckets, aggregator.metadata()));
         }
 
         Ok(map)
     }
+
+    /// Consumes all groups, passing each bucket to the given function (ordered by group),
+    /// returning the amount of buckets.
+    pub(crate) fn for_each_bucket(
+        self,
+        mut f: impl FnMut(&str, &Bucket) -> std::io::Result<()>,
+    ) -> crate::Result<u64> {
+        let mut groups = self.0.into_iter().collect::<Vec<_>>();
+        groups.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut count = 0;
+
+        for (group, mut aggregator) in groups {
+            let max_points = aggregator.config.max_points;
+
+            // NOTE: Both need all buckets of the group, so only then the group is materialized
+            if aggregator.config.having.is_some() || max_points.is_some() {
+                let buckets = aggregator.by_ref().collect::<crate::Result<Vec<_>>>()?;
+
+                if !aggregator.config.keeps_group(&buckets) {
+                    continue;
+                }
+
+                let buckets = match max_points {
+                    Some(max_points) => super::lttb::downsample(&buckets, max_points),
+                    None => buckets,
+                };
+
+                for bucket in &buckets {
+                    f(&group, bucket)?;
+                    count += 1;
+                }
+            } else {
+                for bucket in aggregator {
+                    f(&group, &bucket?)?;
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(count)
+    }
 }
@@ -0,0 +1,283 @@
+use super::{Agg, Bucket, Builder, Multi, TimeZone};
+use crate::{Database, MetricName, Timestamp, Value};
+use std::{borrow::Cow, iter::Peekable, sync::Arc};
+
+/// Function combining the bucket values of both metrics of a join
+pub type JoinFn<'a> = Arc<dyn Fn(Value, Value) -> Value + Send + Sync + 'a>;
+
+/// Buckets of one side of a join
+type BucketStream<'a> = Box<dyn Iterator<Item = crate::Result<Bucket>> + 'a>;
+
+/// What to do with buckets (and groups) only one metric of a join has data points for
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MissingDataPolicy {
+    /// The bucket is skipped (inner join)
+    #[default]
+    Skip,
+
+    /// The missing value is replaced with the given value (outer join)
+    Fill(Value),
+}
+
+/// Builder for a join of two metrics, see [`Database::join`]
+pub struct JoinBuilder<'a> {
+    database: &'a Database,
+    metrics: (MetricName<'a>, MetricName<'a>),
+    aggs: (Agg, Agg),
+    tags: Vec<Cow<'a, str>>,
+    filter_expr: Cow<'a, str>,
+    bucket_width: Option<Timestamp>,
+    min_ts: Option<Timestamp>,
+    max_ts: Option<Timestamp>,
+    time_zone: TimeZone,
+    missing: MissingDataPolicy,
+    f: JoinFn<'a>,
+}
+
+impl<'a> JoinBuilder<'a> {
+    pub(crate) fn new(database: &'a Database, a: MetricName<'a>, b: MetricName<'a>) -> Self {
+        Self {
+            database,
+            metrics: (a, b),
+            aggs: (Agg::Sum, Agg::Sum),
+            tags: vec![],
+            filter_expr: Cow::Borrowed("*"),
+            bucket_width: None,
+            min_ts: None,
+            max_ts: None,
+            time_zone: TimeZone::Fixed(0),
+            missing: MissingDataPolicy::default(),
+            f: Arc::new(|a, b| a / b),
+        }
+    }
+
+    /// Joins the series of both metrics on the values of the given tags.
+    ///
+    /// Groups are named by the tag values, joined by `;` (e.g. `h-1;eu`).
+    ///
+    /// If no tags are given, all series of each metric are aggregated into one group, named `""`.
+    #[must_use]
+    pub fn on_tags(mut self, tags: &[&'a str]) -> Self {
+        self.tags = tags.iter().map(|&tag| Cow::Borrowed(tag)).collect();
+        self
+    }
+
+    /// Filter expression applied to the series of both metrics.
+    #[must_use]
+    pub fn filter(mut self, filter_expr: impl Into<Cow<'a, str>>) -> Self {
+        self.filter_expr = filter_expr.into();
+        self
+    }
+
+    /// Sets the aggregations of both metrics.
+    ///
+    /// Default = [`Agg::Sum`] for both
+    #[must_use]
+    pub fn aggregations(mut self, a: Agg, b: Agg) -> Self {
+        self.aggs = (a, b);
+        self
+    }
+
+    /// Bucket "width" in nanoseconds
+    #[must_use]
+    pub fn granularity(mut self, bucket: Timestamp) -> Self {
+        self.bucket_width = Some(bucket);
+        self
+    }
+
+    /// Sets the minimum timestamp to scan.
+    #[must_use]
+    pub fn start(mut self, ts: Timestamp) -> Self {
+        self.min_ts = Some(ts);
+        self
+    }
+
+    /// Sets the maximum timestamp to scan.
+    #[must_use]
+    pub fn end(mut self, ts: Timestamp) -> Self {
+        self.max_ts = Some(ts);
+        self
+    }
+
+    /// Aligns buckets to the local time of the given time zone,
+    /// see [`Builder::align_timezone`].
+    ///
+    /// Buckets of joins are always aligned, so the buckets of both metrics cover the same time ranges.
+    ///
+    /// Default = `0` (UTC)
+    #[must_use]
+    pub fn align_timezone(mut self, tz: impl Into<TimeZone>) -> Self {
+        self.time_zone = tz.into();
+        self
+    }
+
+    /// Sets what to do with buckets only one metric has data points for.
+    ///
+    /// Default = [`MissingDataPolicy::Skip`]
+    #[must_use]
+    pub fn missing_data(mut self, policy: MissingDataPolicy) -> Self {
+        self.missing = policy;
+        self
+    }
+
+    /// Sets the function combining the bucket values of both metrics.
+    ///
+    /// Default = `a / b`
+    #[must_use]
+    pub fn map(mut self, f: impl Fn(Value, Value) -> Value + Send + Sync + 'a) -> Self {
+        self.f = Arc::new(f);
+        self
+    }
+
+    fn side(&self, metric: MetricName<'a>, agg: Agg) -> Builder<'a, Multi> {
+        let mut builder = self
+            .database
+            .aggregate_many(metric, self.tags.join(","), &[agg])
+            .filter(self.filter_expr.clone())
+            .align_timezone(self.time_zone.clone());
+
+        builder.group_by_tags = Some(self.tags.clone());
+
+        if let Some(width) = self.bucket_width {
+            builder = builder.granularity(width);
+        }
+        if let Some(ts) = self.min_ts {
+            builder = builder.start(ts);
+        }
+        if let Some(ts) = self.max_ts {
+            builder = builder.end(ts);
+        }
+
+        builder
+    }
+
+    /// Runs the join, returning the joined bucket stream of each group.
+    ///
+    /// Both metrics are read from the same snapshot, taken when calling `build`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    pub fn build(self) -> crate::Result<crate::HashMap<String, JoinStream<'a>>> {
+        let snapshot = self.database.snapshot();
+
+        let mut a = self
+            .side(self.metrics.0, self.aggs.0)
+            .build_at(&snapshot)?
+            .0;
+        let mut b = self
+            .side(self.metrics.1, self.aggs.1)
+            .build_at(&snapshot)?
+            .0;
+
+        let mut groups = a.keys().chain(b.keys()).cloned().collect::<Vec<_>>();
+        groups.sort_unstable();
+        groups.dedup();
+
+        let mut map =
+            crate::HashMap::with_capacity_and_hasher(groups.len(), rustc_hash::FxBuildHasher);
+
+        for group in groups {
+            let (a, b) = (a.remove(&group), b.remove(&group));
+
+            // NOTE: Without a fill value, a group of only one metric can not produce any bucket
+            if (a.is_none() || b.is_none()) && self.missing == MissingDataPolicy::Skip {
+                continue;
+            }
+
+            let side = |aggregator: Option<_>| -> BucketStream<'a> {
+                match aggregator {
+                    Some(aggregator) => Box::new(aggregator),
+                    None => Box::new(std::iter::empty()),
+                }
+            };
+
+            let stream = JoinStream {
+                a: side(a).peekable(),
+                b: side(b).peekable(),
+                missing: self.missing,
+                f: self.f.clone(),
+            };
+
+            map.insert(group, stream);
+        }
+
+        Ok(map)
+    }
+
+    /// Runs the join, and collects the joined buckets of each group.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    pub fn collect(self) -> crate::Result<crate::HashMap<String, Vec<Bucket>>> {
+        self.build()?
+            .into_iter()
+            .map(|(group, stream)| Ok((group, stream.collect::<crate::Result<Vec<_>>>()?)))
+            .collect()
+    }
+}
+
+/// Joined buckets of a group, ordered from newest to oldest, see [`JoinBuilder::build`]
+///
+/// The value of each bucket is the result of the join function (see [`JoinBuilder::map`]),
+/// `len` is the amount of data points of both metrics, and `sum` is the joined value as well,
+/// because joined buckets have no raw sum.
+pub struct JoinStream<'a> {
+    a: Peekable<BucketStream<'a>>,
+    b: Peekable<BucketStream<'a>>,
+    missing: MissingDataPolicy,
+    f: JoinFn<'a>,
+}
+
+impl JoinStream<'_> {
+    fn joined(&self, bucket: &Bucket, a: Value, b: Value, len: u64) -> Bucket {
+        let value = (self.f)(a, b);
+
+        Bucket {
+            start: bucket.start,
+            end: bucket.end,
+            value,
+            len,
+            sum: value,
+        }
+    }
+}
+
+impl Iterator for JoinStream<'_> {
+    type Item = crate::Result<Bucket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // NOTE: Buckets are aligned & ordered newest first, so the newer bucket
+            // has no counterpart if the start times differ
+            let newer = match (self.a.peek(), self.b.peek()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) => return self.a.next(),
+                (_, Some(Err(_))) => return self.b.next(),
+                (Some(Ok(a)), Some(Ok(b))) => a.start.cmp(&b.start),
+                (Some(Ok(_)), None) => std::cmp::Ordering::Greater,
+                (None, Some(Ok(_))) => std::cmp::Ordering::Less,
+            };
+
+            let (a, b) = match newer {
+                std::cmp::Ordering::Equal => (self.a.next(), self.b.next()),
+                std::cmp::Ordering::Greater => (self.a.next(), None),
+                std::cmp::Ordering::Less => (None, self.b.next()),
+            };
+
+            let bucket = match (a, b, self.missing) {
+                (Some(Ok(a)), Some(Ok(b)), _) => self.joined(&a, a.value, b.value, a.len + b.len),
+                (Some(Ok(a)), None, MissingDataPolicy::Fill(fill)) => {
+                    self.joined(&a, a.value, fill, a.len)
+                }
+                (None, Some(Ok(b)), MissingDataPolicy::Fill(fill)) => {
+                    self.joined(&b, fill, b.value, b.len)
+                }
+                _ => continue,
+            };
+
+            return Some(Ok(bucket));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::Bucket;
+
+// NOTE: Value is f64 using the `high_precision` feature
+#[allow(clippy::cast_precision_loss, clippy::useless_conversion)]
+fn point(bucket: &Bucket) -> (f64, f64) {
+    (bucket.middle() as f64, f64::from(bucket.value))
+}
+
+/// Downsamples buckets to at most `max_points` buckets using the
+/// Largest-Triangle-Three-Buckets algorithm, which keeps the visual shape of the series.
+///
+/// The first and last bucket are always kept.
+///
+/// See <https://skemman.is/bitstream/1946/15343/3/SS_MSthesis.pdf>
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn downsample(buckets: &[Bucket], max_points: usize) -> Vec<Bucket> {
+    if buckets.len() <= max_points {
+        return buckets.to_vec();
+    }
+
+    match (max_points, buckets.first(), buckets.last()) {
+        (0, _, _) | (_, None, _) | (_, _, None) => return vec![],
+        (1, Some(first), _) => return vec![*first],
+        (2, Some(first), Some(last)) => return vec![*first, *last],
+        _ => {}
+    }
+
+    // NOTE: The first and last bucket are kept, the rest is split into equally sized ranges
+    let every = (buckets.len() - 2) as f64 / (max_points - 2) as f64;
+    let range_start = |idx: usize| ((idx as f64 * every) as usize + 1).min(buckets.len() - 1);
+
+    let mut sampled = Vec::with_capacity(max_points);
+    let mut a = 0;
+
+    sampled.extend(buckets.first());
+
+    for idx in 0..(max_points - 2) {
+        let range = buckets
+            .get(range_start(idx)..range_start(idx + 1))
+            .unwrap_or_default();
+
+        // NOTE: The third point of the triangle is the average of the next range
+        let next_range = buckets
+            .get(range_start(idx + 1)..range_start(idx + 2).max(range_start(idx + 1) + 1))
+            .unwrap_or_default();
+
+        let (sum_x, sum_y) = next_range
+            .iter()
+            .map(point)
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let len = next_range.len().max(1) as f64;
+        let (avg_x, avg_y) = (sum_x / len, sum_y / len);
+
+        let (a_x, a_y) = buckets.get(a).map(point).unwrap_or_default();
+
+        let max = range
+            .iter()
+            .enumerate()
+            .map(|(offset, bucket)| {
+                let (x, y) = point(bucket);
+                let area = (a_x - avg_x)
+                    .mul_add(y - a_y, -((a_x - x) * (avg_y - a_y)))
+                    .abs();
+                (offset, area)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((offset, _)) = max {
+            a = range_start(idx) + offset;
+            sampled.extend(buckets.get(a));
+        }
+    }
+
+    sampled.extend(buckets.last());
+
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    fn buckets(values: &[Value]) -> Vec<Bucket> {
+        (0..)
+            .zip(values)
+            .map(|(idx, &value)| Bucket {
+                start: idx * 10,
+                end: idx * 10 + 10,
+                value,
+                len: 1,
+                sum: value,
+            })
+            .collect()
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn lttb_downsample() {
+        let input = buckets(&[0.0, 1.0, 0.0, 0.0, 9.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+        let sampled = downsample(&input, 4);
+        assert_eq!(4, sampled.len());
+        assert_eq!(input.first(), sampled.first());
+        assert_eq!(input.last(), sampled.last());
+
+        // NOTE: The spike is kept
+        assert!(sampled.iter().any(|bucket| bucket.value == 9.0));
+
+        assert_eq!(input, downsample(&input, 10));
+        assert_eq!(input, downsample(&input, 100));
+        assert_eq!(2, downsample(&input, 2).len());
+        assert!(downsample(&input, 0).is_empty());
+    }
+}
@@ -1,8 +1,32 @@
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Min;
 
 impl super::stream::Aggregation for Min {
-    fn transform(accu: crate::Value, x: crate::Value) -> crate::Value {
+    fn transform(&mut self, accu: crate::Value, x: crate::Value) -> crate::Value {
         accu.min(x)
     }
+
+    fn transform_batch(&mut self, accu: crate::Value, values: &[crate::Value]) -> crate::Value {
+        let mut lanes = [accu; super::sum::LANES];
+        let mut chunks = values.chunks_exact(super::sum::LANES);
+
+        for chunk in &mut chunks {
+            for (lane, x) in lanes.iter_mut().zip(chunk) {
+                *lane = lane.min(*x);
+            }
+        }
+
+        lanes
+            .into_iter()
+            .chain(chunks.remainder().iter().copied())
+            .fold(accu, crate::Value::min)
+    }
+
+    fn init_stat(&mut self, stat: &crate::Stat) -> crate::Value {
+        stat.min
+    }
+
+    fn transform_stat(&mut self, accu: crate::Value, stat: &crate::Stat) -> crate::Value {
+        accu.min(stat.min)
+    }
 }
@@ -1,21 +1,41 @@
+mod active;
+mod align;
 mod avg;
+mod batch;
 mod builder;
 mod count;
+mod distinct;
 mod group;
+mod join;
+mod lttb;
 mod max;
 mod min;
+mod multi;
+mod quantile;
 mod stream;
 mod sum;
+mod summary;
+mod value_filter;
 
 use crate::{Timestamp, Value};
 
+pub use active::ActiveSeries;
+pub use align::TimeZone;
 pub use avg::Average;
-pub use builder::Builder;
+pub use batch::QuerySpec;
+pub use builder::GroupMapping;
+pub use builder::{Builder, MissingTagPolicy};
 pub use count::Count;
+pub use distinct::Distinct;
 pub use group::GroupedAggregation;
+pub use join::{JoinBuilder, JoinStream, MissingDataPolicy};
 pub use max::Max;
 pub use min::Min;
+pub use multi::{Agg, Multi};
+pub use stream::Aggregation;
 pub use sum::Sum;
+pub use summary::{Summary, SummaryBucket};
+pub use value_filter::ValueFilter;
 
 /// A data point which spans some time
 #[derive(Copy, Clone, Default, Debug, PartialEq)]
@@ -30,7 +50,36 @@ pub struct Bucket {
     pub value: Value,
 
     /// The amount of raw data points that were contained in this bucket
-    pub len: usize,
+    pub len: u64,
+
+    /// The sum of the raw data points that were contained in this bucket
+    ///
+    /// Unlike `value`, it is not scaled, offset or extrapolated, so buckets of the same
+    /// time range can be re-combined (e.g. the average of merged buckets is `Σsum / Σlen`).
+    pub sum: Value,
+}
+
+/// Scan statistics of a group, see [`GroupedAggregation::collect_with_metadata`]
+///
+/// Can be used to display data completeness hints.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct GroupMetadata {
+    /// The amount of series merged into the group
+    pub series_count: usize,
+
+    /// The amount of stored data points that were scanned
+    pub points_scanned: u64,
+
+    /// The timestamp of the oldest scanned data point
+    pub start: Option<Timestamp>,
+
+    /// The timestamp of the newest scanned data point
+    pub end: Option<Timestamp>,
+
+    /// `true` if scanning stopped early because the query reached its
+    /// [`max_scanned_points`](crate::AggregationBuilder::max_scanned_points),
+    /// so the group is missing (older) data points
+    pub truncated: bool,
 }
 
 impl Bucket {
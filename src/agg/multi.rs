@@ -0,0 +1,307 @@
+use super::{
+    quantile::Quantile, stream::Aggregation, ActiveSeries, Average, Bucket, Count, Distinct,
+    GroupedAggregation, Max, Min, Sum,
+};
+use crate::{db::StreamItem, QuantileSketch, SeriesId, Stat, Value};
+use std::sync::Arc;
+
+/// A built-in aggregation, see [`crate::Database::aggregate_many`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Agg {
+    /// Sum of values
+    Sum,
+
+    /// Average value
+    Avg,
+
+    /// Lowest value
+    Min,
+
+    /// Highest value
+    Max,
+
+    /// Amount of data points
+    Count,
+
+    /// Approximate amount of distinct values
+    Distinct,
+
+    /// Amount of series with data points
+    ActiveSeries,
+
+    /// Exact quantile (e.g. `0.95`), all values of a bucket are buffered
+    Quantile(f64),
+}
+
+impl Agg {
+    /// Median
+    pub const P50: Self = Self::Quantile(0.5);
+
+    /// 90th percentile
+    pub const P90: Self = Self::Quantile(0.9);
+
+    /// 95th percentile
+    pub const P95: Self = Self::Quantile(0.95);
+
+    /// 99th percentile
+    pub const P99: Self = Self::Quantile(0.99);
+
+    /// Returns the name the aggregation's buckets are returned under (e.g. `avg`, `p95`).
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            Self::Sum => "sum".into(),
+            Self::Avg => "avg".into(),
+            Self::Min => "min".into(),
+            Self::Max => "max".into(),
+            Self::Count => "count".into(),
+            Self::Distinct => "distinct".into(),
+            Self::ActiveSeries => "active_series".into(),
+            Self::Quantile(q) => {
+                // NOTE: Round, so 0.999 is named p99.9 instead of p99.89999999999999
+                let percentile = (q.clamp(0.0, 1.0) * 100.0 * 1_000_000.0).round() / 1_000_000.0;
+                format!("p{percentile}")
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum State {
+    Sum(Sum),
+    Avg(Average),
+    Min(Min),
+    Max(Max),
+    Count(Count),
+    Distinct(Distinct),
+    ActiveSeries(ActiveSeries),
+    Quantile(Quantile),
+}
+
+/// Calls the same method on the aggregation of any state
+macro_rules! dispatch {
+    ($state:expr, $agg:ident => $body:expr) => {
+        match $state {
+            State::Sum($agg) => $body,
+            State::Avg($agg) => $body,
+            State::Min($agg) => $body,
+            State::Max($agg) => $body,
+            State::Count($agg) => $body,
+            State::Distinct($agg) => $body,
+            State::ActiveSeries($agg) => $body,
+            State::Quantile($agg) => $body,
+        }
+    };
+}
+
+impl From<Agg> for State {
+    fn from(agg: Agg) -> Self {
+        match agg {
+            Agg::Sum => Self::Sum(Sum),
+            Agg::Avg => Self::Avg(Average),
+            Agg::Min => Self::Min(Min),
+            Agg::Max => Self::Max(Max),
+            Agg::Count => Self::Count(Count),
+            Agg::Distinct => Self::Distinct(Distinct::default()),
+            Agg::ActiveSeries => Self::ActiveSeries(ActiveSeries::default()),
+            Agg::Quantile(q) => Self::Quantile(Quantile::new(q)),
+        }
+    }
+}
+
+/// Computes multiple aggregations over the same scan
+///
+/// The bucket value is the value of the first aggregation, the other values are
+/// returned by [`GroupedAggregation::collect_many`].
+#[derive(Clone, Default)]
+pub struct Multi {
+    aggs: Arc<[Agg]>,
+    states: Vec<State>,
+
+    /// Accumulated value of each aggregation in the current bucket
+    accus: Vec<Value>,
+
+    /// Values of each aggregation in the last finished bucket
+    finished: Vec<Value>,
+}
+
+impl Multi {
+    pub(crate) fn new(aggs: Arc<[Agg]>) -> Self {
+        let states = aggs.iter().copied().map(State::from).collect::<Vec<_>>();
+
+        Self {
+            accus: vec![0.0; states.len()],
+            finished: vec![0.0; states.len()],
+            aggs,
+            states,
+        }
+    }
+
+    fn first(&self) -> Value {
+        self.accus.first().copied().unwrap_or_default()
+    }
+}
+
+impl Aggregation for Multi {
+    fn init(&mut self, value: Value) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.init(value));
+        }
+        self.first()
+    }
+
+    fn transform(&mut self, _: Value, x: Value) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.transform(*accu, x));
+        }
+        self.first()
+    }
+
+    fn transform_batch(&mut self, _: Value, values: &[Value]) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.transform_batch(*accu, values));
+        }
+        self.first()
+    }
+
+    fn init_stat(&mut self, stat: &Stat) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.init_stat(stat));
+        }
+        self.first()
+    }
+
+    fn transform_stat(&mut self, _: Value, stat: &Stat) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.transform_stat(*accu, stat));
+        }
+        self.first()
+    }
+
+    fn init_sketch(&mut self, stat: &Stat, sketch: &QuantileSketch) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.init_sketch(stat, sketch));
+        }
+        self.first()
+    }
+
+    fn transform_sketch(&mut self, _: Value, stat: &Stat, sketch: &QuantileSketch) -> Value {
+        for (state, accu) in self.states.iter_mut().zip(&mut self.accus) {
+            *accu = dispatch!(state, agg => agg.transform_sketch(*accu, stat, sketch));
+        }
+        self.first()
+    }
+
+    fn observe_series(&mut self, series_id: SeriesId) {
+        for state in &mut self.states {
+            dispatch!(state, agg => agg.observe_series(series_id));
+        }
+    }
+
+    fn finish(&mut self, bucket: &Bucket) -> Value {
+        for ((state, accu), finished) in self
+            .states
+            .iter_mut()
+            .zip(&self.accus)
+            .zip(&mut self.finished)
+        {
+            let bucket = Bucket {
+                value: *accu,
+                ..*bucket
+            };
+            *finished = dispatch!(state, agg => agg.finish(&bucket));
+        }
+
+        self.finished.first().copied().unwrap_or_default()
+    }
+
+    fn extrapolate(&mut self, value: Value, factor: f64) -> Value {
+        self.states.first_mut().map_or(
+            value,
+            |state| dispatch!(state, agg => agg.extrapolate(value, factor)),
+        )
+    }
+}
+
+impl<I> GroupedAggregation<'_, Multi, I>
+where
+    I: Iterator<Item = crate::Result<StreamItem>>,
+{
+    /// Consumes all groups, returning the buckets of every aggregation
+    /// per group, keyed by the aggregation's name (see [`Agg::name`]).
+    ///
+    /// The query cache is bypassed, because it only stores one value per bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    #[allow(clippy::cast_possible_truncation, clippy::useless_conversion)]
+    pub fn collect_many(
+        self,
+    ) -> crate::Result<crate::HashMap<String, crate::HashMap<String, Vec<Bucket>>>> {
+        let mut map =
+            crate::HashMap::with_capacity_and_hasher(self.0.len(), rustc_hash::FxBuildHasher);
+
+        for (group, mut aggregator) in self.0 {
+            let sample_rate = aggregator.config.sample_rate;
+            let (scale, offset) = (aggregator.config.scale, aggregator.config.offset);
+            let max_points = aggregator.config.max_points;
+
+            let names = aggregator
+                .aggregation
+                .aggs
+                .iter()
+                .map(Agg::name)
+                .collect::<Vec<_>>();
+
+            let mut series = vec![vec![]; names.len()];
+
+            // NOTE: Buckets of the first aggregation, for the `having` predicate
+            let mut first = vec![];
+
+            // NOTE: The values are read from the aggregation after each bucket,
+            // so the aggregator can not be borrowed by a for loop
+            #[allow(clippy::while_let_on_iterator)]
+            while let Some(bucket) = aggregator.next() {
+                let bucket = bucket?;
+                first.push(bucket);
+
+                let multi = &mut aggregator.aggregation;
+
+                for ((state, &value), buckets) in multi
+                    .states
+                    .iter_mut()
+                    .zip(&multi.finished)
+                    .zip(&mut series)
+                {
+                    let value = sample_rate.map_or(
+                        value,
+                        |rate| dispatch!(state, agg => agg.extrapolate(value, rate.recip())),
+                    );
+
+                    // NOTE: Value is f64 when using the `high_precision` feature
+                    let value = f64::from(value).mul_add(scale, offset) as Value;
+
+                    buckets.push(Bucket { value, ..bucket });
+                }
+            }
+
+            if !aggregator.config.keeps_group(&first) {
+                continue;
+            }
+
+            let aggs = names
+                .into_iter()
+                .zip(series)
+                .map(|(name, buckets)| match max_points {
+                    Some(max_points) => (name, super::lttb::downsample(&buckets, max_points)),
+                    None => (name, buckets),
+                })
+                .collect();
+
+            map.insert(group, aggs);
+        }
+
+        Ok(map)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{QuantileSketch, Stat, Value};
+
+/// Computes the exact quantile of each bucket (nearest rank)
+///
+/// All values of the bucket are buffered, so memory usage grows with the bucket size.
+///
+/// If the bucket contains pre-aggregated samples with a quantile sketch (see
+/// [`crate::Database::write_sketch`]), the sketches are merged, and the quantile is
+/// read from the merged sketch instead, so it is accurate within the sketch's relative error.
+#[derive(Clone)]
+pub struct Quantile {
+    q: f64,
+
+    /// Values with their weight, pre-aggregated samples are not stored per data point
+    values: Vec<(Value, u64)>,
+
+    /// Merged sketches of the bucket's pre-aggregated samples
+    sketch: Option<QuantileSketch>,
+}
+
+impl Default for Quantile {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl Quantile {
+    /// Creates a quantile aggregation, `q` is clamped to `[0.0, 1.0]`.
+    pub fn new(q: f64) -> Self {
+        Self {
+            q: q.clamp(0.0, 1.0),
+            values: vec![],
+            sketch: None,
+        }
+    }
+
+    /// Only the minimum, maximum & sum of a pre-aggregated sample are known, so
+    /// its remaining data points are approximated by their mean
+    #[allow(clippy::cast_precision_loss)]
+    fn insert_stat(&mut self, stat: &Stat) {
+        match stat.count {
+            0 => {}
+            1 => self.values.push((stat.sum, 1)),
+            count => {
+                let rest = count - 2;
+                self.values.push((stat.min, 1));
+                self.values.push((stat.max, 1));
+
+                if rest > 0 {
+                    let mean = (stat.sum - stat.min - stat.max) / rest as Value;
+                    self.values.push((mean, rest));
+                }
+            }
+        }
+    }
+}
+
+impl super::stream::Aggregation for Quantile {
+    fn init(&mut self, value: Value) -> Value {
+        self.values.clear();
+        self.values.push((value, 1));
+        self.sketch = None;
+        0.0
+    }
+
+    fn transform(&mut self, accu: Value, x: Value) -> Value {
+        self.values.push((x, 1));
+        accu
+    }
+
+    fn transform_batch(&mut self, accu: Value, values: &[Value]) -> Value {
+        self.values.extend(values.iter().map(|&x| (x, 1)));
+        accu
+    }
+
+    fn init_stat(&mut self, stat: &Stat) -> Value {
+        self.values.clear();
+        self.insert_stat(stat);
+        self.sketch = None;
+        0.0
+    }
+
+    fn transform_stat(&mut self, accu: Value, stat: &Stat) -> Value {
+        self.insert_stat(stat);
+        accu
+    }
+
+    fn init_sketch(&mut self, _: &Stat, sketch: &QuantileSketch) -> Value {
+        self.values.clear();
+        self.sketch = Some(sketch.clone());
+        0.0
+    }
+
+    fn transform_sketch(&mut self, accu: Value, _: &Stat, sketch: &QuantileSketch) -> Value {
+        match &mut self.sketch {
+            Some(merged) => merged.merge(sketch),
+            None => self.sketch = Some(sketch.clone()),
+        }
+        accu
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn finish(&mut self, _: &super::Bucket) -> Value {
+        if let Some(mut sketch) = self.sketch.take() {
+            for &(value, weight) in &self.values {
+                sketch.insert_n(value, weight);
+            }
+            return sketch.quantile(self.q).unwrap_or_default();
+        }
+
+        self.values
+            .sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let total = self.values.iter().map(|(_, weight)| weight).sum::<u64>();
+        let rank = ((self.q * total as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+
+        for (value, weight) in &self.values {
+            seen += weight;
+
+            if seen >= rank {
+                return *value;
+            }
+        }
+
+        self.values
+            .last()
+            .map(|(value, _)| *value)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agg::{stream::Aggregation, Bucket};
+
+    #[test_log::test]
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_lossless,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn quantile_nearest_rank() {
+        let values = (1..=100).map(|x| x as Value).collect::<Vec<_>>();
+
+        for (q, expected) in [
+            (0.0, 1.0),
+            (0.5, 50.0),
+            (0.95, 95.0),
+            (0.99, 99.0),
+            (1.0, 100.0),
+        ] {
+            let mut quantile = Quantile::new(q);
+            let accu = quantile.init(values[0]);
+            quantile.transform_batch(accu, &values[1..]);
+            assert_eq!(expected, quantile.finish(&Bucket::default()), "q={q}");
+        }
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn quantile_stat() {
+        let stat = Stat {
+            count: 10,
+            sum: 50.0,
+            min: 1.0,
+            max: 9.0,
+        };
+
+        for (q, expected) in [(0.0, 1.0), (0.5, 5.0), (1.0, 9.0)] {
+            let mut quantile = Quantile::new(q);
+            quantile.init_stat(&stat);
+            assert_eq!(expected, quantile.finish(&Bucket::default()), "q={q}");
+        }
+    }
+
+    #[test_log::test]
+    fn quantile_sketch() {
+        let mut sketch = QuantileSketch::default();
+        sketch.insert_n(1.0, 90);
+        sketch.insert_n(1_000.0, 5);
+
+        let mut quantile = Quantile::new(0.95);
+        let accu = quantile.init_sketch(&sketch.stat(), &sketch);
+        quantile.transform_batch(accu, &[500.0; 5]);
+
+        // NOTE: Raw values are merged into the sketch
+        let p95 = quantile.finish(&Bucket::default());
+        assert!((p95 - 500.0).abs() <= 5.0, "{p95}");
+    }
+}
@@ -1,24 +1,189 @@
-use super::{builder::Builder, Bucket};
-use crate::{db::StreamItem, Value};
-use std::marker::PhantomData;
+use super::align::{aligned_bounds, month_bounds};
+use super::{builder::Builder, Bucket, GroupMetadata, TimeZone};
+use crate::{db::StreamItem, QuantileSketch, SeriesId, Stat, Timestamp, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Amount of data points scanned between two checks of the query deadline
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// Raw values are aggregated in chunks, so wide buckets of dense series do not buffer all their values
+const VALUE_CHUNK_SIZE: usize = 1_024;
+
+/// Returns `true` if the data point of the series at the given timestamp is part of the sample.
+///
+/// The decision is based on a hash of the series ID & timestamp, so repeated queries read the
+/// same sample, and series that share timestamps are sampled independently.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn is_sampled(series_id: SeriesId, ts: Timestamp, rate: f64) -> bool {
+    // NOTE: splitmix64 finalizer, the series ID is spread by the golden ratio
+    let mut x = (ts as u64) ^ ((ts >> 64) as u64) ^ series_id.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+
+    // NOTE: Use the upper 53 bits, so the fraction is exact
+    let fraction = (x >> 11) as f64 / (1_u64 << 53) as f64;
+    fraction < rate
+}
+
+/// Amount of data points a query may still scan, shared by the aggregators of all its groups,
+/// see [`Builder::max_scanned_points`]
+pub struct ScanBudget {
+    remaining: AtomicU64,
+
+    /// Set once a data point could not be scanned, because the budget was exhausted
+    truncated: AtomicBool,
+}
+
+impl ScanBudget {
+    pub fn new(max_points: u64) -> Self {
+        Self {
+            remaining: AtomicU64::new(max_points),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes one data point from the budget, returning `false` if the budget is exhausted.
+    fn take(&self) -> bool {
+        let taken = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+
+        if !taken {
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+
+        taken
+    }
+
+    /// Returns `true` if any aggregator stopped scanning early.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+}
 
 /// Defines an aggregation.
 ///
+/// - `init` initializes a bucket using its first value (default: Identity)
+///
 /// - `transform` defines what to do with each value (default: Add)
 ///
+/// - `transform_batch` adds a contiguous run of values of the same bucket (default: `transform` for each value)
+///
 /// - `finish` can transform the result value (default: Identity)
-pub trait Aggregation {
-    fn init(value: Value) -> Value {
+///
+/// - `extrapolate` scales the result of a sampled query up to the full data (default: Identity)
+///
+/// - `observe_series` is called with the series of each data point added to a bucket (default: Noop)
+///
+/// - `init_stat` and `transform_stat` define how pre-aggregated samples are merged (default: Add sum)
+///
+/// - `init_sketch` and `transform_sketch` define how pre-aggregated samples with a quantile sketch are merged
+///   (default: Same as samples without sketch)
+///
+/// An aggregation instance is owned by its aggregator, so it can keep
+/// additional per-bucket state (e.g. a sketch) in between calls.
+///
+/// Use [`crate::Database::aggregate`] to run a custom aggregation.
+///
+/// # Examples
+///
+/// ```
+/// use talna::{Aggregation, Bucket, Value};
+///
+/// /// Returns the difference between the highest and lowest value
+/// #[derive(Default)]
+/// struct Range {
+///     min: Value,
+///     max: Value,
+/// }
+///
+/// impl Aggregation for Range {
+///     fn init(&mut self, value: Value) -> Value {
+///         self.min = value;
+///         self.max = value;
+///         0.0
+///     }
+///
+///     fn transform(&mut self, accu: Value, x: Value) -> Value {
+///         self.min = self.min.min(x);
+///         self.max = self.max.max(x);
+///         accu
+///     }
+///
+///     fn finish(&mut self, _: &Bucket) -> Value {
+///         self.max - self.min
+///     }
+/// }
+/// ```
+pub trait Aggregation: Default {
+    /// Initializes a new bucket with its first value, returning the bucket's initial value.
+    fn init(&mut self, value: Value) -> Value {
         value
     }
 
-    fn transform(accu: Value, x: Value) -> Value {
+    /// Adds a value to the bucket, returning the bucket's new value.
+    fn transform(&mut self, accu: Value, x: Value) -> Value {
         accu + x
     }
 
-    fn finish(bucket: &Bucket) -> Value {
+    /// Adds multiple values to the bucket, returning the bucket's new value.
+    ///
+    /// Values are buffered per bucket, so overriding this allows aggregating
+    /// them in tight (vectorizable) loops.
+    fn transform_batch(&mut self, accu: Value, values: &[Value]) -> Value {
+        values.iter().fold(accu, |accu, &x| self.transform(accu, x))
+    }
+
+    /// Initializes a new bucket with a pre-aggregated sample, returning the bucket's initial value.
+    fn init_stat(&mut self, stat: &Stat) -> Value {
+        self.init(stat.sum)
+    }
+
+    /// Adds a pre-aggregated sample to the bucket, returning the bucket's new value.
+    fn transform_stat(&mut self, accu: Value, stat: &Stat) -> Value {
+        self.transform(accu, stat.sum)
+    }
+
+    /// Initializes a new bucket with a pre-aggregated sample that was written with
+    /// a quantile sketch, returning the bucket's initial value.
+    #[allow(unused_variables)]
+    fn init_sketch(&mut self, stat: &Stat, sketch: &QuantileSketch) -> Value {
+        self.init_stat(stat)
+    }
+
+    /// Adds a pre-aggregated sample that was written with a quantile sketch
+    /// to the bucket, returning the bucket's new value.
+    #[allow(unused_variables)]
+    fn transform_sketch(&mut self, accu: Value, stat: &Stat, sketch: &QuantileSketch) -> Value {
+        self.transform_stat(accu, stat)
+    }
+
+    /// Called with the series ID of every data point added to the current bucket,
+    /// after the data point was passed to `init` or `transform`.
+    ///
+    /// Values of a bucket are aggregated in batches, so this may be called before
+    /// the data point's value reaches `transform_batch`.
+    #[allow(unused_variables)]
+    fn observe_series(&mut self, series_id: SeriesId) {}
+
+    /// Returns the final value of the bucket.
+    fn finish(&mut self, bucket: &Bucket) -> Value {
         bucket.value
     }
+
+    /// Extrapolates the final value of a bucket that only contains a sample of the
+    /// data points (see [`Builder::sample`]), where `factor` is the inverse sample rate.
+    ///
+    /// Aggregations that grow with the amount of data points (e.g. sum, count) should scale
+    /// the value by the factor, others (e.g. min, average) can return the value as is.
+    #[allow(unused_variables)]
+    fn extrapolate(&mut self, value: Value, factor: f64) -> Value {
+        value
+    }
 }
 
 /// A streaming aggregator
@@ -29,10 +194,28 @@ where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>,
 {
-    config: Builder<'a, A>,
+    pub(crate) config: Builder<'a, A>,
     bucket: Bucket,
     reader: I,
-    phantom: PhantomData<A>,
+    pub(crate) aggregation: A,
+
+    /// Raw values of the current bucket that have not been aggregated yet
+    values: Vec<Value>,
+
+    /// Point in time after which the aggregation is aborted
+    deadline: Option<Instant>,
+
+    /// Amount of data points read, used to check the deadline periodically
+    read_count: usize,
+
+    /// Set once the aggregation timed out, so iteration stops
+    timed_out: bool,
+
+    /// Points the query may still scan, if limited
+    budget: Option<Arc<ScanBudget>>,
+
+    /// Scan statistics of the data points read so far
+    metadata: GroupMetadata,
 }
 
 impl<'a, A, I> Aggregator<'a, A, I>
@@ -40,17 +223,139 @@ where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>,
 {
-    pub fn new(builder: Builder<'a, A>, reader: I) -> Self {
+    pub fn new(
+        builder: Builder<'a, A>,
+        reader: I,
+        deadline: Option<Instant>,
+        budget: Option<Arc<ScanBudget>>,
+        series_count: usize,
+    ) -> Self {
+        let aggregation = builder
+            .aggregation_factory
+            .as_ref()
+            .map_or_else(A::default, |factory| factory());
+
         Self {
             config: builder,
             bucket: Bucket::default(),
             reader,
-            phantom: PhantomData,
+            aggregation,
+            values: vec![],
+            deadline,
+            read_count: 0,
+            timed_out: false,
+            budget,
+            metadata: GroupMetadata {
+                series_count,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the scan statistics of the data points read so far.
+    ///
+    /// The statistics are complete once the aggregator is exhausted.
+    pub fn metadata(&self) -> GroupMetadata {
+        self.metadata
+    }
+
+    /// Aggregates all buffered values into the bucket
+    ///
+    /// NOTE: Takes the fields separately, because the reader is borrowed while iterating
+    fn flush_values(aggregation: &mut A, bucket: &mut Bucket, values: &mut Vec<Value>) {
+        if !values.is_empty() {
+            bucket.value = aggregation.transform_batch(bucket.value, values);
+            bucket.sum += super::sum::sum_chunked(values);
+            values.clear();
+        }
+    }
+
+    /// Buffers a raw value, aggregating the buffered values once a chunk is full
+    ///
+    /// NOTE: Takes the fields separately, because the reader is borrowed while iterating
+    fn push_value(aggregation: &mut A, bucket: &mut Bucket, values: &mut Vec<Value>, value: Value) {
+        values.push(value);
+
+        if values.len() >= VALUE_CHUNK_SIZE {
+            Self::flush_values(aggregation, bucket, values);
+        }
+    }
+
+    /// Initializes the bucket using its first (newest) data point
+    ///
+    /// NOTE: Takes the fields separately, because the reader is borrowed while iterating
+    fn init_bucket(
+        config: &Builder<'a, A>,
+        aggregation: &mut A,
+        bucket: &mut Bucket,
+        data_point: &StreamItem,
+        len: u64,
+    ) {
+        bucket.len = len;
+        bucket.sum = data_point.stat.map_or(data_point.value, |stat| stat.sum);
+
+        (bucket.start, bucket.end) = match (config.bucket_months, &config.time_zone) {
+            (Some(months), tz) => month_bounds(
+                data_point.ts,
+                months,
+                tz.as_ref().unwrap_or(&TimeZone::Fixed(0)),
+            ),
+            (None, Some(tz)) => aligned_bounds(data_point.ts, config.bucket_width, tz),
+            (None, None) => (data_point.ts, data_point.ts),
+        };
+
+        bucket.value = match (&data_point.stat, &data_point.sketch) {
+            (Some(stat), Some(sketch)) => aggregation.init_sketch(stat, sketch),
+            (Some(stat), None) => aggregation.init_stat(stat),
+            (None, _) => aggregation.init(data_point.value),
+        };
+
+        aggregation.observe_series(data_point.series_id);
+    }
+
+    /// Returns `true` if the data point belongs to the current bucket
+    ///
+    /// Data points are expected newest first, but the bounds are checked on both sides,
+    /// so a data point that is out of order starts a new bucket, instead of underflowing.
+    ///
+    /// NOTE: Takes the fields separately, because the reader is borrowed while iterating
+    fn fits(config: &Builder<'a, A>, bucket: &Bucket, ts: Timestamp) -> bool {
+        if config.is_aligned() {
+            (bucket.start..bucket.end).contains(&ts)
+        } else {
+            let start = bucket.start.min(ts);
+            let end = bucket.end.max(ts);
+            (end - start) <= config.bucket_width
         }
     }
+
+    /// Returns the current bucket, and initializes a new empty bucket
+    fn take_bucket(&mut self) -> Bucket {
+        Self::flush_values(&mut self.aggregation, &mut self.bucket, &mut self.values);
+
+        let mut bucket = std::mem::take(&mut self.bucket);
+        bucket.value = self.finish(&bucket);
+        bucket
+    }
+
+    /// Finishes the bucket, and applies the scale & offset post-processing
+    fn finish(&mut self, bucket: &Bucket) -> Value {
+        let value = self.aggregation.finish(bucket);
+
+        let value = match self.config.sample_rate {
+            Some(rate) => self.aggregation.extrapolate(value, rate.recip()),
+            None => value,
+        };
+
+        // NOTE: Value is f64 when using the `high_precision` feature
+        #[allow(clippy::cast_possible_truncation, clippy::useless_conversion)]
+        let value = f64::from(value).mul_add(self.config.scale, self.config.offset) as Value;
+
+        value
+    }
 }
 
-impl<'a, A, I> Iterator for Aggregator<'a, A, I>
+impl<A, I> Iterator for Aggregator<'_, A, I>
 where
     A: Aggregation,
     I: Iterator<Item = crate::Result<StreamItem>>,
@@ -58,41 +363,318 @@ where
     type Item = crate::Result<Bucket>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.timed_out || self.metadata.truncated {
+            return None;
+        }
+
         for data_point in self.reader.by_ref() {
+            if let Some(deadline) = self.deadline {
+                self.read_count += 1;
+
+                if self.read_count % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() > deadline {
+                    self.timed_out = true;
+                    return Some(Err(crate::Error::Timeout));
+                }
+            }
+
             let data_point = match data_point {
                 Ok(v) => v,
                 Err(e) => return Some(Err(e)),
             };
 
+            if let Some(budget) = &self.budget {
+                if !budget.take() {
+                    // NOTE: Return the partial bucket (if any), and stop scanning
+                    self.metadata.truncated = true;
+                    break;
+                }
+            }
+
+            self.metadata.points_scanned += 1;
+            self.metadata.start = Some(
+                self.metadata
+                    .start
+                    .map_or(data_point.ts, |ts| ts.min(data_point.ts)),
+            );
+            self.metadata.end = Some(
+                self.metadata
+                    .end
+                    .map_or(data_point.ts, |ts| ts.max(data_point.ts)),
+            );
+
+            if let Some(rate) = self.config.sample_rate {
+                if !is_sampled(data_point.series_id, data_point.ts, rate) {
+                    continue;
+                }
+            }
+
+            if let Some(filter) = &self.config.value_filter {
+                let matches = data_point.stat.as_ref().map_or_else(
+                    || filter.matches(data_point.value),
+                    |stat| filter.matches_stat(stat),
+                );
+
+                if !matches {
+                    continue;
+                }
+            }
+
+            // NOTE: Pre-aggregated samples contain multiple raw data points
+            let len = data_point.stat.map_or(1, |stat| stat.count);
+
             if self.bucket.len == 0 {
-                // NOTE: Initialize bucket
-                self.bucket.len = 1;
-                self.bucket.start = data_point.ts;
-                self.bucket.end = data_point.ts;
-                self.bucket.value = A::init(data_point.value);
+                Self::init_bucket(
+                    &self.config,
+                    &mut self.aggregation,
+                    &mut self.bucket,
+                    &data_point,
+                    len,
+                );
                 continue;
             }
 
-            if (self.bucket.end - data_point.ts) <= self.config.bucket_width {
+            if Self::fits(&self.config, &self.bucket, data_point.ts) {
                 // NOTE: Add to bucket
-                self.bucket.len += 1;
-                self.bucket.value = A::transform(self.bucket.value, data_point.value);
-                self.bucket.start = data_point.ts;
+                self.bucket.len += len;
+
+                if let Some(stat) = &data_point.stat {
+                    // NOTE: Keep order of values & samples
+                    Self::flush_values(&mut self.aggregation, &mut self.bucket, &mut self.values);
+                    self.bucket.value = match &data_point.sketch {
+                        Some(sketch) => {
+                            self.aggregation
+                                .transform_sketch(self.bucket.value, stat, sketch)
+                        }
+                        None => self.aggregation.transform_stat(self.bucket.value, stat),
+                    };
+                    self.bucket.sum += stat.sum;
+                } else {
+                    Self::push_value(
+                        &mut self.aggregation,
+                        &mut self.bucket,
+                        &mut self.values,
+                        data_point.value,
+                    );
+                }
+
+                self.aggregation.observe_series(data_point.series_id);
+
+                if !self.config.is_aligned() {
+                    self.bucket.start = self.bucket.start.min(data_point.ts);
+                    self.bucket.end = self.bucket.end.max(data_point.ts);
+                }
             } else {
-                // NOTE: Return bucket, and initialize new empty bucket
-                let mut bucket = std::mem::take(&mut self.bucket);
-                bucket.value = A::finish(&bucket);
+                // NOTE: Return bucket, and initialize new bucket using the current data point
+                let bucket = self.take_bucket();
+                Self::init_bucket(
+                    &self.config,
+                    &mut self.aggregation,
+                    &mut self.bucket,
+                    &data_point,
+                    len,
+                );
                 return Some(Ok(bucket));
             }
         }
 
         if self.bucket.len > 0 {
             // NOTE: Return last bucket
-            let mut bucket = std::mem::take(&mut self.bucket);
-            bucket.value = A::finish(&bucket);
-            Some(Ok(bucket))
+            Some(Ok(self.take_bucket()))
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{Database, MetricName};
+
+    /// Sums the data points using the given bucket width & alignment
+    fn sum_buckets(
+        db: &Database,
+        width: Timestamp,
+        utc_offset: Option<i32>,
+        points: &[(Timestamp, Value)],
+    ) -> crate::Result<Vec<Bucket>> {
+        let mut builder = db
+            .sum(MetricName::try_from("cpu").unwrap(), "host")
+            .granularity(width);
+
+        if let Some(utc_offset) = utc_offset {
+            builder = builder.align_timezone(utc_offset);
+        }
+
+        let items = points.iter().map(|&(ts, value)| {
+            Ok(StreamItem {
+                series_id: 0,
+                ts,
+                value,
+                stat: None,
+                sketch: None,
+            })
+        });
+
+        Aggregator::new(builder, items, None, None, 1).collect()
+    }
+
+    #[test_log::test]
+    fn bucketing_ascending() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let buckets = sum_buckets(&db, 10, None, &[(0, 1.0), (5, 2.0), (5, 3.0), (20, 4.0)])?;
+
+        assert_eq!(
+            vec![(0, 5, 3, 6.0), (20, 20, 1, 4.0)],
+            buckets
+                .iter()
+                .map(|b| (b.start, b.end, b.len, b.value))
+                .collect::<Vec<_>>(),
+        );
+
+        let buckets = sum_buckets(&db, 10, Some(0), &[(0, 1.0), (25, 2.0), (9, 3.0)])?;
+        assert_eq!(
+            vec![(0, 10, 1), (20, 30, 1), (0, 10, 1)],
+            buckets
+                .iter()
+                .map(|b| (b.start, b.end, b.len))
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn sampling_independent_across_series() {
+        let both = (0..10_000)
+            .filter(|&ts| is_sampled(0, ts, 0.5) && is_sampled(1, ts, 0.5))
+            .count();
+
+        // NOTE: ~25% if independent, 50% if the same timestamps were picked for every series
+        assert!((2_200..2_800).contains(&both), "{both}");
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn wide_bucket_bounded_buffer() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let builder = db
+            .sum(MetricName::try_from("cpu").unwrap(), "host")
+            .granularity(100_000);
+
+        let items = std::iter::once(200_000).chain((0..5_000).rev()).map(|ts| {
+            Ok(StreamItem {
+                series_id: 0,
+                ts,
+                value: 1.0,
+                stat: None,
+                sketch: None,
+            })
+        });
+
+        let mut aggregator = Aggregator::new(builder, items, None, None, 1);
+
+        let bucket = aggregator.next().unwrap()?;
+        assert_eq!(1, bucket.len);
+
+        let bucket = aggregator.next().unwrap()?;
+        assert_eq!(5_000, bucket.len);
+        assert_eq!(5_000.0, bucket.value);
+        assert!(aggregator.values.capacity() <= VALUE_CHUNK_SIZE);
+
+        Ok(())
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+        use proptest::test_runner::TestRunner;
+
+        fn points() -> impl Strategy<Value = Vec<(Timestamp, Value)>> {
+            prop::collection::vec((0..1_000_u128, (0..100_u8).prop_map(Value::from)), 0..64)
+        }
+
+        /// Checks the invariants every bucketing has to uphold, whatever the order of data points
+        #[allow(clippy::float_cmp)]
+        fn check_buckets(
+            buckets: &[Bucket],
+            width: Timestamp,
+            aligned: bool,
+            points: &[(Timestamp, Value)],
+        ) -> Result<(), TestCaseError> {
+            prop_assert_eq!(
+                points.len() as u64,
+                buckets.iter().map(|b| b.len).sum::<u64>()
+            );
+            prop_assert_eq!(
+                points.iter().map(|(_, value)| value).sum::<Value>(),
+                buckets.iter().map(|b| b.value).sum::<Value>(),
+            );
+
+            for bucket in buckets {
+                prop_assert!(bucket.start <= bucket.end);
+
+                if aligned {
+                    // NOTE: Aligned buckets are at least 1ns wide
+                    prop_assert!(bucket.start < bucket.end);
+                    prop_assert!(bucket.end - bucket.start <= width.max(1));
+                } else {
+                    prop_assert!(bucket.end - bucket.start <= width);
+                }
+            }
+
+            Ok(())
+        }
+
+        #[test_log::test]
+        fn bucketing_any_order() -> crate::Result<()> {
+            let folder = tempfile::tempdir()?;
+            let db = Database::builder().open(&folder)?;
+
+            let strategy = (points(), 0..200_u128, prop::option::of(-12..=12_i32));
+
+            TestRunner::default()
+                .run(&strategy, |(points, width, utc_offset)| {
+                    let utc_offset = utc_offset.map(|hours| hours * 3_600);
+                    let buckets = sum_buckets(&db, width, utc_offset, &points).unwrap();
+                    check_buckets(&buckets, width, utc_offset.is_some(), &points)
+                })
+                .unwrap();
+
+            Ok(())
+        }
+
+        #[test_log::test]
+        #[allow(clippy::indexing_slicing)]
+        fn bucketing_descending() -> crate::Result<()> {
+            let folder = tempfile::tempdir()?;
+            let db = Database::builder().open(&folder)?;
+
+            let strategy = (points(), 1..200_u128, prop::option::of(-12..=12_i32));
+
+            TestRunner::default()
+                .run(&strategy, |(mut points, width, utc_offset)| {
+                    points.sort_by_key(|point| std::cmp::Reverse(point.0));
+
+                    let utc_offset = utc_offset.map(|hours| hours * 3_600);
+                    let buckets = sum_buckets(&db, width, utc_offset, &points).unwrap();
+                    check_buckets(&buckets, width, utc_offset.is_some(), &points)?;
+
+                    // NOTE: In the expected order, buckets are disjoint & newest first
+                    for pair in buckets.windows(2) {
+                        prop_assert!(pair[1].end <= pair[0].start);
+                    }
+
+                    Ok(())
+                })
+                .unwrap();
+
+            Ok(())
+        }
+    }
+}
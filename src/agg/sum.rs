@@ -1,4 +1,50 @@
-#[derive(Clone)]
+use crate::Value;
+
+/// Amount of independent accumulators, so the compiler can vectorize the loops
+pub const LANES: usize = 8;
+
+/// Sums up values using multiple accumulators
+///
+/// NOTE: The summation order differs from a sequential sum,
+/// so the result may differ in the last bits.
+pub fn sum_chunked(values: &[Value]) -> Value {
+    let mut lanes = [0.0; LANES];
+    let mut chunks = values.chunks_exact(LANES);
+
+    for chunk in &mut chunks {
+        for (lane, x) in lanes.iter_mut().zip(chunk) {
+            *lane += x;
+        }
+    }
+
+    lanes.iter().sum::<Value>() + chunks.remainder().iter().sum::<Value>()
+}
+
+#[derive(Clone, Default)]
 pub struct Sum;
 
-impl super::stream::Aggregation for Sum {}
+impl super::stream::Aggregation for Sum {
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::cast_possible_truncation, clippy::useless_conversion)]
+    fn extrapolate(&mut self, value: Value, factor: f64) -> Value {
+        (f64::from(value) * factor) as Value
+    }
+
+    fn transform_batch(&mut self, accu: Value, values: &[Value]) -> Value {
+        accu + sum_chunked(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn agg_sum_chunked() {
+        let values = (0..=100_u16).map(Value::from).collect::<Vec<_>>();
+        assert_eq!(5_050.0, sum_chunked(&values));
+        assert_eq!(6.0, sum_chunked(&values[..4]));
+        assert_eq!(0.0, sum_chunked(&[]));
+    }
+}
@@ -0,0 +1,157 @@
+use super::{stream::Aggregation, Bucket, GroupedAggregation};
+use crate::{db::StreamItem, Stat, Timestamp, Value};
+
+/// Basic statistics of a bucket, see [`crate::Database::summary`]
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct SummaryBucket {
+    /// The lower time bound (nanosecond timestamp)
+    pub start: Timestamp,
+
+    /// The upper time bound (nanosecond timestamp)
+    pub end: Timestamp,
+
+    /// The lowest value
+    pub min: Value,
+
+    /// The highest value
+    pub max: Value,
+
+    /// The sum of all values
+    pub sum: Value,
+
+    /// The amount of raw data points
+    pub count: u64,
+
+    /// The average value
+    pub avg: Value,
+}
+
+/// Computes minimum, maximum, sum, count and average of each bucket in one pass
+///
+/// The bucket value is the average, the other statistics are returned by
+/// [`GroupedAggregation::collect_summary`].
+#[derive(Clone, Default)]
+pub struct Summary {
+    min: Value,
+    max: Value,
+
+    /// Statistics of the last finished bucket
+    finished: SummaryBucket,
+}
+
+impl Aggregation for Summary {
+    fn init(&mut self, value: Value) -> Value {
+        self.min = value;
+        self.max = value;
+        value
+    }
+
+    fn transform(&mut self, accu: Value, x: Value) -> Value {
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        accu + x
+    }
+
+    fn transform_batch(&mut self, accu: Value, values: &[Value]) -> Value {
+        for &x in values {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+        }
+        accu + super::sum::sum_chunked(values)
+    }
+
+    fn init_stat(&mut self, stat: &Stat) -> Value {
+        self.min = stat.min;
+        self.max = stat.max;
+        stat.sum
+    }
+
+    fn transform_stat(&mut self, accu: Value, stat: &Stat) -> Value {
+        self.min = self.min.min(stat.min);
+        self.max = self.max.max(stat.max);
+        accu + stat.sum
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&mut self, bucket: &Bucket) -> Value {
+        let avg = bucket.value / bucket.len as Value;
+
+        self.finished = SummaryBucket {
+            start: bucket.start,
+            end: bucket.end,
+            min: self.min,
+            max: self.max,
+            sum: bucket.value,
+            count: bucket.len,
+            avg,
+        };
+
+        avg
+    }
+}
+
+impl<I> GroupedAggregation<'_, Summary, I>
+where
+    I: Iterator<Item = crate::Result<StreamItem>>,
+{
+    /// Consumes all groups, returning the statistics of every bucket.
+    ///
+    /// Sums and counts of sampled queries are extrapolated, scale & offset are applied to
+    /// all statistics (the sum is offset once per data point). `downsample_lttb` is ignored.
+    ///
+    /// The query cache is bypassed, because it only stores bucket values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss,
+        clippy::useless_conversion
+    )]
+    pub fn collect_summary(self) -> crate::Result<crate::HashMap<String, Vec<SummaryBucket>>> {
+        let mut map =
+            crate::HashMap::with_capacity_and_hasher(self.0.len(), rustc_hash::FxBuildHasher);
+
+        for (group, mut aggregator) in self.0 {
+            let factor = aggregator.config.sample_rate.map_or(1.0, f64::recip);
+            let (scale, offset) = (aggregator.config.scale, aggregator.config.offset);
+
+            // NOTE: Value is f64 when using the `high_precision` feature
+            let apply = |x: Value| f64::from(x).mul_add(scale, offset) as Value;
+
+            let mut buckets = vec![];
+
+            // NOTE: Buckets of the summarized aggregation, for the `having` predicate
+            let mut raw_buckets = vec![];
+
+            // NOTE: The summary is read from the aggregation after each bucket,
+            // so the aggregator can not be borrowed by a for loop
+            #[allow(clippy::while_let_on_iterator)]
+            while let Some(bucket) = aggregator.next() {
+                raw_buckets.push(bucket?);
+
+                let mut summary = aggregator.aggregation.finished;
+
+                let count = (summary.count as f64 * factor).round();
+                summary.count = count as u64;
+                summary.sum =
+                    (f64::from(summary.sum) * factor).mul_add(scale, offset * count) as Value;
+                summary.min = apply(summary.min);
+                summary.max = apply(summary.max);
+                summary.avg = apply(summary.avg);
+
+                buckets.push(summary);
+            }
+
+            if !aggregator.config.keeps_group(&raw_buckets) {
+                continue;
+            }
+
+            map.insert(group, buckets);
+        }
+
+        Ok(map)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{Stat, Value};
+
+/// Comparison applied to each data point's value while scanning, see
+/// [`Builder::value_filter`](crate::AggregationBuilder::value_filter)
+///
+/// Pre-aggregated samples are only included if all their raw values
+/// match (based on the sample's minimum and maximum).
+///
+/// Values are compared exactly, so `Eq` and `Ne` are best used with integral values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ValueFilter {
+    /// Value is greater than
+    Gt(Value),
+
+    /// Value is greater than or equal to
+    Gte(Value),
+
+    /// Value is less than
+    Lt(Value),
+
+    /// Value is less than or equal to
+    Lte(Value),
+
+    /// Value is equal to
+    Eq(Value),
+
+    /// Value is not equal to
+    Ne(Value),
+}
+
+impl ValueFilter {
+    /// Returns `true` if the value matches.
+    #[must_use]
+    #[allow(clippy::float_cmp)]
+    pub fn matches(&self, value: Value) -> bool {
+        match *self {
+            Self::Gt(x) => value > x,
+            Self::Gte(x) => value >= x,
+            Self::Lt(x) => value < x,
+            Self::Lte(x) => value <= x,
+            Self::Eq(x) => value == x,
+            Self::Ne(x) => value != x,
+        }
+    }
+
+    /// Returns `true` if all raw values of the pre-aggregated sample match.
+    #[must_use]
+    #[allow(clippy::float_cmp)]
+    pub fn matches_stat(&self, stat: &Stat) -> bool {
+        match *self {
+            Self::Gt(_) | Self::Gte(_) => self.matches(stat.min),
+            Self::Lt(_) | Self::Lte(_) => self.matches(stat.max),
+            Self::Eq(x) => stat.min == x && stat.max == x,
+            Self::Ne(x) => x < stat.min || x > stat.max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn value_filter_matches() {
+        assert!(ValueFilter::Gt(1.0).matches(2.0));
+        assert!(!ValueFilter::Gt(1.0).matches(1.0));
+        assert!(ValueFilter::Gte(1.0).matches(1.0));
+        assert!(ValueFilter::Lt(1.0).matches(0.5));
+        assert!(!ValueFilter::Lte(1.0).matches(1.5));
+        assert!(ValueFilter::Eq(1.0).matches(1.0));
+        assert!(ValueFilter::Ne(1.0).matches(2.0));
+    }
+
+    #[test_log::test]
+    fn value_filter_matches_stat() {
+        let stat = Stat {
+            count: 3,
+            sum: 6.0,
+            min: 1.0,
+            max: 3.0,
+        };
+
+        assert!(ValueFilter::Gte(1.0).matches_stat(&stat));
+        assert!(!ValueFilter::Gt(1.0).matches_stat(&stat));
+        assert!(ValueFilter::Lt(4.0).matches_stat(&stat));
+        assert!(!ValueFilter::Lt(3.0).matches_stat(&stat));
+        assert!(!ValueFilter::Eq(2.0).matches_stat(&stat));
+        assert!(!ValueFilter::Ne(2.0).matches_stat(&stat));
+        assert!(ValueFilter::Ne(5.0).matches_stat(&stat));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::MetricName;
+use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+const PARTITION_NAME: &str = "alias";
+
+/// Maps metric names to the (old) metric names they alias
+///
+/// Stored as `<new>#<old>` keys, and kept in memory because
+/// every query needs to resolve its metric names.
+pub struct MetricAliases {
+    partition: TxPartition,
+    map: RwLock<crate::HashMap<String, Vec<String>>>,
+}
+
+impl MetricAliases {
+    pub fn new(keyspace: &TxKeyspace, prefix: &str) -> crate::Result<Self> {
+        let opts = PartitionCreateOptions::default()
+            .block_size(4_096)
+            .compression(CompressionType::Lz4);
+
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        let mut map: crate::HashMap<String, Vec<String>> = crate::HashMap::default();
+
+        for kv in keyspace.read_tx().keys(&partition) {
+            let key = kv?;
+            let key = String::from_utf8_lossy(&key);
+
+            if let Some((new, old)) = key.split_once('#') {
+                map.entry(new.into()).or_default().push(old.into());
+            }
+        }
+
+        Ok(Self {
+            partition,
+            map: RwLock::new(map),
+        })
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, crate::HashMap<String, Vec<String>>> {
+        // NOTE: The map is never left in an inconsistent state, so poisoning can be ignored
+        self.map.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn insert(&self, new: MetricName, old: MetricName) -> crate::Result<()> {
+        let mut map = self.map.write().unwrap_or_else(PoisonError::into_inner);
+
+        let olds = map.entry(new.to_string()).or_default();
+
+        if !olds.iter().any(|x| x == *old) {
+            self.partition.insert(format!("{new}#{old}"), [])?;
+            olds.push(old.to_string());
+        }
+
+        drop(map);
+
+        Ok(())
+    }
+
+    /// Returns `true` if no aliases exist.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Returns all metric names that have aliases.
+    pub fn names(&self) -> Vec<String> {
+        self.read().keys().cloned().collect()
+    }
+
+    /// Returns the metric and all metrics it (transitively) aliases.
+    pub fn resolve(&self, metric: &str) -> Vec<String> {
+        let map = self.read();
+
+        let mut metrics = vec![metric.to_string()];
+        let mut idx = 0;
+
+        while let Some(metric) = metrics.get(idx) {
+            for old in map.get(metric).into_iter().flatten() {
+                if !metrics.contains(old) {
+                    metrics.push(old.clone());
+                }
+            }
+
+            idx += 1;
+        }
+
+        drop(map);
+
+        metrics
+    }
+
+    /// Returns all metrics that (transitively) alias the given metric.
+    pub fn aliased_by(&self, metric: &str) -> Vec<String> {
+        let map = self.read();
+
+        let mut metrics = vec![metric.to_string()];
+        let mut idx = 0;
+
+        while let Some(metric) = metrics.get(idx).cloned() {
+            for (new, olds) in map.iter() {
+                if olds.contains(&metric) && !metrics.contains(new) {
+                    metrics.push(new.clone());
+                }
+            }
+
+            idx += 1;
+        }
+
+        drop(map);
+
+        metrics.remove(0);
+        metrics
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn metric_aliases() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+
+        let a = MetricName::try_from("a").unwrap();
+        let b = MetricName::try_from("b").unwrap();
+        let c = MetricName::try_from("c").unwrap();
+
+        {
+            let aliases = MetricAliases::new(&keyspace, "_talna#v1#")?;
+            aliases.insert(b, a)?;
+            aliases.insert(c, b)?;
+        }
+
+        let aliases = MetricAliases::new(&keyspace, "_talna#v1#")?;
+        assert_eq!(vec!["c", "b", "a"], aliases.resolve("c"));
+        assert_eq!(vec!["a"], aliases.resolve("a"));
+        assert_eq!(vec!["b", "c"], aliases.aliased_by("a"));
+        assert!(aliases.aliased_by("c").is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{SeriesId, Timestamp};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Identifies an archive chunk
+const MAGIC: &[u8; 4] = b"TLNA";
+
+/// Chunk format version
+const VERSION: u8 = 1;
+
+/// Destination of archive chunks, e.g. an S3-compatible object store,
+/// see [`crate::Database::archive`]
+///
+/// # Examples
+///
+/// ```
+/// use std::{collections::HashMap, sync::Mutex};
+/// use talna::ArchiveSink;
+///
+/// #[derive(Default)]
+/// struct InMemory(Mutex<HashMap<String, Vec<u8>>>);
+///
+/// impl ArchiveSink for InMemory {
+///     fn put(&self, name: &str, chunk: &[u8]) -> talna::Result<()> {
+///         self.0.lock().unwrap().insert(name.into(), chunk.into());
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait ArchiveSink {
+    /// Stores a chunk under the given name.
+    ///
+    /// Names are unique per time range, and sort by time.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the chunk could not be stored.
+    fn put(&self, name: &str, chunk: &[u8]) -> crate::Result<()>;
+}
+
+/// Returns the object name of the chunk of the given time range
+pub fn chunk_name(start: Timestamp, end: Timestamp) -> String {
+    // NOTE: Zero padded, so object listings are ordered by time
+    format!("{start:020}-{end:020}.talna")
+}
+
+/// A series in an archive chunk
+pub struct ArchivedSeries {
+    pub metric: String,
+    pub tags: Vec<(String, String)>,
+
+    /// Timestamp & raw value of every data point
+    pub points: Vec<(Timestamp, Vec<u8>)>,
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> std::io::Result<()> {
+    // NOTE: Metric names & tags are short, so the length fits
+    #[allow(clippy::cast_possible_truncation)]
+    writer.write_u16::<BigEndian>(s.len() as u16)?;
+    writer.write_all(s.as_bytes())
+}
+
+fn read_str<R: Read>(reader: &mut R) -> std::io::Result<String> {
+    let len = reader.read_u16::<BigEndian>()?;
+    let mut buf = vec![0; usize::from(len)];
+    reader.read_exact(&mut buf)?;
+
+    String::from_utf8(buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Writes the series of an archive chunk, one series at a time
+pub struct ChunkWriter {
+    buf: Vec<u8>,
+    series_count: u32,
+    point_count: u64,
+}
+
+impl ChunkWriter {
+    pub fn new() -> Self {
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+
+        // NOTE: Series count is written in `finish`
+        buf.extend_from_slice(&0u32.to_be_bytes());
+
+        Self {
+            buf,
+            series_count: 0,
+            point_count: 0,
+        }
+    }
+
+    pub fn point_count(&self) -> u64 {
+        self.point_count
+    }
+
+    /// Appends a series, skipping it if it has no data points,
+    /// returning the amount of written data points
+    ///
+    /// The data points are streamed into the chunk, so they are not buffered twice.
+    pub fn write_series<V: AsRef<[u8]>>(
+        &mut self,
+        metric: &str,
+        tags: &[(&str, &str)],
+        points: impl IntoIterator<Item = crate::Result<(Timestamp, V)>>,
+    ) -> crate::Result<u64> {
+        let start = self.buf.len();
+
+        write_str(&mut self.buf, metric)?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        self.buf.write_u16::<BigEndian>(tags.len() as u16)?;
+
+        for (key, value) in tags {
+            write_str(&mut self.buf, key)?;
+            write_str(&mut self.buf, value)?;
+        }
+
+        // NOTE: The point count is written once all points are written
+        let count_offset = self.buf.len();
+        self.buf.write_u64::<BigEndian>(0)?;
+
+        let mut count = 0u64;
+
+        for point in points {
+            let (ts, value) = match point {
+                Ok(point) => point,
+                Err(e) => {
+                    self.buf.truncate(start);
+                    return Err(e);
+                }
+            };
+            let value = value.as_ref();
+
+            self.buf.write_u128::<BigEndian>(ts)?;
+
+            #[allow(clippy::cast_possible_truncation)]
+            self.buf.write_u16::<BigEndian>(value.len() as u16)?;
+            self.buf.write_all(value)?;
+
+            count += 1;
+        }
+
+        if count == 0 {
+            self.buf.truncate(start);
+            return Ok(0);
+        }
+
+        if let Some(header) = self.buf.get_mut(count_offset..count_offset + 8) {
+            header.copy_from_slice(&count.to_be_bytes());
+        }
+
+        self.series_count += 1;
+        self.point_count += count;
+
+        Ok(count)
+    }
+
+    /// Returns the chunk, or `None` if no series was written
+    pub fn finish(mut self) -> Option<Vec<u8>> {
+        if self.series_count == 0 {
+            return None;
+        }
+
+        let offset = MAGIC.len() + 1;
+
+        if let Some(header) = self.buf.get_mut(offset..offset + 4) {
+            header.copy_from_slice(&self.series_count.to_be_bytes());
+        }
+
+        Some(self.buf)
+    }
+}
+
+/// Parses an archive chunk
+pub fn read_chunk(mut reader: &[u8]) -> std::io::Result<Vec<ArchivedSeries>> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+
+    if &magic != MAGIC {
+        return Err(invalid("not an archive chunk"));
+    }
+
+    if reader.read_u8()? != VERSION {
+        return Err(invalid("unknown archive chunk version"));
+    }
+
+    let series_count = reader.read_u32::<BigEndian>()?;
+    let mut series = Vec::with_capacity(series_count as usize);
+
+    for _ in 0..series_count {
+        let metric = read_str(&mut reader)?;
+
+        let tag_count = reader.read_u16::<BigEndian>()?;
+        let tags = (0..tag_count)
+            .map(|_| Ok((read_str(&mut reader)?, read_str(&mut reader)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let point_count = reader.read_u64::<BigEndian>()?;
+        let mut points = vec![];
+
+        for _ in 0..point_count {
+            let ts = reader.read_u128::<BigEndian>()?;
+
+            let len = reader.read_u16::<BigEndian>()?;
+            let mut value = vec![0; usize::from(len)];
+            reader.read_exact(&mut value)?;
+
+            points.push((ts, value));
+        }
+
+        series.push(ArchivedSeries {
+            metric,
+            tags,
+            points,
+        });
+    }
+
+    Ok(series)
+}
+
+/// Returns the series ID & timestamp of a data point key
+pub fn parse_data_point_key(key: &[u8]) -> std::io::Result<(SeriesId, Timestamp)> {
+    let mut key = key;
+    let series_id = key.read_u64::<BigEndian>()?;
+
+    // NOTE: Invert timestamp back to original value
+    let ts = !key.read_u128::<BigEndian>()?;
+
+    Ok((series_id, ts))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    #[allow(clippy::indexing_slicing)]
+    fn chunk_roundtrip() -> crate::Result<()> {
+        let mut writer = ChunkWriter::new();
+        let count = writer.write_series(
+            "cpu.total",
+            &[("env", "prod"), ("host", "h-1")],
+            [Ok((5, &b"abcd"[..])), Ok((3, &b"ef"[..]))],
+        )?;
+        assert_eq!(2, count);
+        assert_eq!(
+            0,
+            writer.write_series::<&[u8]>("cpu.total", &[("host", "h-2")], [])?
+        );
+        writer.write_series("mem", &[], [Ok((1, &b"1234"[..]))])?;
+        assert_eq!(3, writer.point_count());
+
+        // NOTE: A failing series is not written partially
+        assert!(writer
+            .write_series(
+                "mem",
+                &[("host", "h-3")],
+                [Ok((1, &b"1234"[..])), Err(crate::Error::QuotaExceeded)],
+            )
+            .is_err());
+        assert_eq!(3, writer.point_count());
+
+        let chunk = writer.finish().unwrap();
+        let series = read_chunk(&chunk)?;
+        assert_eq!(2, series.len());
+
+        assert_eq!("cpu.total", series[0].metric);
+        assert_eq!(
+            vec![("env".into(), "prod".into()), ("host".into(), "h-1".into())],
+            series[0].tags
+        );
+        assert_eq!(
+            vec![(5, b"abcd".to_vec()), (3, b"ef".to_vec())],
+            series[0].points
+        );
+
+        assert_eq!("mem", series[1].metric);
+        assert!(series[1].tags.is_empty());
+
+        assert!(read_chunk(b"nope").is_err());
+        assert!(read_chunk(&chunk[..chunk.len() - 1]).is_err());
+        assert!(ChunkWriter::new().finish().is_none());
+
+        assert_eq!(
+            "00000000000000000010-00000000000000000020.talna",
+            chunk_name(10, 20)
+        );
+
+        Ok(())
+    }
+}
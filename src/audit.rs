@@ -0,0 +1,93 @@
+use crate::{SeriesId, Timestamp};
+
+/// Why data points were removed, see [`AuditSink`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum RemovalReason {
+    /// Deleted using [`crate::Database::delete`]
+    ///
+    /// The data points are hidden from queries, but stay on disk until the tombstone is compacted.
+    Delete {
+        /// ID of the tombstone
+        tombstone: u64,
+    },
+
+    /// Data points of a delete were removed from disk, see [`crate::Database::compact_tombstones`]
+    Compaction {
+        /// ID of the compacted tombstone
+        tombstone: u64,
+    },
+
+    /// Idle series were removed, see [`crate::Database::gc_idle_series`]
+    IdleSeries,
+
+    /// Data points were removed after being archived, see [`crate::Database::archive`]
+    Archive,
+}
+
+/// Time range of a series whose data points were removed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RemovedRange {
+    /// ID of the series
+    pub series_id: SeriesId,
+
+    /// Series key (`metric#key:value;...`)
+    pub series_key: String,
+
+    /// Minimum removed timestamp (inclusive)
+    pub start: Timestamp,
+
+    /// Maximum removed timestamp (inclusive)
+    pub end: Timestamp,
+}
+
+/// A data removal, see [`AuditSink::on_removal`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RemovalEvent {
+    /// What removed the data points
+    pub reason: RemovalReason,
+
+    /// Time of the removal
+    pub timestamp: Timestamp,
+
+    /// Affected series & time ranges
+    pub ranges: Vec<RemovedRange>,
+}
+
+/// Receives data removal events, so regulated environments can keep an audit log
+///
+/// Sinks are called synchronously, after the data points were removed.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use std::sync::Arc;
+/// use talna::{AuditSink, Database, MetricName, RemovalEvent, tagset};
+///
+/// struct LogSink;
+///
+/// impl AuditSink for LogSink {
+///     fn on_removal(&self, event: &RemovalEvent) {
+///         for range in &event.ranges {
+///             println!(
+///                 "{:?}: removed [{}, {}] of {}",
+///                 event.reason, range.start, range.end, range.series_key,
+///             );
+///         }
+///     }
+/// }
+///
+/// let db = Database::builder()
+///     .audit_sink(Arc::new(LogSink))
+///     .open(&folder)?;
+///
+/// let metric_name = MetricName::try_from("cpu.total").unwrap();
+/// db.write_at(metric_name, 5, 1.0, tagset!("host" => "h-1"))?;
+/// db.delete(metric_name, "host:h-1", 0, 10)?;
+/// #
+/// # Ok::<_, talna::Error>(())
+/// ```
+pub trait AuditSink: Send + Sync {
+    /// Called after data points were removed (or hidden, in case of deletes).
+    fn on_removal(&self, event: &RemovalEvent);
+}
@@ -0,0 +1,288 @@
+use crate::{
+    agg::{Aggregation, Bucket, GroupedAggregation},
+    db::StreamItem,
+    Timestamp,
+};
+use arrow_array::{
+    builder::{Float64Builder, StringDictionaryBuilder, TimestampNanosecondBuilder, UInt64Builder},
+    types::Int32Type,
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::sync::Arc;
+
+#[cfg(feature = "parquet")]
+use std::path::Path;
+
+pub fn arrow_error(e: ArrowError) -> crate::Error {
+    crate::Error::Io(std::io::Error::other(e))
+}
+
+#[cfg(feature = "parquet")]
+pub fn parquet_error(e: parquet::errors::ParquetError) -> crate::Error {
+    crate::Error::Io(std::io::Error::other(e))
+}
+
+fn dictionary_field(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+        false,
+    )
+}
+
+fn timestamp_field(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        false,
+    )
+}
+
+/// Arrow timestamps are signed 64-bit integers, so timestamps after 2262 are saturated
+fn to_arrow_timestamp(ts: Timestamp) -> i64 {
+    i64::try_from(ts).unwrap_or(i64::MAX)
+}
+
+fn timestamp_builder(capacity: usize) -> TimestampNanosecondBuilder {
+    TimestampNanosecondBuilder::with_capacity(capacity).with_timezone("UTC")
+}
+
+/// Schema of aggregation results: `group`, `start`, `end`, `value`, `count`
+pub fn bucket_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        dictionary_field("group"),
+        timestamp_field("start"),
+        timestamp_field("end"),
+        Field::new("value", DataType::Float64, false),
+        Field::new("count", DataType::UInt64, false),
+    ]))
+}
+
+/// Converts aggregation results into a record batch, ordered by group
+///
+/// Group names are dictionary encoded, so every group name is only stored once.
+// NOTE: Value is f64 when using the `high_precision` feature
+#[allow(clippy::useless_conversion)]
+pub fn buckets_to_record_batch(
+    map: &crate::HashMap<String, Vec<Bucket>>,
+) -> crate::Result<RecordBatch> {
+    let len = map.values().map(Vec::len).sum();
+
+    let mut groups = StringDictionaryBuilder::<Int32Type>::with_capacity(len, map.len(), 0);
+    let mut starts = timestamp_builder(len);
+    let mut ends = timestamp_builder(len);
+    let mut values = Float64Builder::with_capacity(len);
+    let mut counts = UInt64Builder::with_capacity(len);
+
+    let mut names = map.keys().collect::<Vec<_>>();
+    names.sort_unstable();
+
+    for name in names {
+        for bucket in map.get(name).into_iter().flatten() {
+            groups.append(name).map_err(arrow_error)?;
+            starts.append_value(to_arrow_timestamp(bucket.start));
+            ends.append_value(to_arrow_timestamp(bucket.end));
+            values.append_value(bucket.value.into());
+            counts.append_value(bucket.len);
+        }
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(groups.finish()),
+        Arc::new(starts.finish()),
+        Arc::new(ends.finish()),
+        Arc::new(values.finish()),
+        Arc::new(counts.finish()),
+    ];
+
+    RecordBatch::try_new(bucket_schema(), columns).map_err(arrow_error)
+}
+
+/// Schema of raw data points: `metric`, `tags`, `timestamp`, `value`, `count`
+///
+/// The value of pre-aggregated samples is their sum.
+#[cfg(feature = "parquet")]
+pub fn data_point_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        dictionary_field("metric"),
+        dictionary_field("tags"),
+        timestamp_field("timestamp"),
+        Field::new("value", DataType::Float64, false),
+        Field::new("count", DataType::UInt64, false),
+    ]))
+}
+
+/// Builds record batches of raw data points
+#[cfg(feature = "parquet")]
+pub struct DataPointBatchBuilder {
+    metrics: StringDictionaryBuilder<Int32Type>,
+    tags: StringDictionaryBuilder<Int32Type>,
+    timestamps: TimestampNanosecondBuilder,
+    values: Float64Builder,
+    counts: UInt64Builder,
+    len: usize,
+}
+
+#[cfg(feature = "parquet")]
+impl DataPointBatchBuilder {
+    /// Amount of rows per record batch
+    pub const BATCH_SIZE: usize = 65_536;
+
+    pub fn new() -> Self {
+        Self {
+            metrics: StringDictionaryBuilder::new(),
+            tags: StringDictionaryBuilder::new(),
+            timestamps: timestamp_builder(Self::BATCH_SIZE),
+            values: Float64Builder::with_capacity(Self::BATCH_SIZE),
+            counts: UInt64Builder::with_capacity(Self::BATCH_SIZE),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::useless_conversion)]
+    pub fn append(&mut self, metric: &str, tags: &str, item: &StreamItem) -> crate::Result<()> {
+        self.metrics.append(metric).map_err(arrow_error)?;
+        self.tags.append(tags).map_err(arrow_error)?;
+        self.timestamps.append_value(to_arrow_timestamp(item.ts));
+        self.values.append_value(item.value.into());
+        self.counts
+            .append_value(item.stat.map_or(1, |stat| stat.count));
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the rows appended so far as a record batch, and resets the builder
+    pub fn finish(&mut self) -> crate::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.metrics.finish()),
+            Arc::new(self.tags.finish()),
+            Arc::new(self.timestamps.finish()),
+            Arc::new(self.values.finish()),
+            Arc::new(self.counts.finish()),
+        ];
+        self.len = 0;
+
+        RecordBatch::try_new(data_point_schema(), columns).map_err(arrow_error)
+    }
+}
+
+/// Writes record batches into a Parquet file
+#[cfg(feature = "parquet")]
+pub struct ParquetFile(parquet::arrow::ArrowWriter<std::fs::File>);
+
+#[cfg(feature = "parquet")]
+impl ParquetFile {
+    pub fn create(path: &Path, schema: SchemaRef) -> crate::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        let writer =
+            parquet::arrow::ArrowWriter::try_new(file, schema, None).map_err(parquet_error)?;
+        Ok(Self(writer))
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> crate::Result<()> {
+        self.0.write(batch).map_err(parquet_error)
+    }
+
+    pub fn close(self) -> crate::Result<()> {
+        self.0.close().map_err(parquet_error)?;
+        Ok(())
+    }
+}
+
+impl<A, I> GroupedAggregation<'_, A, I>
+where
+    A: Aggregation,
+    I: Iterator<Item = crate::Result<StreamItem>>,
+{
+    /// Consumes all groups like [`GroupedAggregation::collect`], returning
+    /// the buckets as an Arrow record batch.
+    ///
+    /// The batch has the columns `group` (dictionary encoded string), `start` & `end`
+    /// (nanosecond timestamps), `value` (f64) and `count` (amount of data points).
+    /// Rows are ordered by group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    pub fn collect_to_arrow(self) -> crate::Result<RecordBatch> {
+        buckets_to_record_batch(&self.collect()?)
+    }
+
+    /// Consumes all groups like [`GroupedAggregation::collect`], and writes the
+    /// buckets into a Parquet file, returning the amount of written rows.
+    ///
+    /// The file has the same columns as [`GroupedAggregation::collect_to_arrow`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    #[cfg(feature = "parquet")]
+    pub fn collect_to_parquet<P: AsRef<Path>>(self, path: P) -> crate::Result<u64> {
+        let batch = self.collect_to_arrow()?;
+
+        let mut file = ParquetFile::create(path.as_ref(), bucket_schema())?;
+        file.write(&batch)?;
+        file.close()?;
+
+        Ok(batch.num_rows() as u64)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use arrow_array::{cast::AsArray, types::Float64Type, Array};
+
+    #[test_log::test]
+    fn buckets_record_batch() -> crate::Result<()> {
+        let mut map = crate::HashMap::default();
+        map.insert(
+            "b".to_string(),
+            vec![Bucket {
+                start: 10,
+                end: 19,
+                value: 2.0,
+                len: 3,
+                sum: 6.0,
+            }],
+        );
+        map.insert(
+            "a".to_string(),
+            vec![
+                Bucket {
+                    start: 10,
+                    end: 19,
+                    value: 1.0,
+                    len: 1,
+                    sum: 1.0,
+                },
+                Bucket {
+                    start: 0,
+                    end: 9,
+                    value: 4.0,
+                    len: 2,
+                    sum: 8.0,
+                },
+            ],
+        );
+
+        let batch = buckets_to_record_batch(&map)?;
+        assert_eq!(3, batch.num_rows());
+        assert_eq!(bucket_schema(), batch.schema());
+
+        let groups = batch.column(0).as_dictionary::<Int32Type>();
+        assert_eq!(2, groups.values().len());
+
+        let values = batch.column(3).as_primitive::<Float64Type>();
+        assert_eq!(vec![1.0, 4.0, 2.0], values.values().to_vec());
+
+        Ok(())
+    }
+}
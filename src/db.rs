@@ -1,35 +1,210 @@
-use crate::query::filter::parse_filter_query;
+use crate::aliases::MetricAliases;
+use crate::archive::{ArchiveSink, ChunkWriter};
+use crate::audit::{AuditSink, RemovalEvent, RemovalReason, RemovedRange};
+use crate::encoding::{decode_half, HALF_TAG, STAT_TAG, VALUE_LEN};
+use crate::export::{line_protocol_prefix, ExportCursor};
+use crate::line_protocol::Line;
+use crate::memory::MemoryUsage;
+use crate::metadata::{MetricMetadata, MetricMetadataStore};
+use crate::observer::ObserverState;
+use crate::orphans::OrphanedSeries;
+use crate::point_counts::PointCounts;
+use crate::pre_agg::{Buffered, PreAggregation, RollupWindow, Window};
+use crate::progress::{no_progress, Progress};
+use crate::query::filter::{parse_filter_query, Filter, Node};
+use crate::query::grammar::{parse_strict, GrammarVersion};
+use crate::query::planner::{QueryPlanner, SelectivityPlanner};
+use crate::query_cache::QueryCache;
+use crate::quota::Quota;
+use crate::schema::Schemas;
+use crate::series_bounds::SeriesBounds;
 use crate::series_key::SeriesKey;
+use crate::series_lock::SeriesLocks;
+use crate::series_stream::SeriesStream;
+use crate::series_writer::SeriesWriter;
+use crate::sketch::QuantileSketch;
 use crate::smap::SeriesMapping;
+use crate::stat::Stat;
+use crate::storage::{KvResult, StorageIter, StoragePartition, StorageSnapshot};
 use crate::tag_index::TagIndex;
 use crate::tag_sets::OwnedTagSets;
 use crate::tag_sets::TagSets;
+use crate::tier::{ColdTier, DataSnapshot, MergeTiers};
 use crate::time::timestamp;
+use crate::tombstones::{Tombstone, Tombstones};
+use crate::Aggregation;
 use crate::DatabaseBuilder;
+use crate::Metric;
+use crate::MetricGlob;
 use crate::MetricName;
+use crate::MetricSelector;
 use crate::SeriesId;
 use crate::TagSet;
 use crate::Timestamp;
 use crate::Value;
+use crate::ValueEncoding;
 use byteorder::{BigEndian, ReadBytesExt};
-use fjall::{Partition, PartitionCreateOptions, TxKeyspace};
+use fjall::{Partition, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::borrow::Cow;
 use std::io::Cursor;
 use std::marker::PhantomData;
-use std::ops::Bound;
-use std::sync::Arc;
+use std::ops::{Bound, ControlFlow};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLockReadGuard};
 
 pub const MINUTE_IN_NS: u128 = 60_000_000_000;
 
+/// A data point of a series
 #[derive(Debug)]
 pub struct StreamItem {
+    /// ID of the series the data point belongs to
     pub series_id: SeriesId,
+
+    /// Nanosecond timestamp
     pub ts: Timestamp,
+
+    /// The raw value, or the sum if the data point is pre-aggregated
     pub value: Value,
+
+    /// Set if the data point is a pre-aggregated sample
+    pub stat: Option<Stat>,
+
+    /// Set if the pre-aggregated sample was written with a quantile sketch
+    pub sketch: Option<Box<QuantileSketch>>,
+}
+
+impl StreamItem {
+    /// Returns the data point as a pre-aggregated sample
+    fn as_stat(&self) -> Stat {
+        self.stat.unwrap_or(Stat {
+            count: 1,
+            sum: self.value,
+            min: self.value,
+            max: self.value,
+        })
+    }
+
+    /// Merges two data points at the same timestamp into a pre-aggregated sample,
+    /// returning the serialized sample
+    ///
+    /// If either data point has a quantile sketch, the other one is added to it.
+    fn merge(&self, other: &Self) -> Vec<u8> {
+        let (a, b) = (self.as_stat(), other.as_stat());
+
+        let stat = Stat {
+            count: a.count + b.count,
+            sum: a.sum + b.sum,
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        };
+
+        let sketch = match (&self.sketch, &other.sketch) {
+            (None, None) => None,
+            (Some(sketch), None) => {
+                let mut sketch = (**sketch).clone();
+                sketch.insert_stat(&b);
+                Some(sketch)
+            }
+            (None, Some(sketch)) => {
+                let mut sketch = (**sketch).clone();
+                sketch.insert_stat(&a);
+                Some(sketch)
+            }
+            (Some(x), Some(y)) => {
+                let mut sketch = (**x).clone();
+                sketch.merge(y);
+                Some(sketch)
+            }
+        };
+
+        let mut bytes = stat.serialize();
+
+        if let Some(sketch) = sketch {
+            sketch.serialize_into(&mut bytes);
+        }
+
+        bytes
+    }
+}
+
+/// Stream of a series' data points, ordered from newest to oldest
+pub type SeriesReader = Box<dyn Iterator<Item = crate::Result<StreamItem>> + Send>;
+
+/// Amount of key-value pairs a [`SeriesScan`] reads at once
+const SCAN_CHUNK_SIZE: usize = 256;
+
+/// Reads the data points of a series of both tiers in chunks, ordered from newest to oldest
+///
+/// Storage iterators are not `Send`, so instead of keeping one open, every chunk is read
+/// by a short-lived iterator that continues after the last data point of the previous chunk.
+/// The scan owns its snapshot, so it can be moved to another thread.
+struct SeriesScan {
+    snapshot: Arc<DataSnapshot>,
+    series_id: SeriesId,
+
+    /// Remaining time range, shrinks after every chunk
+    bounds: (Bound<Timestamp>, Bound<Timestamp>),
+
+    chunk: std::vec::IntoIter<KvResult>,
+    done: bool,
+}
+
+impl SeriesScan {
+    fn new(
+        snapshot: Arc<DataSnapshot>,
+        series_id: SeriesId,
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> Self {
+        Self {
+            snapshot,
+            series_id,
+            bounds,
+            chunk: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    fn read_chunk(&mut self) {
+        let chunk = Database::tiered_series_range(&self.snapshot, self.series_id, self.bounds)
+            .take(SCAN_CHUNK_SIZE)
+            .collect::<Vec<_>>();
+
+        self.done = chunk.len() < SCAN_CHUNK_SIZE;
+
+        if let Some(last) = chunk.last() {
+            // NOTE: Data points are ordered from newest to oldest, so the next chunk
+            // continues with the data points older than the last one
+            let ts = last.as_ref().ok().and_then(|(k, _)| {
+                let inverted_ts = k.get(std::mem::size_of::<SeriesId>()..)?;
+                Some(!Timestamp::from_be_bytes(inverted_ts.try_into().ok()?))
+            });
+
+            match ts {
+                Some(ts) => self.bounds.1 = Bound::Excluded(ts),
+                None => self.done = true,
+            }
+        }
+
+        self.chunk = chunk.into_iter();
+    }
 }
 
-pub struct SeriesStream {
-    pub(crate) tags: OwnedTagSets,
-    pub(crate) reader: Box<dyn Iterator<Item = crate::Result<StreamItem>>>,
+impl Iterator for SeriesScan {
+    type Item = KvResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.chunk.next() {
+                return Some(kv);
+            }
+
+            if self.done {
+                return None;
+            }
+
+            self.read_chunk();
+        }
+    }
 }
 
 pub struct DatabaseInner {
@@ -38,6 +213,10 @@ pub struct DatabaseInner {
     /// Actual time series data
     data: Partition,
 
+    /// Transactional handle of the data partition, used to write the
+    /// first data point of a new series atomically with its metadata
+    tx_data: TxPartition,
+
     /// Series mapping, series key -> series ID
     smap: SeriesMapping,
 
@@ -49,12 +228,121 @@ pub struct DatabaseInner {
 
     #[allow(unused)]
     hyper_mode: bool,
+
+    /// On-disk encoding per metric, metrics not contained use full precision
+    value_encodings: crate::HashMap<String, ValueEncoding>,
+
+    /// Cache of collected query results, if enabled
+    query_cache: Option<QueryCache>,
+
+    /// Tags added to every written data point
+    default_tags: Vec<(String, String)>,
+
+    /// Metric names that include the data of other (renamed) metrics
+    aliases: MetricAliases,
+
+    /// Units & descriptions of metrics
+    metadata: MetricMetadataStore,
+
+    /// Hooks into the write path, if installed
+    write_observer: Option<ObserverState>,
+
+    /// Receives data removal events, if installed
+    audit_sink: Option<Arc<dyn AuditSink>>,
+
+    /// Maximum on-disk size, if configured
+    quota: Option<Quota>,
+
+    /// Approximate amount of data points per metric
+    point_counts: PointCounts,
+
+    /// Oldest & newest timestamp per series
+    series_bounds: SeriesBounds,
+
+    /// Inclusive range of timestamps that can be written, if configured
+    timestamp_bounds: Option<(Timestamp, Timestamp)>,
+
+    /// Maximum amount of nanoseconds a timestamp can be ahead of the current time, if configured
+    max_clock_skew: Option<Timestamp>,
+
+    /// Storage location of old data points, if configured
+    cold_tier: Option<ColdTier>,
+
+    /// Buffered windows of pre-aggregated metrics
+    pre_aggregation: PreAggregation,
+
+    /// Default maximum amount of data points scanned per query, if configured
+    max_scanned_points: Option<u64>,
+
+    /// Decides how filters are evaluated
+    planner: Arc<dyn QueryPlanner>,
+
+    /// Pinned filter grammar, if configured
+    filter_grammar: Option<GrammarVersion>,
+
+    /// Block cache of the keyspace, if known
+    block_cache: Option<Arc<fjall::BlockCache>>,
+
+    /// Declared tag keys per metric
+    schemas: Schemas,
+
+    /// Deleted time ranges that were not compacted yet
+    tombstones: Tombstones,
+
+    /// Removed series whose data points are still on disk
+    orphans: OrphanedSeries,
+
+    /// Counts the removed series, so [`SeriesWriter`]s only check if
+    /// their series still exists after a series was removed
+    series_removals: AtomicU64,
+
+    /// Serializes writes to a series with its removal
+    series_locks: SeriesLocks,
+}
+
+impl Drop for DatabaseInner {
+    fn drop(&mut self) {
+        if let Err(e) = self.point_counts.persist() {
+            log::error!("Failed to persist point counts on drop: {e:?}");
+        }
+
+        if let Err(e) = self.series_bounds.persist() {
+            log::error!("Failed to persist series bounds on drop: {e:?}");
+        }
+
+        // NOTE: Writes are only buffered in the journal, so make sure
+        // they reach the OS even if the database was not flushed explicitly
+        if let Err(e) = self.keyspace.persist(fjall::PersistMode::Buffer) {
+            log::error!("Failed to persist writes on drop: {e:?}");
+        }
+    }
 }
 
 /// An embeddable time series database
+///
+/// The database is `Send + Sync` and cheap to clone, so it can be shared between threads.
+///
+/// Aggregation builders and the built [`GroupedAggregation`](crate::GroupedAggregation)s
+/// are `Send` as well, so a query can be built or collected in a worker thread.
 #[derive(Clone)]
 pub struct Database(Arc<DatabaseInner>);
 
+impl Drop for Database {
+    fn drop(&mut self) {
+        // NOTE: The last handle writes the windows of pre-aggregated metrics that are still
+        // buffered, because they can not be written by the inner state, which has no handle anymore
+        if Arc::strong_count(&self.0) > 1 {
+            return;
+        }
+
+        for window in self.0.pre_aggregation.drain() {
+            if let Err(e) = self.write_window(&window) {
+                log::error!("Failed to write pre-aggregated window on drop: {e:?}");
+            }
+        }
+    }
+}
+
 impl Database {
     /// Creates a new database builder.
     #[must_use]
@@ -62,39 +350,171 @@ impl Database {
         DatabaseBuilder::new()
     }
 
-    pub(crate) fn from_keyspace(keyspace: TxKeyspace, hyper_mode: bool) -> crate::Result<Self> {
+    pub(crate) fn from_keyspace(
+        keyspace: TxKeyspace,
+        config: DatabaseBuilder,
+    ) -> crate::Result<Self> {
         log::info!("Opening database using existing keyspace");
 
         log::info!("Opening meta partitions");
 
-        let tag_index = TagIndex::new(&keyspace)?;
-        let tag_sets = TagSets::new(&keyspace)?;
-        let series_mapping = SeriesMapping::new(&keyspace)?;
+        let prefix = config.partition_name_prefix();
+
+        let tag_index = TagIndex::new(
+            &keyspace,
+            &prefix,
+            config.postings_cache_size_mib * 1_024 * 1_024,
+        )?;
+        let aliases = MetricAliases::new(&keyspace, &prefix)?;
+        let metadata = MetricMetadataStore::new(&keyspace, &prefix)?;
+        let tombstones = Tombstones::new(&keyspace, &prefix)?;
+        let orphans = OrphanedSeries::new(&keyspace, &prefix)?;
+        let tag_sets = TagSets::new(
+            &keyspace,
+            &prefix,
+            config.tag_set_cache_size_mib * 1_024 * 1_024,
+        )?;
+        let series_mapping = SeriesMapping::new(
+            &keyspace,
+            &prefix,
+            config.series_cache_size_mib * 1_024 * 1_024,
+        )?;
 
         log::info!("Opening data partition");
 
-        let data = keyspace
-            .open_partition(
-                "_talna#v1#data",
-                PartitionCreateOptions::default()
-                    .use_bloom_filters(false)
-                    .manual_journal_persist(true)
-                    .block_size(64_000)
-                    .compression(fjall::CompressionType::Lz4),
-            )?
-            .inner()
-            .clone();
+        let tx_data = keyspace.open_partition(
+            &format!("{prefix}data"),
+            PartitionCreateOptions::default()
+                .use_bloom_filters(false)
+                .manual_journal_persist(true)
+                .block_size(config.data_block_size)
+                .compression(fjall::CompressionType::Lz4),
+        )?;
+        let data = tx_data.inner().clone();
+
+        let point_counts = PointCounts::new(&keyspace, &prefix, &series_mapping.partition, &data)?;
+
+        let cold_tier = config
+            .cold_tier
+            .as_ref()
+            .map(|(location, age)| ColdTier::open(location, &prefix, age.as_nanos()))
+            .transpose()?;
+
+        let series_bounds = SeriesBounds::new(
+            &keyspace,
+            &prefix,
+            &series_mapping.partition,
+            &data,
+            cold_tier.as_ref().map(|tier| &*tier.data),
+        )?;
 
         Ok(Self(Arc::new(DatabaseInner {
             keyspace,
             data,
+            tx_data,
             smap: series_mapping,
             tag_index,
             tag_sets,
-            hyper_mode,
+            hyper_mode: config.hyper_mode,
+            value_encodings: config.value_encodings,
+            query_cache: config
+                .query_cache
+                .map(|(capacity, ttl)| QueryCache::new(capacity, ttl)),
+            default_tags: config.default_tags,
+            aliases,
+            metadata,
+            write_observer: config
+                .write_observer
+                .map(|(observer, every)| ObserverState::new(observer, every)),
+            audit_sink: config.audit_sink,
+            quota: config.max_disk_space.map(Quota::new),
+            point_counts,
+            series_bounds,
+            timestamp_bounds: config.timestamp_bounds,
+            max_clock_skew: config.max_clock_skew.map(|skew| skew.as_nanos()),
+            cold_tier,
+            pre_aggregation: PreAggregation::new(config.pre_aggregations),
+            max_scanned_points: config.max_scanned_points,
+            planner: config
+                .query_planner
+                .unwrap_or_else(|| Arc::new(SelectivityPlanner)),
+            filter_grammar: config.filter_grammar,
+            block_cache: config.block_cache,
+            schemas: Schemas::new(config.schema_policy),
+            tombstones,
+            orphans,
+            series_removals: AtomicU64::new(0),
+            series_locks: SeriesLocks::new(),
         })))
     }
 
+    pub(crate) fn query_cache(&self) -> Option<&QueryCache> {
+        self.0.query_cache.as_ref()
+    }
+
+    /// Reports removed data points to the audit sink (if installed)
+    ///
+    /// The affected ranges are only computed if a sink is installed.
+    fn audit(
+        &self,
+        reason: RemovalReason,
+        ranges: impl FnOnce() -> crate::Result<Vec<RemovedRange>>,
+    ) -> crate::Result<()> {
+        let Some(sink) = &self.0.audit_sink else {
+            return Ok(());
+        };
+
+        let ranges = ranges()?;
+
+        if !ranges.is_empty() {
+            sink.on_removal(&RemovalEvent {
+                reason,
+                timestamp: timestamp(),
+                ranges,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the removed time range (inclusive) of an existing series, for auditing
+    fn removed_range(
+        &self,
+        metric: &str,
+        series_id: SeriesId,
+        (start, end): (Timestamp, Timestamp),
+    ) -> crate::Result<RemovedRange> {
+        let tags = self.tag_set(series_id)?;
+        let tags = tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+
+        let mut series_key = SeriesKey::allocate_string_for_tags(&tags, metric.len() + 1);
+        series_key.push_str(metric);
+        series_key.push('#');
+        SeriesKey::join_tags(&mut series_key, &tags);
+
+        Ok(RemovedRange {
+            series_id,
+            series_key,
+            start,
+            end,
+        })
+    }
+
+    pub(crate) fn invalidate_query_cache(&self, metric: MetricName) {
+        if let Some(cache) = &self.0.query_cache {
+            cache.invalidate(&metric);
+
+            if !self.0.aliases.is_empty() {
+                for alias in self.0.aliases.aliased_by(&metric) {
+                    cache.invalidate(&alias);
+                }
+            }
+        }
+    }
+
     fn format_data_point_key(series_id: SeriesId, ts: Timestamp) -> [u8; 24] {
         let mut data_point_key =
             [0; std::mem::size_of::<SeriesId>() + std::mem::size_of::<Timestamp>()];
@@ -104,325 +524,6232 @@ impl Database {
         data_point_key
     }
 
-    fn prepare_query(
-        &self,
-        series_ids: &[SeriesId],
+    /// Opens a point-in-time view of the data partition (of both tiers).
+    ///
+    /// All readers of a query read from the same snapshot, so a query does not
+    /// observe data points written after it has started.
+    pub(crate) fn snapshot(&self) -> Arc<DataSnapshot> {
+        // NOTE: The hot tier is read first, so data points that are moved in between
+        // are visible in both tiers (and deduplicated), instead of neither
+        let hot = self.0.data.snapshot();
+        let cold = self.0.cold_tier.as_ref().map(|tier| tier.data.snapshot());
+
+        Arc::new(DataSnapshot { hot, cold })
+    }
+
+    /// Returns the data points of a series in the given time range, ordered from newest to oldest
+    fn series_range(
+        snapshot: &dyn StorageSnapshot,
+        series_id: SeriesId,
         (min, max): (Bound<Timestamp>, Bound<Timestamp>),
-    ) -> crate::Result<Vec<SeriesStream>> {
-        use fjall::Slice;
+    ) -> StorageIter {
         use Bound::{Excluded, Included, Unbounded};
 
+        let key = |ts| Self::format_data_point_key(series_id, ts).to_vec();
+
+        match (min, max) {
+            (Unbounded, Unbounded) => snapshot.prefix(&series_id.to_be_bytes()),
+            (min @ (Included(_) | Excluded(_)), Unbounded) => {
+                snapshot.range((Included(key(Timestamp::MAX)), min.map(key)))
+            }
+            (Unbounded, max @ (Included(_) | Excluded(_))) => {
+                snapshot.range((max.map(key), Included(key(0))))
+            }
+            (min @ (Included(_) | Excluded(_)), max @ (Included(_) | Excluded(_))) => {
+                snapshot.range((max.map(key), min.map(key)))
+            }
+        }
+    }
+
+    /// Returns the data points of a series in the given time range of both tiers,
+    /// ordered from newest to oldest
+    fn tiered_series_range(
+        snapshot: &DataSnapshot,
+        series_id: SeriesId,
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> StorageIter {
+        let hot = Self::series_range(&snapshot.hot, series_id, bounds);
+
+        match &snapshot.cold {
+            Some(cold) => Box::new(MergeTiers::new(
+                hot,
+                Self::series_range(&**cold, series_id, bounds),
+            )),
+            None => hot,
+        }
+    }
+
+    /// Returns the series that may have data points in the given time range,
+    /// so the other series do not need to be read at all.
+    pub(crate) fn series_in_bounds(
+        &self,
+        series_ids: &[SeriesId],
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> Vec<SeriesId> {
+        series_ids
+            .iter()
+            .copied()
+            .filter(|&series_id| self.0.series_bounds.overlaps(series_id, bounds))
+            .collect()
+    }
+
+    pub(crate) fn prepare_query(
+        snapshot: &Arc<DataSnapshot>,
+        series_ids: &[SeriesId],
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> crate::Result<Vec<SeriesReader>> {
         series_ids
             .iter()
             .map(|&series_id| {
-                // TODO: maybe cache tagsets in QuickCache...
-                let tags = self.0.tag_sets.get(series_id)?;
+                let kv_stream = SeriesScan::new(snapshot.clone(), series_id, bounds);
 
-                let kv_stream: Box<dyn Iterator<Item = fjall::Result<(Slice, Slice)>>> =
-                    match (min, max) {
-                        (Unbounded, Unbounded) => {
-                            Box::new(self.0.data.prefix(series_id.to_be_bytes()))
-                        }
-                        (min @ (Included(_) | Excluded(_)), Unbounded) => {
-                            let max =
-                                Included(Self::format_data_point_key(series_id, Timestamp::MAX));
-                            let min = min.map(|ts| Self::format_data_point_key(series_id, ts));
+                let reader: SeriesReader = Box::new(kv_stream.map(move |x| match x {
+                    Ok((k, v)) => {
+                        let mut k = Cursor::new(k);
 
-                            Box::new(self.0.data.range((max, min)))
-                        }
-                        (Unbounded, max @ (Included(_) | Excluded(_))) => {
-                            let min = Self::format_data_point_key(series_id, 0);
-                            let max = max.map(|ts| Self::format_data_point_key(series_id, ts));
-                            Box::new(self.0.data.range((max, Included(min))))
-                        }
-                        (min @ (Included(_) | Excluded(_)), max @ (Included(_) | Excluded(_))) => {
-                            let min = min.map(|ts| Self::format_data_point_key(series_id, ts));
-                            let max = max.map(|ts| Self::format_data_point_key(series_id, ts));
-                            Box::new(self.0.data.range((max, min)))
-                        }
-                    };
+                        // Skip series ID
+                        k.set_position(std::mem::size_of::<SeriesId>() as u64);
 
-                Ok(SeriesStream {
-                    tags,
-                    reader: Box::new(kv_stream.map(move |x| match x {
-                        Ok((k, v)) => {
-                            use std::io::Seek;
+                        let ts = k.read_u128::<BigEndian>()?;
+                        // NOTE: Invert timestamp back to original value
+                        let ts = !ts;
 
-                            let mut k = Cursor::new(k);
+                        // NOTE: Raw values are stored untagged,
+                        // every other format starts with its format tag
+                        if v.len() != VALUE_LEN {
+                            return match v.first() {
+                                Some(&HALF_TAG) => {
+                                    let mut reader = v.get(1..).unwrap_or_default();
+                                    let half = reader.read_u16::<BigEndian>()?;
 
-                            // Skip series ID
-                            k.seek_relative(std::mem::size_of::<SeriesId>() as i64)?;
+                                    Ok(StreamItem {
+                                        series_id,
+                                        ts,
+                                        value: decode_half(half.to_be_bytes()),
+                                        stat: None,
+                                        sketch: None,
+                                    })
+                                }
+                                Some(&STAT_TAG) => {
+                                    let mut reader = &v[..];
+                                    let stat = Stat::deserialize(&mut reader)?;
 
-                            let ts = k.read_u128::<BigEndian>()?;
-                            // NOTE: Invert timestamp back to original value
-                            let ts = !ts;
+                                    // NOTE: Pre-aggregated samples may be followed by a quantile sketch
+                                    let sketch = if reader.is_empty() {
+                                        None
+                                    } else {
+                                        Some(Box::new(QuantileSketch::deserialize(
+                                            &mut reader,
+                                            &stat,
+                                        )?))
+                                    };
 
-                            let mut v = Cursor::new(v);
+                                    Ok(StreamItem {
+                                        series_id,
+                                        ts,
+                                        value: stat.sum,
+                                        stat: Some(stat),
+                                        sketch,
+                                    })
+                                }
+                                tag => Err(std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    format!("unknown value format {tag:?}"),
+                                )
+                                .into()),
+                            };
+                        }
 
-                            #[cfg(feature = "high_precision")]
-                            let value = v.read_f64::<BigEndian>()?;
+                        let mut v = Cursor::new(v);
 
-                            #[cfg(not(feature = "high_precision"))]
-                            let value = v.read_f32::<BigEndian>()?;
+                        #[cfg(feature = "high_precision")]
+                        let value = v.read_f64::<BigEndian>()?;
 
-                            Ok(StreamItem {
-                                series_id,
-                                ts,
-                                value,
-                            })
-                        }
-                        Err(e) => Err(e.into()),
-                    })),
-                })
+                        #[cfg(not(feature = "high_precision"))]
+                        let value = v.read_f32::<BigEndian>()?;
+
+                        Ok(StreamItem {
+                            series_id,
+                            ts,
+                            value,
+                            stat: None,
+                            sketch: None,
+                        })
+                    }
+                    Err(e) => Err(e),
+                }));
+
+                Ok(reader)
             })
             .collect::<crate::Result<Vec<_>>>()
     }
 
-    pub(crate) fn start_query(
+    /// Like [`Database::prepare_query`], but skips data points that were deleted
+    /// (unless `include_deleted` is set), see [`Database::delete`]
+    pub(crate) fn prepare_visible_query(
         &self,
-        metric: &str,
-        filter_expr: &str,
-        (min, max): (Bound<Timestamp>, Bound<Timestamp>),
-    ) -> crate::Result<Vec<SeriesStream>> {
-        let Ok(filter) = parse_filter_query(filter_expr) else {
-            return Err(crate::Error::InvalidQuery);
-        };
+        snapshot: &Arc<DataSnapshot>,
+        series_ids: &[SeriesId],
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+        include_deleted: bool,
+    ) -> crate::Result<Vec<SeriesReader>> {
+        let readers = Self::prepare_query(snapshot, series_ids, bounds)?;
 
-        let series_ids = filter.evaluate(&self.0.smap, &self.0.tag_index, metric)?;
-        if series_ids.is_empty() {
-            log::debug!("Query {filter_expr:?} did not match any series");
-            return Ok(vec![]);
+        if include_deleted {
+            return Ok(readers);
         }
 
-        log::trace!(
-            "Querying metric {metric}{{{filter}}} [{min:?}..{max:?}] in series {series_ids:?}"
-        );
+        Ok(readers
+            .into_iter()
+            .zip(series_ids)
+            .map(|(reader, &series_id)| {
+                let deleted = self.0.tombstones.ranges(series_id);
 
-        let streams = self.prepare_query(&series_ids, (min, max))?;
+                if deleted.is_empty() {
+                    return reader;
+                }
 
-        Ok(streams)
-    }
+                let reader: SeriesReader = Box::new(reader.filter(move |item| {
+                    item.as_ref().map_or(true, |item| {
+                        !deleted
+                            .iter()
+                            .any(|&(start, end)| (start..=end).contains(&item.ts))
+                    })
+                }));
 
-    /// Returns an aggregation builder.
-    ///
-    /// The aggregation returns the average value for each bucket.
-    #[must_use]
-    pub fn avg<'a>(
-        &'a self,
-        metric: MetricName<'a>,
-        group_by: &'a str,
-    ) -> crate::agg::Builder<crate::agg::Average> {
-        crate::agg::Builder {
-            phantom: PhantomData,
-            database: self,
-            metric_name: &metric,
-            filter_expr: "*",
-            bucket_width: MINUTE_IN_NS,
-            group_by,
-            max_ts: None,
-            min_ts: None,
-        }
+                reader
+            })
+            .collect())
     }
 
-    /// Returns an aggregation builder.
+    /// Parses a filter expression (e.g. `env:prod AND service:db`) once, so it can be
+    /// passed to many queries using [`AggregationBuilder::filter_compiled`](crate::AggregationBuilder::filter_compiled).
     ///
-    /// The aggregation returns the sum of the values of each bucket.
-    #[must_use]
-    pub fn sum<'a>(
-        &'a self,
-        metric: MetricName<'a>,
-        group_by: &'a str,
-    ) -> crate::agg::Builder<crate::agg::Sum> {
-        crate::agg::Builder {
-            phantom: PhantomData,
-            database: self,
-            metric_name: &metric,
-            filter_expr: "*",
-            bucket_width: MINUTE_IN_NS,
-            group_by,
-            max_ts: None,
-            min_ts: None,
-        }
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid.
+    pub fn parse_filter(&self, filter_expr: &str) -> crate::Result<Filter> {
+        let node = self.parse_filter_expr(filter_expr)?.into_owned();
+        Ok(Filter::new(filter_expr, node))
     }
 
-    /// Returns an aggregation builder.
-    ///
-    /// The aggregation returns the minimum value for each bucket.
-    #[must_use]
-    pub fn min<'a>(
-        &'a self,
-        metric: MetricName<'a>,
-        group_by: &'a str,
-    ) -> crate::agg::Builder<crate::agg::Min> {
-        crate::agg::Builder {
-            phantom: PhantomData,
-            database: self,
-            metric_name: &metric,
-            filter_expr: "*",
-            bucket_width: MINUTE_IN_NS,
-            group_by,
-            max_ts: None,
-            min_ts: None,
+    /// Parses a filter expression using the pinned grammar, if configured.
+    pub(crate) fn parse_filter_expr<'a>(&self, filter_expr: &'a str) -> crate::Result<Node<'a>> {
+        if let Some(version) = self.0.filter_grammar {
+            return Ok(parse_strict(filter_expr, version)?);
         }
+
+        parse_filter_query(filter_expr).map_err(|_| crate::Error::InvalidQuery)
     }
 
-    /// Returns an aggregation builder.
-    ///
-    /// The aggregation returns the maximum value for each bucket.
-    #[must_use]
-    pub fn max<'a>(
-        &'a self,
-        metric: MetricName<'a>,
-        group_by: &'a str,
-    ) -> crate::agg::Builder<crate::agg::Max> {
-        crate::agg::Builder {
-            phantom: PhantomData,
-            database: self,
-            metric_name: &metric,
-            filter_expr: "*",
-            bucket_width: MINUTE_IN_NS,
-            group_by,
-            max_ts: None,
-            min_ts: None,
-        }
+    /// Returns the IDs of all series of the metric that match the filter expression.
+    pub(crate) fn query_series(
+        &self,
+        metric: &str,
+        filter_expr: &str,
+    ) -> crate::Result<Vec<SeriesId>> {
+        let filter = self.parse_filter_expr(filter_expr)?;
+        self.query_series_compiled(metric, &filter)
     }
 
-    /// Returns an aggregation builder.
-    ///
-    /// The aggregation counts data points (ignores their value) per bucket.
-    #[must_use]
-    pub fn count<'a>(
-        &'a self,
-        metric: MetricName<'a>,
-        group_by: &'a str,
-    ) -> crate::agg::Builder<crate::agg::Count> {
-        crate::agg::Builder {
-            phantom: PhantomData,
-            database: self,
-            metric_name: &metric,
-            filter_expr: "*",
-            bucket_width: MINUTE_IN_NS,
-            group_by,
-            max_ts: None,
-            min_ts: None,
+    /// Returns the IDs of all series of the metric that match the parsed filter.
+    pub(crate) fn query_series_compiled(
+        &self,
+        metric: &str,
+        filter: &Node,
+    ) -> crate::Result<Vec<SeriesId>> {
+        let span = span!(
+            "filter",
+            metric,
+            filter = %filter,
+            series = tracing::field::Empty,
+        );
+
+        let plan = self.0.planner.plan(metric, filter, &self.0.tag_index)?;
+        log::trace!("Planned filter {filter} as {plan}");
+
+        let series_ids = plan.evaluate(&self.0.tag_index, metric)?;
+        span.record("series", series_ids.len());
+
+        if series_ids.is_empty() {
+            log::debug!("Query {filter} did not match any series");
+            return Ok(vec![]);
         }
+
+        log::trace!("Querying metric {metric}{{{filter}}} in series {series_ids:?}");
+
+        Ok(series_ids)
     }
 
-    /// Write a data point to the database for the given metric, and tags it accordingly.
+    /// Lists all metrics matching the given pattern (including aliases), in ascending order.
+    ///
+    /// Use [`Database::metric_metadata`] to get the unit & description of a metric.
     ///
     /// # Errors
     ///
     /// Returns error if an I/O error occurred.
-    pub fn write(&self, metric: MetricName, value: Value, tags: &TagSet) -> crate::Result<()> {
-        self.write_at(metric, timestamp(), value, tags)
+    pub fn list_metrics(&self, glob: MetricGlob) -> crate::Result<Vec<String>> {
+        let mut metrics = self.0.tag_index.list_metrics(glob.prefix())?;
+        metrics.extend(self.0.aliases.names());
+        metrics.retain(|metric| glob.matches(metric));
+        metrics.sort_unstable();
+        metrics.dedup();
+        Ok(metrics)
     }
 
-    #[doc(hidden)]
-    pub fn write_at(
-        &self,
+    /// Lists all metrics (including aliases) that have a series with the given tag,
+    /// in ascending order, e.g. to show everything that is known about a host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    ///
+    /// db.write(MetricName::try_from("cpu.total").unwrap(), 4.0, tagset!("host" => "h-1"))?;
+    /// db.write(MetricName::try_from("mem.used").unwrap(), 4.0, tagset!("host" => "h-1"))?;
+    /// db.write(MetricName::try_from("mem.used").unwrap(), 4.0, tagset!("host" => "h-2"))?;
+    ///
+    /// assert_eq!(vec!["cpu.total", "mem.used"], db.metrics_with_tag("host", "h-1")?);
+    /// assert_eq!(vec!["mem.used"], db.metrics_with_tag("host", "h-2")?);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn metrics_with_tag(&self, key: &str, value: &str) -> crate::Result<Vec<String>> {
+        let mut metrics = self.0.tag_index.metrics_with_tag(key, value)?;
+
+        if !self.0.aliases.is_empty() {
+            for metric in metrics.clone() {
+                metrics.extend(self.0.aliases.aliased_by(&metric));
+            }
+        }
+
+        metrics.sort_unstable();
+        metrics.dedup();
+        Ok(metrics)
+    }
+
+    /// Returns indexed tag keys of the metric that are close to the given (misspelled) key,
+    /// closest first, e.g. to show hints when a filter does not match any series.
+    ///
+    /// If the input is a `key:value` tag, `key:value` tags are suggested instead.
+    /// Existing keys (or tags) are returned as their own closest match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// db.write(metric_name, 4.0, tagset!("service" => "db", "host" => "h-1"))?;
+    ///
+    /// assert_eq!(vec!["service"], db.suggest(metric_name, "serivce")?);
+    /// assert_eq!(vec!["service:db"], db.suggest(metric_name, "servce:db")?);
+    /// assert_eq!(vec!["host:h-1"], db.suggest(metric_name, "host:h1")?);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn suggest(&self, metric: MetricName, input: &str) -> crate::Result<Vec<String>> {
+        let mut tags = vec![];
+
+        for metric in self.resolve_metric(&metric) {
+            tags.extend(self.0.tag_index.list_tags(&metric)?);
+        }
+
+        let Some((key, value)) = input.split_once(':') else {
+            return Ok(crate::suggest::closest(
+                input,
+                tags.into_iter().map(|(key, _)| key),
+            ));
+        };
+
+        // NOTE: Keys and values are compared separately, so long keys
+        // do not allow more typos in short values
+        let keys = if tags.iter().any(|(k, _)| k == key) {
+            vec![key.to_owned()]
+        } else {
+            crate::suggest::closest(key, tags.iter().map(|(key, _)| key.clone()))
+        };
+
+        let mut suggestions = vec![];
+
+        for key in keys {
+            let values = tags
+                .iter()
+                .filter(|(k, _)| *k == key)
+                .map(|(_, value)| value.clone());
+
+            suggestions.extend(
+                crate::suggest::closest(value, values)
+                    .into_iter()
+                    .map(|value| format!("{key}:{value}")),
+            );
+        }
+
+        Ok(suggestions)
+    }
+
+    /// Returns the metric and all metrics it aliases.
+    pub(crate) fn resolve_metric(&self, metric: &str) -> Vec<String> {
+        self.0.aliases.resolve(metric)
+    }
+
+    /// Makes queries of the `new` metric include all data written to the `old` metric.
+    ///
+    /// This allows renaming a metric without losing its history.
+    /// Aliases are persisted, and resolved transitively (if `old` itself is an alias).
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn alias_metric(&self, old: MetricName, new: MetricName) -> crate::Result<()> {
+        if old == new {
+            return Ok(());
+        }
+
+        self.0.aliases.insert(new, old)?;
+        self.invalidate_query_cache(old);
+
+        Ok(())
+    }
+
+    /// Sets the unit & description of a metric, replacing any previous metadata.
+    ///
+    /// Metadata is persisted, and does not affect queries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("http.latency").unwrap();
+    ///
+    /// db.describe_metric(metric_name, "ms", "Time until the response is sent")?;
+    ///
+    /// let metadata = db.metric_metadata(metric_name).unwrap();
+    /// assert_eq!("ms", metadata.unit);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn describe_metric(
+        &self,
         metric: MetricName,
-        ts: Timestamp,
-        value: Value,
-        tags: &TagSet,
+        unit: &str,
+        description: &str,
     ) -> crate::Result<()> {
-        let series_key = SeriesKey::format(metric, tags);
-        let series_id: Option<SeriesId> = self.0.smap.get(&series_key)?;
+        self.0.metadata.insert(
+            metric,
+            MetricMetadata {
+                unit: unit.into(),
+                description: description.into(),
+            },
+        )
+    }
 
-        let series_id = if let Some(series_id) = series_id {
-            // NOTE: Series already exists (happy path)
-            series_id
-        } else {
-            // NOTE: Create series
-            self.initialize_new_series(&series_key, metric, tags)?
+    /// Declares the tag keys every series of the metric is expected to have.
+    ///
+    /// Writes with missing or undeclared tag keys are then logged or rejected
+    /// (see [`DatabaseBuilder::schema_policy`]), which catches instrumentation typos
+    /// (e.g. `serivce`) that would otherwise silently split series.
+    /// Keys of default tags (see [`DatabaseBuilder::default_tag`]) are always allowed.
+    ///
+    /// Declarations are not persisted, and replace any previous declaration of the metric.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{tagset, Database, MetricName, SchemaPolicy};
+    ///
+    /// let db = Database::builder()
+    ///     .schema_policy(SchemaPolicy::Error)
+    ///     .open(&folder)?;
+    ///
+    /// let metric_name = MetricName::try_from("http.latency").unwrap();
+    /// db.declare_metric(metric_name, &["env", "service"]);
+    ///
+    /// db.write(metric_name, 4.0, tagset!("env" => "prod", "service" => "db"))?;
+    /// assert!(db.write(metric_name, 4.0, tagset!("env" => "prod", "serivce" => "db")).is_err());
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    pub fn declare_metric(&self, metric: MetricName, tag_keys: &[&str]) {
+        self.0.schemas.declare(metric, tag_keys);
+    }
+
+    /// Returns the declared tag keys of a metric (sorted), see [`Database::declare_metric`].
+    #[must_use]
+    pub fn declared_tag_keys(&self, metric: MetricName) -> Option<Vec<String>> {
+        self.0.schemas.declared_keys(&metric)
+    }
+
+    /// Returns the unit & description of a metric, if set.
+    ///
+    /// If the metric itself has no metadata, the metadata of the metrics it aliases
+    /// (see [`Database::alias_metric`]) is returned, so renamed metrics keep their metadata.
+    #[must_use]
+    pub fn metric_metadata(&self, metric: MetricName) -> Option<MetricMetadata> {
+        self.resolve_metric(&metric)
+            .iter()
+            .find_map(|metric| self.0.metadata.get(metric))
+    }
+
+    pub(crate) fn tag_set(&self, series_id: SeriesId) -> crate::Result<Arc<OwnedTagSets>> {
+        self.0.tag_sets.get(series_id)
+    }
+
+    /// Returns an aggregation builder for a custom [`Aggregation`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Aggregation, Database, MetricName};
+    ///
+    /// /// Sums up the squares of all values
+    /// #[derive(Default)]
+    /// struct SumOfSquares;
+    ///
+    /// impl Aggregation for SumOfSquares {
+    ///     fn init(&mut self, value: talna::Value) -> talna::Value {
+    ///         value * value
+    ///     }
+    ///
+    ///     fn transform(&mut self, accu: talna::Value, x: talna::Value) -> talna::Value {
+    ///         accu + x * x
+    ///     }
+    /// }
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("hello").unwrap();
+    ///
+    /// let buckets = db
+    ///     .aggregate::<SumOfSquares>(metric_name, "host")
+    ///     .build()?
+    ///     .collect()?;
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn aggregate<'a, A: Aggregation>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, A> {
+        let (metric_name, metric_glob) = match metric.into() {
+            MetricSelector::Name(name) => (*name, false),
+            MetricSelector::Glob(glob) => (glob.as_str(), true),
         };
 
-        let data_point_key = Self::format_data_point_key(series_id, ts);
-        self.0.data.insert(data_point_key, value.to_be_bytes())?;
+        crate::agg::Builder {
+            phantom: PhantomData,
+            database: self,
+            metric_name: Cow::Borrowed(metric_name),
+            metric_glob,
+            split_by_metric: false,
+            filter_expr: Cow::Borrowed("*"),
+            compiled_filter: None,
+            bucket_width: MINUTE_IN_NS,
+            group_by: group_by.into(),
+            group_by_tags: None,
+            group_mapping: crate::agg::GroupMapping::default(),
+            missing_tag: crate::MissingTagPolicy::default(),
+            max_groups: None,
+            groups_after: None,
+            max_ts: None,
+            min_ts: None,
+            max_window: None,
+            min_window: None,
+            scale: 1.0,
+            offset: 0.0,
+            timeout: None,
+            max_scanned_points: self.0.max_scanned_points,
+            max_points: None,
+            bucket_months: None,
+            time_zone: None,
+            value_filter: None,
+            sample_rate: None,
+            having: None,
+            aggregation_factory: None,
+            include_deleted: false,
+        }
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation returns the average value for each bucket.
+    #[must_use]
+    pub fn avg<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Average> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation returns the sum of the values of each bucket.
+    #[must_use]
+    pub fn sum<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Sum> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation returns the minimum value for each bucket.
+    #[must_use]
+    pub fn min<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Min> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation returns the maximum value for each bucket.
+    #[must_use]
+    pub fn max<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Max> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation counts data points (ignores their value) per bucket.
+    #[must_use]
+    pub fn count<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Count> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation treats values as identifiers (e.g. user IDs), and returns
+    /// the approximate amount of distinct values per bucket (using `HyperLogLog`).
+    #[must_use]
+    pub fn distinct<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Distinct> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation ignores values, and returns the amount of distinct series
+    /// that have at least one data point per bucket (e.g. active hosts per hour).
+    #[must_use]
+    pub fn active_series<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::ActiveSeries> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder.
+    ///
+    /// The aggregation computes the minimum, maximum, sum, count and average per bucket
+    /// in one scan. Use [`GroupedAggregation::collect_summary`](crate::GroupedAggregation::collect_summary)
+    /// to get all statistics, `collect` returns the averages.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// db.write(metric_name, 10.0, tagset!("host" => "h-1"))?;
+    /// db.write(metric_name, 20.0, tagset!("host" => "h-1"))?;
+    ///
+    /// let summary = db.summary(metric_name, "host").build()?.collect_summary()?;
+    /// assert_eq!(15.0, summary["h-1"][0].avg);
+    /// assert_eq!(2, summary["h-1"][0].count);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn summary<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+    ) -> crate::agg::Builder<'a, crate::agg::Summary> {
+        self.aggregate(metric, group_by)
+    }
+
+    /// Returns an aggregation builder that computes multiple aggregations over the same scan.
+    ///
+    /// Use [`GroupedAggregation::collect_many`](crate::GroupedAggregation::collect_many)
+    /// to get the buckets of all aggregations, `collect` returns the first aggregation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Agg, Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("latency").unwrap();
+    ///
+    /// for x in 1..=100 {
+    ///     db.write(metric_name, x as _, tagset!("host" => "h-1"))?;
+    /// }
+    ///
+    /// let result = db
+    ///     .aggregate_many(metric_name, "host", &[Agg::Avg, Agg::P95, Agg::Max])
+    ///     .granularity(u128::MAX)
+    ///     .build()?
+    ///     .collect_many()?;
+    ///
+    /// assert_eq!(50.5, result["h-1"]["avg"][0].value);
+    /// assert_eq!(95.0, result["h-1"]["p95"][0].value);
+    /// assert_eq!(100.0, result["h-1"]["max"][0].value);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn aggregate_many<'a>(
+        &'a self,
+        metric: impl Into<MetricSelector<'a>>,
+        group_by: impl Into<Cow<'a, str>>,
+        aggs: &[crate::Agg],
+    ) -> crate::agg::Builder<'a, crate::agg::Multi> {
+        let aggs: Arc<[crate::Agg]> = aggs.into();
+
+        let mut builder = self.aggregate(metric, group_by);
+        builder.aggregation_factory = Some(Arc::new(move || crate::agg::Multi::new(aggs.clone())));
+        builder
+    }
+
+    /// Returns a builder joining the buckets of two metrics, e.g. to compute
+    /// an error rate from an error count and a request count.
+    ///
+    /// Buckets of both metrics are aligned (see [`JoinBuilder::align_timezone`](crate::JoinBuilder::align_timezone)),
+    /// and combined using [`JoinBuilder::map`](crate::JoinBuilder::map) (default: `a / b`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, MissingDataPolicy, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let errors = MetricName::try_from("http.errors").unwrap();
+    /// let requests = MetricName::try_from("http.requests").unwrap();
+    ///
+    /// db.write_at(errors, 0, 5.0, tagset!("host" => "h-1"))?;
+    /// db.write_at(requests, 0, 100.0, tagset!("host" => "h-1"))?;
+    /// db.write_at(requests, 0, 50.0, tagset!("host" => "h-2"))?;
+    ///
+    /// let error_rate = db
+    ///     .join(errors, requests)
+    ///     .on_tags(&["host"])
+    ///     .map(|errors, requests| errors / requests)
+    ///     .missing_data(MissingDataPolicy::Fill(0.0))
+    ///     .collect()?;
+    ///
+    /// assert_eq!(0.05, error_rate["h-1"][0].value);
+    /// assert_eq!(0.0, error_rate["h-2"][0].value);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn join<'a>(&'a self, a: MetricName<'a>, b: MetricName<'a>) -> crate::JoinBuilder<'a> {
+        crate::JoinBuilder::new(self, a, b)
+    }
+
+    /// Runs multiple independent queries (e.g. the panels of a dashboard),
+    /// returning the result of each query keyed by its [`QuerySpec::id`](crate::QuerySpec::id).
+    ///
+    /// All queries read from the same snapshot, so they observe the same data points.
+    /// Using the `rayon` feature flag, the queries run in parallel.
+    ///
+    /// Queries fail individually (e.g. because of an invalid filter), so one broken
+    /// query does not prevent the others from returning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Agg, Database, MetricName, QuerySpec, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    ///
+    /// db.write(MetricName::try_from("cpu.total").unwrap(), 4.0, tagset!("host" => "h-1"))?;
+    /// db.write(MetricName::try_from("mem.used").unwrap(), 6.0, tagset!("host" => "h-1"))?;
+    ///
+    /// let mut results = db.query_batch(vec![
+    ///     QuerySpec::new("cpu", "cpu.total", "host", Agg::Avg),
+    ///     QuerySpec::new("mem", "mem.used", "host", Agg::Max).filter("host:h-1"),
+    ///     QuerySpec::new("broken", "mem.used", "host", Agg::Max).filter("host:"),
+    /// ]);
+    ///
+    /// assert_eq!(4.0, results.remove("cpu").unwrap()?["h-1"][0].value);
+    /// assert_eq!(6.0, results.remove("mem").unwrap()?["h-1"][0].value);
+    /// assert!(results.remove("broken").unwrap().is_err());
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn query_batch(
+        &self,
+        queries: Vec<crate::QuerySpec>,
+    ) -> crate::HashMap<String, crate::Result<crate::HashMap<String, Vec<crate::Bucket>>>> {
+        let snapshot = self.snapshot();
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+            queries
+                .into_par_iter()
+                .map(|query| {
+                    let result = query.run(self, &snapshot);
+                    (query.id, result)
+                })
+                .collect()
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            queries
+                .into_iter()
+                .map(|query| {
+                    let result = query.run(self, &snapshot);
+                    (query.id, result)
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the raw data points of each series of the metric that matches the filter expression,
+    /// in the time range `[start, end]`, together with the series' tags.
+    ///
+    /// Streams are ordered by metric and tags, and read from a snapshot taken when calling this.
+    /// Deleted data points are skipped, see [`Database::delete`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, Timestamp, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let used = MetricName::try_from("mem.used").unwrap();
+    /// let total = MetricName::try_from("mem.total").unwrap();
+    ///
+    /// for (host, value) in [("h-1", 2.0), ("h-2", 6.0)] {
+    ///     db.write_at(used, 0, value, tagset!("host" => host))?;
+    ///     db.write_at(total, 0, 8.0, tagset!("host" => host))?;
+    /// }
+    ///
+    /// // NOTE: Join both metrics by host, dividing the newest values
+    /// let mut totals = std::collections::HashMap::new();
+    ///
+    /// for mut stream in db.series_streams(total, "*", 0, Timestamp::MAX)? {
+    ///     if let Some(point) = stream.next().transpose()? {
+    ///         totals.insert(stream.tags["host"].clone(), point.value);
+    ///     }
+    /// }
+    ///
+    /// for mut stream in db.series_streams(used, "*", 0, Timestamp::MAX)? {
+    ///     let used = stream.next().transpose()?.unwrap().value;
+    ///     let host = &stream.tags["host"];
+    ///     println!("{host}: {}%", used / totals[host] * 100.0);
+    /// }
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    pub fn series_streams(
+        &self,
+        metric: MetricName,
+        filter_expr: &str,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> crate::Result<Vec<SeriesStream>> {
+        let filter = self.parse_filter_expr(filter_expr)?;
+        let snapshot = self.snapshot();
+        let bounds = (Bound::Included(start), Bound::Included(end));
+
+        let mut streams = vec![];
+
+        for source in self.resolve_metric(&metric) {
+            let series_ids = self.query_series_compiled(&source, &filter)?;
+            let series_ids = self.series_in_bounds(&series_ids, bounds);
+            let readers = self.prepare_visible_query(&snapshot, &series_ids, bounds, false)?;
+
+            for (series_id, reader) in series_ids.into_iter().zip(readers) {
+                let tags = self
+                    .tag_set(series_id)?
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+
+                streams.push(SeriesStream {
+                    series_id,
+                    metric: source.clone(),
+                    tags,
+                    reader,
+                });
+            }
+        }
+
+        streams.sort_by(|a, b| (&a.metric, &a.tags).cmp(&(&b.metric, &b.tags)));
+
+        Ok(streams)
+    }
+
+    /// Write a data point to the database for the given metric, and tags it accordingly.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the timestamp is out of range
+    /// (see [`DatabaseBuilder::timestamp_bounds`]).
+    pub fn write(&self, metric: MetricName, value: Value, tags: &TagSet) -> crate::Result<()> {
+        self.write_at(metric, timestamp(), value, tags)
+    }
+
+    /// Starts a timer, which writes the elapsed time in nanoseconds to the metric when stopped.
+    ///
+    /// Use [`AggregationBuilder::unit`](crate::AggregationBuilder::unit) to query
+    /// durations in other units.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Agg, Database, DurationUnit, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("http.latency").unwrap();
+    ///
+    /// let tags = tagset!("route" => "/");
+    ///
+    /// let timer = db.timer(metric_name, tags);
+    /// std::thread::sleep(std::time::Duration::from_millis(2));
+    /// timer.stop()?;
+    ///
+    /// let latencies = db
+    ///     .aggregate_many(metric_name, "route", &[Agg::P50, Agg::P99, Agg::Max])
+    ///     .unit(DurationUnit::Millis)
+    ///     .build()?
+    ///     .collect_many()?;
+    ///
+    /// assert!(latencies["/"]["p99"][0].value >= 2.0);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    pub fn timer<'a>(&'a self, metric: MetricName<'a>, tags: &'a TagSet<'a>) -> crate::Timer<'a> {
+        crate::Timer::start(self, metric, tags)
+    }
+
+    /// Runs the closure, and writes its duration in nanoseconds to the metric.
+    ///
+    /// The duration is written even if the closure returns an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn time<T>(
+        &self,
+        metric: MetricName,
+        tags: &TagSet,
+        f: impl FnOnce() -> T,
+    ) -> crate::Result<T> {
+        let timer = self.timer(metric, tags);
+        let result = f();
+        timer.stop()?;
+        Ok(result)
+    }
+
+    #[doc(hidden)]
+    pub fn write_at(
+        &self,
+        metric: MetricName,
+        ts: Timestamp,
+        value: Value,
+        tags: &TagSet,
+    ) -> crate::Result<()> {
+        let _span = write_span!("write", metric = %metric);
+
+        if let Some(resolution) = self.0.pre_aggregation.resolution(&metric) {
+            return self.pre_aggregate(metric, ts, value, tags, resolution);
+        }
+
+        let encoding = self.value_encoding(metric);
+
+        self.run_write(|| {
+            self.write_data_point(metric, tags, ts, encoding.encode(value).as_ref())
+        })?;
+        self.invalidate_query_cache(metric);
+        self.count_points(metric, 1);
+
+        Ok(())
+    }
+
+    /// Buffers the sample in the current window of its series, writing the previous window
+    /// once a sample of a newer window arrives
+    fn pre_aggregate(
+        &self,
+        metric: MetricName,
+        ts: Timestamp,
+        value: Value,
+        tags: &TagSet,
+        resolution: Timestamp,
+    ) -> crate::Result<()> {
+        // NOTE: Reject invalid samples right away, instead of when the window is written
+        self.check_timestamp(ts)?;
+
+        let series_key = SeriesKey::format(metric, tags);
+
+        match self
+            .0
+            .pre_aggregation
+            .add(&metric, series_key, tags, ts, value, resolution)
+        {
+            Buffered::Added => Ok(()),
+            Buffered::Late => {
+                let encoding = self.value_encoding(metric);
+
+                self.run_write(|| {
+                    self.write_data_point(metric, tags, ts, encoding.encode(value).as_ref())
+                })?;
+                self.invalidate_query_cache(metric);
+                self.count_points(metric, 1);
+
+                Ok(())
+            }
+            Buffered::Closed(window) => self.write_window(&window),
+        }
+    }
+
+    /// Writes a window of pre-aggregated samples
+    ///
+    /// If the window was already written by a flush, the stored sample is overwritten.
+    fn write_window(&self, window: &Window) -> crate::Result<()> {
+        if window.written == window.stat.count {
+            return Ok(());
+        }
+
+        // NOTE: The metric name was validated when the sample was buffered
+        let metric = MetricName::try_from(window.metric.as_str())?;
+        let new_points = u64::from(window.written == 0);
+
+        let tags = window
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+
+        if window.stat.count == 1 {
+            let encoding = self.value_encoding(metric);
+
+            self.run_write(|| {
+                self.write_data_point(
+                    metric,
+                    &tags,
+                    window.start,
+                    encoding.encode(window.stat.sum).as_ref(),
+                )
+            })?;
+            self.invalidate_query_cache(metric);
+            self.count_points(metric, new_points);
+
+            return Ok(());
+        }
+
+        self.run_write(|| {
+            self.write_data_point(metric, &tags, window.start, &window.stat.serialize())
+        })?;
+        self.invalidate_query_cache(metric);
+        self.count_points(metric, new_points);
+
+        Ok(())
+    }
+
+    /// Writes every value of the struct as a data point of its metric,
+    /// tagged with the struct's tags.
+    ///
+    /// All values are validated before anything is written, so a rejected value
+    /// (e.g. because of an invalid metric name or tag set, or a schema violation) does not
+    /// leave any series or data point behind.
+    ///
+    /// Missing series are created first, then the data points are written in a single batch,
+    /// so either all or none of them are written. An I/O error while writing the batch can leave
+    /// the created series without data points.
+    ///
+    /// Values of pre-aggregated metrics (see [`DatabaseBuilder::pre_aggregate`]) are not part
+    /// of the batch, they are buffered after the batch was written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// # #[cfg(feature = "derive")]
+    /// # {
+    /// use talna::{Database, Metric};
+    ///
+    /// #[derive(Metric)]
+    /// #[metric(prefix = "cpu")]
+    /// struct CpuSample {
+    ///     #[metric(tag)]
+    ///     host: String,
+    ///     user: f32,
+    ///     system: f32,
+    /// }
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    ///
+    /// let sample = CpuSample {
+    ///     host: "h-1".into(),
+    ///     user: 12.5,
+    ///     system: 3.0,
+    /// };
+    ///
+    /// // writes to `cpu.user` and `cpu.system`
+    /// db.write_struct(talna::timestamp(), &sample)?;
+    /// # }
+    /// #
+    /// # Ok::<_, talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, a metric name is invalid, the tag set is invalid,
+    /// or the timestamp is out of range.
+    pub fn write_struct<M: Metric>(&self, ts: Timestamp, metric: &M) -> crate::Result<()> {
+        let tags = metric.tags();
+        let values = metric.values();
+
+        // NOTE: Validate everything first, so the struct is not written partially
+        let values = values
+            .into_iter()
+            .map(|(name, value)| Ok((MetricName::try_from(name)?, value)))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        self.check_timestamp(ts)?;
+        crate::tagset::validate(&tags)?;
+
+        for (name, _) in &values {
+            self.0.schemas.check(*name, &tags, &self.0.default_tags)?;
+        }
+
+        let mut batch = self.0.keyspace.inner().batch();
+        let mut counts = crate::HashMap::<MetricName, u64>::default();
+        let mut pre_aggregated = vec![];
+
+        for (name, value) in values {
+            // NOTE: Pre-aggregated samples are buffered instead of written, see `write_at`
+            if self.0.pre_aggregation.resolution(&name).is_some() {
+                pre_aggregated.push((name, value));
+                continue;
+            }
+
+            let series_id = self.get_or_create_series(name, &tags)?;
+            self.0.series_bounds.extend(series_id, ts)?;
+
+            batch.insert(
+                &self.0.data,
+                Self::format_data_point_key(series_id, ts),
+                self.value_encoding(name).encode(value).as_ref(),
+            );
+
+            *counts.entry(name).or_default() += 1;
+        }
+
+        // NOTE: All data points are written in a single batch, so the struct is not written partially
+        self.commit_write_batch(batch, &mut counts)?;
+
+        if !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        for (name, value) in pre_aggregated {
+            self.write_at(name, ts, value, &tags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes many data points, optimized for bulk imports (e.g. historical backfill).
+    ///
+    /// Data points are expected to arrive sorted by series (and time), so the series of
+    /// consecutive data points is only resolved once, and data points are written in
+    /// large batches without persisting the journal after each write.
+    /// Unsorted input is written correctly, but does not benefit from the optimizations.
+    ///
+    /// Data points that were not persisted may be lost after a crash, so call
+    /// [`Database::flush`] once the import is done.
+    ///
+    /// Data points of pre-aggregated metrics (see [`DatabaseBuilder::pre_aggregate`])
+    /// are not buffered, but written as raw data points.
+    ///
+    /// Returns the amount of written data points.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, TagSet, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    /// let tags: &TagSet = tagset!("host" => "h-1");
+    ///
+    /// let count = db.ingest_sorted((0..1_000).map(|ts| (metric_name, tags, ts, 4.0)))?;
+    /// assert_eq!(1_000, count);
+    ///
+    /// db.flush(true)?;
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, a tag set is invalid, or a timestamp is out of range.
+    /// Batches that were committed before the error stay written (and counted).
+    pub fn ingest_sorted<'a, I>(&self, points: I) -> crate::Result<u64>
+    where
+        I: IntoIterator<Item = (MetricName<'a>, &'a TagSet<'a>, Timestamp, Value)>,
+    {
+        /// Amount of data points per write batch
+        const BATCH_SIZE: usize = 10_000;
+
+        let mut count = 0;
+
+        let mut current: Option<(MetricName, &TagSet, SeriesId, ValueEncoding)> = None;
+        let mut counts = crate::HashMap::<MetricName, u64>::default();
+
+        let mut batch = self.0.keyspace.inner().batch();
+        let mut batch_len = 0;
+
+        for (metric, tags, ts, value) in points {
+            self.check_timestamp(ts)?;
+
+            let (series_id, encoding) = match current {
+                Some((prev_metric, prev_tags, series_id, encoding))
+                    if prev_metric == metric && prev_tags == tags =>
+                {
+                    (series_id, encoding)
+                }
+                _ => {
+                    let series_id = self.get_or_create_series(metric, tags)?;
+                    let encoding = self.value_encoding(metric);
+                    current = Some((metric, tags, series_id, encoding));
+                    (series_id, encoding)
+                }
+            };
+
+            self.0.series_bounds.extend(series_id, ts)?;
+
+            batch.insert(
+                &self.0.data,
+                Self::format_data_point_key(series_id, ts),
+                encoding.encode(value).as_ref(),
+            );
+            batch_len += 1;
+
+            *counts.entry(metric).or_default() += 1;
+            count += 1;
+
+            if batch_len >= BATCH_SIZE {
+                let full = std::mem::replace(&mut batch, self.0.keyspace.inner().batch());
+                self.commit_write_batch(full, &mut counts)?;
+                batch_len = 0;
+            }
+        }
+
+        if batch_len > 0 {
+            self.commit_write_batch(batch, &mut counts)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Commits a write batch (e.g. of [`Database::ingest_sorted`]), and accounts for its data points
+    ///
+    /// Every committed batch is accounted for right away, so an error in a later batch
+    /// does not leave point counts & cached query results stale.
+    fn commit_write_batch(
+        &self,
+        batch: fjall::Batch,
+        counts: &mut crate::HashMap<MetricName, u64>,
+    ) -> crate::Result<()> {
+        self.run_write(|| batch.commit().map_err(Into::into))?;
+
+        for (metric, n) in counts.drain() {
+            self.invalidate_query_cache(metric);
+            self.count_points(metric, n);
+        }
+
+        Ok(())
+    }
+
+    /// Creates a writer for the series of the given metric and tags.
+    ///
+    /// The series is resolved (and created if needed) once, so following
+    /// writes skip formatting and looking up the series key.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the tag set is invalid.
+    pub fn writer(&self, metric: MetricName, tags: &TagSet) -> crate::Result<SeriesWriter> {
+        let series_id = self.get_or_create_series(metric, tags)?;
+
+        Ok(SeriesWriter {
+            db: self.clone(),
+            metric: metric.into(),
+            series_id,
+            encoding: self.value_encoding(metric),
+            checked_removals: AtomicU64::new(self.series_removals()),
+        })
+    }
+
+    pub(crate) fn value_encoding(&self, metric: MetricName) -> ValueEncoding {
+        self.0
+            .value_encodings
+            .get(*metric)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Writes a pre-aggregated sample to the database for the given metric, and tags it accordingly.
+    ///
+    /// Aggregations merge pre-aggregated samples with raw data points, e.g.
+    /// a count aggregation will add the sample's count, not 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the timestamp is out of range.
+    pub fn write_stat(
+        &self,
+        metric: MetricName,
+        ts: Timestamp,
+        stat: Stat,
+        tags: &TagSet,
+    ) -> crate::Result<()> {
+        self.run_write(|| self.write_data_point(metric, tags, ts, &stat.serialize()))?;
+        self.invalidate_query_cache(metric);
+        self.count_points(metric, 1);
+
+        Ok(())
+    }
+
+    /// Writes a rolled-up bucket as a quantile sketch to the database for the given metric, and tags it accordingly.
+    ///
+    /// The sketch is stored as a pre-aggregated sample, so all aggregations can use it, and
+    /// quantile aggregations merge the sketch instead of approximating the sample's distribution.
+    ///
+    /// Empty sketches are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the timestamp is out of range.
+    pub fn write_sketch(
+        &self,
+        metric: MetricName,
+        ts: Timestamp,
+        sketch: &QuantileSketch,
+        tags: &TagSet,
+    ) -> crate::Result<()> {
+        if sketch.is_empty() {
+            return Ok(());
+        }
+
+        let mut bytes = sketch.stat().serialize();
+        sketch.serialize_into(&mut bytes);
+
+        self.run_write(|| self.write_data_point(metric, tags, ts, &bytes))?;
+        self.invalidate_query_cache(metric);
+        self.count_points(metric, 1);
+
+        Ok(())
+    }
+
+    fn get_or_create_series(&self, metric: MetricName, tags: &TagSet) -> crate::Result<SeriesId> {
+        self.0.schemas.check(metric, tags, &self.0.default_tags)?;
+
+        if !self.0.default_tags.is_empty() {
+            let tags = self.with_default_tags(tags);
+            return self.get_or_create_series_inner(metric, &tags);
+        }
+
+        self.get_or_create_series_inner(metric, tags)
+    }
+
+    pub(crate) fn count_points(&self, metric: MetricName, n: u64) {
+        self.0.point_counts.add(&metric, n);
+    }
+
+    /// Returns the approximate amount of data points of the metric (including its aliases),
+    /// without scanning the data.
+    ///
+    /// Counts are persisted when flushing; after a crash, the data points are counted
+    /// again when opening the database. Pre-aggregated samples count as one data point.
+    #[must_use]
+    pub fn point_count(&self, metric: MetricName) -> u64 {
+        self.resolve_metric(&metric)
+            .iter()
+            .map(|source| self.0.point_counts.get(source))
+            .sum()
+    }
+
+    /// Returns an error if a data point with the given timestamp & tags would be rejected
+    /// (see `write_at`), without writing it
+    #[cfg(feature = "server")]
+    pub(crate) fn validate_write(
+        &self,
+        metric: MetricName,
+        ts: Timestamp,
+        tags: &TagSet,
+    ) -> crate::Result<()> {
+        self.check_timestamp(ts)?;
+        crate::tagset::validate(tags)?;
+        self.0.schemas.check(metric, tags, &self.0.default_tags)
+    }
+
+    /// Returns the amount of series that were removed since the database was opened
+    pub(crate) fn series_removals(&self) -> u64 {
+        self.0.series_removals.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if the series was not removed
+    pub(crate) fn series_exists(&self, series_id: SeriesId) -> crate::Result<bool> {
+        self.0.tag_sets.contains(series_id)
+    }
+
+    /// Returns an error if the timestamp is outside the configured bounds
+    pub(crate) fn check_timestamp(&self, ts: Timestamp) -> crate::Result<()> {
+        if let Some((min, max)) = self.0.timestamp_bounds {
+            if !(min..=max).contains(&ts) {
+                return Err(crate::Error::TimestampOutOfRange(ts));
+            }
+        }
+
+        if let Some(skew) = self.0.max_clock_skew {
+            if ts > timestamp().saturating_add(skew) {
+                return Err(crate::Error::TimestampOutOfRange(ts));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the write after checking the storage quota, and reports
+    /// its timing to the write observer (if installed)
+    pub(crate) fn run_write(&self, f: impl FnOnce() -> crate::Result<()>) -> crate::Result<()> {
+        if let Some(quota) = &self.0.quota {
+            quota.check(&self.0.keyspace)?;
+        }
+
+        match &self.0.write_observer {
+            Some(observer) => observer.observe(f),
+            None => f(),
+        }
+    }
+
+    /// Writes a data point into the series of the given metric and tags, creating the series if needed
+    fn write_data_point(
+        &self,
+        metric: MetricName,
+        tags: &TagSet,
+        ts: Timestamp,
+        value: &[u8],
+    ) -> crate::Result<()> {
+        self.check_timestamp(ts)?;
+        self.0.schemas.check(metric, tags, &self.0.default_tags)?;
+
+        let merged_tags;
+
+        let tags = if self.0.default_tags.is_empty() {
+            tags
+        } else {
+            merged_tags = self.with_default_tags(tags);
+            &merged_tags
+        };
+
+        let series_key = SeriesKey::format(metric, tags);
+
+        let removals = self.series_removals();
+
+        if let Some(series_id) = self.0.smap.get(&series_key)? {
+            // NOTE: Series already exists (happy path)
+            if self.insert_into_series(series_id, removals, ts, value)? {
+                return Ok(());
+            }
+
+            // NOTE: Series was removed since it was looked up, so create it again
+        }
+
+        self.initialize_new_series(&series_key, metric, tags, Some((ts, value)))?;
+
+        Ok(())
+    }
+
+    /// Writes a data point into an existing series,
+    /// returning `false` if the series was removed since `removals` was loaded
+    ///
+    /// The series is locked while writing, so it can not be removed in between.
+    fn insert_into_series(
+        &self,
+        series_id: SeriesId,
+        removals: u64,
+        ts: Timestamp,
+        value: &[u8],
+    ) -> crate::Result<bool> {
+        let _lock = self.0.series_locks.write_points(series_id);
+
+        if self.series_removals() != removals && !self.series_exists(series_id)? {
+            return Ok(false);
+        }
+
+        self.insert_data_point(series_id, ts, value)?;
+
+        Ok(true)
+    }
+
+    /// Locks the series for writing data points, see [`SeriesLocks`]
+    pub(crate) fn lock_series(&self, series_id: SeriesId) -> RwLockReadGuard<'_, ()> {
+        self.0.series_locks.write_points(series_id)
+    }
+
+    /// Adds the default tags that are not overridden by the given tags
+    fn with_default_tags<'a>(&'a self, tags: &TagSet<'a>) -> Vec<(&'a str, &'a str)> {
+        let mut merged = Vec::with_capacity(tags.len() + self.0.default_tags.len());
+        merged.extend_from_slice(tags);
+
+        for (key, value) in &self.0.default_tags {
+            if !tags.iter().any(|(k, _)| k == key) {
+                merged.push((key, value));
+            }
+        }
+
+        merged
+    }
+
+    fn get_or_create_series_inner(
+        &self,
+        metric: MetricName,
+        tags: &TagSet,
+    ) -> crate::Result<SeriesId> {
+        let series_key = SeriesKey::format(metric, tags);
+
+        if let Some(series_id) = self.0.smap.get(&series_key)? {
+            // NOTE: Series already exists (happy path)
+            return Ok(series_id);
+        }
+
+        // NOTE: Create series
+        self.initialize_new_series(&series_key, metric, tags, None)
+    }
+
+    pub(crate) fn insert_data_point<V: AsRef<[u8]>>(
+        &self,
+        series_id: SeriesId,
+        ts: Timestamp,
+        value: V,
+    ) -> crate::Result<()> {
+        self.0.series_bounds.extend(series_id, ts)?;
+
+        let data_point_key = Self::format_data_point_key(series_id, ts);
+        self.0.data.insert(data_point_key, value)?;
+
+        if !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a series, and writes its first data point (if given)
+    ///
+    /// The first data point is written in the same transaction as the series metadata,
+    /// so after a crash, there can neither be data points without a tag set, nor a
+    /// series that was created by a write that was lost.
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn initialize_new_series(
+        &self,
+        series_key: &str,
+        metric: MetricName,
+        tags: &TagSet,
+        first_point: Option<(Timestamp, &[u8])>,
+    ) -> crate::Result<SeriesId> {
+        // NOTE: We need to run in a transaction (for serializability)
+        //
+        // Because we cannot rely on the series not being created since the
+        // start of the function, we need to again look it up inside the transaction
+        // to really make sure
+        let removals = self.series_removals();
+        let mut tx = self.0.keyspace.write_tx();
+
+        let series_id = tx
+            .get(&self.0.smap.partition, series_key)?
+            .map(|bytes| self.0.smap.deserialize_series_id(series_key, &bytes))
+            .transpose()?;
+
+        if let Some(series_id) = series_id {
+            // NOTE: Series was created since the start of the function
+            drop(tx);
+
+            if let Some((ts, value)) = first_point {
+                if !self.insert_into_series(series_id, removals, ts, value)? {
+                    // NOTE: ...and removed again in the meantime
+                    return self.initialize_new_series(series_key, metric, tags, first_point);
+                }
+            }
+
+            return Ok(series_id);
+        }
+
+        // NOTE: Actually create series
+        crate::tagset::validate(tags)?;
+
+        let next_series_id = self.0.smap.next_series_id(&mut tx)?;
+
+        log::trace!("Creating series {next_series_id} for permutation {series_key:?}");
+
+        self.0.smap.insert(&mut tx, series_key, next_series_id);
+
+        self.0
+            .tag_index
+            .index(&mut tx, metric, tags, next_series_id)?;
+
+        let mut serialized_tag_set = SeriesKey::allocate_string_for_tags(tags, 0);
+        SeriesKey::join_tags(&mut serialized_tag_set, tags);
+
+        self.0
+            .tag_sets
+            .insert(&mut tx, next_series_id, &serialized_tag_set);
+
+        if let Some((ts, value)) = first_point {
+            self.0.series_bounds.extend(next_series_id, ts)?;
+
+            let data_point_key = Self::format_data_point_key(next_series_id, ts);
+            tx.insert(&self.0.tx_data, data_point_key, value);
+        }
+
+        tx.commit()?;
+
+        if first_point.is_some() && !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        self.0.tag_sets.invalidate(next_series_id);
+        self.0.tag_index.invalidate(&metric, tags.iter().copied());
+        self.0.smap.cache(series_key, next_series_id);
+
+        if let Some(observer) = &self.0.write_observer {
+            observer
+                .observer
+                .on_series_created(metric, tags, next_series_id);
+        }
+
+        Ok(next_series_id)
+    }
+
+    /// Rewrites the series of a metric that match the filter to a new tag set,
+    /// e.g. to fix labeling mistakes after the fact.
+    ///
+    /// For every matching series, the tags in `remove_tags` are removed, and the
+    /// tags in `add_tags` are added (replacing existing values of the same key).
+    /// Data points are moved to the resulting series, which are merged if
+    /// they already exist. The old series are removed.
+    ///
+    /// If both series have a data point at the same timestamp, the data points are merged
+    /// into a pre-aggregated sample (see [`Database::write_stat`]). Writes into both
+    /// series are blocked while the data points are moved.
+    ///
+    /// [`SeriesWriter`]s of retagged series return [`crate::Error::SeriesRemoved`], so they need to be recreated.
+    ///
+    /// Returns the amount of retagged series.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression or resulting tag set is invalid, or an I/O error occurred.
+    pub fn retag(
+        &self,
+        metric: MetricName,
+        filter_expr: &str,
+        add_tags: &TagSet,
+        remove_tags: &[&str],
+    ) -> crate::Result<usize> {
+        let series_ids = self.query_series(&metric, filter_expr)?;
+
+        // NOTE: Negated filters may match series of other metrics
+        let metric_series = self.0.tag_index.query_eq(&metric)?;
+
+        let mut count = 0;
+
+        for series_id in series_ids {
+            if !metric_series.contains(&series_id) {
+                continue;
+            }
+
+            let old_tags = self.tag_set(series_id)?;
+
+            let mut new_tags = old_tags
+                .iter()
+                .filter(|(key, _)| !remove_tags.contains(&key.as_str()))
+                .filter(|(key, _)| !add_tags.iter().any(|(k, _)| k == key))
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+            new_tags.extend_from_slice(add_tags);
+
+            let unchanged = new_tags.len() == old_tags.len()
+                && new_tags
+                    .iter()
+                    .all(|(k, v)| old_tags.get(*k).is_some_and(|x| x == v));
+
+            if unchanged {
+                continue;
+            }
+
+            let moved = loop {
+                let new_series_id = self.get_or_create_series_inner(metric, &new_tags)?;
+
+                // NOTE: Block writes into both series until the data points are moved
+                let _locks = self.0.series_locks.remove_pair(series_id, new_series_id);
+
+                if !self.series_exists(series_id)? {
+                    // NOTE: Removed concurrently
+                    break false;
+                }
+
+                if !self.series_exists(new_series_id)? {
+                    // NOTE: The new series was removed by a GC run in the meantime, so create it again
+                    continue;
+                }
+
+                log::debug!("Retagging series {series_id} into series {new_series_id}");
+
+                self.move_series(metric, series_id, &old_tags, new_series_id)?;
+
+                break true;
+            };
+
+            if moved {
+                count += 1;
+            }
+        }
+
+        self.invalidate_query_cache(metric);
+
+        Ok(count)
+    }
+
+    /// Moves all data points of a series into another series, and removes the old series.
+    ///
+    /// Data points at a timestamp that already exists in the other series are merged into
+    /// a pre-aggregated sample. Writes into both series need to be blocked by the caller.
+    fn move_series(
+        &self,
+        metric: MetricName,
+        series_id: SeriesId,
+        tags: &OwnedTagSets,
+        new_series_id: SeriesId,
+    ) -> crate::Result<()> {
+        let snapshot = self.snapshot();
+        let merged = Self::merge_colliding_points(&snapshot, series_id, new_series_id)?;
+
+        let hot: (&dyn StorageSnapshot, &dyn StoragePartition) = (&snapshot.hot, &self.0.data);
+
+        let tiers = std::iter::once(hot).chain(
+            snapshot
+                .cold
+                .as_deref()
+                .zip(self.0.cold_tier.as_ref().map(|tier| &*tier.data)),
+        );
+
+        // NOTE: Copy data points first, so a crash never loses data points,
+        // (at worst, they are visible in both series)
+        for (snapshot, data) in tiers {
+            for kv in snapshot.prefix(&series_id.to_be_bytes()) {
+                let (k, v) = kv?;
+
+                let inverted_ts = k.get(std::mem::size_of::<SeriesId>()..).unwrap_or_default();
+                let mut reader = inverted_ts;
+                let ts = !reader.read_u128::<BigEndian>()?;
+
+                if merged.contains_key(&ts) {
+                    continue;
+                }
+
+                self.0.series_bounds.extend(new_series_id, ts)?;
+
+                let mut key = new_series_id.to_be_bytes().to_vec();
+                key.extend_from_slice(inverted_ts);
+
+                data.insert(&key, &v)?;
+            }
+        }
+
+        // NOTE: Merged data points are written to the hot tier, which shadows the cold tier
+        for (&ts, value) in &merged {
+            self.insert_data_point(new_series_id, ts, value)?;
+        }
+
+        let tag_list = tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        let series_key = SeriesKey::format(metric, &tag_list);
+
+        self.remove_series_metadata(&series_key, &metric, series_id, tags)?;
+        self.remove_series_data(&snapshot, series_id, (Bound::Unbounded, Bound::Unbounded))?;
+
+        // NOTE: Each merged data point replaces two data points
+        self.0.point_counts.sub(&metric, merged.len() as u64);
+
+        Ok(())
+    }
+
+    /// Merges the data points of a series with the data points of another series at the same timestamp,
+    /// returning the serialized merged data points by timestamp
+    fn merge_colliding_points(
+        snapshot: &Arc<DataSnapshot>,
+        series_id: SeriesId,
+        other_series_id: SeriesId,
+    ) -> crate::Result<crate::HashMap<Timestamp, Vec<u8>>> {
+        let bounds = (Bound::Unbounded, Bound::Unbounded);
+        let mut merged = crate::HashMap::default();
+
+        let mut other = Self::prepare_query(snapshot, &[other_series_id], bounds)?
+            .into_iter()
+            .flatten()
+            .peekable();
+
+        // NOTE: Both series are ordered from newest to oldest
+        for item in Self::prepare_query(snapshot, &[series_id], bounds)?
+            .into_iter()
+            .flatten()
+        {
+            let item = item?;
+
+            while let Some(next) = other.next_if(|x| x.as_ref().map_or(true, |x| x.ts >= item.ts)) {
+                let next = next?;
+
+                if next.ts == item.ts {
+                    merged.insert(item.ts, item.merge(&next));
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Removes a series from the series mapping, tag index and tag sets
+    fn remove_series_metadata(
+        &self,
+        series_key: &str,
+        metric: &str,
+        series_id: SeriesId,
+        tags: &OwnedTagSets,
+    ) -> crate::Result<()> {
+        {
+            let mut tx = self.0.keyspace.write_tx();
+            self.0.smap.remove(&mut tx, series_key);
+            self.0.tag_index.remove(&mut tx, metric, tags, series_id)?;
+            self.0.tag_sets.remove(&mut tx, series_id);
+            tx.commit()?;
+        }
+
+        self.0.series_bounds.remove(series_id)?;
+        self.0.series_removals.fetch_add(1, Ordering::SeqCst);
+
+        self.0.smap.invalidate(series_key);
+        self.0.tag_sets.invalidate(series_id);
+        self.0
+            .tag_index
+            .invalidate(metric, tags.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+        Ok(())
+    }
+
+    /// Deletes the data points of a series in the given time range,
+    /// returning the amount of deleted data points
+    fn remove_series_data(
+        &self,
+        snapshot: &DataSnapshot,
+        series_id: SeriesId,
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> crate::Result<u64> {
+        let mut count = 0;
+
+        for kv in Self::series_range(&snapshot.hot, series_id, bounds) {
+            let (k, _) = kv?;
+            self.0.data.remove(k)?;
+            count += 1;
+        }
+
+        if let (Some(snapshot), Some(tier)) = (&snapshot.cold, &self.0.cold_tier) {
+            for kv in Self::series_range(&**snapshot, series_id, bounds) {
+                let (k, _) = kv?;
+                tier.data.remove(&k)?;
+                count += 1;
+            }
+        }
+
+        if !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Moves data points that are older than the cold tier's age from the hot tier
+    /// to the cold tier (see [`DatabaseBuilder::cold_tier`]).
+    ///
+    /// Moved data points are synced to the cold tier before they are removed from
+    /// the hot tier, so a crash never loses data points.
+    ///
+    /// Returns the amount of moved data points, or 0 if no cold tier is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn move_to_cold_tier(&self) -> crate::Result<u64> {
+        /// Amount of moved data points that are synced at once
+        const CHUNK_SIZE: usize = 10_000;
+
+        let Some(tier) = &self.0.cold_tier else {
+            return Ok(0);
+        };
+
+        let cutoff = timestamp().saturating_sub(tier.age);
+        let snapshot = self.0.data.snapshot();
+
+        let mut count = 0;
+        let mut moved = Vec::with_capacity(CHUNK_SIZE);
+
+        let mut remove_moved = |moved: &mut Vec<fjall::Slice>| -> crate::Result<()> {
+            tier.persist()?;
+
+            for key in moved.drain(..) {
+                self.0.data.remove(key)?;
+                count += 1;
+            }
+
+            Ok(())
+        };
+
+        for series_id in self.0.smap.list_all()? {
+            let range = Self::series_range(
+                &snapshot,
+                series_id,
+                (Bound::Unbounded, Bound::Excluded(cutoff)),
+            );
+
+            for kv in range {
+                let (k, v) = kv?;
+                tier.data.insert(&k, &v)?;
+                moved.push(k);
+
+                if moved.len() >= CHUNK_SIZE {
+                    remove_moved(&mut moved)?;
+                }
+            }
+        }
+
+        if !moved.is_empty() {
+            remove_moved(&mut moved)?;
+        }
+
+        if !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        log::debug!("Moved {count} data points to cold tier");
+
+        Ok(count)
+    }
+
+    /// Exports all data points in the time range `[start, end)` as an archive chunk,
+    /// and stores it in the given sink (e.g. an object store), enabling long-term
+    /// retention outside of the database.
+    ///
+    /// The chunk contains the tags & raw values of every series, and can be imported
+    /// using [`Database::restore_archive`]. Chunks are named after their time range.
+    ///
+    /// If `remove` is `true`, the archived data points are deleted after the chunk was stored.
+    /// Data points that are overwritten while archiving are kept.
+    ///
+    /// The chunk is built in memory before it is passed to the sink, so on memory-constrained
+    /// devices, archive bounded time ranges (e.g. one day at a time).
+    ///
+    /// Deleted data points (see [`Database::delete`]) are neither archived nor removed,
+    /// they are removed when their tombstone is compacted.
+    ///
+    /// Returns the amount of archived data points, no chunk is stored if there are none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use std::{collections::HashMap, sync::Mutex};
+    /// use talna::{ArchiveSink, Database, MetricName, tagset};
+    ///
+    /// #[derive(Default)]
+    /// struct InMemory(Mutex<HashMap<String, Vec<u8>>>);
+    ///
+    /// impl ArchiveSink for InMemory {
+    ///     fn put(&self, name: &str, chunk: &[u8]) -> talna::Result<()> {
+    ///         self.0.lock().unwrap().insert(name.into(), chunk.into());
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// for ts in 0..100 {
+    ///     db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+    /// }
+    ///
+    /// let sink = InMemory::default();
+    /// assert_eq!(50, db.archive(0, 50, &sink, true)?);
+    /// assert_eq!(1, sink.0.lock().unwrap().len());
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the sink failed to store the chunk.
+    pub fn archive(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        sink: &dyn ArchiveSink,
+        remove: bool,
+    ) -> crate::Result<u64> {
+        let snapshot = self.snapshot();
+        let bounds = (Bound::Included(start), Bound::Excluded(end));
+
+        let mut writer = ChunkWriter::new();
+        let mut archived = vec![];
+
+        for kv in self.0.keyspace.read_tx().iter(&self.0.smap.partition) {
+            let (series_key, series_id) = kv?;
+            let series_id = self.0.smap.deserialize_series_id(&series_key, &series_id)?;
+
+            let mut points = self
+                .archived_points(&snapshot, series_id, bounds)
+                .map(|point| point.map(|(_, ts, v)| (ts, v)))
+                .peekable();
+
+            if points.peek().is_none() {
+                continue;
+            }
+
+            let series_key = String::from_utf8_lossy(&series_key);
+            let metric = series_key
+                .split_once('#')
+                .map_or(&*series_key, |(metric, _)| metric);
+
+            let tags = self.0.tag_sets.get(series_id)?;
+            let mut tags = tags
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+            tags.sort_unstable();
+
+            let count = writer.write_series(metric, &tags, points)?;
+
+            if remove && count > 0 {
+                archived.push((series_key.to_string(), series_id));
+            }
+        }
+
+        let count = writer.point_count();
+
+        if let Some(chunk) = writer.finish() {
+            sink.put(&crate::archive::chunk_name(start, end), &chunk)?;
+        }
+
+        let mut removed = Vec::with_capacity(archived.len());
+
+        // NOTE: Only remove data points once the chunk is stored
+        //
+        // The archived data points are read from the snapshot again, instead of being kept in memory
+        for (series_key, series_id) in archived {
+            let metric = series_key
+                .split_once('#')
+                .map_or(&*series_key, |(metric, _)| metric);
+
+            let mut count = 0;
+            let mut range: Option<(Timestamp, Timestamp)> = None;
+
+            for point in self.archived_points(&snapshot, series_id, bounds) {
+                let (key, ts, value) = point?;
+
+                // NOTE: A data point that was overwritten since the snapshot was not archived, so keep it.
+                // Only the hot tier is written to, the cold tier only receives data points from it
+                let current = self.0.data.get(&key)?;
+
+                if current.as_ref().is_some_and(|current| *current != value) {
+                    continue;
+                }
+
+                if current.is_some() {
+                    self.0.data.remove(&key)?;
+                }
+
+                if let Some(tier) = &self.0.cold_tier {
+                    tier.data.remove(&key)?;
+                }
+
+                count += 1;
+
+                // NOTE: Data points are ordered from newest to oldest
+                range = Some(range.map_or((ts, ts), |(_, end)| (ts, end)));
+            }
+
+            self.0.point_counts.sub(metric, count);
+
+            if let Ok(metric) = MetricName::try_from(metric) {
+                self.invalidate_query_cache(metric);
+            }
+
+            if let Some((start, end)) = range {
+                removed.push(RemovedRange {
+                    series_id,
+                    series_key,
+                    start,
+                    end,
+                });
+            }
+        }
+
+        if remove && !self.0.hyper_mode {
+            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
+        }
+
+        self.audit(RemovalReason::Archive, || Ok(removed))?;
+
+        Ok(count)
+    }
+
+    /// Returns the key, timestamp & raw value of the data points of a series that are archived,
+    /// skipping deleted data points
+    fn archived_points<'a>(
+        &self,
+        snapshot: &'a DataSnapshot,
+        series_id: SeriesId,
+        bounds: (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> impl Iterator<Item = crate::Result<(fjall::Slice, Timestamp, fjall::Slice)>> + 'a {
+        let deleted = self.0.tombstones.ranges(series_id);
+
+        Self::tiered_series_range(snapshot, series_id, bounds)
+            .map(|kv| {
+                let (k, v) = kv?;
+                let (_, ts) = crate::archive::parse_data_point_key(&k)?;
+                Ok((k, ts, v))
+            })
+            .filter(move |point| {
+                point.as_ref().map_or(true, |(_, ts, _)| {
+                    !deleted.iter().any(|(min, max)| (min..=max).contains(&ts))
+                })
+            })
+    }
+
+    /// Imports an archive chunk that was created by [`Database::archive`].
+    ///
+    /// Series are created if needed, data points with the same timestamp are overwritten.
+    /// Timestamp bounds are not checked, because archived data points were checked when they were written.
+    ///
+    /// Returns the amount of imported data points.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the chunk is invalid.
+    pub fn restore_archive(&self, chunk: &[u8]) -> crate::Result<u64> {
+        self.restore_archive_with_progress(chunk, no_progress)
+    }
+
+    /// Like [`Database::restore_archive`], but calls `progress` after each imported series.
+    ///
+    /// If `progress` returns [`ControlFlow::Break`], the remaining series of the chunk are not imported.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or the chunk is invalid.
+    pub fn restore_archive_with_progress(
+        &self,
+        chunk: &[u8],
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> crate::Result<u64> {
+        let mut count = 0;
+
+        let chunk = crate::archive::read_chunk(chunk)?;
+
+        let mut total = Progress {
+            done: 0,
+            total: Some(chunk.len() as u64),
+        };
+
+        for series in chunk {
+            let metric = MetricName::try_from(series.metric.as_str())?;
+
+            let tags = series
+                .tags
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+
+            let series_id = self.get_or_create_series_inner(metric, &tags)?;
+
+            let mut batch = self.0.keyspace.inner().batch();
+
+            for (ts, value) in &series.points {
+                self.0.series_bounds.extend(series_id, *ts)?;
+
+                batch.insert(
+                    &self.0.data,
+                    Self::format_data_point_key(series_id, *ts),
+                    value,
+                );
+            }
+
+            self.run_write(|| batch.commit().map_err(Into::into))?;
+
+            self.invalidate_query_cache(metric);
+            self.count_points(metric, series.points.len() as u64);
+            count += series.points.len() as u64;
+
+            total.done += 1;
+            if progress(total).is_break() {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Removes series that did not receive any data point at or after `cutoff`
+    /// (nanosecond timestamp), e.g. series of ephemeral tags like pod IDs.
+    ///
+    /// Removed series are no longer returned by queries. If `remove_data` is `true`,
+    /// their data points are deleted as well, otherwise they are kept on disk
+    /// (e.g. to be deleted by a later, less busy GC run), but are not queryable anymore.
+    /// A GC run with `remove_data` set deletes the data points that earlier runs kept.
+    ///
+    /// A series is rechecked while writes into it are blocked, so a series that receives
+    /// a data point during the GC run is kept.
+    ///
+    /// Writing to a removed series creates it again. [`SeriesWriter`]s of removed
+    /// series return [`crate::Error::SeriesRemoved`], so they need to be recreated.
+    ///
+    /// Returns the amount of removed series.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn gc_idle_series(&self, cutoff: Timestamp, remove_data: bool) -> crate::Result<usize> {
+        let snapshot = self.snapshot();
+        let mut idle = vec![];
+
+        for kv in self.0.keyspace.read_tx().iter(&self.0.smap.partition) {
+            let (series_key, bytes) = kv?;
+            let series_key = String::from_utf8_lossy(&series_key).into_owned();
+            let series_id = self.0.smap.deserialize_series_id(&series_key, &bytes)?;
+
+            if !Self::has_data_since(&snapshot, series_id, cutoff)? {
+                idle.push((series_key, series_id));
+            }
+        }
+
+        let mut removed = Vec::with_capacity(idle.len());
+
+        for (series_key, series_id) in &idle {
+            // NOTE: Block writes into the series until it is removed
+            let _lock = self.0.series_locks.remove(*series_id);
+
+            // NOTE: Evict the cached lookups first, so the checks below read the current state
+            self.0.smap.invalidate(series_key);
+            self.0.tag_sets.invalidate(*series_id);
+
+            if !self.series_exists(*series_id)? {
+                // NOTE: Removed concurrently
+                continue;
+            }
+
+            // NOTE: A data point may have been written since the series was found to be idle
+            let snapshot = self.snapshot();
+
+            if Self::has_data_since(&snapshot, *series_id, cutoff)? {
+                continue;
+            }
+
+            let metric = series_key
+                .split_once('#')
+                .map_or(&**series_key, |(metric, _)| metric);
+            let tags = self.tag_set(*series_id)?;
+
+            self.0
+                .tag_index
+                .invalidate(metric, tags.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+            log::debug!("Removing idle series {series_id} ({series_key:?})");
+
+            // NOTE: Bounds are forgotten when removing the series, and are only missing
+            // if the series never had data points
+            let (start, end) = self
+                .0
+                .series_bounds
+                .get(*series_id)
+                .unwrap_or_else(|| (0, cutoff.saturating_sub(1)));
+
+            removed.push(RemovedRange {
+                series_id: *series_id,
+                series_key: series_key.clone(),
+                start,
+                end,
+            });
+
+            self.remove_series_metadata(series_key, metric, *series_id, &tags)?;
+
+            if remove_data {
+                let count = self.remove_series_data(
+                    &snapshot,
+                    *series_id,
+                    (Bound::Unbounded, Bound::Unbounded),
+                )?;
+                self.0.point_counts.sub(metric, count);
+            } else {
+                // NOTE: The series is recorded after it was removed, so a crash in between
+                // leaks its data points, instead of removing the data points of a live series
+                self.0.orphans.insert(*series_id, metric)?;
+            }
+
+            if let Ok(metric) = MetricName::try_from(metric) {
+                self.invalidate_query_cache(metric);
+            }
+        }
+
+        let count = removed.len();
+
+        self.audit(RemovalReason::IdleSeries, || Ok(removed))?;
+
+        if remove_data {
+            for (series_id, metric) in self.0.orphans.list()? {
+                let count = self.remove_series_data(
+                    &snapshot,
+                    series_id,
+                    (Bound::Unbounded, Bound::Unbounded),
+                )?;
+                self.0.point_counts.sub(&metric, count);
+
+                log::debug!("Removed {count} data points of orphaned series {series_id}");
+
+                self.0.orphans.remove(series_id)?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Returns `true` if the series has a data point at or after `cutoff`
+    fn has_data_since(
+        snapshot: &Arc<DataSnapshot>,
+        series_id: SeriesId,
+        cutoff: Timestamp,
+    ) -> crate::Result<bool> {
+        // NOTE: Data points are ordered from newest to oldest
+        let newest = Self::prepare_query(
+            snapshot,
+            &[series_id],
+            (Bound::Included(cutoff), Bound::Unbounded),
+        )?
+        .into_iter()
+        .flatten()
+        .next()
+        .transpose()?;
+
+        Ok(newest.is_some())
+    }
+
+    /// Deletes the data points of the series of a metric that match the filter,
+    /// in the time range `[start, end]` (nanosecond timestamps).
+    ///
+    /// Deletes are recorded as tombstones: the data points are hidden from queries
+    /// immediately, but stay on disk until the tombstone is compacted using
+    /// [`Database::compact_tombstones`]. Until then, they can be inspected using
+    /// [`AggregationBuilder::include_deleted`](crate::AggregationBuilder::include_deleted),
+    /// and the delete can be undone using [`Database::undelete`].
+    ///
+    /// Data points that are written into the deleted time range of a deleted series
+    /// are hidden (and removed when compacting) as well.
+    ///
+    /// Returns the ID of the tombstone, or `None` if no series matched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// for ts in 0..10 {
+    ///     db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+    /// }
+    ///
+    /// let id = db.delete(metric_name, "host:h-1", 0, 4)?.unwrap();
+    ///
+    /// let count = |include_deleted| -> talna::Result<_> {
+    ///     let mut buckets = db
+    ///         .count(metric_name, "host")
+    ///         .granularity(u128::MAX)
+    ///         .include_deleted(include_deleted)
+    ///         .build()?
+    ///         .collect()?;
+    ///     Ok(buckets.remove("h-1").unwrap().pop().unwrap().value)
+    /// };
+    /// assert_eq!(5.0, count(false)?);
+    /// assert_eq!(10.0, count(true)?);
+    ///
+    /// // NOTE: Oops, wrong host
+    /// assert!(db.undelete(id)?);
+    /// assert_eq!(10.0, count(false)?);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the filter expression is invalid, or an I/O error occurred.
+    pub fn delete(
+        &self,
+        metric: MetricName,
+        filter_expr: &str,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> crate::Result<Option<u64>> {
+        let series_ids = self.query_series(&metric, filter_expr)?;
+
+        // NOTE: Negated filters may match series of other metrics
+        let metric_series = self.0.tag_index.query_eq(&metric)?;
+
+        let series_ids = series_ids
+            .into_iter()
+            .filter(|series_id| metric_series.contains(series_id))
+            .collect::<Vec<_>>();
+
+        if series_ids.is_empty() {
+            return Ok(None);
+        }
+
+        let id = self
+            .0
+            .tombstones
+            .insert(&metric, &series_ids, (start, end), timestamp())?;
+
+        log::debug!("Deleted time range [{start}, {end}] of metric {metric:?} (tombstone {id})");
+
+        self.audit(RemovalReason::Delete { tombstone: id }, || {
+            series_ids
+                .iter()
+                .map(|&series_id| self.removed_range(&metric, series_id, (start, end)))
+                .collect()
+        })?;
+
+        self.invalidate_query_cache(metric);
+
+        Ok(Some(id))
+    }
+
+    /// Undoes a delete whose tombstone was not compacted yet, see [`Database::delete`].
+    ///
+    /// Returns `false` if there is no such tombstone (anymore).
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn undelete(&self, id: u64) -> crate::Result<bool> {
+        let Some(tombstone) = self.0.tombstones.remove(id)? else {
+            return Ok(false);
+        };
+
+        if let Ok(metric) = MetricName::try_from(tombstone.metric.as_str()) {
+            self.invalidate_query_cache(metric);
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the tombstones of deletes that were not compacted yet, ordered by ID.
+    #[must_use]
+    pub fn tombstones(&self) -> Vec<Tombstone> {
+        self.0.tombstones.list()
+    }
+
+    /// Removes the data points of deletes that happened before `cutoff` (nanosecond timestamp)
+    /// from disk, and drops their tombstones, so they can not be undone anymore.
+    ///
+    /// Call it periodically with the current time minus the grace period
+    /// accidental deletes should be recoverable for.
+    ///
+    /// Returns the amount of removed data points.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn compact_tombstones(&self, cutoff: Timestamp) -> crate::Result<u64> {
+        self.compact_tombstones_with_progress(cutoff, no_progress)
+    }
+
+    /// Like [`Database::compact_tombstones`], but calls `progress` after each compacted tombstone.
+    ///
+    /// If `progress` returns [`ControlFlow::Break`], compaction stops, and the remaining
+    /// tombstones are compacted by the next call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use std::ops::ControlFlow;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// for ts in 0..10 {
+    ///     db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+    /// }
+    /// db.delete(metric_name, "*", 0, 1)?;
+    /// db.delete(metric_name, "*", 2, 3)?;
+    ///
+    /// // NOTE: Stop after the first tombstone
+    /// let count = db.compact_tombstones_with_progress(u128::MAX, |progress| {
+    ///     assert_eq!(Some(2), progress.total);
+    ///     ControlFlow::Break(())
+    /// })?;
+    /// assert_eq!(2, count);
+    /// assert_eq!(1, db.tombstones().len());
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn compact_tombstones_with_progress(
+        &self,
+        cutoff: Timestamp,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> crate::Result<u64> {
+        let snapshot = self.snapshot();
+        let mut count = 0;
+
+        let tombstones = self
+            .0
+            .tombstones
+            .list()
+            .into_iter()
+            .filter(|tombstone| tombstone.deleted_at < cutoff)
+            .collect::<Vec<_>>();
+
+        let mut total = Progress {
+            done: 0,
+            total: Some(tombstones.len() as u64),
+        };
+
+        for tombstone in tombstones {
+            let bounds = (
+                Bound::Included(tombstone.start),
+                Bound::Included(tombstone.end),
+            );
+
+            let mut removed = 0;
+
+            for &series_id in &tombstone.series_ids {
+                removed += self.remove_series_data(&snapshot, series_id, bounds)?;
+            }
+
+            // NOTE: Data points are removed first, so a crash never makes them visible again
+            self.0.tombstones.remove(tombstone.id)?;
+
+            log::debug!(
+                "Compacted tombstone {}, removing {removed} data points",
+                tombstone.id,
+            );
+
+            self.0.point_counts.sub(&tombstone.metric, removed);
+
+            self.audit(
+                RemovalReason::Compaction {
+                    tombstone: tombstone.id,
+                },
+                || {
+                    tombstone
+                        .series_ids
+                        .iter()
+                        .map(|&series_id| {
+                            self.removed_range(
+                                &tombstone.metric,
+                                series_id,
+                                (tombstone.start, tombstone.end),
+                            )
+                        })
+                        .collect()
+                },
+            )?;
+
+            if let Ok(metric) = MetricName::try_from(tombstone.metric.as_str()) {
+                self.invalidate_query_cache(metric);
+            }
+
+            count += removed;
+
+            total.done += 1;
+            if progress(total).is_break() {
+                log::debug!(
+                    "Tombstone compaction aborted after {} tombstones",
+                    total.done
+                );
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Rolls up the historical data points of a metric into pre-aggregated samples
+    /// (see [`DatabaseBuilder::pre_aggregate`]), e.g. after pre-aggregation was enabled
+    /// for a metric that already has raw data points.
+    ///
+    /// The data points of each series are aggregated into windows of the given granularity,
+    /// which are written at the start of the window, replacing the data points.
+    /// Windows that contain a quantile sketch or deleted data points (see [`Database::delete`])
+    /// are left as is.
+    ///
+    /// `progress` is called after each series. If it returns [`ControlFlow::Break`], the remaining
+    /// series are not rolled up, and can be rolled up by calling this function again.
+    ///
+    /// This is an offline maintenance operation: the rollup of a window is written before its data
+    /// points are removed, so data points written concurrently, or a crash in between, may cause
+    /// a window to be counted twice.
+    ///
+    /// Returns the amount of data points that were rolled up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use std::ops::ControlFlow;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// for ts in 0..100 {
+    ///     db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+    /// }
+    ///
+    /// let count = db.backfill_rollups(metric_name, 10, |progress| {
+    ///     println!("{:.0}%", progress.fraction().unwrap_or_default() * 100.0);
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// assert_eq!(100, count);
+    /// assert_eq!(10, db.point_count(metric_name));
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn backfill_rollups(
+        &self,
+        metric: MetricName,
+        granularity: Timestamp,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> crate::Result<u64> {
+        let granularity = granularity.max(1);
+        let series_ids = self.0.tag_index.query_eq(&metric)?;
+        let snapshot = self.snapshot();
+
+        let mut total = Progress {
+            done: 0,
+            total: Some(series_ids.len() as u64),
+        };
+        let mut rolled_up = 0;
+        let mut rollups = 0;
+
+        for &series_id in &series_ids {
+            let deleted = self.0.tombstones.ranges(series_id);
+            let bounds = (Bound::Unbounded, Bound::Unbounded);
+
+            let mut window: Option<RollupWindow> = None;
+
+            for reader in Self::prepare_query(&snapshot, &[series_id], bounds)? {
+                for item in reader {
+                    let item = item?;
+                    let start = item.ts - item.ts % granularity;
+
+                    if let Some(current) = window.take_if(|window| window.start != start) {
+                        if self.write_rollup(&snapshot, series_id, &current, granularity)? {
+                            rolled_up += current.points;
+                            rollups += 1;
+                        }
+                    }
+
+                    window
+                        .get_or_insert_with(|| RollupWindow::new(start, &deleted, granularity))
+                        .add(&item);
+                }
+            }
+
+            if let Some(current) = window {
+                if self.write_rollup(&snapshot, series_id, &current, granularity)? {
+                    rolled_up += current.points;
+                    rollups += 1;
+                }
+            }
+
+            total.done += 1;
+            if progress(total).is_break() {
+                break;
+            }
+        }
+
+        // NOTE: Each rollup replaces its data points, but is a data point itself
+        self.0.point_counts.sub(&metric, rolled_up - rollups);
+        self.invalidate_query_cache(metric);
+
+        log::debug!(
+            "Rolled up {rolled_up} data points of metric {metric:?} into {rollups} rollups"
+        );
+
+        Ok(rolled_up)
+    }
+
+    /// Replaces the data points of a window with its rollup, returning `false` if the window is skipped
+    fn write_rollup(
+        &self,
+        snapshot: &DataSnapshot,
+        series_id: SeriesId,
+        window: &RollupWindow,
+        granularity: Timestamp,
+    ) -> crate::Result<bool> {
+        if window.skip || window.points < 2 {
+            return Ok(false);
+        }
+
+        // NOTE: The rollup overwrites the data point at the start of the window (if any),
+        // and shadows a data point at the same timestamp in the cold tier
+        self.insert_data_point(series_id, window.start, window.stat.serialize())?;
+
+        let end = window.start.saturating_add(granularity - 1);
+        self.remove_series_data(
+            snapshot,
+            series_id,
+            (Bound::Excluded(window.start), Bound::Included(end)),
+        )?;
+
+        if let Some(tier) = &self.0.cold_tier {
+            tier.data
+                .remove(&Self::format_data_point_key(series_id, window.start))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the amount of series.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn series_count(&self) -> crate::Result<usize> {
+        Ok(self.0.smap.partition.inner().len()?)
+    }
+
+    /// Returns the disk space used by the database in bytes.
+    #[must_use]
+    pub fn disk_space(&self) -> u64 {
+        self.0.keyspace.disk_space()
+    }
+
+    /// Returns the approximate memory consumption of the database, so embedders
+    /// can verify it stays within its configured cache & write buffer sizes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().cache_size_mib(8).open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    ///
+    /// db.write(metric_name, 25.42, tagset!("host" => "h-1"))?;
+    ///
+    /// let usage = db.memory_usage();
+    /// assert!(usage.write_buffer > 0);
+    /// assert!(usage.block_cache <= 8 * 1_024 * 1_024);
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    #[must_use]
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            block_cache: self
+                .0
+                .block_cache
+                .as_ref()
+                .map_or(0, |block_cache| block_cache.size()),
+            write_buffer: self.0.keyspace.write_buffer_size(),
+            postings_cache: self.0.tag_index.cache_size(),
+            tag_set_cache: self.0.tag_sets.cache_size(),
+            series_cache: self.0.smap.cache_size(),
+            query_cache: self.0.query_cache.as_ref().map_or(0, QueryCache::size),
+            pre_aggregation: self.0.pre_aggregation.size(),
+        }
+    }
+
+    /// Exports all data points in line protocol, one per line:
+    /// `<metric>[,<key>=<value>...] <value> <timestamp>`
+    ///
+    /// Pre-aggregated samples are exported as their sum. Deleted data points
+    /// (see [`Database::delete`]) are not exported.
+    ///
+    /// Returns the amount of exported data points.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn export<W: std::io::Write>(&self, writer: &mut W) -> crate::Result<u64> {
+        let (count, _) = self.export_from(writer, None, u64::MAX)?;
+        Ok(count)
+    }
+
+    /// Exports up to `max_points` data points in line protocol (see [`Database::export`]),
+    /// starting after the given cursor (or at the beginning).
+    ///
+    /// Returns the amount of exported data points, and the cursor to continue the export with,
+    /// or `None` if all data points were exported.
+    ///
+    /// Calling this repeatedly (flushing the writer, and persisting the cursor as token
+    /// after each call) allows a long export to be resumed after a crash, see [`ExportCursor`].
+    /// Each call reads from a new snapshot, so data points written in between calls are only
+    /// exported if they are located after the cursor.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn export_from<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        cursor: Option<&ExportCursor>,
+        max_points: u64,
+    ) -> crate::Result<(u64, Option<ExportCursor>)> {
+        let read_tx = self.0.keyspace.read_tx();
+        let snapshot = self.snapshot();
+        let mut count = 0;
+
+        // NOTE: Otherwise, no progress could be made
+        let max_points = max_points.max(1);
+
+        // NOTE: Position of the last exported data point
+        let mut last = cursor.cloned();
+
+        let start = cursor.map_or(Bound::Unbounded, |cursor| {
+            Bound::Included(cursor.series_key.as_bytes().to_vec())
+        });
+
+        for kv in read_tx.range(&self.0.smap.partition, (start, Bound::Unbounded)) {
+            let (series_key, series_id) = kv?;
+            let series_id = self.0.smap.deserialize_series_id(&series_key, &series_id)?;
+
+            let series_key = String::from_utf8_lossy(&series_key);
+            let prefix = line_protocol_prefix(&series_key);
+
+            // NOTE: Continue with the data point that is older than the last exported one
+            let end = match cursor {
+                Some(cursor) if cursor.series_key == series_key => Bound::Excluded(cursor.ts),
+                _ => Bound::Unbounded,
+            };
+
+            let mut last_ts = None;
+
+            for reader in
+                self.prepare_visible_query(&snapshot, &[series_id], (Bound::Unbounded, end), false)?
+            {
+                for item in reader {
+                    if count >= max_points {
+                        if let Some(ts) = last_ts {
+                            last = Some(ExportCursor {
+                                series_key: series_key.into_owned(),
+                                ts,
+                            });
+                        }
+                        return Ok((count, last));
+                    }
+
+                    let item = item?;
+                    writeln!(writer, "{prefix} {} {}", item.value, item.ts)?;
+                    last_ts = Some(item.ts);
+                    count += 1;
+                }
+            }
+
+            if let Some(ts) = last_ts {
+                last = Some(ExportCursor {
+                    series_key: series_key.into_owned(),
+                    ts,
+                });
+            }
+        }
+
+        Ok((count, None))
+    }
+
+    /// Exports all data points into a Parquet file, so they can be analyzed using
+    /// other tools (e.g. `DuckDB`, Spark).
+    ///
+    /// The file has the columns `metric` & `tags` (dictionary encoded strings, tags are
+    /// formatted as `key:value;...`), `timestamp` (nanoseconds), `value` (f64) and `count`.
+    /// The value of a pre-aggregated sample is its sum, and its count the amount of raw data points.
+    ///
+    /// Returns the amount of exported data points.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet<P: AsRef<std::path::Path>>(&self, path: P) -> crate::Result<u64> {
+        use crate::columnar::{data_point_schema, DataPointBatchBuilder, ParquetFile};
+
+        let read_tx = self.0.keyspace.read_tx();
+        let snapshot = self.snapshot();
+        let mut count = 0;
+
+        let mut file = ParquetFile::create(path.as_ref(), data_point_schema())?;
+        let mut batch = DataPointBatchBuilder::new();
+
+        for kv in read_tx.iter(&self.0.smap.partition) {
+            let (series_key, series_id) = kv?;
+            let series_id = self.0.smap.deserialize_series_id(&series_key, &series_id)?;
+
+            let series_key = String::from_utf8_lossy(&series_key);
+            let (metric, tags) = series_key.split_once('#').unwrap_or((&series_key, ""));
+
+            for reader in self.prepare_visible_query(
+                &snapshot,
+                &[series_id],
+                (Bound::Unbounded, Bound::Unbounded),
+                false,
+            )? {
+                for item in reader {
+                    batch.append(metric, tags, &item?)?;
+                    count += 1;
+
+                    if batch.len() >= DataPointBatchBuilder::BATCH_SIZE {
+                        file.write(&batch.finish()?)?;
+                    }
+                }
+            }
+        }
+
+        if batch.len() > 0 {
+            file.write(&batch.finish()?)?;
+        }
+
+        file.close()?;
+
+        Ok(count)
+    }
+
+    /// Imports data points in line protocol (see [`Database::export`]).
+    ///
+    /// If a line has no timestamp, the current time is used.
+    ///
+    /// Returns the amount of imported data points.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or a line is invalid.
+    pub fn import<R: std::io::BufRead>(&self, reader: R) -> crate::Result<u64> {
+        self.import_with_progress(reader, no_progress)
+    }
+
+    /// Like [`Database::import`], but calls `progress` after each imported data point.
+    ///
+    /// The total is unknown, because the lines are streamed from the reader.
+    /// If `progress` returns [`ControlFlow::Break`], the remaining lines are not imported.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, or a line is invalid.
+    pub fn import_with_progress<R: std::io::BufRead>(
+        &self,
+        reader: R,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> crate::Result<u64> {
+        let mut count = 0;
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let line = Line::parse(&line).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("line {}: {e}", idx + 1),
+                )
+            })?;
+
+            self.write_at(
+                line.metric,
+                line.ts.unwrap_or_else(timestamp),
+                line.value,
+                &line.tags,
+            )?;
+
+            count += 1;
+
+            let progress = progress(Progress {
+                done: count,
+                total: None,
+            });
+
+            if progress.is_break() {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Flushes writes, including windows of pre-aggregated metrics
+    /// (see [`DatabaseBuilder::pre_aggregate`]).
+    ///
+    /// If sync is `true`, the writes are guaranteed to be written to disk
+    /// when this function exits.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn flush(&self, sync: bool) -> crate::Result<()> {
+        use fjall::PersistMode::{Buffer, SyncAll};
+
+        for window in self.0.pre_aggregation.flush() {
+            self.write_window(&window)?;
+        }
+
+        self.0.point_counts.persist()?;
+        self.0.series_bounds.persist()?;
+
+        self.0
+            .keyspace
+            .persist(if sync { SyncAll } else { Buffer })?;
+
+        Ok(())
+    }
+
+    /// Syncs all writes to disk, and closes this database handle.
+    ///
+    /// Background threads are stopped and the database lock is released once
+    /// the last handle (including clones and [`SeriesWriter`](crate::SeriesWriter)s) is dropped.
+    ///
+    /// Dropping the database without closing it persists buffered writes as well
+    /// (including windows of pre-aggregated metrics), but does not sync them to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn close(self) -> crate::Result<()> {
+        self.flush(true)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::tagset;
+    use test_log::test;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_query_cache() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 1, 4.0, tagset!("host" => "h-1"))?;
+
+        let query = || -> crate::Result<Value> {
+            let mut buckets = db.sum(metric_name, "host").build()?.collect()?;
+            Ok(buckets.remove("h-1").unwrap().pop().unwrap().value)
+        };
+        assert_eq!(4.0, query()?);
+
+        // NOTE: Bypass cache invalidation to observe the cached result
+        let series_id = db.get_or_create_series(metric_name, tagset!("host" => "h-1"))?;
+        db.insert_data_point(series_id, 2, ValueEncoding::Full.encode(6.0))?;
+        assert_eq!(4.0, query()?);
+
+        // NOTE: Different aggregation is not served from cache
+        let mut buckets = db.count(metric_name, "host").build()?.collect()?;
+        assert_eq!(2.0, buckets.remove("h-1").unwrap().pop().unwrap().value);
+
+        db.write_at(metric_name, 3, 10.0, tagset!("host" => "h-1"))?;
+        assert_eq!(20.0, query()?);
+
+        // NOTE: Scan limits change the result, so they are part of the cache key
+        let mut buckets = db
+            .sum(metric_name, "host")
+            .max_scanned_points(1)
+            .build()?
+            .collect()?;
+        assert_eq!(10.0, buckets.remove("h-1").unwrap().pop().unwrap().value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_query_cache_ttl() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::ZERO)
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 1, 4.0, tagset!("host" => "h-1"))?;
+        db.sum(metric_name, "host").build()?.collect()?;
+
+        let series_id = db.get_or_create_series(metric_name, tagset!("host" => "h-1"))?;
+        db.insert_data_point(series_id, 2, ValueEncoding::Full.encode(6.0))?;
+
+        let mut buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(10.0, buckets.remove("h-1").unwrap().pop().unwrap().value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_batch() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..21 {
+            #[allow(clippy::cast_precision_loss)]
+            let value = ((ts * 7) % 21) as Value;
+            db.write_at(metric_name, ts, value, tagset!("host" => "h-1"))?;
+        }
+
+        let value = |buckets: crate::HashMap<String, Vec<crate::Bucket>>| {
+            let mut buckets = buckets.into_iter().next().unwrap().1;
+            assert_eq!(1, buckets.len());
+            buckets.pop().unwrap().value
+        };
+
+        // NOTE: Values cycle through 0, 7, 14
+        assert_eq!(0.0, value(db.min(metric_name, "host").build()?.collect()?));
+        assert_eq!(14.0, value(db.max(metric_name, "host").build()?.collect()?));
+        assert_eq!(
+            21.0,
+            value(db.count(metric_name, "host").build()?.collect()?)
+        );
+        assert_eq!(
+            147.0,
+            value(db.sum(metric_name, "host").build()?.collect()?)
+        );
+        assert_eq!(7.0, value(db.avg(metric_name, "host").build()?.collect()?));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_collect_parallel() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for host in 0..20 {
+            let host = format!("h-{host}");
+
+            for ts in 0..10 {
+                #[allow(clippy::cast_precision_loss)]
+                let value = ts as Value;
+                db.write_at(
+                    metric_name,
+                    ts,
+                    value,
+                    tagset!("host" => host.as_str(), "env" => "prod"),
+                )?;
+            }
+        }
+
+        let expected = db
+            .avg(metric_name, "host")
+            .filter("env:prod")
+            .granularity(4)
+            .build()?
+            .collect()?;
+
+        let actual = db
+            .avg(metric_name, "host")
+            .filter("env:prod")
+            .granularity(4)
+            .collect_parallel()?;
+
+        assert_eq!(20, actual.len());
+        assert_eq!(expected, actual);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_query_timeout() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..1_000 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        assert!(matches!(
+            db.sum(metric_name, "host")
+                .timeout(std::time::Duration::ZERO)
+                .build()?
+                .collect(),
+            Err(crate::Error::Timeout)
+        ));
+
+        let mut buckets = db
+            .sum(metric_name, "host")
+            .timeout(std::time::Duration::from_secs(60))
+            .build()?
+            .collect()?;
+        assert_eq!(1_000.0, buckets.remove("h-1").unwrap().pop().unwrap().value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_invalid_tag_set() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        assert!(matches!(
+            db.write(metric_name, 1.0, tagset!("host" => "h-1", "host" => "h-2")),
+            Err(crate::Error::InvalidTagSet(
+                crate::TagSetError::DuplicateKey(_)
+            )),
+        ));
+        assert_eq!(0, db.series_count()?);
+
+        let tags = crate::TagSetBuf::new([("service", "db"), ("host", "h-1")])?;
+        db.write(metric_name, 1.0, &tags)?;
+        db.write(
+            metric_name,
+            2.0,
+            tagset!("host" => "h-1", "service" => "db"),
+        )?;
+        assert_eq!(1, db.series_count()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_default_tags() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .default_tag("region", "eu")
+            .default_tag("host", "h-1")
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 0, 4.0, tagset!())?;
+        db.write_at(metric_name, 1, 6.0, tagset!("host" => "h-2"))?;
+
+        let buckets = db
+            .sum(metric_name, "host")
+            .filter("region:eu")
+            .build()?
+            .collect()?;
+
+        assert_eq!(4.0, buckets["h-1"][0].value);
+        assert_eq!(6.0, buckets["h-2"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_series_writer() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let writer = db.writer(metric_name, tagset!("host" => "h-1"))?;
+        writer.write_at(0, 4.0)?;
+
+        let sum = || -> crate::Result<Value> {
+            let buckets = db.sum(metric_name, "host").build()?.collect()?;
+            Ok(buckets["h-1"][0].value)
+        };
+        assert_eq!(4.0, sum()?);
+
+        writer.write_at(1, 6.0)?;
+        db.write_at(metric_name, 2, 1.0, tagset!("host" => "h-1"))?;
+        assert_eq!(11.0, sum()?);
+        assert_eq!(1, db.series_count()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_query_snapshot() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 0, 4.0, tagset!("host" => "h-1"))?;
+
+        let aggregation = db.sum(metric_name, "host").build()?;
+
+        db.write_at(metric_name, 1, 6.0, tagset!("host" => "h-1"))?;
+
+        let buckets = aggregation.collect()?;
+        assert_eq!(4.0, buckets["h-1"][0].value);
+
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(10.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_metric_glob() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        for (metric, value) in [("cpu.user", 1.0), ("cpu.system", 2.0), ("mem.used", 4.0)] {
+            let metric_name = MetricName::try_from(metric).unwrap();
+            db.write_at(metric_name, 0, value, tagset!("host" => "h-1"))?;
+        }
+
+        let glob = crate::MetricGlob::try_from("cpu.*").unwrap();
+
+        let buckets = db.sum(glob, "host").build()?.collect()?;
+        assert_eq!(1, buckets.len());
+        assert_eq!(3.0, buckets["h-1"][0].value);
+
+        let buckets = db.sum(glob, "host").split_by_metric().build()?.collect()?;
+        assert_eq!(2, buckets.len());
+        assert_eq!(1.0, buckets["cpu.user#h-1"][0].value);
+        assert_eq!(2.0, buckets["cpu.system#h-1"][0].value);
+
+        let glob = crate::MetricGlob::try_from("disk.*").unwrap();
+        assert!(db.sum(glob, "host").build()?.collect()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_alias_metric() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+
+        let old = MetricName::try_from("cpu_total").unwrap();
+        let new = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(old, 0, 4.0, tagset!("host" => "h-1"))?;
+        db.alias_metric(old, new)?;
+        db.write_at(new, 1, 6.0, tagset!("host" => "h-1"))?;
+
+        let sum = || -> crate::Result<Value> {
+            let buckets = db.sum(new, "host").build()?.collect()?;
+            Ok(buckets["h-1"][0].value)
+        };
+        assert_eq!(10.0, sum()?);
+
+        // NOTE: Writes to the old metric invalidate cached queries of the new metric
+        db.write_at(old, 2, 1.0, tagset!("host" => "h-1"))?;
+        assert_eq!(11.0, sum()?);
+
+        let buckets = db.sum(old, "host").build()?.collect()?;
+        assert_eq!(5.0, buckets["h-1"][0].value);
+
+        drop(db);
+
+        let db = Database::builder().open(&folder)?;
+        let buckets = db.sum(new, "host").build()?.collect()?;
+        assert_eq!(11.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(
+        clippy::cast_lossless,
+        clippy::float_cmp,
+        clippy::indexing_slicing,
+        clippy::cast_precision_loss
+    )]
+    fn test_retag_merge_points() -> crate::Result<()> {
+        use crate::{Agg, QuantileSketch};
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("latency").unwrap();
+
+        db.write_at(metric_name, 0, 1.0, tagset!("host" => "h1"))?;
+        db.write_at(metric_name, 1, 2.0, tagset!("host" => "h1"))?;
+        db.write_at(metric_name, 1, 4.0, tagset!("host" => "h-1"))?;
+        db.write_at(metric_name, 2, 8.0, tagset!("host" => "h-1"))?;
+
+        let mut sketch = QuantileSketch::default();
+        for x in 1..=99 {
+            sketch.insert(x as Value);
+        }
+        db.write_sketch(metric_name, 3, &sketch, tagset!("host" => "h1"))?;
+        db.write_at(metric_name, 3, 1_000.0, tagset!("host" => "h-1"))?;
+        assert_eq!(6, db.point_count(metric_name));
+
+        assert_eq!(
+            1,
+            db.retag(metric_name, "host:h1", tagset!("host" => "h-1"), &[])?
+        );
+        assert_eq!(1, db.series_count()?);
+
+        // NOTE: Data points at the same timestamp are merged, instead of overwritten
+        assert_eq!(4, db.point_count(metric_name));
+
+        let result = db
+            .aggregate_many(
+                metric_name,
+                "host",
+                &[Agg::Count, Agg::Sum, Agg::Min, Agg::Max, Agg::P99],
+            )
+            .build()?
+            .collect_many()?;
+
+        let aggs = &result["h-1"];
+        let value = |name: &str| aggs[name][0].value;
+
+        assert_eq!(104.0, value("count"));
+        assert_eq!(5_965.0, value("sum"));
+        assert_eq!(1.0, value("min"));
+        assert_eq!(1_000.0, value("max"));
+        assert!((value("p99") - 99.0).abs() <= 0.99);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_retag_concurrent_write() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let writer = db.writer(metric_name, tagset!("host" => "h1"))?;
+        writer.write_at(0, 1.0)?;
+
+        // NOTE: Hold the series lock like a write that looked up the series before retagging,
+        // so the data points are only moved after the write finished
+        let lock = db.lock_series(writer.series_id);
+
+        let retag = std::thread::spawn({
+            let db = db.clone();
+            move || db.retag(metric_name, "host:h1", tagset!("host" => "h-1"), &[])
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        db.insert_data_point(writer.series_id, 1, ValueEncoding::Full.encode(2.0))?;
+        drop(lock);
+
+        assert_eq!(1, retag.join().unwrap()?);
+        assert_eq!(1, db.series_count()?);
+
+        assert!(matches!(
+            writer.write_at(2, 4.0),
+            Err(crate::Error::SeriesRemoved(_))
+        ));
+
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(3.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_retag() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(
+            metric_name,
+            0,
+            1.0,
+            tagset!("env" => "prod", "host" => "h1"),
+        )?;
+        db.write_at(
+            metric_name,
+            1,
+            2.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            2,
+            4.0,
+            tagset!("env" => "prod", "host" => "h-2", "tmp" => "x"),
+        )?;
+        assert_eq!(3, db.series_count()?);
+
+        assert_eq!(
+            1,
+            db.retag(metric_name, "host:h1", tagset!("host" => "h-1"), &[])?
+        );
+        assert_eq!(1, db.retag(metric_name, "*", tagset!(), &["tmp"])?);
+        assert_eq!(0, db.retag(metric_name, "*", tagset!(), &["tmp"])?);
+        assert_eq!(2, db.series_count()?);
+
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(2, buckets.len());
+        assert_eq!(3.0, buckets["h-1"][0].value);
+        assert_eq!(4.0, buckets["h-2"][0].value);
+
+        assert!(db
+            .sum(metric_name, "host")
+            .filter("tmp:x")
+            .build()?
+            .collect()?
+            .is_empty());
+
+        // NOTE: Series IDs of removed series are not reused
+        db.write_at(
+            metric_name,
+            3,
+            8.0,
+            tagset!("env" => "dev", "host" => "h-3"),
+        )?;
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(3, buckets.len());
+        assert_eq!(3.0, buckets["h-1"][0].value);
+        assert_eq!(8.0, buckets["h-3"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_downsample_lttb() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..100 {
+            let value = if ts == 42 { 100.0 } else { 1.0 };
+            db.write_at(metric_name, ts, value, tagset!("host" => "h-1"))?;
+        }
+
+        let buckets = db
+            .max(metric_name, "host")
+            .granularity(1)
+            .downsample_lttb(10)
+            .build()?
+            .collect()?;
+
+        let buckets = &buckets["h-1"];
+        assert_eq!(10, buckets.len());
+        assert!(buckets.iter().any(|bucket| bucket.value == 100.0));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_group_by_mapping() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for (host, value) in [("r1-h01", 1.0), ("r1-h02", 2.0), ("r2-h01", 4.0)] {
+            db.write_at(metric_name, 0, value, tagset!("host" => host))?;
+        }
+
+        let buckets = db
+            .sum(metric_name, "env")
+            .group_by_prefix("host", 2)
+            .build()?
+            .collect()?;
+        assert_eq!(2, buckets.len());
+        assert_eq!(3.0, buckets["r1"][0].value);
+        assert_eq!(4.0, buckets["r2"][0].value);
+
+        let buckets = db
+            .sum(metric_name, "env")
+            .group_by_map("host", |host| {
+                host.ends_with("h01").then(|| String::from("first"))
+            })
+            .build()?
+            .collect()?;
+        assert_eq!(1, buckets.len());
+        assert_eq!(5.0, buckets["first"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_group_by_label() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for (host, value) in [("web-1.eu", 1.0), ("web-2.eu", 2.0), ("db-1.us", 4.0)] {
+            db.write_at(metric_name, 0, value, tagset!("host" => host))?;
+        }
+        db.write_at(metric_name, 0, 8.0, tagset!("env" => "prod"))?;
+
+        let roles = [("web", "frontend"), ("db", "storage")]
+            .into_iter()
+            .collect::<crate::HashMap<_, _>>();
+
+        let buckets = db
+            .sum(metric_name, "host")
+            .missing_tag(crate::MissingTagPolicy::Group)
+            .group_by_label("host", |host| {
+                // NOTE: Strip instance suffix, then map via lookup table
+                let role = host.split('-').next().unwrap_or(host);
+                roles.get(role).map_or(role, |role| *role).to_string()
+            })
+            .build()?
+            .collect()?;
+        assert_eq!(3, buckets.len());
+        assert_eq!(3.0, buckets["frontend"][0].value);
+        assert_eq!(4.0, buckets["storage"][0].value);
+        assert_eq!(8.0, buckets["<none>"][0].value);
+
+        let buckets = db
+            .sum(metric_name, "env")
+            .group_by_prefix("host", 3)
+            .group_by_label("host", str::to_uppercase)
+            .build()?
+            .collect()?;
+
+        // NOTE: The label mapping replaces the prefix mapping
+        assert_eq!(3, buckets.len());
+        assert_eq!(1.0, buckets["WEB-1.EU"][0].value);
+        assert_eq!(4.0, buckets["DB-1.US"][0].value);
+
+        // NOTE: Results of label mappings are not cached
+        let buckets = db
+            .sum(metric_name, "env")
+            .group_by_prefix("host", 3)
+            .build()?
+            .collect()?;
+        assert!(buckets.contains_key("web"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_max_groups() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        // NOTE: Written out of order, with multiple series per group
+        for host in [7, 3, 9, 0, 5, 1, 8, 2, 6, 4] {
+            let host = format!("h-{host}");
+            db.write_at(
+                metric_name,
+                0,
+                1.0,
+                tagset!("host" => host.as_str(), "env" => "prod"),
+            )?;
+            db.write_at(
+                metric_name,
+                0,
+                2.0,
+                tagset!("host" => host.as_str(), "env" => "dev"),
+            )?;
+        }
+
+        let mut pages = vec![];
+        let mut after: Option<String> = None;
+
+        loop {
+            let mut builder = db.sum(metric_name, "host").max_groups(4);
+
+            if let Some(after) = &after {
+                builder = builder.groups_after(after.clone());
+            }
+
+            let page = builder.build()?;
+            after = page.next_page().map(String::from);
+
+            let mut groups = page.collect()?.into_iter().collect::<Vec<_>>();
+            groups.sort_by(|a, b| a.0.cmp(&b.0));
+
+            for (_, buckets) in &groups {
+                assert_eq!(3.0, buckets[0].value);
+            }
+            pages.push(
+                groups
+                    .into_iter()
+                    .map(|(group, _)| group)
+                    .collect::<Vec<_>>(),
+            );
+
+            if after.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(
+            vec![
+                vec!["h-0", "h-1", "h-2", "h-3"],
+                vec!["h-4", "h-5", "h-6", "h-7"],
+                vec!["h-8", "h-9"],
+            ],
+            pages,
+        );
+
+        let page = db.sum(metric_name, "host").max_groups(10).build()?;
+        assert!(page.next_page().is_none());
+        assert_eq!(10, page.len());
+
+        let page = db.sum(metric_name, "host").max_groups(0).build()?;
+        assert_eq!(Some(""), page.next_page());
+        assert!(page.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_point_count() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let cpu = MetricName::try_from("cpu.total").unwrap();
+        let mem = MetricName::try_from("mem.used").unwrap();
+
+        {
+            let db = Database::builder().open(&folder)?;
+
+            for ts in 0..10 {
+                db.write_at(cpu, ts, 1.0, tagset!("host" => "h-1"))?;
+                db.write_at(cpu, ts, 1.0, tagset!("host" => "h-2"))?;
+            }
+
+            let writer = db.writer(mem, tagset!("host" => "h-1"))?;
+            writer.write_at(0, 1.0)?;
+
+            assert_eq!(20, db.point_count(cpu));
+            assert_eq!(1, db.point_count(mem));
+            assert_eq!(0, db.point_count(MetricName::try_from("disk").unwrap()));
+
+            db.write_at(cpu, 100, 1.0, tagset!("host" => "h-3"))?;
+            assert_eq!(3, db.gc_idle_series(50, true)?);
+            assert_eq!(1, db.point_count(cpu));
+        }
+
+        let db = Database::builder().open(&folder)?;
+        assert_eq!(1, db.point_count(cpu));
+        assert_eq!(0, db.point_count(mem));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_gc_idle_series() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 10, 1.0, tagset!("pod" => "a"))?;
+        db.write_at(metric_name, 100, 1.0, tagset!("pod" => "a"))?;
+        db.write_at(metric_name, 10, 2.0, tagset!("pod" => "b"))?;
+        db.write_at(metric_name, 20, 4.0, tagset!("pod" => "c"))?;
+        let _ = db.writer(metric_name, tagset!("pod" => "d"))?;
+        assert_eq!(4, db.series_count()?);
+
+        assert_eq!(3, db.gc_idle_series(50, true)?);
+        assert_eq!(1, db.series_count()?);
+        assert_eq!(0, db.gc_idle_series(50, true)?);
+
+        let buckets = db.sum(metric_name, "pod").build()?.collect()?;
+        assert_eq!(1, buckets.len());
+        assert!(buckets.contains_key("a"));
+
+        // NOTE: Writing to a removed series creates a new, empty series
+        db.write_at(metric_name, 200, 8.0, tagset!("pod" => "b"))?;
+
+        let buckets = db
+            .sum(metric_name, "pod")
+            .filter("pod:b")
+            .build()?
+            .collect()?;
+        assert_eq!(8.0, buckets["b"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_idle_series_writer() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let idle = db.writer(metric_name, tagset!("pod" => "a"))?;
+        let active = db.writer(metric_name, tagset!("pod" => "b"))?;
+        active.write_at(100, 1.0)?;
+
+        assert_eq!(1, db.gc_idle_series(50, true)?);
+
+        assert!(matches!(
+            idle.write_at(200, 1.0),
+            Err(crate::Error::SeriesRemoved(_))
+        ));
+        assert!(matches!(
+            idle.write_stat(
+                200,
+                Stat {
+                    count: 1,
+                    sum: 1.0,
+                    min: 1.0,
+                    max: 1.0,
+                },
+            ),
+            Err(crate::Error::SeriesRemoved(_))
+        ));
+        active.write_at(200, 1.0)?;
+        assert_eq!(1, db.series_count()?);
+
+        // NOTE: Recreating the writer creates the series again
+        db.writer(metric_name, tagset!("pod" => "a"))?
+            .write_at(200, 1.0)?;
+        assert_eq!(2, db.series_count()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_gc_idle_series_concurrent_write() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let writer = db.writer(metric_name, tagset!("pod" => "a"))?;
+        writer.write_at(10, 1.0)?;
+
+        // NOTE: Hold the series lock like a write that looked up the series before the GC run,
+        // so the GC run finds the series idle, but has to wait for the write to finish
+        let lock = db.lock_series(writer.series_id);
+
+        let gc = std::thread::spawn({
+            let db = db.clone();
+            move || db.gc_idle_series(50, true)
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        db.insert_data_point(writer.series_id, 100, ValueEncoding::Full.encode(2.0))?;
+        drop(lock);
+
+        assert_eq!(0, gc.join().unwrap()?);
+        assert_eq!(1, db.series_count()?);
+
+        writer.write_at(200, 4.0)?;
+
+        let buckets = db.sum(metric_name, "pod").build()?.collect()?;
+        assert_eq!(7.0, buckets["a"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_idle_series_corruption() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        db.0.smap.partition.insert("cpu.total#pod:a", [0, 1, 2])?;
+
+        assert!(matches!(
+            db.gc_idle_series(50, true),
+            Err(crate::Error::Corruption { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_idle_series_keep_data() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let stored = |db: &Database, series_id| {
+            Database::series_range(
+                &db.snapshot().hot,
+                series_id,
+                (Bound::Unbounded, Bound::Unbounded),
+            )
+            .count()
+        };
+
+        let series_id = {
+            let db = Database::builder().open(&folder)?;
+
+            for ts in 0..10 {
+                db.write_at(metric_name, ts, 1.0, tagset!("pod" => "a"))?;
+            }
+
+            let series_id =
+                db.0.smap
+                    .get(&SeriesKey::format(metric_name, tagset!("pod" => "a")))?
+                    .unwrap();
+
+            assert_eq!(1, db.gc_idle_series(Timestamp::MAX, false)?);
+            assert_eq!(0, db.series_count()?);
+            assert_eq!(10, stored(&db, series_id));
+            assert_eq!(10, db.point_count(metric_name));
+
+            series_id
+        };
+
+        // NOTE: The kept data points are removed by a later run that removes data, even after reopening
+        let db = Database::builder().open(&folder)?;
+        assert_eq!(0, db.gc_idle_series(Timestamp::MAX, true)?);
+        assert_eq!(0, stored(&db, series_id));
+        assert_eq!(0, db.point_count(metric_name));
+        assert!(db.0.orphans.list()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_disk_space() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().max_disk_space(4_096).open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let mut result = Ok(());
+
+        for ts in 0..10_000 {
+            result = db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"));
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        assert!(matches!(result, Err(crate::Error::QuotaExceeded)));
+
+        let writer = db.writer(metric_name, tagset!("host" => "h-1"))?;
+        assert!(matches!(
+            writer.write_at(10_000, 1.0),
+            Err(crate::Error::QuotaExceeded),
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_observer() -> crate::Result<()> {
+        use crate::{SeriesId, WriteObserver, WriteStats};
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Observer {
+            series: Mutex<Vec<(String, SeriesId)>>,
+            batches: Mutex<Vec<WriteStats>>,
+        }
+
+        impl WriteObserver for Observer {
+            fn on_series_created(&self, metric: MetricName, _: &TagSet, series_id: SeriesId) {
+                self.series
+                    .lock()
+                    .unwrap()
+                    .push((metric.to_string(), series_id));
+            }
+
+            fn on_writes(&self, stats: &WriteStats) {
+                self.batches.lock().unwrap().push(*stats);
+            }
+        }
+
+        let observer = Arc::new(Observer::default());
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .write_observer(observer.clone(), 5)
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..8 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        let writer = db.writer(metric_name, tagset!("host" => "h-2"))?;
+        writer.write_at(0, 1.0)?;
+        writer.write_at(1, 1.0)?;
+
+        assert_eq!(
+            vec![("cpu.total".to_string(), 0), ("cpu.total".to_string(), 1)],
+            *observer.series.lock().unwrap(),
+        );
+
+        let batches = observer.batches.lock().unwrap();
+        assert_eq!(2, batches.len());
+        assert!(batches.iter().all(|stats| stats.writes == 5));
+        drop(batches);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_value_filter() -> crate::Result<()> {
+        use crate::ValueFilter;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("http.latency").unwrap();
+
+        for (ts, value) in [(0, 50.0), (1, 150.0), (2, 100.0), (3, 250.0), (4, 20.0)] {
+            db.write_at(metric_name, ts, value, tagset!("host" => "h-1"))?;
+        }
+
+        let buckets = db
+            .count(metric_name, "host")
+            .value_filter(ValueFilter::Gt(100.0))
+            .build()?
+            .collect()?;
+        assert_eq!(2.0, buckets["h-1"][0].value);
+
+        let buckets = db
+            .sum(metric_name, "host")
+            .value_filter(ValueFilter::Lte(100.0))
+            .build()?
+            .collect()?;
+        assert_eq!(170.0, buckets["h-1"][0].value);
+
+        let buckets = db
+            .count(metric_name, "host")
+            .value_filter(ValueFilter::Gt(1_000.0))
+            .build()?
+            .collect()?;
+        assert!(buckets["h-1"].is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_sample() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("requests").unwrap();
+
+        for ts in 0..10_000 {
+            db.write_at(metric_name, ts, 2.0, tagset!("host" => "h-1"))?;
+        }
+
+        let sampled = db
+            .count(metric_name, "host")
+            .granularity(u128::MAX)
+            .sample(0.1)
+            .build()?
+            .collect()?;
+        let bucket = sampled["h-1"][0];
+
+        // NOTE: ~10% of the data points are read, but the count is extrapolated
+        assert!((800..1_200).contains(&bucket.len));
+        assert!((8_000.0..12_000.0).contains(&bucket.value));
+
+        let sum = db
+            .sum(metric_name, "host")
+            .granularity(u128::MAX)
+            .sample(0.1)
+            .build()?
+            .collect()?;
+        assert!((16_000.0..24_000.0).contains(&sum["h-1"][0].value));
+
+        let avg = db
+            .avg(metric_name, "host")
+            .granularity(u128::MAX)
+            .sample(0.1)
+            .build()?
+            .collect()?;
+        assert_eq!(2.0, avg["h-1"][0].value);
+
+        // NOTE: The sample is deterministic
+        let again = db
+            .count(metric_name, "host")
+            .granularity(u128::MAX)
+            .sample(0.1)
+            .build()?
+            .collect()?;
+        assert_eq!(sampled, again);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_bucket_boundary() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        let buckets = db
+            .count(metric_name, "host")
+            .granularity(4)
+            .build()?
+            .collect()?;
+
+        // NOTE: The first data point of each bucket must not be lost
+        let buckets = &buckets["h-1"];
+        assert_eq!(10, buckets.iter().map(|bucket| bucket.len).sum::<u64>());
+        assert_eq!(9, buckets.first().unwrap().end);
+        assert_eq!(0, buckets.last().unwrap().start);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_align_timezone() -> crate::Result<()> {
+        use crate::Duration;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("orders").unwrap();
+
+        let day = Duration::days(1.0);
+        let hour = Duration::hours(1.0);
+
+        // NOTE: 22:30 & 23:30 UTC on day 1, 00:30 UTC on day 2
+        for ts in [
+            day + 22 * hour + hour / 2,
+            day + 23 * hour + hour / 2,
+            2 * day + hour / 2,
+        ] {
+            db.write_at(metric_name, ts, 1.0, tagset!("shop" => "berlin"))?;
+        }
+
+        let buckets = db
+            .count(metric_name, "shop")
+            .granularity(day)
+            .align_timezone(0)
+            .build()?
+            .collect()?;
+        let buckets = &buckets["berlin"];
+        assert_eq!(2, buckets.len());
+        assert_eq!((2 * day, 3 * day, 1), {
+            let b = buckets[0];
+            (b.start, b.end, b.len)
+        });
+        assert_eq!((day, 2 * day, 2), {
+            let b = buckets[1];
+            (b.start, b.end, b.len)
+        });
+
+        // NOTE: In UTC+1, 23:30 UTC is already the next day
+        let buckets = db
+            .count(metric_name, "shop")
+            .granularity(day)
+            .align_timezone(3_600)
+            .build()?
+            .collect()?;
+        let buckets = &buckets["berlin"];
+        assert_eq!(2, buckets.len());
+        assert_eq!((2 * day - hour, 3 * day - hour, 2), {
+            let b = buckets[0];
+            (b.start, b.end, b.len)
+        });
+        assert_eq!((day - hour, 2 * day - hour, 1), {
+            let b = buckets[1];
+            (b.start, b.end, b.len)
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_granularity_months() -> crate::Result<()> {
+        use crate::Duration;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("orders").unwrap();
+
+        let second = Duration::seconds(1.0);
+        let hour = Duration::hours(1.0);
+
+        let february = 1_706_745_600 * second;
+        let march = 1_709_251_200 * second;
+        let april = 1_711_929_600 * second;
+
+        // NOTE: 23:30 UTC on January 31st, 00:30 UTC on February 1st, 12:00 UTC on March 1st
+        for ts in [february - hour / 2, february + hour / 2, march + 12 * hour] {
+            db.write_at(metric_name, ts, 1.0, tagset!("shop" => "berlin"))?;
+        }
+
+        let query = |tz: i32| -> crate::Result<Vec<(Timestamp, Timestamp, u64)>> {
+            let buckets = db
+                .count(metric_name, "shop")
+                .granularity_months(1)
+                .align_timezone(tz)
+                .build()?
+                .collect()?;
+
+            Ok(buckets
+                .get("berlin")
+                .unwrap()
+                .iter()
+                .map(|b| (b.start, b.end, b.len))
+                .collect())
+        };
+
+        assert_eq!(
+            vec![
+                (march, april, 1),
+                (february, march, 1),
+                (1_704_067_200 * second, february, 1),
+            ],
+            query(0)?,
+        );
+
+        // NOTE: In UTC+1, 23:30 UTC on January 31st is already February
+        assert_eq!(
+            vec![
+                (march - hour, april - hour, 1),
+                (february - hour, march - hour, 2),
+            ],
+            query(3_600)?,
+        );
+
+        // NOTE: Quarters
+        let buckets = db
+            .count(metric_name, "shop")
+            .granularity_months(3)
+            .build()?
+            .collect()?;
+        let buckets = buckets.get("berlin").unwrap();
+        assert_eq!(1, buckets.len());
+        assert_eq!(3, buckets.first().unwrap().len);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "tz")]
+    fn test_align_timezone_daylight_saving_time() -> crate::Result<()> {
+        use crate::Duration;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("orders").unwrap();
+
+        let hour = Duration::hours(1.0);
+        let march_30 = 1_711_756_800 * Duration::seconds(1.0);
+        let march_31 = march_30 + 24 * hour;
+
+        // NOTE: 22:30 UTC on March 30th is 23:30 CET, 22:30 UTC on March 31st is 00:30 CEST on April 1st
+        for ts in [
+            march_30 + 22 * hour + hour / 2,
+            march_31 + 22 * hour + hour / 2,
+        ] {
+            db.write_at(metric_name, ts, 1.0, tagset!("shop" => "berlin"))?;
+        }
+
+        let berlin = jiff::tz::TimeZone::get("Europe/Berlin").unwrap();
+
+        let buckets = db
+            .count(metric_name, "shop")
+            .granularity(Duration::days(1.0))
+            .align_timezone(berlin)
+            .build()?
+            .collect()?;
+
+        // NOTE: March 31st only has 23 hours
+        assert_eq!(
+            vec![
+                (march_31 + 22 * hour, march_31 + 46 * hour, 1),
+                (march_30 - hour, march_31 - hour, 1),
+            ],
+            buckets
+                .get("berlin")
+                .unwrap()
+                .iter()
+                .map(|b| (b.start, b.end, b.len))
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_unicode_tags() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("temperature").unwrap();
+
+        db.write_at(metric_name, 0, 1.0, tagset!("city" => "münchen"))?;
+        db.write_at(metric_name, 0, 2.0, tagset!("city" => "köln"))?;
+        db.write_at(metric_name, 0, 4.0, tagset!("city" => "münster"))?;
+
+        let buckets = db
+            .sum(metric_name, "city")
+            .filter("city:münchen")
+            .build()?
+            .collect()?;
+        assert_eq!(1, buckets.len());
+        assert_eq!(1.0, buckets["münchen"][0].value);
+
+        let buckets = db
+            .sum(metric_name, "city")
+            .filter("city:mün*")
+            .build()?
+            .collect()?;
+        assert_eq!(2, buckets.len());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_filter_compiled() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(
+            metric_name,
+            0,
+            1.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            0,
+            2.0,
+            tagset!("env" => "dev", "host" => "h-2"),
+        )?;
+        db.write_at(
+            metric_name,
+            0,
+            4.0,
+            tagset!("env" => "prod", "host" => "h-3"),
+        )?;
+
+        assert!(matches!(
+            db.parse_filter("env:prod AND"),
+            Err(crate::Error::InvalidQuery),
+        ));
+
+        let filter = db.parse_filter("env:prod")?;
+        assert_eq!("env:prod", filter.as_str());
+
+        for _ in 0..3 {
+            let buckets = db
+                .sum(metric_name, "env")
+                .filter_compiled(&filter)
+                .build()?
+                .collect()?;
+
+            assert_eq!(1, buckets.len());
+            assert_eq!(5.0, buckets["prod"][0].value);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_series_creation_with_first_point() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        {
+            let db = Database::builder().open(&folder)?;
+            db.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+            db.write_stat(
+                metric_name,
+                0,
+                Stat {
+                    count: 2,
+                    sum: 5.0,
+                    min: 2.0,
+                    max: 3.0,
+                },
+                tagset!("host" => "h-2"),
+            )?;
+        }
+
+        let db = Database::builder().open(&folder)?;
+
+        for (series_id, host) in [(0, "h-1"), (1, "h-2")] {
+            assert_eq!(
+                Some(host),
+                db.tag_set(series_id)?.get("host").map(String::as_str),
+            );
+        }
+
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(1.0, buckets["h-1"][0].value);
+        assert_eq!(5.0, buckets["h-2"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_close() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        {
+            let db = Database::builder()
+                .flush_interval(std::time::Duration::from_millis(100))
+                .open(&folder)?;
+            db.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+            db.close()?;
+        }
+
+        {
+            let db = Database::builder().open(&folder)?;
+            db.write_at(metric_name, 1, 2.0, tagset!("host" => "h-1"))?;
+        }
+
+        let db = Database::builder().open(&folder)?;
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(3.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_shared_block_cache() -> crate::Result<()> {
+        let block_cache =
+            std::sync::Arc::new(fjall::BlockCache::with_capacity_bytes(1_024 * 1_024));
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let folder_a = tempfile::tempdir()?;
+        let folder_b = tempfile::tempdir()?;
+
+        let db_a = Database::builder()
+            .block_cache(block_cache.clone())
+            .write_buffer_size_mib(1)
+            .open(&folder_a)?;
+        let db_b = Database::builder()
+            .block_cache(block_cache.clone())
+            .open(&folder_b)?;
+
+        db_a.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+        db_b.write_at(metric_name, 0, 2.0, tagset!("host" => "h-1"))?;
+
+        assert_eq!(
+            1.0,
+            db_a.sum(metric_name, "host").build()?.collect()?["h-1"][0].value
+        );
+        assert_eq!(
+            2.0,
+            db_b.sum(metric_name, "host").build()?.collect()?["h-1"][0].value
+        );
+
+        // NOTE: Both databases use the given cache instead of creating their own
+        assert!(std::sync::Arc::strong_count(&block_cache) >= 3);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_data_block_size() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        // NOTE: Clamped to the minimum block size, instead of panicking
+        let db = Database::builder().data_block_size(1).open(&folder)?;
+
+        for ts in 0..1_000 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+        db.0.data.rotate_memtable_and_wait()?;
+
+        let sum = |db: &Database| -> crate::Result<Value> {
+            Ok(db
+                .sum(metric_name, "host")
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?["h-1"][0]
+                .value)
+        };
+        assert_eq!(1_000.0, sum(&db)?);
+
+        // NOTE: Existing databases keep their block size
+        drop(db);
+        let db = Database::builder()
+            .data_block_size(u32::MAX)
+            .open(&folder)?;
+        assert_eq!(1_000.0, sum(&db)?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_cold_tier() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let cold_folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let open = || {
+            Database::builder()
+                .cold_tier(&cold_folder, std::time::Duration::from_secs(3_600))
+                .open(&folder)
+        };
+
+        let sum = |db: &Database| -> crate::Result<Vec<(Timestamp, Value)>> {
+            let buckets = db
+                .sum(metric_name, "host")
+                .granularity(10)
+                .end(100)
+                .build()?
+                .collect()?;
+            Ok(buckets["h-1"].iter().map(|b| (b.start, b.value)).collect())
+        };
+
+        {
+            let db = open()?;
+
+            for ts in 0..20 {
+                db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            }
+            db.write(metric_name, 1.0, tagset!("host" => "h-1"))?;
+
+            assert_eq!(20, db.move_to_cold_tier()?);
+            assert_eq!(0, db.move_to_cold_tier()?);
+            assert_eq!(20, db.snapshot().cold.as_ref().unwrap().len()?);
+            assert_eq!(1, db.snapshot().hot.len()?);
+
+            // NOTE: Late data points end up in the hot tier, until they are moved as well,
+            // and overwrite data points with the same timestamp in the cold tier
+            db.write_at(metric_name, 15, 2.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, 25, 1.0, tagset!("host" => "h-1"))?;
+            assert_eq!(vec![(15, 7.0), (4, 11.0), (0, 4.0)], sum(&db)?);
+
+            assert_eq!(2, db.move_to_cold_tier()?);
+            assert_eq!(vec![(15, 7.0), (4, 11.0), (0, 4.0)], sum(&db)?);
+        }
+
+        {
+            let db = open()?;
+            assert_eq!(vec![(15, 7.0), (4, 11.0), (0, 4.0)], sum(&db)?);
+
+            // NOTE: Data points of removed series are deleted from both tiers
+            assert_eq!(1, db.gc_idle_series(Timestamp::MAX, true)?);
+            assert_eq!(0, db.snapshot().cold.as_ref().unwrap().len()?);
+            assert_eq!(0, db.snapshot().hot.len()?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_cold_tier_storage() -> crate::Result<()> {
+        use crate::{MemoryStorage, Storage, StoragePartition};
+        use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+        /// Fails to persist while `fail` is set
+        #[derive(Default)]
+        struct FailingStorage {
+            inner: MemoryStorage,
+            fail: AtomicBool,
+        }
+
+        impl Storage for FailingStorage {
+            fn open_partition(&self, name: &str) -> crate::Result<Arc<dyn StoragePartition>> {
+                self.inner.open_partition(name)
+            }
+
+            fn persist(&self) -> crate::Result<()> {
+                if self.fail.load(Relaxed) {
+                    return Err(crate::Error::Io(std::io::Error::other("injected")));
+                }
+                self.inner.persist()
+            }
+        }
+
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let storage = Arc::new(FailingStorage::default());
+        let db = Database::builder()
+            .cold_tier_storage(storage.clone(), std::time::Duration::from_secs(3_600))
+            .open(&folder)?;
+
+        for ts in 0..20 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        let sum = || -> crate::Result<Value> {
+            let buckets = db
+                .sum(metric_name, "host")
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?;
+            Ok(buckets["h-1"].iter().map(|b| b.value).sum())
+        };
+
+        // NOTE: Data points are only removed from the hot tier once the cold tier is persisted
+        storage.fail.store(true, Relaxed);
+        assert!(db.move_to_cold_tier().is_err());
+        assert_eq!(20, db.snapshot().hot.len()?);
+        assert_eq!(20.0, sum()?);
+
+        storage.fail.store(false, Relaxed);
+        assert_eq!(20, db.move_to_cold_tier()?);
+        assert!(db.snapshot().hot.is_empty()?);
+        assert_eq!(20, db.snapshot().cold.as_ref().unwrap().len()?);
+        assert_eq!(20.0, sum()?);
+
+        assert_eq!(1, db.gc_idle_series(Timestamp::MAX, true)?);
+        assert!(db.snapshot().cold.as_ref().unwrap().is_empty()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "arrow")]
+    fn test_collect_to_arrow() -> crate::Result<()> {
+        use arrow_array::{cast::AsArray, types::Float64Type, types::Int32Type};
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..20 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 2.0, tagset!("host" => "h-2"))?;
+        }
+
+        let batch = db
+            .sum(metric_name, "host")
+            .granularity(10)
+            .build()?
+            .collect_to_arrow()?;
+        assert_eq!(4, batch.num_rows());
+
+        let groups = batch.column(0).as_dictionary::<Int32Type>();
+        assert_eq!(2, groups.values().len());
+        assert_eq!(vec![0, 0, 1, 1], groups.keys().values().to_vec());
+
+        assert_eq!(
+            vec![11.0, 9.0, 22.0, 18.0],
+            batch
+                .column(3)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec(),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    #[allow(clippy::indexing_slicing)]
+    fn test_parquet() -> crate::Result<()> {
+        use arrow_array::{cast::AsArray, types::Float64Type, types::UInt64Type};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let read = |path: &std::path::Path| {
+            let file = std::fs::File::open(path).unwrap();
+            ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..20 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 2.0, tagset!("host" => "h-2"))?;
+        }
+
+        let path = folder.path().join("result.parquet");
+        let rows = db
+            .sum(metric_name, "host")
+            .granularity(10)
+            .build()?
+            .collect_to_parquet(&path)?;
+        assert_eq!(4, rows);
+
+        let batches = read(&path);
+        assert_eq!(1, batches.len());
+
+        let batch = &batches[0];
+        assert_eq!(
+            vec!["group", "start", "end", "value", "count"],
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().as_str())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            vec![11.0, 9.0, 22.0, 18.0],
+            batch
+                .column(3)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec(),
+        );
+        assert_eq!(
+            vec![11, 9, 11, 9],
+            batch
+                .column(4)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+        );
+
+        let path = folder.path().join("raw.parquet");
+        assert_eq!(40, db.export_parquet(&path)?);
+
+        let rows = read(&path)
+            .iter()
+            .map(arrow_array::RecordBatch::num_rows)
+            .sum::<usize>();
+        assert_eq!(40, rows);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_archive() -> crate::Result<()> {
+        use crate::ArchiveSink;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Sink(Mutex<Vec<(String, Vec<u8>)>>);
+
+        impl ArchiveSink for Sink {
+            fn put(&self, name: &str, chunk: &[u8]) -> crate::Result<()> {
+                self.0.lock().unwrap().push((name.into(), chunk.into()));
+                Ok(())
+            }
+        }
+
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, ts as Value, tagset!("host" => "h-1"))?;
+            db.write_at(
+                metric_name,
+                ts,
+                1.0,
+                tagset!("host" => "h-2", "env" => "prod"),
+            )?;
+        }
+        db.write_stat(
+            metric_name,
+            5,
+            Stat {
+                count: 2,
+                sum: 10.0,
+                min: 4.0,
+                max: 6.0,
+            },
+            tagset!("host" => "h-3"),
+        )?;
+
+        let sink = Sink::default();
+        assert_eq!(0, db.archive(100, 200, &sink, true)?);
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        assert_eq!(10, db.archive(0, 5, &sink, false)?);
+        assert_eq!(11, db.archive(5, 10, &sink, true)?);
+        assert_eq!(2, sink.0.lock().unwrap().len());
+
+        // NOTE: Archived data points were removed
+        let buckets = db
+            .sum(metric_name, "host")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(10.0, buckets["h-1"][0].value);
+        assert!(buckets["h-3"].is_empty());
+        assert_eq!(10, db.point_count(metric_name));
+
+        let restored_folder = tempfile::tempdir()?;
+        let restored = Database::builder().open(&restored_folder)?;
+
+        for (name, chunk) in sink.0.lock().unwrap().iter() {
+            assert_eq!(
+                Some("talna"),
+                std::path::Path::new(name)
+                    .extension()
+                    .and_then(|ext| ext.to_str()),
+            );
+            restored.restore_archive(chunk)?;
+        }
+
+        let buckets = restored
+            .sum(metric_name, "host")
+            .filter("env:prod OR host:h-1 OR host:h-3")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(45.0, buckets["h-1"][0].value);
+        assert_eq!(10.0, buckets["h-2"][0].value);
+        assert_eq!(10.0, buckets["h-3"][0].value);
+
+        // NOTE: Pre-aggregated samples are restored as is
+        let buckets = restored.count(metric_name, "host").build()?.collect()?;
+        assert_eq!(2.0, buckets["h-3"][0].value);
+
+        assert!(restored.restore_archive(b"garbage").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_archive_skips_deleted() -> crate::Result<()> {
+        use crate::ArchiveSink;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Sink(Mutex<Vec<Vec<u8>>>);
+
+        impl ArchiveSink for Sink {
+            fn put(&self, _: &str, chunk: &[u8]) -> crate::Result<()> {
+                self.0.lock().unwrap().push(chunk.into());
+                Ok(())
+            }
+        }
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..20 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+        db.delete(metric_name, "*", 0, 9)?;
+
+        let sink = Sink::default();
+        assert_eq!(10, db.archive(0, 100, &sink, true)?);
+
+        // NOTE: Deleted data points stay until their tombstone is compacted
+        assert_eq!(10, db.point_count(metric_name));
+        assert_eq!(10, db.compact_tombstones(Timestamp::MAX)?);
+
+        let restored_folder = tempfile::tempdir()?;
+        let restored = Database::builder().open(&restored_folder)?;
+        assert_eq!(10, restored.restore_archive(&sink.0.lock().unwrap()[0])?);
+
+        let buckets = restored
+            .count(metric_name, "host")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(10.0, buckets["h-1"][0].value);
+        assert_eq!(10, buckets["h-1"][0].start);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_archive_keeps_concurrent_overwrite() -> crate::Result<()> {
+        use crate::ArchiveSink;
+
+        /// Overwrites an archived data point while the chunk is stored
+        struct Sink<'a>(&'a Database, MetricName<'a>);
+
+        impl ArchiveSink for Sink<'_> {
+            fn put(&self, _: &str, _: &[u8]) -> crate::Result<()> {
+                self.0.write_at(self.1, 5, 100.0, tagset!("host" => "h-1"))
+            }
+        }
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        assert_eq!(10, db.archive(0, 100, &Sink(&db, metric_name), true)?);
+
+        let buckets = db
+            .sum(metric_name, "host")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(100.0, buckets["h-1"][0].value);
+        assert_eq!(1, buckets["h-1"][0].len);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_timestamp_bounds() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .timestamp_bounds(100, 200)
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 100, 1.0, tagset!("host" => "h-1"))?;
+        db.write_at(metric_name, 200, 1.0, tagset!("host" => "h-1"))?;
+
+        for ts in [0, 99, 201, Timestamp::MAX] {
+            assert!(matches!(
+                db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1")),
+                Err(crate::Error::TimestampOutOfRange(x)) if x == ts,
+            ));
+        }
+
+        let writer = db.writer(metric_name, tagset!("host" => "h-2"))?;
+        assert!(matches!(
+            writer.write_at(300, 1.0),
+            Err(crate::Error::TimestampOutOfRange(300)),
+        ));
+
+        let h1: &TagSet = tagset!("host" => "h-1");
+        assert!(matches!(
+            db.ingest_sorted([(metric_name, h1, 150, 1.0), (metric_name, h1, 50, 1.0)]),
+            Err(crate::Error::TimestampOutOfRange(50)),
+        ));
+
+        let buckets = db.count(metric_name, "host").build()?.collect()?;
+        assert_eq!(2.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_max_clock_skew() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .max_clock_skew(std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        // NOTE: Historical backfill is allowed
+        db.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+        db.write(metric_name, 1.0, tagset!("host" => "h-1"))?;
+        db.write_at(
+            metric_name,
+            timestamp() + 30 * 1_000_000_000,
+            1.0,
+            tagset!("host" => "h-1"),
+        )?;
+
+        // NOTE: Year ~30000
+        let far_future = 900_000_000_000_000_000_000;
+        assert!(matches!(
+            db.write_at(metric_name, far_future, 1.0, tagset!("host" => "h-1")),
+            Err(crate::Error::TimestampOutOfRange(_)),
+        ));
+
+        let buckets = db
+            .count(metric_name, "host")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(3.0, buckets["h-1"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_partition_prefix() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&folder).open_transactional()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let db_a = Database::builder()
+            .partition_prefix("metrics_a")
+            .open_in_keyspace(keyspace.clone())?;
+        let db_b = Database::builder()
+            .partition_prefix("metrics_b")
+            .open_in_keyspace(keyspace.clone())?;
+
+        db_a.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+        db_b.write_at(metric_name, 0, 2.0, tagset!("host" => "h-2"))?;
+        db_b.write_at(metric_name, 1, 3.0, tagset!("host" => "h-2"))?;
+
+        let buckets = db_a.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(1, buckets.len());
+        assert_eq!(1.0, buckets["h-1"][0].value);
+
+        let buckets = db_b.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(1, buckets.len());
+        assert_eq!(5.0, buckets["h-2"][0].value);
+
+        assert!(keyspace.partition_exists("_talna#metrics_a#v1#data"));
+        assert!(keyspace.partition_exists("_talna#metrics_b#v1#data"));
+        assert!(!keyspace.partition_exists("_talna#v1#data"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_ingest_sorted() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let cpu = MetricName::try_from("cpu.total").unwrap();
+        let mem = MetricName::try_from("mem.used").unwrap();
+
+        let h1: &TagSet = tagset!("host" => "h-1");
+        let h2: &TagSet = tagset!("host" => "h-2");
+
+        // NOTE: Exceeds the batch size
+        let points = (0..15_000)
+            .map(|ts| (cpu, h1, ts, 1.0))
+            .chain((0..100).map(|ts| (cpu, h2, ts, ts as Value)))
+            .chain((0..10).map(|ts| (mem, h1, ts, 2.0)))
+            // NOTE: Unsorted input still ends up in the right series
+            .chain([(cpu, h1, 20_000, 1.0)]);
+
+        assert_eq!(15_111, db.ingest_sorted(points)?);
+
+        let buckets = db.sum(cpu, "host").build()?.collect()?;
+        assert_eq!(15_001.0, buckets["h-1"][0].value);
+        assert_eq!(4_950.0, buckets["h-2"][0].value);
+
+        let buckets = db.count(mem, "host").build()?.collect()?;
+        assert_eq!(10.0, buckets["h-1"][0].value);
+
+        assert_eq!(15_101, db.point_count(cpu));
+        assert_eq!(10, db.point_count(mem));
+        assert_eq!(3, db.series_count()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_ingest_sorted_error() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .timestamp_bounds(0, 20_000)
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+        let tags: &TagSet = tagset!("host" => "h-1");
+
+        let count = || -> crate::Result<Value> {
+            Ok(db
+                .count(metric_name, "host")
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?
+                .get("h-1")
+                .map_or(0.0, |buckets| buckets[0].value))
+        };
+        assert_eq!(0.0, count()?);
+
+        // NOTE: The first batch is committed before the out-of-range timestamp fails
+        let points = (0..15_000).map(|ts| (metric_name, tags, ts, 1.0)).chain([(
+            metric_name,
+            tags,
+            30_000,
+            1.0,
+        )]);
+        assert!(matches!(
+            db.ingest_sorted(points),
+            Err(crate::Error::TimestampOutOfRange(..))
+        ));
+
+        assert_eq!(10_000, db.point_count(metric_name));
+        assert_eq!(10_000.0, count()?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "derive")]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_write_struct() -> crate::Result<()> {
+        use crate::Metric;
+
+        #[derive(Metric)]
+        #[metric(prefix = "disk")]
+        struct DiskSample<'a> {
+            #[metric(tag)]
+            host: String,
+
+            #[metric(tag, name = "mount")]
+            mount_point: &'a str,
+
+            used: u64,
+
+            #[metric(name = "free_bytes")]
+            free: f64,
+
+            #[metric(skip)]
+            #[allow(dead_code)]
+            sampled_by: String,
+        }
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let sample = DiskSample {
+            host: "h-1".into(),
+            mount_point: "root",
+            used: 10,
+            free: 5.0,
+            sampled_by: "agent".into(),
+        };
+        db.write_struct(0, &sample)?;
+
+        let used = MetricName::try_from("disk.used").unwrap();
+        let buckets = db
+            .sum(used, "mount")
+            .filter("host:h-1")
+            .build()?
+            .collect()?;
+        assert_eq!(10.0, buckets["root"][0].value);
+
+        let free = MetricName::try_from("disk.free_bytes").unwrap();
+        let buckets = db.sum(free, "host").build()?.collect()?;
+        assert_eq!(5.0, buckets["h-1"][0].value);
+
+        assert_eq!(
+            vec!["disk.free_bytes", "disk.used"],
+            db.list_metrics(MetricGlob::try_from("disk.*").unwrap())?,
+        );
+
+        // NOTE: If any value is rejected, no value of the struct is written,
+        // even though the value of `disk.used` comes before the rejected value
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .schema_policy(crate::SchemaPolicy::Error)
+            .open(&folder)?;
+        db.declare_metric(free, &["host", "mount", "region"]);
+
+        assert!(matches!(
+            db.write_struct(0, &sample),
+            Err(crate::Error::SchemaViolation { .. })
+        ));
+        assert_eq!(0, db.series_count()?);
+        assert_eq!(0, db.point_count(used));
+        assert!(db
+            .sum(used, "host")
+            .build()?
+            .collect()?
+            .values()
+            .all(Vec::is_empty));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_summary() -> crate::Result<()> {
+        use crate::SummaryBucket;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 0, 4.0, tagset!("host" => "h-1"))?;
+        db.write_at(metric_name, 1, 2.0, tagset!("host" => "h-1"))?;
+        db.write_stat(
+            metric_name,
+            2,
+            Stat {
+                count: 2,
+                sum: 10.0,
+                min: 1.0,
+                max: 9.0,
+            },
+            tagset!("host" => "h-1"),
+        )?;
+        db.write_at(metric_name, 100, 7.0, tagset!("host" => "h-1"))?;
+
+        let summary = db
+            .summary(metric_name, "host")
+            .granularity(10)
+            .build()?
+            .collect_summary()?;
+
+        let buckets = &summary["h-1"];
+        assert_eq!(2, buckets.len());
+
+        assert_eq!(
+            SummaryBucket {
+                start: 100,
+                end: 100,
+                min: 7.0,
+                max: 7.0,
+                sum: 7.0,
+                count: 1,
+                avg: 7.0,
+            },
+            buckets[0],
+        );
+        assert_eq!(
+            SummaryBucket {
+                start: 0,
+                end: 2,
+                min: 1.0,
+                max: 9.0,
+                sum: 16.0,
+                count: 4,
+                avg: 4.0,
+            },
+            buckets[1],
+        );
+
+        // NOTE: `collect` returns the averages
+        let buckets = db
+            .summary(metric_name, "host")
+            .granularity(10)
+            .build()?
+            .collect()?;
+        assert_eq!(
+            vec![7.0, 4.0],
+            buckets["h-1"].iter().map(|b| b.value).collect::<Vec<_>>()
+        );
+
+        // NOTE: The sum is offset once per data point
+        let summary = db
+            .summary(metric_name, "host")
+            .granularity(10)
+            .scale(2.0)
+            .offset(1.0)
+            .build()?
+            .collect_summary()?;
+        let bucket = summary["h-1"][1];
+        assert_eq!(
+            (3.0, 19.0, 36.0, 9.0),
+            (bucket.min, bucket.max, bucket.sum, bucket.avg)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_aggregate_many() -> crate::Result<()> {
+        use crate::Agg;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..4 {
+            db.write_at(metric_name, ts, ts as Value, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, 100 + ts, 10.0, tagset!("host" => "h-1"))?;
+        }
+
+        let result = db
+            .aggregate_many(metric_name, "host", &[Agg::Count, Agg::Sum, Agg::P50])
+            .granularity(10)
+            .build()?
+            .collect_many()?;
+
+        let aggs = &result["h-1"];
+        assert_eq!(3, aggs.len());
+
+        let values = |name: &str| aggs[name].iter().map(|b| b.value).collect::<Vec<_>>();
+        assert_eq!(vec![4.0, 4.0], values("count"));
+        assert_eq!(vec![40.0, 6.0], values("sum"));
+        assert_eq!(vec![10.0, 1.0], values("p50"));
+
+        // NOTE: All aggregations share the same buckets
+        for buckets in aggs.values() {
+            assert_eq!(
+                vec![(100, 103), (0, 3)],
+                buckets.iter().map(|b| (b.start, b.end)).collect::<Vec<_>>()
+            );
+        }
+
+        // NOTE: `collect` returns the first aggregation
+        let buckets = db
+            .aggregate_many(metric_name, "host", &[Agg::Count, Agg::Sum])
+            .granularity(10)
+            .build()?
+            .collect()?;
+        assert_eq!(4.0, buckets["h-1"][0].value);
+
+        assert_eq!("p99.9", Agg::Quantile(0.999).name());
+        assert_eq!("p95", Agg::P95.name());
+
+        Ok(())
+    }
+
+    #[test]
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_lossless,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_write_sketch() -> crate::Result<()> {
+        use crate::{Agg, QuantileSketch};
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("latency").unwrap();
+
+        // NOTE: Two rolled-up buckets, the second one contains the outliers
+        let mut a = QuantileSketch::default();
+        let mut b = QuantileSketch::default();
+
+        for x in 1..=95 {
+            a.insert(x as Value);
+        }
+        for x in 0..5 {
+            b.insert(1_000.0 + x as Value);
+        }
+
+        db.write_sketch(metric_name, 0, &a, tagset!("host" => "h-1"))?;
+        db.write_sketch(metric_name, 1, &b, tagset!("host" => "h-1"))?;
+        db.write_sketch(
+            metric_name,
+            2,
+            &QuantileSketch::default(),
+            tagset!("host" => "h-1"),
+        )?;
+
+        let result = db
+            .aggregate_many(
+                metric_name,
+                "host",
+                &[Agg::Count, Agg::Max, Agg::P50, Agg::P99],
+            )
+            .build()?
+            .collect_many()?;
+
+        let aggs = &result["h-1"];
+        let value = |name: &str| aggs[name][0].value;
+
+        // NOTE: Empty sketches are not written
+        assert_eq!(100.0, value("count"));
+        assert_eq!(1_004.0, value("max"));
+        assert!((value("p50") - 50.0).abs() <= 0.5);
+        assert!((value("p99") - 1_003.0).abs() <= 10.03);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_bucket_sum() -> crate::Result<()> {
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let folder_a = tempfile::tempdir()?;
+        let db_a = Database::builder().open(&folder_a)?;
+        db_a.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+        db_a.write_at(metric_name, 1, 2.0, tagset!("host" => "h-1"))?;
+        db_a.write_stat(
+            metric_name,
+            2,
+            Stat {
+                count: 3,
+                sum: 9.0,
+                min: 2.0,
+                max: 4.0,
+            },
+            tagset!("host" => "h-1"),
+        )?;
+
+        let folder_b = tempfile::tempdir()?;
+        let db_b = Database::builder().open(&folder_b)?;
+        db_b.write_at(metric_name, 0, 10.0, tagset!("host" => "h-1"))?;
+
+        let a = db_a
+            .avg(metric_name, "host")
+            .scale(2.0)
+            .build()?
+            .collect()?["h-1"][0];
+        let b = db_b
+            .avg(metric_name, "host")
+            .scale(2.0)
+            .build()?
+            .collect()?["h-1"][0];
+
+        assert_eq!((5, 12.0, 4.8), (a.len, a.sum, a.value));
+        assert_eq!((1, 10.0, 20.0), (b.len, b.sum, b.value));
+
+        // NOTE: Averages of merged buckets need the raw sums & counts
+        let merged_avg = (a.sum + b.sum) / Value::from(u16::try_from(a.len + b.len).unwrap());
+        assert!((merged_avg - 22.0 / 6.0).abs() < 0.001);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_collect_with_metadata() -> crate::Result<()> {
+        use crate::GroupMetadata;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(
+            metric_name,
+            10,
+            1.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            20,
+            2.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            15,
+            3.0,
+            tagset!("env" => "prod", "host" => "h-2"),
+        )?;
+        db.write_at(
+            metric_name,
+            30,
+            4.0,
+            tagset!("env" => "dev", "host" => "h-3"),
+        )?;
+
+        let result = db
+            .avg(metric_name, "env")
+            .build()?
+            .collect_with_metadata()?;
+
+        assert_eq!(2, result.len());
+        assert_eq!(
+            GroupMetadata {
+                series_count: 2,
+                points_scanned: 3,
+                start: Some(10),
+                end: Some(20),
+                truncated: false,
+            },
+            result["prod"].1,
+        );
+        assert_eq!(
+            GroupMetadata {
+                series_count: 1,
+                points_scanned: 1,
+                start: Some(30),
+                end: Some(30),
+                truncated: false,
+            },
+            result["dev"].1,
+        );
+        assert_eq!(1, result["dev"].0.len());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_having() -> crate::Result<()> {
+        use crate::Agg;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, 10.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 60.0, tagset!("host" => "h-2"))?;
+            db.write_at(metric_name, ts, 90.0, tagset!("host" => "h-3"))?;
+        }
+
+        let busy = |buckets: &[crate::Bucket]| {
+            let sum = buckets.iter().map(|bucket| bucket.sum).sum::<Value>();
+            let len = buckets.iter().map(|bucket| bucket.len).sum::<u64>();
+
+            #[allow(clippy::cast_precision_loss)]
+            let avg = sum / len as Value;
+            avg > 50.0
+        };
+
+        let mut hosts = db
+            .avg(metric_name, "host")
+            .granularity(2)
+            .having(busy)
+            .build()?
+            .collect()?
+            .into_keys()
+            .collect::<Vec<_>>();
+        hosts.sort();
+        assert_eq!(vec!["h-2", "h-3"], hosts);
+
+        // NOTE: The predicate is not part of the cache key, so it must bypass the cache
+        assert_eq!(
+            3,
+            db.avg(metric_name, "host")
+                .granularity(2)
+                .build()?
+                .collect()?
+                .len()
+        );
+
+        let result = db
+            .aggregate_many(metric_name, "host", &[Agg::Max, Agg::Count])
+            .having(|buckets| buckets.iter().any(|bucket| bucket.value > 80.0))
+            .build()?
+            .collect_many()?;
+        assert_eq!(1, result.len());
+        assert_eq!(10.0, result["h-3"]["count"][0].value);
+
+        let result = db
+            .summary(metric_name, "host")
+            .having(busy)
+            .build()?
+            .collect_summary()?;
+        assert_eq!(2, result.len());
+
+        #[cfg(feature = "rayon")]
+        assert_eq!(
+            2,
+            db.avg(metric_name, "host")
+                .having(busy)
+                .collect_parallel()?
+                .len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_max_scanned_points() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .max_scanned_points(150)
+            .query_cache(16, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..100 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-2"))?;
+        }
+
+        let result = db
+            .count(metric_name, "host")
+            .granularity(10)
+            .build()?
+            .collect_with_metadata()?;
+
+        let scanned = result
+            .values()
+            .map(|(_, metadata)| metadata.points_scanned)
+            .sum::<u64>();
+        assert_eq!(150, scanned);
+
+        let truncated = result
+            .values()
+            .filter(|(_, metadata)| metadata.truncated)
+            .collect::<Vec<_>>();
+        assert_eq!(1, truncated.len());
+
+        // NOTE: The newest data points are scanned first
+        let (buckets, metadata) = truncated[0];
+        assert_eq!(50, metadata.points_scanned);
+        assert_eq!(Some(50), metadata.start);
+        assert_eq!(50, buckets.iter().map(|bucket| bucket.len).sum::<u64>());
+
+        // NOTE: Partial results are not cached
+        let count =
+            |builder: crate::AggregationBuilder<'_, crate::agg::Count>| -> crate::Result<u64> {
+                Ok(builder
+                    .granularity(Timestamp::MAX)
+                    .build()?
+                    .collect()?
+                    .values()
+                    .flatten()
+                    .map(|bucket| bucket.len)
+                    .sum())
+            };
+        assert_eq!(150, count(db.count(metric_name, "host"))?);
+        assert_eq!(
+            200,
+            count(db.count(metric_name, "host").max_scanned_points(u64::MAX))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_missing_tag_policy() -> crate::Result<()> {
+        use crate::MissingTagPolicy;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 0, 1.0, tagset!("host" => "h-1"))?;
+        db.write_at(metric_name, 0, 2.0, tagset!("env" => "prod"))?;
+
+        let buckets = db.sum(metric_name, "host").build()?.collect()?;
+        assert_eq!(1, buckets.len());
+
+        let buckets = db
+            .sum(metric_name, "host")
+            .missing_tag(MissingTagPolicy::Group)
+            .build()?
+            .collect()?;
+        assert_eq!(2, buckets.len());
+        assert_eq!(2.0, buckets["<none>"][0].value);
+
+        assert!(matches!(
+            db.sum(metric_name, "host")
+                .missing_tag(MissingTagPolicy::Error)
+                .build(),
+            Err(crate::Error::MissingTag(tag)) if tag == "host",
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        fn assert_send<T: Send>() {}
+
+        assert_send_sync::<Database>();
+        assert_send_sync::<crate::AggregationBuilder<'_, crate::agg::Average>>();
+        assert_send_sync::<crate::Bucket>();
+        assert_send_sync::<crate::Stat>();
+        assert_send_sync::<StreamItem>();
+        assert_send_sync::<SeriesWriter>();
+
+        assert_send::<SeriesReader>();
+        assert_send::<crate::SeriesStream>();
+        assert_send::<
+            crate::GroupedAggregation<'_, crate::agg::Average, crate::Merger<SeriesReader>>,
+        >();
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_grouped_aggregation_send() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        // NOTE: More data points than fit into a single chunk of a series scan
+        for ts in 0..1_000 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 2.0, tagset!("host" => "h-2"))?;
+        }
+
+        let aggregation = db.count(metric_name, "host").build()?;
+
+        let result =
+            std::thread::scope(|scope| scope.spawn(move || aggregation.collect()).join().unwrap())?;
+
+        assert_eq!(1_000.0, result["h-1"][0].value);
+        assert_eq!(1_000.0, result["h-2"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_builder_send() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        db.write_at(metric_name, 0, 4.0, tagset!("host" => "h-1"))?;
+        db.write_at(metric_name, 1, 6.0, tagset!("host" => "h-2"))?;
+
+        let builders = vec![
+            db.sum(metric_name, "host").filter("host:h-1"),
+            db.sum(metric_name, "host").filter("host:h-2"),
+        ];
+
+        let results = std::thread::scope(|scope| {
+            builders
+                .into_iter()
+                .map(|builder| scope.spawn(move || builder.build()?.collect()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<crate::Result<Vec<_>>>()
+        })?;
+
+        assert_eq!(4.0, results[0]["h-1"][0].value);
+        assert_eq!(6.0, results[1]["h-2"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_with_tag() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let cpu = MetricName::try_from("cpu.total").unwrap();
+        let mem = MetricName::try_from("mem.used").unwrap();
+        let renamed = MetricName::try_from("memory.used").unwrap();
+
+        db.write_at(cpu, 10, 1.0, tagset!("host" => "h-1", "env" => "prod"))?;
+        db.write_at(mem, 0, 1.0, tagset!("host" => "h-1"))?;
+        db.write_at(mem, 10, 1.0, tagset!("host" => "h-2", "env" => "prod"))?;
+
+        assert_eq!(
+            vec!["cpu.total", "mem.used"],
+            db.metrics_with_tag("host", "h-1")?
+        );
+        assert_eq!(vec!["mem.used"], db.metrics_with_tag("host", "h-2")?);
+        assert!(db.metrics_with_tag("env", "dev")?.is_empty());
+
+        // NOTE: Aliases include the series of the metrics they alias
+        db.alias_metric(mem, renamed)?;
+        assert_eq!(
+            vec!["cpu.total", "mem.used", "memory.used"],
+            db.metrics_with_tag("host", "h-1")?
+        );
+
+        // NOTE: Removed series are unindexed
+        assert_eq!(1, db.gc_idle_series(5, true)?);
+        assert_eq!(vec!["cpu.total"], db.metrics_with_tag("host", "h-1")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_metric() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let latency = MetricName::try_from("http.latency").unwrap();
+        let renamed = MetricName::try_from("http.duration").unwrap();
+
+        {
+            let db = Database::builder().open(&folder)?;
+            assert_eq!(None, db.metric_metadata(latency));
+
+            db.describe_metric(latency, "us", "Latency")?;
+            db.describe_metric(latency, "ms", "Time until the response is sent")?;
+            db.alias_metric(latency, renamed)?;
+        }
+
+        {
+            let db = Database::builder().open(&folder)?;
+
+            let expected = crate::MetricMetadata {
+                unit: "ms".into(),
+                description: "Time until the response is sent".into(),
+            };
+            assert_eq!(Some(&expected), db.metric_metadata(latency).as_ref());
+
+            // NOTE: Renamed metrics inherit the metadata, until it is overridden
+            assert_eq!(Some(expected), db.metric_metadata(renamed));
+
+            db.describe_metric(renamed, "s", "")?;
+            assert_eq!("s", db.metric_metadata(renamed).unwrap().unit);
+            assert_eq!("ms", db.metric_metadata(latency).unwrap().unit);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_pre_aggregate() -> crate::Result<()> {
+        use crate::Agg;
+
+        const SECOND: Timestamp = 1_000_000_000;
+
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+        let raw_metric = MetricName::try_from("cpu.raw").unwrap();
+
+        let db = Database::builder()
+            .pre_aggregate(metric_name, std::time::Duration::from_secs(1))
+            .open(&folder)?;
+
+        // NOTE: 100 samples per second over 3 seconds
+        for i in 0..300 {
+            #[allow(clippy::cast_precision_loss)]
+            let value = (i % 100) as Value;
+            db.write_at(
+                metric_name,
+                i * SECOND / 100,
+                value,
+                tagset!("host" => "h-1"),
+            )?;
+            db.write_at(
+                raw_metric,
+                i * SECOND / 100,
+                value,
+                tagset!("host" => "h-1"),
+            )?;
+        }
+
+        // NOTE: Late samples are written directly
+        db.write_at(metric_name, SECOND / 2, 1_000.0, tagset!("host" => "h-1"))?;
+
+        // NOTE: The last window is only written when flushing
+        assert_eq!(3, db.point_count(metric_name));
+        assert_eq!(300, db.point_count(raw_metric));
+
+        db.flush(false)?;
+        assert_eq!(4, db.point_count(metric_name));
+
+        let results = db
+            .aggregate_many(
+                metric_name,
+                "host",
+                &[Agg::Count, Agg::Sum, Agg::Min, Agg::Max, Agg::Avg],
+            )
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect_many()?;
+
+        let value = |agg: &str| results["h-1"][agg][0].value;
+
+        // NOTE: Aggregations over the pre-aggregated windows are exact
+        assert_eq!(301.0, value("count"));
+        assert_eq!(15_850.0, value("sum"));
+        assert_eq!(0.0, value("min"));
+        assert_eq!(1_000.0, value("max"));
+        assert_eq!(15_850.0 / 301.0, value("avg"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_pre_aggregate_flush_mid_window() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let count = |db: &Database| -> crate::Result<Value> {
+            Ok(db
+                .count(metric_name, "host")
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?["h-1"][0]
+                .value)
+        };
+
+        {
+            let db = Database::builder()
+                .pre_aggregate(metric_name, std::time::Duration::from_secs(1))
+                .open(&folder)?;
+
+            for ts in 1..=3 {
+                db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+                db.flush(false)?;
+            }
+
+            // NOTE: Flushing in the middle of a window does not lose its samples
+            assert_eq!(3.0, count(&db)?);
+            assert_eq!(1, db.point_count(metric_name));
+
+            // NOTE: Written without flushing, so the window is only written on drop
+            db.write_at(metric_name, 4, 1.0, tagset!("host" => "h-1"))?;
+        }
+
+        let db = Database::builder()
+            .pre_aggregate(metric_name, std::time::Duration::from_secs(1))
+            .open(&folder)?;
+        assert_eq!(4.0, count(&db)?);
+        assert_eq!(1, db.point_count(metric_name));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_timer() -> crate::Result<()> {
+        use crate::DurationUnit;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("http.latency").unwrap();
+
+        let result = db.time(metric_name, tagset!("route" => "/"), || {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            42
+        })?;
+        assert_eq!(42, result);
+
+        let elapsed = db.timer(metric_name, tagset!("route" => "/")).stop()?;
+        assert!(elapsed < std::time::Duration::from_millis(2));
+
+        let max = |unit| -> crate::Result<Value> {
+            Ok(db
+                .max(metric_name, "route")
+                .granularity(Timestamp::MAX)
+                .unit(unit)
+                .build()?
+                .collect()?["/"][0]
+                .value)
+        };
+
+        let millis = max(DurationUnit::Millis)?;
+        assert!((2.0..1_000.0).contains(&millis), "{millis}");
+        assert!((max(DurationUnit::Micros)? / millis - 1_000.0).abs() < 1.0);
+        assert!((max(DurationUnit::Nanos)? / millis - 1_000_000.0).abs() < 1_000.0);
+        assert!(max(DurationUnit::Seconds)?.mul_add(1_000.0, -millis).abs() < 0.001);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_concurrent_writers_and_readers() -> crate::Result<()> {
+        const WRITERS: u64 = 8;
+        const READERS: usize = 4;
+        const HOSTS: u64 = 16;
+        const POINTS_PER_WRITER: u64 = 400;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let total = |db: &Database| -> crate::Result<(Value, u64)> {
+            let buckets = db
+                .count(metric_name, "host")
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?;
+
+            let count = buckets.values().flatten().map(|b| b.value).sum();
+            let series = buckets.len() as u64;
+
+            Ok((count, series))
+        };
+
+        let done = std::sync::atomic::AtomicUsize::new(0);
+
+        std::thread::scope(|scope| -> crate::Result<()> {
+            let writers = (0..WRITERS)
+                .map(|writer| {
+                    let (db, done) = (&db, &done);
+
+                    scope.spawn(move || -> crate::Result<()> {
+                        for i in 0..POINTS_PER_WRITER {
+                            // NOTE: Writers start at different hosts, so all writers
+                            // race to create every series
+                            let host = format!("h-{}", (writer + i) % HOSTS);
+                            let ts = writer * POINTS_PER_WRITER + i;
+                            db.write_at(
+                                metric_name,
+                                ts.into(),
+                                1.0,
+                                tagset!("host" => host.as_str()),
+                            )?;
+                        }
+
+                        done.fetch_add(1, std::sync::atomic::Ordering::Release);
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let readers = (0..READERS)
+                .map(|_| {
+                    let (db, done) = (&db, &done);
+
+                    scope.spawn(move || -> crate::Result<()> {
+                        let (mut prev_count, mut prev_series) = (0.0, 0);
+
+                        while done.load(std::sync::atomic::Ordering::Acquire) < WRITERS as usize {
+                            // NOTE: Data points are never removed, so results only grow
+                            let (count, series) = total(db)?;
+                            assert!(count >= prev_count, "{count} < {prev_count}");
+                            assert!(series >= prev_series, "{series} < {prev_series}");
+                            assert!(series <= HOSTS);
+
+                            (prev_count, prev_series) = (count, series);
+                        }
+
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for handle in writers.into_iter().chain(readers) {
+                handle.join().unwrap()?;
+            }
+
+            Ok(())
+        })?;
+
+        assert_eq!(HOSTS as usize, db.series_count()?);
+        assert_eq!(((WRITERS * POINTS_PER_WRITER) as Value, HOSTS), total(&db)?);
+
+        // NOTE: Every series is found exactly once through the tag index
+        for host in 0..HOSTS {
+            let buckets = db
+                .count(metric_name, "host")
+                .filter(format!("host:h-{host}"))
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?;
+
+            assert_eq!(1, buckets.len());
+            assert_eq!(
+                (WRITERS * POINTS_PER_WRITER / HOSTS) as Value,
+                buckets[&format!("h-{host}")][0].value
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_builder_owned_strings() -> crate::Result<()> {
+        fn host_query<'a>(
+            db: &'a Database,
+            metric: MetricName<'a>,
+            env: &str,
+        ) -> crate::AggregationBuilder<'a, crate::agg::Sum> {
+            db.sum(metric, String::from("host"))
+                .filter(format!("env:{env}"))
+        }
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
 
-        if !self.0.hyper_mode {
-            self.0.keyspace.persist(fjall::PersistMode::Buffer)?;
-        }
+        db.write_at(
+            metric_name,
+            0,
+            4.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            1,
+            6.0,
+            tagset!("env" => "dev", "host" => "h-1"),
+        )?;
+
+        let mut buckets = host_query(&db, metric_name, "prod").build()?.collect()?;
+        assert_eq!(4.0, buckets.remove("h-1").unwrap().pop().unwrap().value);
 
         Ok(())
     }
 
-    fn initialize_new_series(
-        &self,
-        series_key: &str,
-        metric: MetricName,
-        tags: &TagSet,
-    ) -> crate::Result<SeriesId> {
-        // NOTE: We need to run in a transaction (for serializability)
-        //
-        // Because we cannot rely on the series not being created since the
-        // start of the function, we need to again look it up inside the transaction
-        // to really make sure
-        let mut tx = self.0.keyspace.write_tx();
+    #[test]
+    fn test_export_resume() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
 
-        let series_id = tx.get(&self.0.smap.partition, series_key)?.map(|bytes| {
-            let mut reader = &bytes[..];
-            reader.read_u64::<BigEndian>().expect("should deserialize")
-        });
+        let mut full = vec![];
 
-        let series_id = if let Some(series_id) = series_id {
-            // NOTE: Series was created since the start of the function
-            series_id
-        } else {
-            // NOTE: Actually create series
+        {
+            let db = Database::builder().open(&folder)?;
 
-            // TODO: 1.0.0 atomic, persistent counter
-            let next_series_id = self.0.smap.partition.inner().len()? as SeriesId;
+            for host in ["h-1", "h-2", "h-3"] {
+                for ts in 0..10 {
+                    db.write_at(metric_name, ts, 1.0, tagset!("host" => host))?;
+                }
+            }
 
-            log::trace!("Creating series {next_series_id} for permutation {series_key:?}");
+            assert_eq!(30, db.export(&mut full)?);
+        }
 
-            self.0.smap.insert(&mut tx, series_key, next_series_id);
+        let mut out = vec![];
+        let mut token: Option<String> = None;
+        let mut chunks = 0;
 
-            self.0
-                .tag_index
-                .index(&mut tx, metric, tags, next_series_id)?;
+        // NOTE: Reopen the database for every chunk, as if the export job crashed
+        loop {
+            let db = Database::builder().open(&folder)?;
 
-            let mut serialized_tag_set = SeriesKey::allocate_string_for_tags(tags, 0);
-            SeriesKey::join_tags(&mut serialized_tag_set, tags);
+            let cursor = token
+                .as_deref()
+                .map(str::parse::<crate::ExportCursor>)
+                .transpose()?;
 
-            self.0
-                .tag_sets
-                .insert(&mut tx, next_series_id, &serialized_tag_set);
+            let (count, cursor) = db.export_from(&mut out, cursor.as_ref(), 10)?;
+            assert!(count <= 10);
+            chunks += 1;
 
-            tx.commit()?;
+            match cursor {
+                Some(cursor) => token = Some(cursor.to_string()),
+                None => break,
+            }
+        }
 
-            next_series_id
-        };
+        // NOTE: The last chunk ends exactly at the end of the data, and is detected as such
+        assert_eq!(3, chunks);
+        assert_eq!(full, out);
 
-        Ok(series_id)
+        Ok(())
     }
 
-    /// Flushes writes.
-    ///
-    /// If sync is `true`, the writes are guaranteed to be written to disk
-    /// when this function exits.
-    ///
-    /// # Errors
-    ///
-    /// Returns error if an I/O error occurred.
-    pub fn flush(&self, sync: bool) -> crate::Result<()> {
-        use fjall::PersistMode::{Buffer, SyncAll};
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_export_import() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
 
-        self.0
-            .keyspace
-            .persist(if sync { SyncAll } else { Buffer })?;
+        db.write_at(
+            metric_name,
+            1,
+            4.0,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            2,
+            6.5,
+            tagset!("env" => "prod", "host" => "h-1"),
+        )?;
+        db.write_at(metric_name, 3, 1.0, tagset!())?;
+
+        let mut buf = vec![];
+        assert_eq!(3, db.export(&mut buf)?);
+        assert_eq!(2, db.series_count()?);
+
+        let exported = String::from_utf8(buf).unwrap();
+        assert!(exported.contains("cpu.total,env=prod,host=h-1 6.5 2\n"));
+        assert!(exported.contains("cpu.total 1 3\n"));
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        assert_eq!(3, db.import(exported.as_bytes())?);
+
+        let mut buckets = db.sum(metric_name, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(10.5, bucket.value);
+
+        assert!(matches!(
+            db.import(&b"cpu.total abc"[..]),
+            Err(crate::Error::Io(_))
+        ));
 
         Ok(())
     }
-}
-
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
-    use crate::tagset;
-    use test_log::test;
 
     #[test]
+    #[allow(clippy::float_cmp)]
     fn test_range_cnt() -> crate::Result<()> {
         let folder = tempfile::tempdir()?;
         let db = Database::builder().open(&folder)?;
@@ -539,7 +6866,198 @@ mod tests {
     }
 
     #[test]
-    fn test_agg_cnt() -> crate::Result<()> {
+    #[allow(clippy::float_cmp)]
+    fn test_agg_cnt() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("hello").unwrap();
+
+        db.write_at(
+            metric_name,
+            0,
+            4.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            1,
+            10.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            2,
+            6.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            3,
+            10.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            4,
+            20.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+
+        db.write_at(
+            metric_name,
+            5,
+            7.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            6,
+            5.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+
+        let aggregator = db.count(metric_name, "service").build()?;
+        assert_eq!(2, aggregator.len());
+        assert!(aggregator.contains_key("talna"));
+        assert!(aggregator.contains_key("smoltable"));
+
+        for (group, mut aggregator) in aggregator {
+            let bucket = aggregator.next().unwrap()?;
+
+            match group.as_ref() {
+                "talna" => {
+                    assert_eq!(5.0, bucket.value);
+                    assert_eq!(0, bucket.start);
+                    assert_eq!(4, bucket.end);
+                    assert_eq!(5, bucket.len);
+                }
+                "smoltable" => {
+                    assert_eq!(2.0, bucket.value);
+                    assert_eq!(5, bucket.start);
+                    assert_eq!(6, bucket.end);
+                    assert_eq!(2, bucket.len);
+                }
+                _ => {
+                    unreachable!();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_max() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("hello").unwrap();
+
+        db.write_at(
+            metric_name,
+            0,
+            4.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            1,
+            10.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            2,
+            6.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            3,
+            10.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            4,
+            20.0,
+            tagset!(
+                "service" => "talna",
+            ),
+        )?;
+
+        db.write_at(
+            metric_name,
+            5,
+            7.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            6,
+            5.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+
+        let aggregator = db.max(metric_name, "service").build()?;
+        assert_eq!(2, aggregator.len());
+        assert!(aggregator.contains_key("talna"));
+        assert!(aggregator.contains_key("smoltable"));
+
+        for (group, mut aggregator) in aggregator {
+            let bucket = aggregator.next().unwrap()?;
+
+            match group.as_ref() {
+                "talna" => {
+                    assert_eq!(20.0, bucket.value);
+                    assert_eq!(0, bucket.start);
+                    assert_eq!(4, bucket.end);
+                    assert_eq!(5, bucket.len);
+                }
+                "smoltable" => {
+                    assert_eq!(7.0, bucket.value);
+                    assert_eq!(5, bucket.start);
+                    assert_eq!(6, bucket.end);
+                    assert_eq!(2, bucket.len);
+                }
+                _ => {
+                    unreachable!();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_min() -> crate::Result<()> {
         let folder = tempfile::tempdir()?;
         let db = Database::builder().open(&folder)?;
         let metric_name = MetricName::try_from("hello").unwrap();
@@ -602,7 +7120,7 @@ mod tests {
             ),
         )?;
 
-        let aggregator = db.count(metric_name, "service").build()?;
+        let aggregator = db.min(metric_name, "service").build()?;
         assert_eq!(2, aggregator.len());
         assert!(aggregator.contains_key("talna"));
         assert!(aggregator.contains_key("smoltable"));
@@ -612,13 +7130,13 @@ mod tests {
 
             match group.as_ref() {
                 "talna" => {
-                    assert_eq!(5.0, bucket.value);
+                    assert_eq!(4.0, bucket.value);
                     assert_eq!(0, bucket.start);
                     assert_eq!(4, bucket.end);
                     assert_eq!(5, bucket.len);
                 }
                 "smoltable" => {
-                    assert_eq!(2.0, bucket.value);
+                    assert_eq!(5.0, bucket.value);
                     assert_eq!(5, bucket.start);
                     assert_eq!(6, bucket.end);
                     assert_eq!(2, bucket.len);
@@ -633,7 +7151,8 @@ mod tests {
     }
 
     #[test]
-    fn test_agg_max() -> crate::Result<()> {
+    #[allow(clippy::float_cmp)]
+    fn test_agg_sum() -> crate::Result<()> {
         let folder = tempfile::tempdir()?;
         let db = Database::builder().open(&folder)?;
         let metric_name = MetricName::try_from("hello").unwrap();
@@ -696,7 +7215,7 @@ mod tests {
             ),
         )?;
 
-        let aggregator = db.max(metric_name, "service").build()?;
+        let aggregator = db.sum(metric_name, "service").build()?;
         assert_eq!(2, aggregator.len());
         assert!(aggregator.contains_key("talna"));
         assert!(aggregator.contains_key("smoltable"));
@@ -706,13 +7225,13 @@ mod tests {
 
             match group.as_ref() {
                 "talna" => {
-                    assert_eq!(20.0, bucket.value);
+                    assert_eq!(50.0, bucket.value);
                     assert_eq!(0, bucket.start);
                     assert_eq!(4, bucket.end);
                     assert_eq!(5, bucket.len);
                 }
                 "smoltable" => {
-                    assert_eq!(7.0, bucket.value);
+                    assert_eq!(12.0, bucket.value);
                     assert_eq!(5, bucket.start);
                     assert_eq!(6, bucket.end);
                     assert_eq!(2, bucket.len);
@@ -727,7 +7246,8 @@ mod tests {
     }
 
     #[test]
-    fn test_agg_min() -> crate::Result<()> {
+    #[allow(clippy::float_cmp)]
+    fn test_agg_avg() -> crate::Result<()> {
         let folder = tempfile::tempdir()?;
         let db = Database::builder().open(&folder)?;
         let metric_name = MetricName::try_from("hello").unwrap();
@@ -790,7 +7310,7 @@ mod tests {
             ),
         )?;
 
-        let aggregator = db.min(metric_name, "service").build()?;
+        let aggregator = db.avg(metric_name, "service").build()?;
         assert_eq!(2, aggregator.len());
         assert!(aggregator.contains_key("talna"));
         assert!(aggregator.contains_key("smoltable"));
@@ -800,13 +7320,13 @@ mod tests {
 
             match group.as_ref() {
                 "talna" => {
-                    assert_eq!(4.0, bucket.value);
+                    assert_eq!(10.0, bucket.value);
                     assert_eq!(0, bucket.start);
                     assert_eq!(4, bucket.end);
                     assert_eq!(5, bucket.len);
                 }
                 "smoltable" => {
-                    assert_eq!(5.0, bucket.value);
+                    assert_eq!(6.0, bucket.value);
                     assert_eq!(5, bucket.start);
                     assert_eq!(6, bucket.end);
                     assert_eq!(2, bucket.len);
@@ -821,7 +7341,268 @@ mod tests {
     }
 
     #[test]
-    fn test_agg_sum() -> crate::Result<()> {
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::float_cmp,
+        clippy::indexing_slicing
+    )]
+    fn test_query_batch() -> crate::Result<()> {
+        use crate::{Agg, QuerySpec};
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let cpu = MetricName::try_from("cpu.total").unwrap();
+        let mem = MetricName::try_from("mem.used").unwrap();
+
+        for ts in 0..100 {
+            db.write_at(cpu, ts, ts as Value, tagset!("host" => "h-1"))?;
+            db.write_at(mem, ts, 1.0, tagset!("host" => "h-2"))?;
+        }
+
+        let queries = vec![
+            QuerySpec::new("sum", "cpu.total", "host", Agg::Sum)
+                .start(10)
+                .end(19)
+                .granularity(Timestamp::MAX),
+            QuerySpec::new("p50", "cpu.total", "host", Agg::P50).granularity(Timestamp::MAX),
+            QuerySpec::new("all", "*", "host", Agg::Count).granularity(Timestamp::MAX),
+            QuerySpec::new("empty", "cpu.total", "host", Agg::Avg).filter("host:h-2"),
+            QuerySpec::new("invalid", "cpu-total", "host", Agg::Avg),
+        ];
+
+        let mut results = db.query_batch(queries);
+        assert_eq!(5, results.len());
+
+        let sum = results.remove("sum").unwrap()?;
+        assert_eq!(145.0, sum["h-1"][0].value);
+
+        let p50 = results.remove("p50").unwrap()?;
+        assert_eq!(49.0, p50["h-1"][0].value.round());
+
+        let all = results.remove("all").unwrap()?;
+        assert_eq!(100.0, all["h-1"][0].value);
+        assert_eq!(100.0, all["h-2"][0].value);
+
+        assert!(results.remove("empty").unwrap()?.is_empty());
+
+        assert!(matches!(
+            results.remove("invalid").unwrap(),
+            Err(crate::Error::InvalidMetricName(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_agg_active_series() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for (ts, host) in [
+            (0, "h-1"),
+            (1, "h-2"),
+            (2, "h-1"),
+            (3, "h-2"),
+            (100, "h-1"),
+            (101, "h-3"),
+            (102, "h-3"),
+        ] {
+            db.write_at(
+                metric_name,
+                ts,
+                1.0,
+                tagset!("env" => "prod", "host" => host),
+            )?;
+        }
+
+        let mut buckets = db
+            .active_series(metric_name, "env")
+            .granularity(50)
+            .build()?
+            .collect()?
+            .remove("prod")
+            .unwrap();
+        buckets.sort_by_key(|bucket| bucket.start);
+
+        assert_eq!(
+            vec![(0, 2.0, 4), (100, 2.0, 3)],
+            buckets
+                .iter()
+                .map(|bucket| (bucket.start, bucket.value, bucket.len))
+                .collect::<Vec<_>>()
+        );
+
+        let mut groups = db
+            .active_series(metric_name, "host")
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect()?;
+        assert_eq!(1.0, groups.remove("h-3").unwrap().pop().unwrap().value);
+
+        let groups = db
+            .aggregate_many(
+                metric_name,
+                "env",
+                &[crate::Agg::Count, crate::Agg::ActiveSeries],
+            )
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect_many()?;
+        let aggs = groups.get("prod").unwrap();
+        assert_eq!(7.0, aggs.get("count").unwrap()[0].value);
+        assert_eq!(3.0, aggs.get("active_series").unwrap()[0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_distinct() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("hello").unwrap();
+
+        for (ts, user_id) in [1.0, 2.0, 1.0, 3.0, 2.0, 1.0].into_iter().enumerate() {
+            db.write_at(
+                metric_name,
+                ts as Timestamp,
+                user_id,
+                tagset!(
+                    "service" => "talna",
+                ),
+            )?;
+        }
+
+        db.write_at(
+            metric_name,
+            6,
+            7.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+        db.write_at(
+            metric_name,
+            7,
+            7.0,
+            tagset!(
+                "service" => "smoltable",
+            ),
+        )?;
+
+        let aggregator = db.distinct(metric_name, "service").build()?;
+        assert_eq!(2, aggregator.len());
+        assert!(aggregator.contains_key("talna"));
+        assert!(aggregator.contains_key("smoltable"));
+
+        for (group, mut aggregator) in aggregator {
+            let bucket = aggregator.next().unwrap()?;
+
+            match group.as_ref() {
+                "talna" => {
+                    assert_eq!(3.0, bucket.value);
+                    assert_eq!(0, bucket.start);
+                    assert_eq!(5, bucket.end);
+                    assert_eq!(6, bucket.len);
+                }
+                "smoltable" => {
+                    assert_eq!(1.0, bucket.value);
+                    assert_eq!(6, bucket.start);
+                    assert_eq!(7, bucket.end);
+                    assert_eq!(2, bucket.len);
+                }
+                _ => {
+                    unreachable!();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_custom() -> crate::Result<()> {
+        /// Returns the last (newest) value of each bucket
+        #[derive(Default)]
+        struct Last;
+
+        impl Aggregation for Last {
+            fn transform(&mut self, accu: Value, _: Value) -> Value {
+                accu
+            }
+        }
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("hello").unwrap();
+
+        for (ts, value) in [4.0, 10.0, 6.0].into_iter().enumerate() {
+            db.write_at(
+                metric_name,
+                ts as Timestamp,
+                value,
+                tagset!(
+                    "service" => "talna",
+                ),
+            )?;
+        }
+
+        let mut buckets = db
+            .aggregate::<Last>(metric_name, "service")
+            .build()?
+            .collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(6.0, bucket.value);
+        assert_eq!(3, bucket.len);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_agg_scale_offset() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("temperature").unwrap();
+
+        for (ts, value) in [290.0, 300.0].into_iter().enumerate() {
+            db.write_at(
+                metric_name,
+                ts as Timestamp,
+                value,
+                tagset!(
+                    "room" => "kitchen",
+                ),
+            )?;
+        }
+
+        let mut buckets = db
+            .avg(metric_name, "room")
+            .offset(-273.0)
+            .build()?
+            .collect()?;
+        let bucket = buckets.remove("kitchen").unwrap().pop().unwrap();
+        assert_eq!(22.0, bucket.value);
+
+        let mut buckets = db
+            .sum(metric_name, "room")
+            .scale(0.5)
+            .offset(10.0)
+            .build()?
+            .collect()?;
+        let bucket = buckets.remove("kitchen").unwrap().pop().unwrap();
+        assert_eq!(305.0, bucket.value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_write_stat() -> crate::Result<()> {
         let folder = tempfile::tempdir()?;
         let db = Database::builder().open(&folder)?;
         let metric_name = MetricName::try_from("hello").unwrap();
@@ -834,10 +7615,15 @@ mod tests {
                 "service" => "talna",
             ),
         )?;
-        db.write_at(
+        db.write_stat(
             metric_name,
             1,
-            10.0,
+            Stat {
+                count: 3,
+                sum: 18.0,
+                min: 2.0,
+                max: 10.0,
+            },
             tagset!(
                 "service" => "talna",
             ),
@@ -845,165 +7631,486 @@ mod tests {
         db.write_at(
             metric_name,
             2,
-            6.0,
+            8.0,
             tagset!(
                 "service" => "talna",
             ),
         )?;
+
+        let mut buckets = db.count(metric_name, "service").build()?.collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(5.0, bucket.value);
+        assert_eq!(5, bucket.len);
+
+        let mut buckets = db.sum(metric_name, "service").build()?.collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(30.0, bucket.value);
+
+        let mut buckets = db.avg(metric_name, "service").build()?.collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(6.0, bucket.value);
+
+        let mut buckets = db.min(metric_name, "service").build()?.collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(2.0, bucket.value);
+
+        let mut buckets = db.max(metric_name, "service").build()?.collect()?;
+        let bucket = buckets.remove("talna").unwrap().pop().unwrap();
+        assert_eq!(10.0, bucket.value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_value_encoding_half() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("temperature").unwrap();
+        let db = Database::builder()
+            .value_encoding(metric_name, ValueEncoding::Half)
+            .open(&folder)?;
+
         db.write_at(
             metric_name,
-            3,
-            10.0,
+            0,
+            21.51,
             tagset!(
-                "service" => "talna",
+                "room" => "kitchen",
             ),
         )?;
         db.write_at(
             metric_name,
-            4,
-            20.0,
+            1,
+            22.5,
             tagset!(
-                "service" => "talna",
+                "room" => "kitchen",
             ),
         )?;
 
+        let mut buckets = db.max(metric_name, "room").build()?.collect()?;
+        let bucket = buckets.remove("kitchen").unwrap().pop().unwrap();
+        assert_eq!(22.5, bucket.value);
+
+        let mut buckets = db.min(metric_name, "room").build()?.collect()?;
+        let bucket = buckets.remove("kitchen").unwrap().pop().unwrap();
+        assert!((bucket.value - 21.51).abs() < 0.01);
+        assert_ne!(21.51, bucket.value);
+
+        // NOTE: Values out of the half-precision range are stored in full precision
         db.write_at(
             metric_name,
-            5,
-            7.0,
+            2,
+            100_000.5,
             tagset!(
-                "service" => "smoltable",
+                "room" => "oven",
             ),
         )?;
+
+        let mut buckets = db.max(metric_name, "room").build()?.collect()?;
+        let bucket = buckets.remove("oven").unwrap().pop().unwrap();
+        assert_eq!(100_000.5, bucket.value);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_filter_has_missing() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+        let other_metric = MetricName::try_from("mem.used").unwrap();
+
         db.write_at(
             metric_name,
-            6,
-            5.0,
-            tagset!(
-                "service" => "smoltable",
-            ),
+            0,
+            1.0,
+            tagset!("host" => "h-1", "env" => "prod"),
+        )?;
+        db.write_at(
+            metric_name,
+            0,
+            2.0,
+            tagset!("host" => "h-2", "pod" => "p-1"),
+        )?;
+        db.write_at(
+            metric_name,
+            0,
+            4.0,
+            tagset!("hostname" => "h-3", "env" => "prod"),
         )?;
+        db.write_at(other_metric, 0, 8.0, tagset!("env" => "prod"))?;
 
-        let aggregator = db.sum(metric_name, "service").build()?;
-        assert_eq!(2, aggregator.len());
-        assert!(aggregator.contains_key("talna"));
-        assert!(aggregator.contains_key("smoltable"));
+        let sum = |filter: &str| -> crate::Result<Value> {
+            Ok(db
+                .sum(metric_name, "env")
+                .missing_tag(crate::MissingTagPolicy::Group)
+                .filter(filter)
+                .build()?
+                .collect()?
+                .values()
+                .flatten()
+                .map(|bucket| bucket.value)
+                .sum())
+        };
 
-        for (group, mut aggregator) in aggregator {
-            let bucket = aggregator.next().unwrap()?;
+        assert_eq!(3.0, sum("has:host")?);
+        assert_eq!(4.0, sum("missing:host")?);
+        assert_eq!(5.0, sum("has:env")?);
+        assert_eq!(2.0, sum("missing:env")?);
+        assert_eq!(1.0, sum("has:host AND env:prod")?);
+        assert_eq!(7.0, sum("missing:pod OR host:h-2")?);
+        assert_eq!(0.0, sum("missing:host AND missing:hostname")?);
 
-            match group.as_ref() {
-                "talna" => {
-                    assert_eq!(50.0, bucket.value);
-                    assert_eq!(0, bucket.start);
-                    assert_eq!(4, bucket.end);
-                    assert_eq!(5, bucket.len);
-                }
-                "smoltable" => {
-                    assert_eq!(12.0, bucket.value);
-                    assert_eq!(5, bucket.start);
-                    assert_eq!(6, bucket.end);
-                    assert_eq!(2, bucket.len);
-                }
-                _ => {
-                    unreachable!();
-                }
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_planner() -> crate::Result<()> {
+        use crate::{IndexStatistics, NaivePlanner, QueryPlanner};
+        use std::sync::Mutex;
+
+        /// Records the plans, and the estimated size of the first AND term
+        #[derive(Default)]
+        struct RecordingPlanner(Mutex<Vec<(String, u64)>>);
+
+        impl QueryPlanner for RecordingPlanner {
+            fn plan<'a>(
+                &self,
+                metric: &str,
+                filter: &Node<'a>,
+                stats: &dyn IndexStatistics,
+            ) -> crate::Result<Node<'a>> {
+                let plan = crate::SelectivityPlanner.plan(metric, filter, stats)?;
+
+                let estimate = match &plan {
+                    Node::And(children) => match children.first() {
+                        Some(Node::Eq(tag)) => stats.tag_count(metric, &tag.key, &tag.value)?,
+                        _ => stats.series_count(metric)?,
+                    },
+                    _ => stats.series_count(metric)?,
+                };
+
+                self.0.lock().unwrap().push((plan.to_string(), estimate));
+                Ok(plan)
             }
         }
 
+        let planner = Arc::new(RecordingPlanner::default());
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder()
+            .query_planner(planner.clone())
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for host in 0..10 {
+            let host = format!("h-{host}");
+            let tags = tagset!("env" => "prod", "host" => host.as_str());
+            db.write_at(metric_name, 0, 1.0, tags)?;
+        }
+        db.write_at(
+            metric_name,
+            0,
+            1.0,
+            tagset!("env" => "dev", "host" => "h-0"),
+        )?;
+
+        let count = |db: &Database| -> crate::Result<u64> {
+            Ok(db
+                .count(metric_name, "env")
+                .filter("env:prod AND host:h-0")
+                .build()?
+                .collect()?
+                .values()
+                .flatten()
+                .map(|bucket| bucket.len)
+                .sum())
+        };
+
+        assert_eq!(1, count(&db)?);
+        assert_eq!(
+            vec![("(host:h-0 AND env:prod)".to_string(), 2)],
+            *planner.0.lock().unwrap(),
+        );
+
+        drop(db);
+
+        // NOTE: Plans match the same series as the filter
+        let db = Database::builder()
+            .query_planner(Arc::new(NaivePlanner))
+            .open(&folder)?;
+        assert_eq!(1, count(&db)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_declare_metric() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        {
+            let db = Database::builder().open(&folder)?;
+            db.declare_metric(metric_name, &["env", "service"]);
+
+            // NOTE: Mismatches are only logged by default
+            db.write(
+                metric_name,
+                1.0,
+                tagset!("env" => "prod", "serivce" => "db"),
+            )?;
+            assert_eq!(1, db.series_count()?);
+        }
+
+        let db = Database::builder()
+            .schema_policy(crate::SchemaPolicy::Error)
+            .default_tag("host", "h-1")
+            .open(&folder)?;
+        assert_eq!(None, db.declared_tag_keys(metric_name));
+
+        db.declare_metric(metric_name, &["service", "env"]);
+        assert_eq!(
+            Some(vec!["env".into(), "service".into()]),
+            db.declared_tag_keys(metric_name)
+        );
+
+        db.write(
+            metric_name,
+            1.0,
+            tagset!("env" => "prod", "service" => "db"),
+        )?;
+
+        assert!(matches!(
+            db.write(metric_name, 1.0, tagset!("env" => "prod", "serivce" => "db")),
+            Err(crate::Error::SchemaViolation { missing, unexpected, .. })
+                if missing == ["service"] && unexpected == ["serivce"],
+        ));
+        let tags: &TagSet = tagset!("env" => "prod");
+        assert!(matches!(
+            db.ingest_sorted([(metric_name, tags, 0, 1.0)]),
+            Err(crate::Error::SchemaViolation { .. }),
+        ));
+        assert!(db.writer(metric_name, tagset!("service" => "db")).is_err());
+
+        // NOTE: The rejected writes did not create any series
+        assert_eq!(2, db.series_count()?);
+
         Ok(())
     }
 
     #[test]
-    fn test_agg_avg() -> crate::Result<()> {
+    fn test_filter_grammar() -> crate::Result<()> {
+        use crate::{Filter, GrammarVersion, SyntaxError};
+
         let folder = tempfile::tempdir()?;
-        let db = Database::builder().open(&folder)?;
-        let metric_name = MetricName::try_from("hello").unwrap();
+        let db = Database::builder()
+            .filter_grammar(GrammarVersion::V1)
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
 
         db.write_at(
             metric_name,
             0,
-            4.0,
-            tagset!(
-                "service" => "talna",
-            ),
-        )?;
-        db.write_at(
-            metric_name,
-            1,
-            10.0,
-            tagset!(
-                "service" => "talna",
-            ),
+            1.0,
+            tagset!("env" => "prod", "host" => "h-1"),
         )?;
         db.write_at(
             metric_name,
-            2,
-            6.0,
-            tagset!(
-                "service" => "talna",
-            ),
+            0,
+            2.0,
+            tagset!("env" => "dev", "host" => "h-2"),
         )?;
+
+        let count = |filter: &str| -> crate::Result<usize> {
+            Ok(db
+                .count(metric_name, "host")
+                .filter(filter)
+                .build()?
+                .collect()?
+                .len())
+        };
+
+        assert_eq!(1, count("env:prod AND host:h-1")?);
+        assert_eq!(2, count("(env:prod AND host:h-1) OR env:dev")?);
+
+        for filter in ["env:prod AND host:h-1 OR env:dev", "env!=prod", "has:host"] {
+            assert!(matches!(count(filter), Err(crate::Error::InvalidQuery)));
+            assert!(db.parse_filter(filter).is_err());
+        }
+
+        assert_eq!(
+            Err(SyntaxError::MixedOperators),
+            Filter::parse_strict("env:prod host:h-1 OR env:dev", GrammarVersion::LATEST)
+                .map(|filter| filter.to_string())
+        );
+
+        // NOTE: Without a pinned grammar, filters are parsed leniently
+        drop(db);
+        let db = Database::builder().open(&folder)?;
+        assert!(db.parse_filter("env:prod AND host:h-1 OR env:dev").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_usage() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+        let buffered = MetricName::try_from("cpu.fast").unwrap();
+
+        let db = Database::builder()
+            .query_cache(8, std::time::Duration::from_secs(60))
+            .pre_aggregate(buffered, std::time::Duration::from_secs(60))
+            .open(&folder)?;
+
+        let usage = db.memory_usage();
+        assert_eq!(0, usage.postings_cache);
+        assert_eq!(0, usage.query_cache);
+        assert_eq!(0, usage.pre_aggregation);
+
+        for host in 0..10 {
+            let host = format!("h-{host}");
+            db.write_at(metric_name, 0, 1.0, tagset!("host" => host.as_str()))?;
+        }
+        db.write_at(buffered, 0, 1.0, tagset!("host" => "h-1"))?;
+
+        db.avg(metric_name, "host")
+            .filter("host:h-1")
+            .build()?
+            .collect()?;
+
+        let usage = db.memory_usage();
+        assert!(usage.write_buffer > 0);
+        assert!(usage.postings_cache > 0);
+        assert!(usage.tag_set_cache > 0);
+        assert!(usage.series_cache > 0);
+        assert!(usage.query_cache > 0);
+        assert!(usage.pre_aggregation > 0);
+        assert!(usage.total() > usage.write_buffer);
+
+        // NOTE: Flushed windows stay buffered until the next window of their series starts
+        let pre_aggregation = db.memory_usage().pre_aggregation;
+        db.flush(false)?;
+        assert_eq!(pre_aggregation, db.memory_usage().pre_aggregation);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_series_bounds() -> crate::Result<()> {
+        use std::ops::Bound::{Included, Unbounded};
+
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let db = Database::builder().open(&folder)?;
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, 100 + ts, 2.0, tagset!("host" => "h-2"))?;
+        }
+
+        let series_id = |db: &Database, host: &str| -> crate::Result<SeriesId> {
+            Ok(db
+                .0
+                .smap
+                .get(&SeriesKey::format(metric_name, tagset!("host" => host)))?
+                .unwrap())
+        };
+        let (a, b) = (series_id(&db, "h-1")?, series_id(&db, "h-2")?);
+
+        assert_eq!(Some((0, 9)), db.0.series_bounds.get(a));
+        assert_eq!(Some((100, 109)), db.0.series_bounds.get(b));
+        assert_eq!(
+            vec![b],
+            db.series_in_bounds(&[a, b], (Included(50), Unbounded))
+        );
+        assert_eq!(
+            vec![a],
+            db.series_in_bounds(&[a, b], (Unbounded, Included(50)))
+        );
+
+        let sum = |db: &Database| -> crate::Result<Value> {
+            Ok(db
+                .sum(metric_name, "host")
+                .start(50)
+                .granularity(Timestamp::MAX)
+                .build()?
+                .collect()?
+                .values()
+                .flatten()
+                .map(|bucket| bucket.value)
+                .sum())
+        };
+        assert_eq!(20.0, sum(&db)?);
+
+        // NOTE: Moved data points extend the bounds of their new series
+        db.retag(metric_name, "host:h-1", tagset!("host" => "h-3"), &[])?;
+        let c = series_id(&db, "h-3")?;
+        assert_eq!(Some((0, 9)), db.0.series_bounds.get(c));
+
+        drop(db);
+
+        let db = Database::builder().open(&folder)?;
+        assert_eq!(Some((0, 9)), db.0.series_bounds.get(c));
+        assert_eq!(Some((100, 109)), db.0.series_bounds.get(b));
+        assert_eq!(20.0, sum(&db)?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_filter_not_shorthand() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+        let other_metric = MetricName::try_from("mem.used").unwrap();
+
         db.write_at(
             metric_name,
-            3,
-            10.0,
-            tagset!(
-                "service" => "talna",
-            ),
+            0,
+            1.0,
+            tagset!("host" => "h-1", "env" => "prod"),
         )?;
         db.write_at(
             metric_name,
-            4,
-            20.0,
-            tagset!(
-                "service" => "talna",
-            ),
+            0,
+            2.0,
+            tagset!("host" => "h-2", "env" => "prod"),
         )?;
-
         db.write_at(
             metric_name,
-            5,
-            7.0,
-            tagset!(
-                "service" => "smoltable",
-            ),
+            0,
+            4.0,
+            tagset!("host" => "h-3", "env" => "dev"),
         )?;
         db.write_at(
-            metric_name,
-            6,
-            5.0,
-            tagset!(
-                "service" => "smoltable",
-            ),
+            other_metric,
+            0,
+            8.0,
+            tagset!("host" => "h-4", "env" => "dev"),
         )?;
 
-        let aggregator = db.avg(metric_name, "service").build()?;
-        assert_eq!(2, aggregator.len());
-        assert!(aggregator.contains_key("talna"));
-        assert!(aggregator.contains_key("smoltable"));
-
-        for (group, mut aggregator) in aggregator {
-            let bucket = aggregator.next().unwrap()?;
+        let sum = |filter: &str| -> crate::Result<Value> {
+            Ok(db
+                .sum(metric_name, "host")
+                .filter(filter)
+                .build()?
+                .collect()?
+                .values()
+                .flatten()
+                .map(|bucket| bucket.value)
+                .sum())
+        };
 
-            match group.as_ref() {
-                "talna" => {
-                    assert_eq!(10.0, bucket.value);
-                    assert_eq!(0, bucket.start);
-                    assert_eq!(4, bucket.end);
-                    assert_eq!(5, bucket.len);
-                }
-                "smoltable" => {
-                    assert_eq!(6.0, bucket.value);
-                    assert_eq!(5, bucket.start);
-                    assert_eq!(6, bucket.end);
-                    assert_eq!(2, bucket.len);
-                }
-                _ => {
-                    unreachable!();
-                }
-            }
-        }
+        // NOTE: Negations only match series of the queried metric
+        assert_eq!(3.0, sum("env!=dev")?);
+        assert_eq!(3.0, sum("!env:dev")?);
+        assert_eq!(4.0, sum("host:![h-1, h-2]")?);
+        assert_eq!(3.0, sum("host:[h-1, h-2]")?);
+        assert_eq!(2.0, sum("env:prod host!=h-1")?);
 
         Ok(())
     }
@@ -1130,4 +8237,439 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_delete_tombstones() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let count = |db: &Database, include_deleted| -> crate::Result<_> {
+            let buckets = db
+                .count(metric_name, "host")
+                .granularity(u128::MAX)
+                .include_deleted(include_deleted)
+                .build()?
+                .collect()?;
+
+            let mut counts = buckets
+                .into_iter()
+                .map(|(host, buckets)| (host, buckets.iter().map(|x| x.len).sum::<u64>()))
+                .collect::<Vec<_>>();
+            counts.sort();
+            Ok(counts)
+        };
+
+        {
+            let db = Database::builder().open(&folder)?;
+
+            for ts in 0..10 {
+                db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+                db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-2"))?;
+            }
+
+            assert_eq!(None, db.delete(metric_name, "host:h-3", 0, 10)?);
+
+            let id = db.delete(metric_name, "host:h-1", 2, 5)?.unwrap();
+            assert_eq!(
+                vec![("h-1".into(), 6), ("h-2".into(), 10)],
+                count(&db, false)?
+            );
+            assert_eq!(
+                vec![("h-1".into(), 10), ("h-2".into(), 10)],
+                count(&db, true)?
+            );
+
+            assert!(db.undelete(id)?);
+            assert!(!db.undelete(id)?);
+            assert_eq!(
+                vec![("h-1".into(), 10), ("h-2".into(), 10)],
+                count(&db, false)?
+            );
+
+            // NOTE: IDs are not reused
+            assert_ne!(id, db.delete(metric_name, "*", 8, 20)?.unwrap());
+            assert_eq!(
+                vec![("h-1".into(), 8), ("h-2".into(), 8)],
+                count(&db, false)?
+            );
+
+            // NOTE: Exports skip deleted data points as well
+            assert_eq!(16, db.export(&mut std::io::sink())?);
+        }
+
+        {
+            // NOTE: Tombstones are persisted
+            let db = Database::builder().open(&folder)?;
+            assert_eq!(1, db.tombstones().len());
+            assert_eq!(
+                vec![("h-1".into(), 8), ("h-2".into(), 8)],
+                count(&db, false)?
+            );
+
+            // NOTE: Tombstone is still within its grace period
+            let deleted_at = db.tombstones().first().unwrap().deleted_at;
+            assert_eq!(0, db.compact_tombstones(deleted_at)?);
+
+            assert_eq!(4, db.compact_tombstones(deleted_at + 1)?);
+            assert!(db.tombstones().is_empty());
+            assert_eq!(
+                vec![("h-1".into(), 8), ("h-2".into(), 8)],
+                count(&db, true)?
+            );
+            assert_eq!(16, db.point_count(metric_name));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_audit_sink() -> crate::Result<()> {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<RemovalEvent>>);
+
+        impl AuditSink for Recorder {
+            fn on_removal(&self, event: &RemovalEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        struct Discard;
+
+        impl ArchiveSink for Discard {
+            fn put(&self, _: &str, _: &[u8]) -> crate::Result<()> {
+                Ok(())
+            }
+        }
+
+        let folder = tempfile::tempdir()?;
+        let recorder = Arc::new(Recorder::default());
+        let db = Database::builder()
+            .audit_sink(recorder.clone())
+            .open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..10 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-1"))?;
+        }
+        db.write_at(metric_name, 100, 1.0, tagset!("host" => "h-2"))?;
+
+        let take = || std::mem::take(&mut *recorder.0.lock().unwrap());
+
+        let id = db.delete(metric_name, "host:h-1", 2, 5)?.unwrap();
+        db.compact_tombstones(Timestamp::MAX)?;
+
+        let range = |series_key: &str, start, end| RemovedRange {
+            series_id: db.0.smap.get(series_key).unwrap().unwrap(),
+            series_key: series_key.into(),
+            start,
+            end,
+        };
+
+        let events = take();
+        assert_eq!(2, events.len());
+        assert_eq!(RemovalReason::Delete { tombstone: id }, events[0].reason);
+        assert_eq!(
+            RemovalReason::Compaction { tombstone: id },
+            events[1].reason
+        );
+        for event in &events {
+            assert_eq!(vec![range("cpu.total#host:h-1", 2, 5)], event.ranges);
+        }
+
+        // NOTE: Nothing matched, so nothing is reported
+        assert_eq!(None, db.delete(metric_name, "host:h-3", 0, 10)?);
+        assert!(take().is_empty());
+
+        db.archive(0, 4, &Discard, true)?;
+        let events = take();
+        assert_eq!(1, events.len());
+        assert_eq!(RemovalReason::Archive, events[0].reason);
+        assert_eq!(vec![range("cpu.total#host:h-1", 0, 1)], events[0].ranges);
+
+        let h1 = range("cpu.total#host:h-1", 0, 9);
+        db.gc_idle_series(50, true)?;
+        let events = take();
+        assert_eq!(1, events.len());
+        assert_eq!(RemovalReason::IdleSeries, events[0].reason);
+        assert_eq!(vec![h1], events[0].ranges);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::indexing_slicing)]
+    fn test_series_streams() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let old = MetricName::try_from("cpu.old").unwrap();
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..5 {
+            db.write_at(
+                metric_name,
+                ts,
+                1.0,
+                tagset!("host" => "h-2", "env" => "prod"),
+            )?;
+            db.write_at(
+                metric_name,
+                ts,
+                2.0,
+                tagset!("host" => "h-1", "env" => "prod"),
+            )?;
+            db.write_at(
+                metric_name,
+                ts,
+                3.0,
+                tagset!("host" => "h-3", "env" => "dev"),
+            )?;
+        }
+        db.write_at(old, 0, 4.0, tagset!("host" => "h-1", "env" => "prod"))?;
+        db.write_at(old, 2, 4.0, tagset!("host" => "h-1", "env" => "prod"))?;
+        db.alias_metric(old, metric_name)?;
+        db.delete(metric_name, "host:h-2", 3, 4)?;
+
+        let streams = db.series_streams(metric_name, "env:prod", 1, 10)?;
+        assert_eq!(
+            vec![
+                ("cpu.old", "h-1"),
+                ("cpu.total", "h-1"),
+                ("cpu.total", "h-2")
+            ],
+            streams
+                .iter()
+                .map(|stream| (stream.metric.as_str(), stream.tags["host"].as_str()))
+                .collect::<Vec<_>>(),
+        );
+
+        let points = streams
+            .into_iter()
+            .map(|stream| {
+                let series_id = stream.series_id;
+
+                stream
+                    .map(|item| {
+                        let item = item?;
+                        assert_eq!(series_id, item.series_id);
+                        Ok((item.ts, item.value))
+                    })
+                    .collect::<crate::Result<Vec<_>>>()
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        assert_eq!(
+            vec![
+                vec![(2, 4.0)],
+                vec![(4, 2.0), (3, 2.0), (2, 2.0), (1, 2.0)],
+                vec![(2, 1.0), (1, 1.0)],
+            ],
+            points,
+        );
+
+        assert!(db.series_streams(metric_name, "env:", 0, 10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_join() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let errors = MetricName::try_from("http.errors").unwrap();
+        let requests = MetricName::try_from("http.requests").unwrap();
+
+        // NOTE: Data points of both metrics are written at different times of the same buckets
+        for (ts, host, region) in [(12, "h-1", "eu"), (15, "h-1", "eu"), (3, "h-2", "us")] {
+            db.write_at(errors, ts, 1.0, tagset!("host" => host, "region" => region))?;
+        }
+        for (ts, host, region) in [(10, "h-1", "eu"), (1, "h-1", "eu"), (8, "h-2", "us")] {
+            db.write_at(
+                requests,
+                ts,
+                4.0,
+                tagset!("host" => host, "region" => region),
+            )?;
+        }
+        db.write_at(requests, 0, 4.0, tagset!("host" => "h-3", "region" => "us"))?;
+
+        let result = db
+            .join(errors, requests)
+            .on_tags(&["host", "region"])
+            .granularity(10)
+            .collect()?;
+        assert_eq!(2, result.len());
+        assert_eq!(
+            vec![(10, 20, 0.5, 3)],
+            result["h-1;eu"]
+                .iter()
+                .map(|b| (b.start, b.end, b.value, b.len))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(0.25, result["h-2;us"][0].value);
+
+        let result = db
+            .join(errors, requests)
+            .on_tags(&["host"])
+            .granularity(10)
+            .aggregations(crate::Agg::Count, crate::Agg::Count)
+            .map(|errors, requests| errors - requests)
+            .missing_data(crate::MissingDataPolicy::Fill(0.0))
+            .collect()?;
+        assert_eq!(3, result.len());
+        assert_eq!(
+            vec![(10, 1.0), (0, -1.0)],
+            result["h-1"]
+                .iter()
+                .map(|b| (b.start, b.value))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(0.0, result["h-2"][0].value);
+        assert_eq!(-1.0, result["h-3"][0].value);
+
+        let result = db
+            .join(errors, requests)
+            .filter("region:us")
+            .granularity(100)
+            .collect()?;
+        assert_eq!(1, result.len());
+        assert_eq!(0.125, result[""][0].value);
+
+        assert!(db.join(errors, requests).filter("host:").build().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::indexing_slicing)]
+    fn test_backfill_rollups() -> crate::Result<()> {
+        use crate::Agg;
+
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..30 {
+            #[allow(clippy::cast_precision_loss)]
+            let value = ts as Value;
+            db.write_at(metric_name, ts, value, tagset!("host" => "h-1"))?;
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h-2"))?;
+        }
+        db.write_at(metric_name, 35, 5.0, tagset!("host" => "h-2"))?;
+
+        // NOTE: Windows with deleted data points are not rolled up
+        db.delete(metric_name, "host:h-2", 12, 14)?;
+        assert_eq!(61, db.point_count(metric_name));
+
+        let query = || -> crate::Result<_> {
+            let results = db
+                .aggregate_many(
+                    metric_name,
+                    "host",
+                    &[Agg::Count, Agg::Sum, Agg::Min, Agg::Max],
+                )
+                .granularity(10)
+                .align_timezone(0)
+                .build()?
+                .collect_many()?;
+
+            Ok(results
+                .into_iter()
+                .map(|(group, results)| {
+                    let results = results
+                        .into_iter()
+                        .map(|(agg, buckets)| {
+                            let values = buckets
+                                .iter()
+                                .map(|bucket| (bucket.start, bucket.value))
+                                .collect::<Vec<_>>();
+                            (agg, values)
+                        })
+                        .collect::<std::collections::BTreeMap<_, _>>();
+                    (group, results)
+                })
+                .collect::<std::collections::BTreeMap<_, _>>())
+        };
+        let before = query()?;
+
+        let mut reports = vec![];
+        let count = db.backfill_rollups(metric_name, 10, |progress| {
+            reports.push(progress);
+            ControlFlow::Continue(())
+        })?;
+
+        // NOTE: h-2's window at 10 has deleted data points, and its window at 30 only one data point
+        assert_eq!(50, count);
+        assert_eq!(
+            vec![
+                Progress {
+                    done: 1,
+                    total: Some(2)
+                },
+                Progress {
+                    done: 2,
+                    total: Some(2)
+                }
+            ],
+            reports,
+        );
+        assert_eq!(16, db.point_count(metric_name));
+        assert_eq!(before, query()?);
+
+        // NOTE: Rollups are rolled up again with a coarser granularity
+        assert_eq!(4, db.backfill_rollups(metric_name, 20, no_progress)?);
+        assert_eq!(14, db.point_count(metric_name));
+        assert_eq!(0, db.backfill_rollups(metric_name, 20, no_progress)?);
+
+        let results = db
+            .aggregate_many(metric_name, "host", &[Agg::Count, Agg::Sum])
+            .granularity(Timestamp::MAX)
+            .build()?
+            .collect_many()?;
+        assert_eq!(30.0, results["h-1"]["count"][0].value);
+        assert_eq!(435.0, results["h-1"]["sum"][0].value);
+        assert_eq!(28.0, results["h-2"]["count"][0].value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_with_progress() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let lines = (0..5)
+            .map(|ts| format!("cpu.total,host=h-1 1 {ts}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // NOTE: Abort after the third data point
+        let mut reports = vec![];
+        let count = db.import_with_progress(lines.as_bytes(), |progress| {
+            reports.push(progress);
+
+            if progress.done == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        assert_eq!(3, count);
+        assert_eq!(3, reports.len());
+        assert!(reports.iter().all(|progress| progress.fraction().is_none()));
+        assert_eq!(3, db.point_count(metric_name));
+
+        let remaining = lines.split_inclusive('\n').skip(3).collect::<String>();
+        assert_eq!(
+            2,
+            db.import_with_progress(remaining.as_bytes(), no_progress)?
+        );
+        assert_eq!(5, db.point_count(metric_name));
+
+        Ok(())
+    }
 }
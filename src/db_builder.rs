@@ -1,11 +1,38 @@
-use crate::Database;
+use crate::query::grammar::GrammarVersion;
+use crate::tier::ColdStorage;
+use crate::{
+    AuditSink, Database, MetricName, QueryPlanner, SchemaPolicy, Storage, Timestamp, ValueEncoding,
+    WriteObserver,
+};
 use fjall::{BlockCache, TxKeyspace};
-use std::{path::Path, sync::Arc};
+use std::{path::Path, sync::Arc, time::Duration};
 
 /// Builder for [`Database`].
 pub struct Builder {
     cache_size_mib: u64,
-    hyper_mode: bool,
+    pub(crate) block_cache: Option<Arc<BlockCache>>,
+    write_buffer_size_mib: Option<u64>,
+    pub(crate) data_block_size: u32,
+    pub(crate) tag_set_cache_size_mib: u64,
+    pub(crate) series_cache_size_mib: u64,
+    pub(crate) postings_cache_size_mib: u64,
+    pub(crate) hyper_mode: bool,
+    pub(crate) value_encodings: crate::HashMap<String, ValueEncoding>,
+    pub(crate) query_cache: Option<(usize, Duration)>,
+    pub(crate) default_tags: Vec<(String, String)>,
+    flush_interval: Option<Duration>,
+    pub(crate) write_observer: Option<(Arc<dyn WriteObserver>, u64)>,
+    pub(crate) audit_sink: Option<Arc<dyn AuditSink>>,
+    pub(crate) max_disk_space: Option<u64>,
+    partition_prefix: Option<String>,
+    pub(crate) timestamp_bounds: Option<(Timestamp, Timestamp)>,
+    pub(crate) max_clock_skew: Option<Duration>,
+    pub(crate) cold_tier: Option<(ColdStorage, Duration)>,
+    pub(crate) pre_aggregations: crate::HashMap<String, Timestamp>,
+    pub(crate) max_scanned_points: Option<u64>,
+    pub(crate) query_planner: Option<Arc<dyn QueryPlanner>>,
+    pub(crate) filter_grammar: Option<GrammarVersion>,
+    pub(crate) schema_policy: SchemaPolicy,
 }
 
 // TODO: 1.0.0 prefix bloom filters would be *really* nice
@@ -15,7 +42,29 @@ impl Builder {
     pub(crate) fn new() -> Self {
         Self {
             cache_size_mib: 32,
+            block_cache: None,
+            write_buffer_size_mib: None,
+            data_block_size: 64_000,
+            tag_set_cache_size_mib: 4,
+            series_cache_size_mib: 4,
+            postings_cache_size_mib: 4,
             hyper_mode: false,
+            value_encodings: crate::HashMap::default(),
+            query_cache: None,
+            default_tags: Vec::new(),
+            flush_interval: None,
+            write_observer: None,
+            audit_sink: None,
+            max_disk_space: None,
+            partition_prefix: None,
+            timestamp_bounds: None,
+            max_clock_skew: None,
+            cold_tier: None,
+            pre_aggregations: crate::HashMap::default(),
+            max_scanned_points: None,
+            query_planner: None,
+            filter_grammar: None,
+            schema_policy: SchemaPolicy::default(),
         }
     }
 
@@ -28,6 +77,87 @@ impl Builder {
         self
     }
 
+    /// Uses an existing block cache instead of creating a new one,
+    /// so multiple databases can share one memory budget.
+    ///
+    /// Overrides [`Builder::cache_size_mib`].
+    ///
+    /// Only applies to [`Builder::open`], keyspaces passed to
+    /// [`Builder::open_in_keyspace`] keep their own configuration.
+    ///
+    /// Default = none
+    #[must_use]
+    pub fn block_cache(mut self, block_cache: Arc<BlockCache>) -> Self {
+        self.block_cache = Some(block_cache);
+        self
+    }
+
+    /// Sets the maximum size of all write buffers (memtables) in MiB.
+    ///
+    /// When exceeded, write buffers are flushed to disk. Values below 1 MiB are clamped.
+    ///
+    /// Only applies to [`Builder::open`], keyspaces passed to
+    /// [`Builder::open_in_keyspace`] keep their own configuration.
+    ///
+    /// Default = 64 MiB
+    #[must_use]
+    pub fn write_buffer_size_mib(mut self, mib: u64) -> Self {
+        self.write_buffer_size_mib = Some(mib.max(1));
+        self
+    }
+
+    /// Sets the block size of the data partition in bytes.
+    ///
+    /// Smaller blocks make queries over short time ranges cheaper on large databases,
+    /// because less data needs to be read & decompressed per lookup.
+    /// Larger blocks compress better and make bulk scans (e.g. long time ranges) faster.
+    ///
+    /// Values are clamped to 1 KiB - 512 KiB.
+    /// Only applies when the database is created, existing databases keep their block size.
+    ///
+    /// Default = 64 KB
+    #[must_use]
+    pub fn data_block_size(mut self, bytes: u32) -> Self {
+        self.data_block_size = bytes.clamp(1_024, 512 * 1_024);
+        self
+    }
+
+    /// Sets the size of the tag set cache in MiB.
+    ///
+    /// Queries need the tag set of every matching series, so caching them
+    /// avoids reading and parsing them on every query.
+    ///
+    /// Default = 4 MiB
+    #[must_use]
+    pub fn tag_set_cache_size_mib(mut self, mib: u64) -> Self {
+        self.tag_set_cache_size_mib = mib;
+        self
+    }
+
+    /// Sets the size of the series key cache in MiB.
+    ///
+    /// Writes need to resolve the series ID of their series key, so caching
+    /// recently used series keys avoids a lookup in the series mapping.
+    ///
+    /// Default = 4 MiB
+    #[must_use]
+    pub fn series_cache_size_mib(mut self, mib: u64) -> Self {
+        self.series_cache_size_mib = mib;
+        self
+    }
+
+    /// Sets the size of the postings list cache in MiB.
+    ///
+    /// Queries need to look up the postings list of every term in the filter expression,
+    /// so caching them avoids reading and deserializing them on every query.
+    ///
+    /// Default = 4 MiB
+    #[must_use]
+    pub fn postings_cache_size_mib(mut self, mib: u64) -> Self {
+        self.postings_cache_size_mib = mib;
+        self
+    }
+
     /// If `true`, writes become faster by skipping the `write()` syscall to OS buffers.
     ///
     /// However, writes are then not application-crash safe.
@@ -37,6 +167,244 @@ impl Builder {
         self
     }
 
+    /// Sets the on-disk encoding of the given metric's data points.
+    ///
+    /// Default = [`ValueEncoding::Full`]
+    #[must_use]
+    pub fn value_encoding(mut self, metric: MetricName, encoding: ValueEncoding) -> Self {
+        self.value_encodings.insert(metric.to_string(), encoding);
+        self
+    }
+
+    /// Aggregates the given metric's samples in memory to windows of the given resolution
+    /// (e.g. 1 second) before writing them, which reduces write amplification of
+    /// high-frequency sources.
+    ///
+    /// Each window is written as a pre-aggregated sample (see [`Database::write_stat`])
+    /// at the start of the window, so sum, avg, min, max & count aggregations stay exact.
+    /// Windows with a single sample are written as a raw data point.
+    ///
+    /// A window is written once a sample of the next window of its series arrives, or
+    /// when the database is flushed, so buffered samples are not visible to queries before that.
+    /// A window that was written by a flush stays buffered, and is overwritten once it receives
+    /// more samples. Buffered windows are written when the last database handle is dropped,
+    /// but are lost on a crash.
+    /// Samples that are older than the buffered window of their series are written directly.
+    ///
+    /// Only [`Database::write`] (and the writes that use it) is buffered, bulk imports
+    /// and [`SeriesWriter`](crate::SeriesWriter)s write their samples directly.
+    ///
+    /// Default = disabled
+    #[must_use]
+    pub fn pre_aggregate(mut self, metric: MetricName, resolution: Duration) -> Self {
+        self.pre_aggregations
+            .insert(metric.to_string(), resolution.as_nanos());
+        self
+    }
+
+    /// Enables caching of collected query results, holding at most `capacity` results.
+    ///
+    /// Cached results expire after the given TTL, and are invalidated when
+    /// their metric is written to.
+    ///
+    /// Queries using `start_relative`/`end_relative` are cached by their relative window,
+    /// so repeatedly requested dashboard panels can be served from the cache.
+    ///
+    /// Default = disabled
+    #[must_use]
+    pub fn query_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.query_cache = Some((capacity, ttl));
+        self
+    }
+
+    /// Sets the default maximum amount of stored data points a query scans, bounding
+    /// the worst-case cost of queries (e.g. when serving multiple tenants).
+    ///
+    /// Queries that reach the limit return a partial result,
+    /// see [`AggregationBuilder::max_scanned_points`](crate::AggregationBuilder::max_scanned_points),
+    /// which can also override the default per query.
+    ///
+    /// Default = unlimited
+    #[must_use]
+    pub fn max_scanned_points(mut self, n: u64) -> Self {
+        self.max_scanned_points = Some(n);
+        self
+    }
+
+    /// Sets the planner that decides how filters are evaluated.
+    ///
+    /// Default = [`SelectivityPlanner`](crate::SelectivityPlanner)
+    #[must_use]
+    pub fn query_planner(mut self, planner: Arc<dyn QueryPlanner>) -> Self {
+        self.query_planner = Some(planner);
+        self
+    }
+
+    /// Pins the filter query language to the given grammar version, see [`GrammarVersion`].
+    ///
+    /// Filters are then parsed strictly (see [`Filter::parse_strict`](crate::Filter::parse_strict)),
+    /// so expressions using newer constructs, or ambiguous expressions like `a AND b OR c`,
+    /// are rejected instead of changing their meaning across upgrades.
+    ///
+    /// Default = latest grammar, parsed leniently
+    #[must_use]
+    pub fn filter_grammar(mut self, version: GrammarVersion) -> Self {
+        self.filter_grammar = Some(version);
+        self
+    }
+
+    /// Sets what happens to writes whose tag keys do not match the declared tag keys
+    /// of their metric, see [`Database::declare_metric`].
+    ///
+    /// Default = [`SchemaPolicy::Warn`]
+    #[must_use]
+    pub fn schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = policy;
+        self
+    }
+
+    /// Adds a tag to every data point written to the database (e.g. `host`, `region`).
+    ///
+    /// Tags passed to a write take precedence over default tags with the same key.
+    ///
+    /// Default = no tags
+    #[must_use]
+    pub fn default_tag(mut self, key: &str, value: &str) -> Self {
+        self.default_tags.retain(|(k, _)| k != key);
+        self.default_tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Installs an observer that is notified when a series is created,
+    /// and after every `every_n_writes` writes with their timing.
+    ///
+    /// Default = none
+    #[must_use]
+    pub fn write_observer(mut self, observer: Arc<dyn WriteObserver>, every_n_writes: u64) -> Self {
+        self.write_observer = Some((observer, every_n_writes));
+        self
+    }
+
+    /// Installs a sink that is notified when data points are deleted or expire
+    /// (e.g. by garbage collecting idle series), with the affected series & time ranges.
+    ///
+    /// Default = none
+    #[must_use]
+    pub fn audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
+
+    /// Sets the maximum on-disk size of the database (in bytes).
+    ///
+    /// Once the database exceeds the size, writes are rejected with [`crate::Error::QuotaExceeded`].
+    /// The size is checked periodically, so it may be exceeded by a couple of writes.
+    ///
+    /// Default = unlimited
+    #[must_use]
+    pub fn max_disk_space(mut self, bytes: u64) -> Self {
+        self.max_disk_space = Some(bytes);
+        self
+    }
+
+    /// Rejects data points with timestamps (in nanoseconds) outside of `min..=max`
+    /// with [`crate::Error::TimestampOutOfRange`].
+    ///
+    /// Historical backfills and future timestamps are allowed by default, so
+    /// this guards range scans against agents with broken clocks.
+    ///
+    /// Default = unbounded
+    #[must_use]
+    pub fn timestamp_bounds(mut self, min: Timestamp, max: Timestamp) -> Self {
+        self.timestamp_bounds = Some((min, max));
+        self
+    }
+
+    /// Rejects data points with timestamps more than `skew` ahead of the current time
+    /// with [`crate::Error::TimestampOutOfRange`].
+    ///
+    /// Can be combined with [`Builder::timestamp_bounds`].
+    ///
+    /// Default = unbounded
+    #[must_use]
+    pub fn max_clock_skew(mut self, skew: Duration) -> Self {
+        self.max_clock_skew = Some(skew);
+        self
+    }
+
+    /// Stores data points older than `age` in a second location (e.g. a larger, slower disk).
+    ///
+    /// Data points are moved by [`Database::move_to_cold_tier`](crate::Database::move_to_cold_tier),
+    /// which should be called periodically. Queries transparently read from both tiers.
+    ///
+    /// The cold tier is not included in [`Builder::max_disk_space`].
+    ///
+    /// Default = disabled
+    #[must_use]
+    pub fn cold_tier<P: AsRef<Path>>(mut self, path: P, age: Duration) -> Self {
+        self.cold_tier = Some((ColdStorage::Path(path.as_ref().into()), age));
+        self
+    }
+
+    /// Like [`Builder::cold_tier`], but stores the cold tier in a custom storage backend
+    /// (see [`Storage`]) instead of a fjall keyspace.
+    ///
+    /// Default = disabled
+    #[must_use]
+    pub fn cold_tier_storage(mut self, storage: Arc<dyn Storage>, age: Duration) -> Self {
+        self.cold_tier = Some((ColdStorage::Custom(storage), age));
+        self
+    }
+
+    /// Sets a name that is added to the names of all partitions of the database,
+    /// so multiple databases can be stored in the same keyspace.
+    ///
+    /// Databases opened with different prefixes are fully independent.
+    ///
+    /// Default = none
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix is empty, longer than 200 characters, or contains
+    /// characters other than alphanumerics, underscore (`_`) and dash (`-`).
+    #[must_use]
+    pub fn partition_prefix(mut self, prefix: &str) -> Self {
+        assert!(
+            !prefix.is_empty()
+                && prefix.len() <= 200
+                && prefix
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+            "invalid partition prefix: {prefix:?}",
+        );
+
+        self.partition_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Returns the string all partition names start with.
+    pub(crate) fn partition_name_prefix(&self) -> String {
+        self.partition_prefix.as_ref().map_or_else(
+            || "_talna#v1#".into(),
+            |prefix| format!("_talna#{prefix}#v1#"),
+        )
+    }
+
+    /// Periodically syncs writes to disk in a background thread, bounding
+    /// the amount of data that can be lost if the process crashes.
+    ///
+    /// The interval is clamped to 1 - 65535 milliseconds.
+    ///
+    /// Only applies to [`Builder::open`], keyspaces passed to
+    /// [`Builder::open_in_keyspace`] keep their own configuration.
+    ///
+    /// Default = disabled
+    #[must_use]
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = Some(interval);
+        self
+    }
+
     /// Opens or recovers a time series database.
     ///
     /// If you have a keyspace already in your application, you may
@@ -45,24 +413,40 @@ impl Builder {
     /// # Errors
     ///
     /// Returns error if an I/O error occurred.
-    pub fn open<P: AsRef<Path>>(self, path: P) -> crate::Result<crate::Database> {
-        let keyspace = fjall::Config::new(path)
-            .block_cache(Arc::new(BlockCache::with_capacity_bytes(
+    pub fn open<P: AsRef<Path>>(mut self, path: P) -> crate::Result<crate::Database> {
+        let block_cache = self.block_cache.clone().unwrap_or_else(|| {
+            Arc::new(BlockCache::with_capacity_bytes(
                 self.cache_size_mib * 1_024 * 1_024,
-            )))
+            ))
+        });
+        self.block_cache = Some(block_cache.clone());
+
+        let mut config = fjall::Config::new(path).block_cache(block_cache);
+
+        if let Some(mib) = self.write_buffer_size_mib {
+            config = config.max_write_buffer_size(mib * 1_024 * 1_024);
+        }
+
+        let keyspace = config
+            .fsync_ms(self.flush_interval.map(|interval| {
+                u16::try_from(interval.as_millis())
+                    .unwrap_or(u16::MAX)
+                    .max(1)
+            }))
             .open_transactional()?;
 
-        Database::from_keyspace(keyspace, self.hyper_mode)
+        Database::from_keyspace(keyspace, self)
     }
 
     /// Uses an existing `fjall` keyspace to open a time series database.
     ///
     /// Partitions are prefixed with `_talna#` to avoid name clashes with other applications.
+    /// To store multiple databases in the same keyspace, use [`Builder::partition_prefix`].
     ///
     /// # Errors
     ///
     /// Returns error if an I/O error occurred.
     pub fn open_in_keyspace(self, keyspace: TxKeyspace) -> crate::Result<crate::Database> {
-        Database::from_keyspace(keyspace, self.hyper_mode)
+        Database::from_keyspace(keyspace, self)
     }
 }
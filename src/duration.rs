@@ -1,64 +1,102 @@
+const NANOS_PER_MICRO: f64 = 1_000.0;
+const NANOS_PER_MILLI: f64 = 1_000.0 * NANOS_PER_MICRO;
+const NANOS_PER_SECOND: f64 = 1_000.0 * NANOS_PER_MILLI;
+const NANOS_PER_MINUTE: f64 = 60.0 * NANOS_PER_SECOND;
+const NANOS_PER_HOUR: f64 = 60.0 * NANOS_PER_MINUTE;
+const NANOS_PER_DAY: f64 = 24.0 * NANOS_PER_HOUR;
+const NANOS_PER_WEEK: f64 = 7.0 * NANOS_PER_DAY;
+const NANOS_PER_MONTH: f64 = 4.0 * NANOS_PER_WEEK;
+const NANOS_PER_YEAR: f64 = 12.0 * NANOS_PER_MONTH;
+
 /// Helpers for calculating durations
+///
+/// Fractional amounts are supported (e.g. `Duration::days(0.5)` is 12 hours),
+/// and rounded to the nearest nanosecond. Negative amounts result in 0.
 pub struct Duration;
 
 impl Duration {
     /// Formats N years as nanosecond time frame.
     #[must_use]
-    pub const fn years(n: f64) -> u128 {
-        Self::months(n) * 12
+    pub fn years(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_YEAR)
     }
 
     /// Formats N months as nanosecond time frame.
     #[must_use]
-    pub const fn months(n: f64) -> u128 {
-        Self::weeks(n) * 4
+    pub fn months(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_MONTH)
     }
 
     /// Formats N weeks as nanosecond time frame.
     #[must_use]
-    pub const fn weeks(n: f64) -> u128 {
-        Self::days(n) * 7
+    pub fn weeks(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_WEEK)
     }
 
     /// Formats N days as nanosecond time frame.
     #[must_use]
-    pub const fn days(n: f64) -> u128 {
-        Self::hours(n) * 24
+    pub fn days(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_DAY)
     }
 
     /// Formats N hours as nanosecond time frame.
     #[must_use]
-    pub const fn hours(n: f64) -> u128 {
-        Self::minutes(n) * 60
+    pub fn hours(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_HOUR)
     }
 
     /// Formats N minutes as nanosecond time frame.
     #[must_use]
-    pub const fn minutes(n: f64) -> u128 {
-        Self::seconds(n) * 60
+    pub fn minutes(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_MINUTE)
     }
 
     /// Formats N seconds as nanosecond time frame.
     #[must_use]
-    pub const fn seconds(n: f64) -> u128 {
-        Self::millis(n) * 1_000
+    pub fn seconds(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_SECOND)
     }
 
     /// Formats N milliseconds as nanosecond time frame.
     #[must_use]
-    pub const fn millis(n: f64) -> u128 {
-        Self::micros(n) * 1_000
+    pub fn millis(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_MILLI)
     }
 
     /// Formats N microseconds as nanosecond time frame.
     #[must_use]
-    pub const fn micros(n: f64) -> u128 {
-        Self::nanos(n) * 1_000
+    pub fn micros(n: f64) -> u128 {
+        Self::nanos(n * NANOS_PER_MICRO)
     }
 
     /// Formats N nanoseconds as nanosecond time frame.
     #[must_use]
-    pub const fn nanos(n: f64) -> u128 {
-        n as u128
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn nanos(n: f64) -> u128 {
+        // NOTE: Float to int casts saturate, so negative amounts become 0
+        n.round() as u128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn duration_whole() {
+        assert_eq!(1_000, Duration::micros(1.0));
+        assert_eq!(60_000_000_000, Duration::minutes(1.0));
+        assert_eq!(86_400_000_000_000, Duration::days(1.0));
+        assert_eq!(28 * Duration::days(1.0), Duration::months(1.0));
+        assert_eq!(12 * Duration::months(1.0), Duration::years(1.0));
+    }
+
+    #[test_log::test]
+    fn duration_fractional() {
+        assert_eq!(Duration::hours(12.0), Duration::days(0.5));
+        assert_eq!(Duration::millis(100.0), Duration::seconds(0.1));
+        assert_eq!(Duration::minutes(90.0), Duration::hours(1.5));
+        assert_eq!(2, Duration::nanos(1.5));
+        assert_eq!(0, Duration::seconds(-1.0));
     }
 }
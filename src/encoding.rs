@@ -0,0 +1,134 @@
+use crate::Value;
+
+/// On-disk encoding of a metric's data points
+///
+/// The encoding is only applied when writing; data points with different
+/// encodings can be mixed in a single series, so the encoding of a metric
+/// can be changed at any time.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ValueEncoding {
+    /// Stores values as [`Value`] (lossless)
+    #[default]
+    Full,
+
+    /// Stores values as half-precision floats (f16)
+    ///
+    /// Half-precision floats have around 3 significant decimal digits,
+    /// and a maximum value of 65504, so this is only suitable for metrics
+    /// where ~0.1% error is acceptable (e.g. temperatures, utilization percentages).
+    ///
+    /// Values that do not fit into a half-precision float (because they are too
+    /// large or too close to zero) are stored using [`ValueEncoding::Full`].
+    Half,
+}
+
+/// Size of a raw value
+///
+/// Raw values are stored untagged, as they were before format tags existed,
+/// every other format starts with its format tag, and is never this long.
+pub const VALUE_LEN: usize = std::mem::size_of::<Value>();
+
+/// Format tag of a half-precision value
+pub const HALF_TAG: u8 = 1;
+
+/// Format tag of a pre-aggregated sample, see [`crate::Stat`]
+pub const STAT_TAG: u8 = 2;
+
+/// Size of a half-precision value, including its format tag
+pub const HALF_LEN: usize = 1 + std::mem::size_of::<half::f16>();
+
+const _: () = assert!(HALF_LEN != VALUE_LEN);
+
+/// A value encoded according to its [`ValueEncoding`]
+pub enum EncodedValue {
+    Full([u8; VALUE_LEN]),
+    Half([u8; HALF_LEN]),
+}
+
+impl AsRef<[u8]> for EncodedValue {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Full(bytes) => bytes,
+            Self::Half(bytes) => bytes,
+        }
+    }
+}
+
+impl ValueEncoding {
+    pub(crate) fn encode(self, value: Value) -> EncodedValue {
+        match self {
+            Self::Full => EncodedValue::Full(value.to_be_bytes()),
+            Self::Half => {
+                #[cfg(feature = "high_precision")]
+                let half = half::f16::from_f64(value);
+
+                #[cfg(not(feature = "high_precision"))]
+                let half = half::f16::from_f32(value);
+
+                // NOTE: Keep values that would overflow to infinity or underflow to zero
+                let overflows = value.is_finite() && !half.is_finite();
+                let underflows = value != 0.0 && half == half::f16::ZERO;
+
+                if overflows || underflows {
+                    return Self::Full.encode(value);
+                }
+
+                let [a, b] = half.to_be_bytes();
+                EncodedValue::Half([HALF_TAG, a, b])
+            }
+        }
+    }
+}
+
+/// Decodes a half-precision value, without its format tag
+pub fn decode_half(bytes: [u8; HALF_LEN - 1]) -> Value {
+    let value = half::f16::from_be_bytes(bytes);
+
+    #[cfg(feature = "high_precision")]
+    let value = value.to_f64();
+
+    #[cfg(not(feature = "high_precision"))]
+    let value = value.to_f32();
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn encoding_half() {
+        let EncodedValue::Half([HALF_TAG, a, b]) = ValueEncoding::Half.encode(21.5) else {
+            panic!("should be half-precision");
+        };
+        assert_eq!(21.5, decode_half([a, b]));
+
+        let EncodedValue::Half([HALF_TAG, a, b]) = ValueEncoding::Half.encode(21.51) else {
+            panic!("should be half-precision");
+        };
+        assert!((decode_half([a, b]) - 21.51).abs() < 0.01);
+
+        let EncodedValue::Half([HALF_TAG, a, b]) = ValueEncoding::Half.encode(0.0) else {
+            panic!("should be half-precision");
+        };
+        assert_eq!(0.0, decode_half([a, b]));
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn encoding_half_fallback() {
+        for value in [100_000.0, -100_000.0, 1e-10] {
+            let EncodedValue::Full(bytes) = ValueEncoding::Half.encode(value) else {
+                panic!("should fall back to full precision");
+            };
+            assert_eq!(value, Value::from_be_bytes(bytes));
+        }
+
+        assert!(matches!(
+            ValueEncoding::Half.encode(Value::INFINITY),
+            EncodedValue::Half(_),
+        ));
+    }
+}
@@ -9,6 +9,70 @@ pub enum Error {
 
     /// An invalid filter query was used.
     InvalidQuery,
+
+    /// The query did not finish before its timeout.
+    Timeout,
+
+    /// An invalid tag set was used to create a series.
+    InvalidTagSet(crate::TagSetError),
+
+    /// An invalid metric name was used, see [`crate::Database::write_struct`].
+    InvalidMetricName(crate::MetricNameError),
+
+    /// A series does not have the `group_by` tag, see [`crate::MissingTagPolicy::Error`].
+    MissingTag(String),
+
+    /// The database exceeds its storage quota, see [`crate::DatabaseBuilder::max_disk_space`].
+    QuotaExceeded,
+
+    /// The tag keys of a write do not match the declared tag keys of its metric,
+    /// see [`crate::Database::declare_metric`].
+    SchemaViolation {
+        /// Name of the metric
+        metric: String,
+
+        /// Declared tag keys the write is missing
+        missing: Vec<String>,
+
+        /// Tag keys of the write that are not declared
+        unexpected: Vec<String>,
+    },
+
+    /// A data point's timestamp is outside the configured bounds, see [`crate::DatabaseBuilder::timestamp_bounds`].
+    TimestampOutOfRange(crate::Timestamp),
+
+    /// The series of a [`crate::SeriesWriter`] was removed, see [`crate::Database::gc_idle_series`].
+    SeriesRemoved(crate::SeriesId),
+
+    /// A stored value could not be deserialized.
+    Corruption {
+        /// Name of the partition the value was read from
+        partition: String,
+
+        /// Key of the malformed value
+        key: Vec<u8>,
+    },
+}
+
+impl Error {
+    pub(crate) fn corruption(partition: &str, key: impl AsRef<[u8]>) -> Self {
+        Self::Corruption {
+            partition: partition.into(),
+            key: key.as_ref().into(),
+        }
+    }
+}
+
+impl From<crate::TagSetError> for Error {
+    fn from(value: crate::TagSetError) -> Self {
+        Self::InvalidTagSet(value)
+    }
+}
+
+impl From<crate::MetricNameError> for Error {
+    fn from(value: crate::MetricNameError) -> Self {
+        Self::InvalidMetricName(value)
+    }
 }
 
 impl From<fjall::Error> for Error {
@@ -17,6 +81,12 @@ impl From<fjall::Error> for Error {
     }
 }
 
+impl From<fjall::LsmError> for Error {
+    fn from(value: fjall::LsmError) -> Self {
+        Self::Storage(value.into())
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self::Io(value)
@@ -27,13 +97,51 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Storage(e) => {
-                write!(f, "{e}",)
+                write!(f, "{e}")
             }
             Self::Io(e) => {
-                write!(f, "{e}",)
+                write!(f, "{e}")
             }
             Self::InvalidQuery => {
-                write!(f, "InvalidQuery",)
+                write!(f, "InvalidQuery")
+            }
+            Self::Timeout => {
+                write!(f, "Timeout")
+            }
+            Self::InvalidTagSet(e) => {
+                write!(f, "InvalidTagSet: {e}")
+            }
+            Self::InvalidMetricName(e) => {
+                write!(f, "InvalidMetricName: {e}")
+            }
+            Self::MissingTag(tag) => {
+                write!(f, "MissingTag: series without tag {tag:?}")
+            }
+            Self::QuotaExceeded => {
+                write!(f, "QuotaExceeded")
+            }
+            Self::SchemaViolation {
+                metric,
+                missing,
+                unexpected,
+            } => {
+                write!(
+                    f,
+                    "SchemaViolation: {metric:?} (missing: {missing:?}, unexpected: {unexpected:?})"
+                )
+            }
+            Self::TimestampOutOfRange(ts) => {
+                write!(f, "TimestampOutOfRange: {ts}")
+            }
+            Self::SeriesRemoved(series_id) => {
+                write!(f, "SeriesRemoved: series {series_id} was removed")
+            }
+            Self::Corruption { partition, key } => {
+                write!(
+                    f,
+                    "Corruption: malformed value in {partition:?} at key {:?}",
+                    String::from_utf8_lossy(key),
+                )
             }
         }
     }
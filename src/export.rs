@@ -0,0 +1,125 @@
+use crate::Timestamp;
+
+/// Position of a resumable export, see [`crate::Database::export_from`]
+///
+/// Can be serialized into a token using `to_string` (and parsed back using `parse`),
+/// so an export job can persist its progress, and continue where it left off after a crash.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use talna::{Database, ExportCursor, MetricName, tagset};
+///
+/// let db = Database::builder().open(&folder)?;
+/// let metric_name = MetricName::try_from("cpu.total").unwrap();
+///
+/// for ts in 0..10 {
+///     db.write_at(metric_name, ts, 4.0, tagset!("host" => "h-1"))?;
+/// }
+///
+/// let mut out = vec![];
+/// let (count, cursor) = db.export_from(&mut out, None, 6)?;
+/// assert_eq!(6, count);
+///
+/// // NOTE: Persist the token, e.g. next to the exported file
+/// let token = cursor.unwrap().to_string();
+///
+/// let cursor = token.parse::<ExportCursor>()?;
+/// let (count, cursor) = db.export_from(&mut out, Some(&cursor), 6)?;
+/// assert_eq!(4, count);
+/// assert!(cursor.is_none());
+/// #
+/// # Ok::<(), talna::Error>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportCursor {
+    /// Series key of the last exported data point
+    pub(crate) series_key: String,
+
+    /// Timestamp of the last exported data point
+    ///
+    /// Series are exported from newest to oldest, so the export continues
+    /// with the next older data point.
+    pub(crate) ts: Timestamp,
+}
+
+impl std::fmt::Display for ExportCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // NOTE: The timestamp comes first, because series keys may contain any character
+        write!(f, "{}:{}", self.ts, self.series_key)
+    }
+}
+
+impl std::str::FromStr for ExportCursor {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid export cursor {s:?}"),
+            )
+        };
+
+        let (ts, series_key) = s.split_once(':').ok_or_else(invalid)?;
+
+        if series_key.is_empty() {
+            return Err(invalid().into());
+        }
+
+        Ok(Self {
+            series_key: series_key.into(),
+            ts: ts.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Formats the series key (`metric#key:value;...`) as the start of
+/// a line protocol line (`metric,key=value,...`)
+pub fn line_protocol_prefix(series_key: &str) -> String {
+    let (metric, tags) = series_key.split_once('#').unwrap_or((series_key, ""));
+
+    let mut prefix = metric.to_string();
+
+    for tag in tags.split(';').filter(|tag| !tag.is_empty()) {
+        let (key, value) = tag.split_once(':').unwrap_or((tag, ""));
+        prefix.push(',');
+        prefix.push_str(key);
+        prefix.push('=');
+        prefix.push_str(value);
+    }
+
+    prefix
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn export_cursor_token() {
+        let cursor = ExportCursor {
+            series_key: "cpu.total#env:prod;url:http://a".into(),
+            ts: 1_700_000_000_000_000_000,
+        };
+
+        let token = cursor.to_string();
+        assert_eq!(cursor, token.parse().unwrap());
+
+        assert!("".parse::<ExportCursor>().is_err());
+        assert!("123".parse::<ExportCursor>().is_err());
+        assert!("123:".parse::<ExportCursor>().is_err());
+        assert!("abc:cpu#".parse::<ExportCursor>().is_err());
+    }
+
+    #[test_log::test]
+    fn line_protocol_prefix_tags() {
+        assert_eq!("cpu", line_protocol_prefix("cpu#"));
+        assert_eq!(
+            "cpu,env=prod,host=h-1",
+            line_protocol_prefix("cpu#env:prod;host:h-1")
+        );
+    }
+}
@@ -6,6 +6,22 @@
 //!
 //! Data points are f32s by default, but can be switched to f64 using the `high_precision` feature flag.
 //!
+//! A minimal embedded HTTP API for ingestion and queries is available using the `server` feature flag.
+//!
+//! An OpenTelemetry metrics exporter is available using the `otel` feature flag.
+//!
+//! A recorder for the [`metrics`](https://docs.rs/metrics) crate is available using the `metrics` feature flag.
+//!
+//! A `StatsD` UDP listener is available using the `statsd` feature flag.
+//!
+//! Groups can be aggregated in parallel (`collect_parallel`) using the `rayon` feature flag.
+//!
+//! Queries and (sampled) writes are instrumented with [`tracing`](https://docs.rs/tracing) spans using the `tracing` feature flag.
+//!
+//! Buckets can be aligned to time zones of the tz database (see [`TimeZone`]) using the `tz` feature flag.
+//!
+//! Structs can be written using `#[derive(Metric)]` (see [`Database::write_struct`]) using the `derive` feature flag, as well as `#[derive(TagSet)]` for tag structs (see [`ToTagSet`]).
+//!
 //! ## Basic usage
 //!
 //! ```
@@ -70,33 +86,144 @@
 #![warn(clippy::result_unit_err)]
 #![warn(clippy::needless_lifetimes)]
 
+// NOTE: Declared first, so the span macros can be used in all other modules
+#[macro_use]
+mod trace;
+
 mod agg;
+mod aliases;
+mod archive;
+mod audit;
+
+#[cfg(feature = "arrow")]
+mod columnar;
+
 mod db;
 mod db_builder;
 mod duration;
+mod encoding;
 mod error;
+mod export;
+
+mod line_protocol;
+
+mod memory;
 mod merge;
+mod metadata;
+mod metric;
 mod metric_name;
+mod observer;
+mod orphans;
+mod output;
+mod point_counts;
+mod pre_agg;
+mod progress;
+
+#[cfg(feature = "otel")]
+mod otel;
+
+#[cfg(feature = "metrics")]
+mod recorder;
+
+#[cfg(any(feature = "otel", feature = "metrics", feature = "statsd"))]
+mod sanitize;
 
 #[doc(hidden)]
 pub mod query;
 
+mod query_cache;
+mod quota;
+mod schema;
+
+mod series_bounds;
 mod series_key;
+mod series_lock;
+mod series_stream;
+mod series_writer;
+
+#[cfg(feature = "server")]
+mod server;
+
+mod sketch;
 mod smap;
+mod stat;
+mod storage;
+
+#[cfg(feature = "statsd")]
+mod statsd;
+
+mod suggest;
 mod tag_index;
 mod tag_sets;
+mod tagset;
+mod tier;
 mod time;
+mod timer;
+mod tombstones;
 
 type SeriesId = u64;
 type HashMap<K, V> = std::collections::HashMap<K, V, rustc_hash::FxBuildHasher>;
 
-pub use agg::{Bucket, GroupedAggregation};
-pub use db::Database;
+pub use agg::{
+    Agg, Aggregation, Bucket, Builder as AggregationBuilder, GroupMetadata, GroupedAggregation,
+    JoinBuilder, JoinStream, MissingDataPolicy, MissingTagPolicy, QuerySpec, SummaryBucket,
+    TimeZone, ValueFilter,
+};
+pub use archive::ArchiveSink;
+pub use audit::{AuditSink, RemovalEvent, RemovalReason, RemovedRange};
+pub use db::{Database, StreamItem};
 pub use db_builder::Builder as DatabaseBuilder;
 pub use duration::Duration;
+pub use encoding::ValueEncoding;
 pub use error::{Error, Result};
-pub use metric_name::MetricName;
+pub use export::ExportCursor;
+pub use memory::MemoryUsage;
+pub use merge::Merger;
+pub use metadata::MetricMetadata;
+pub use metric::Metric;
+pub use metric_name::{MetricGlob, MetricName, MetricNameBuf, MetricNameError, MetricSelector};
+pub use observer::{WriteObserver, WriteStats};
+pub use progress::Progress;
+pub use query::filter::Filter;
+pub use query::grammar::{GrammarVersion, SyntaxError};
+pub use query::planner::{IndexStatistics, NaivePlanner, QueryPlanner, SelectivityPlanner};
+pub use schema::SchemaPolicy;
+pub use series_stream::SeriesStream;
+pub use series_writer::SeriesWriter;
+pub use sketch::QuantileSketch;
+pub use stat::Stat;
+pub use storage::{MemoryStorage, Storage, StorageIter, StoragePartition, StorageSnapshot};
+pub use tagset::{TagSetBuf, TagSetError, ToTagSet};
 pub use time::timestamp;
+pub use timer::{DurationUnit, Timer};
+pub use tombstones::Tombstone;
+
+#[cfg(feature = "derive")]
+pub use talna_derive::{Metric, TagSet};
+
+// NOTE: Derived impls refer to `::talna`, which needs to resolve in our own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as talna;
+
+#[cfg(feature = "otel")]
+pub use otel::OtelExporter;
+
+#[cfg(feature = "metrics")]
+pub use recorder::MetricsRecorder;
+
+#[cfg(feature = "server")]
+pub use server::Server;
+
+#[cfg(feature = "statsd")]
+pub use statsd::StatsdListener;
+
+// NOTE: Re-exported, so record batches can be consumed with a matching Arrow version
+#[cfg(feature = "arrow")]
+pub use arrow_array;
+
+// NOTE: Re-exported, so time zones can be passed with a matching jiff version
+#[cfg(feature = "tz")]
+pub use jiff;
 
 /// A list of tags.
 pub type TagSet<'a> = [(&'a str, &'a str)];
@@ -117,6 +244,8 @@ pub type Value = f32;
 
 /// Macro to create a list of tags.
 ///
+/// Use [`TagSetBuf`] to validate and pre-sort a list of tags.
+///
 /// # Examples
 ///
 /// ```
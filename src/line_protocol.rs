@@ -0,0 +1,105 @@
+use crate::{MetricName, Timestamp, Value};
+
+/// A data point parsed from a line of the line protocol
+///
+/// `<metric>[,<key>=<value>...] <value> [<timestamp>]`
+///
+/// e.g. `cpu.total,env=prod,host=h-1 25.42 1700000000000000000`
+///
+/// If the timestamp is omitted, the current time is used.
+#[derive(Debug, PartialEq)]
+pub struct Line<'a> {
+    pub metric: MetricName<'a>,
+    pub tags: Vec<(&'a str, &'a str)>,
+    pub value: Value,
+    pub ts: Option<Timestamp>,
+}
+
+impl<'a> Line<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, String> {
+        let mut fields = line.split_ascii_whitespace();
+
+        let Some(series) = fields.next() else {
+            return Err("missing metric name".into());
+        };
+
+        let mut series = series.split(',');
+
+        let metric = series.next().unwrap_or_default();
+        let metric = MetricName::try_from(metric).map_err(|e| format!("{e}: {metric:?}"))?;
+
+        let tags = series
+            .map(|tag| {
+                tag.split_once('=')
+                    .filter(|(k, v)| !k.is_empty() && !v.is_empty())
+                    .ok_or_else(|| format!("invalid tag {tag:?}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(value) = fields.next() else {
+            return Err("missing value".into());
+        };
+        let value = value
+            .parse::<Value>()
+            .map_err(|_| format!("invalid value {value:?}"))?;
+
+        let ts = fields
+            .next()
+            .map(|ts| {
+                ts.parse::<Timestamp>()
+                    .map_err(|_| format!("invalid timestamp {ts:?}"))
+            })
+            .transpose()?;
+
+        if fields.next().is_some() {
+            return Err("unexpected trailing field".into());
+        }
+
+        Ok(Self {
+            metric,
+            tags,
+            value,
+            ts,
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn line_protocol_parse() {
+        assert_eq!(
+            Line {
+                metric: MetricName::try_from("cpu.total").unwrap(),
+                tags: vec![("env", "prod"), ("host", "h-1")],
+                value: 25.5,
+                ts: Some(1_000),
+            },
+            Line::parse("cpu.total,env=prod,host=h-1 25.5 1000").unwrap(),
+        );
+
+        assert_eq!(
+            Line {
+                metric: MetricName::try_from("cpu.total").unwrap(),
+                tags: vec![],
+                value: 1.0,
+                ts: None,
+            },
+            Line::parse("cpu.total 1").unwrap(),
+        );
+    }
+
+    #[test_log::test]
+    fn line_protocol_parse_invalid() {
+        assert!(Line::parse("").is_err());
+        assert!(Line::parse("cpu.total").is_err());
+        assert!(Line::parse("cpu.total abc").is_err());
+        assert!(Line::parse("cpu.total,env 1").is_err());
+        assert!(Line::parse("cpu.total 1 -5").is_err());
+        assert!(Line::parse("cpu.total 1 5 6").is_err());
+        assert!(Line::parse("cpu-total 1").is_err());
+    }
+}
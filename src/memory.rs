@@ -0,0 +1,44 @@
+/// Approximate memory consumption of a database in bytes, see [`crate::Database::memory_usage`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryUsage {
+    /// Cached disk blocks, see [`crate::DatabaseBuilder::cache_size_mib`]
+    ///
+    /// The block cache may be shared with other users of the keyspace, and is unknown (0)
+    /// for databases opened in an existing keyspace, unless passed using
+    /// [`crate::DatabaseBuilder::block_cache`].
+    pub block_cache: u64,
+
+    /// Unflushed writes in memtables, see [`crate::DatabaseBuilder::write_buffer_size_mib`]
+    ///
+    /// Includes memtables of all partitions in the keyspace.
+    pub write_buffer: u64,
+
+    /// Cached postings lists, see [`crate::DatabaseBuilder::postings_cache_size_mib`]
+    pub postings_cache: u64,
+
+    /// Cached tag sets, see [`crate::DatabaseBuilder::tag_set_cache_size_mib`]
+    pub tag_set_cache: u64,
+
+    /// Cached series keys, see [`crate::DatabaseBuilder::series_cache_size_mib`]
+    pub series_cache: u64,
+
+    /// Cached query results, see [`crate::DatabaseBuilder::query_cache`]
+    pub query_cache: u64,
+
+    /// Buffered windows of pre-aggregated metrics, see [`crate::DatabaseBuilder::pre_aggregate`]
+    pub pre_aggregation: u64,
+}
+
+impl MemoryUsage {
+    /// Returns the sum of all components.
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.block_cache
+            + self.write_buffer
+            + self.postings_cache
+            + self.tag_set_cache
+            + self.series_cache
+            + self.query_cache
+            + self.pre_aggregation
+    }
+}
@@ -19,8 +19,10 @@ impl PartialOrd for HeapItem {
 }
 
 impl Ord for HeapItem {
+    // NOTE: Streams are scanned from newest to oldest,
+    // so the newest data point needs to be popped first
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.1.ts.cmp(&self.1.ts)
+        self.1.ts.cmp(&other.1.ts)
     }
 }
 
@@ -33,6 +35,38 @@ macro_rules! fail_iter {
     };
 }
 
+/// Merges multiple series streams into a single stream
+///
+/// Each stream is expected to return its data points ordered by
+/// timestamp in descending order (newest first), which is the order series
+/// are scanned in. The merged stream is ordered the same way.
+///
+/// # Examples
+///
+/// ```
+/// use talna::{Merger, StreamItem};
+///
+/// let a = vec![
+///     StreamItem { series_id: 0, ts: 5, value: 1.0, stat: None, sketch: None },
+///     StreamItem { series_id: 0, ts: 1, value: 2.0, stat: None, sketch: None },
+/// ];
+/// let b = vec![
+///     StreamItem { series_id: 1, ts: 3, value: 3.0, stat: None, sketch: None },
+/// ];
+///
+/// let merger = Merger::new(vec![
+///     a.into_iter().map(Ok),
+///     b.into_iter().map(Ok),
+/// ]);
+///
+/// let timestamps = merger
+///     .map(|item| item.map(|item| item.ts))
+///     .collect::<talna::Result<Vec<_>>>()?;
+///
+/// assert_eq!(vec![5, 3, 1], timestamps);
+/// #
+/// # Ok::<(), talna::Error>(())
+/// ```
 pub struct Merger<I: Iterator<Item = crate::Result<StreamItem>>> {
     readers: Vec<I>,
     heap: BinaryHeap<HeapItem>,
@@ -40,6 +74,8 @@ pub struct Merger<I: Iterator<Item = crate::Result<StreamItem>>> {
 }
 
 impl<I: Iterator<Item = crate::Result<StreamItem>>> Merger<I> {
+    /// Creates a new merger over the given streams.
+    #[must_use]
     pub fn new(readers: Vec<I>) -> Self {
         Self {
             readers,
@@ -49,7 +85,7 @@ impl<I: Iterator<Item = crate::Result<StreamItem>>> Merger<I> {
     }
 
     fn advance(&mut self, idx: usize) -> crate::Result<()> {
-        if let Some(item) = self.readers.get_mut(idx).expect("should exist").next() {
+        if let Some(item) = self.readers.get_mut(idx).and_then(Iterator::next) {
             self.heap.push(HeapItem(idx, item?));
         }
         Ok(())
@@ -74,3 +110,37 @@ impl<I: Iterator<Item = crate::Result<StreamItem>>> Iterator for Merger<I> {
         Some(Ok(head.1))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE: Merged iterators yield results
+    #[allow(clippy::unnecessary_wraps)]
+    fn item(series_id: crate::SeriesId, ts: crate::Timestamp) -> crate::Result<StreamItem> {
+        Ok(StreamItem {
+            series_id,
+            ts,
+            value: 0.0,
+            stat: None,
+            sketch: None,
+        })
+    }
+
+    #[test_log::test]
+    fn merge_newest_first() -> crate::Result<()> {
+        let merger = Merger::new(vec![
+            vec![item(0, 8), item(0, 4), item(0, 1)].into_iter(),
+            vec![item(1, 7), item(1, 6), item(1, 2)].into_iter(),
+            vec![item(2, 5), item(2, 3)].into_iter(),
+        ]);
+
+        let timestamps = merger
+            .map(|item| item.map(|item| item.ts))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        assert_eq!(vec![8, 7, 6, 5, 4, 3, 2, 1], timestamps);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::MetricName;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::io::Read;
+use std::sync::{PoisonError, RwLock};
+
+const PARTITION_NAME: &str = "mmeta";
+
+/// Unit & description of a metric, see [`crate::Database::describe_metric`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MetricMetadata {
+    /// Unit of the metric's values (e.g. `ms`, `bytes`, `%`)
+    pub unit: String,
+
+    /// Human-readable description of the metric
+    pub description: String,
+}
+
+impl MetricMetadata {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.unit.len() + self.description.len());
+
+        // NOTE: Units are short, so the length fits
+        #[allow(clippy::cast_possible_truncation)]
+        let _ = bytes.write_u16::<BigEndian>(self.unit.len() as u16);
+        bytes.extend_from_slice(self.unit.as_bytes());
+        bytes.extend_from_slice(self.description.as_bytes());
+
+        bytes
+    }
+
+    fn deserialize(mut bytes: &[u8]) -> std::io::Result<Self> {
+        let len = bytes.read_u16::<BigEndian>()?;
+
+        let mut unit = vec![0; usize::from(len)];
+        bytes.read_exact(&mut unit)?;
+
+        Ok(Self {
+            unit: String::from_utf8_lossy(&unit).into_owned(),
+            description: String::from_utf8_lossy(bytes).into_owned(),
+        })
+    }
+}
+
+/// Stores the metadata of metrics
+///
+/// Kept in memory, because there is at most one (small) entry per metric.
+pub struct MetricMetadataStore {
+    partition: TxPartition,
+    map: RwLock<crate::HashMap<String, MetricMetadata>>,
+}
+
+impl MetricMetadataStore {
+    pub fn new(keyspace: &TxKeyspace, prefix: &str) -> crate::Result<Self> {
+        let opts = PartitionCreateOptions::default()
+            .block_size(4_096)
+            .compression(CompressionType::Lz4);
+
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        let mut map = crate::HashMap::default();
+
+        for kv in keyspace.read_tx().iter(&partition) {
+            let (k, v) = kv?;
+            let metric = String::from_utf8_lossy(&k).into_owned();
+            map.insert(metric, MetricMetadata::deserialize(&v)?);
+        }
+
+        Ok(Self {
+            partition,
+            map: RwLock::new(map),
+        })
+    }
+
+    pub fn insert(&self, metric: MetricName, metadata: MetricMetadata) -> crate::Result<()> {
+        let mut map = self.map.write().unwrap_or_else(PoisonError::into_inner);
+        self.partition.insert(*metric, metadata.serialize())?;
+        map.insert(metric.to_string(), metadata);
+        drop(map);
+
+        Ok(())
+    }
+
+    pub fn get(&self, metric: &str) -> Option<MetricMetadata> {
+        // NOTE: The map is never left in an inconsistent state, so poisoning can be ignored
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(metric)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn metric_metadata_roundtrip() -> crate::Result<()> {
+        let metadata = MetricMetadata {
+            unit: "ms".into(),
+            description: "Request latency: time until the first byte".into(),
+        };
+
+        assert_eq!(
+            metadata,
+            MetricMetadata::deserialize(&metadata.serialize())?
+        );
+
+        assert_eq!(
+            MetricMetadata::default(),
+            MetricMetadata::deserialize(&MetricMetadata::default().serialize())?
+        );
+
+        assert!(MetricMetadata::deserialize(&[0, 5, b'm']).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use crate::Value;
+
+/// A struct whose fields are written as data points of one or more metrics,
+/// see [`crate::Database::write_struct`].
+///
+/// Can be derived using `#[derive(Metric)]` with the `derive` feature flag.
+///
+/// # Examples
+///
+/// ```
+/// use talna::{Metric, Value};
+///
+/// struct CpuSample {
+///     host: String,
+///     user: Value,
+///     system: Value,
+/// }
+///
+/// impl Metric for CpuSample {
+///     fn tags(&self) -> Vec<(&str, &str)> {
+///         vec![("host", self.host.as_str())]
+///     }
+///
+///     fn values(&self) -> Vec<(&'static str, Value)> {
+///         vec![("cpu.user", self.user), ("cpu.system", self.system)]
+///     }
+/// }
+/// ```
+pub trait Metric {
+    /// Returns the tags of all data points.
+    fn tags(&self) -> Vec<(&str, &str)>;
+
+    /// Returns the metric name and value of every data point.
+    fn values(&self) -> Vec<(&'static str, Value)>;
+}
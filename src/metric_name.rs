@@ -1,4 +1,36 @@
-const METRICS_NAME_CHARS: &str = "abcdefghijklmnopqrstuvwxyz_.";
+/// Returns `true` if the character is allowed in a metric name.
+pub fn is_valid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Error returned when parsing an invalid metric name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetricNameError {
+    /// The invalid character
+    pub char: char,
+
+    /// Byte position of the invalid character
+    pub position: usize,
+}
+
+impl std::fmt::Display for MetricNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid character {:?} at position {} in metric name (allowed: a-z A-Z 0-9 . _)",
+            self.char, self.position,
+        )
+    }
+}
+
+impl std::error::Error for MetricNameError {}
+
+fn validate(name: &str) -> Result<(), MetricNameError> {
+    match name.char_indices().find(|(_, c)| !is_valid_char(*c)) {
+        Some((position, char)) => Err(MetricNameError { char, position }),
+        None => Ok(()),
+    }
+}
 
 /// A metric's name.
 ///
@@ -6,21 +38,18 @@ const METRICS_NAME_CHARS: &str = "abcdefghijklmnopqrstuvwxyz_.";
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash, Debug)]
 pub struct MetricName<'a>(&'a str);
 
-impl<'a> std::fmt::Display for MetricName<'a> {
+impl std::fmt::Display for MetricName<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
 impl<'a> TryFrom<&'a str> for MetricName<'a> {
-    type Error = ();
+    type Error = MetricNameError;
 
     fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        if value.chars().any(|c| !METRICS_NAME_CHARS.contains(c)) {
-            Err(())
-        } else {
-            Ok(Self(value))
-        }
+        validate(value)?;
+        Ok(Self(value))
     }
 }
 
@@ -32,8 +61,220 @@ impl<'a> std::ops::Deref for MetricName<'a> {
     }
 }
 
-impl<'a> AsRef<[u8]> for MetricName<'a> {
+impl AsRef<[u8]> for MetricName<'_> {
     fn as_ref(&self) -> &[u8] {
         self.as_bytes()
     }
 }
+
+/// An owned metric name, see [`MetricName`].
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash, Debug)]
+pub struct MetricNameBuf(String);
+
+impl MetricNameBuf {
+    /// Borrows the metric name.
+    #[must_use]
+    pub fn as_metric_name(&self) -> MetricName<'_> {
+        MetricName(&self.0)
+    }
+}
+
+impl std::fmt::Display for MetricNameBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl TryFrom<String> for MetricNameBuf {
+    type Error = MetricNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate(&value)?;
+        Ok(Self(value))
+    }
+}
+
+impl TryFrom<&str> for MetricNameBuf {
+    type Error = MetricNameError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        MetricName::try_from(value).map(Self::from)
+    }
+}
+
+impl<'a> From<MetricName<'a>> for MetricNameBuf {
+    fn from(value: MetricName<'a>) -> Self {
+        Self(value.0.to_owned())
+    }
+}
+
+impl<'a> From<&'a MetricNameBuf> for MetricName<'a> {
+    fn from(value: &'a MetricNameBuf) -> Self {
+        value.as_metric_name()
+    }
+}
+
+impl std::ops::Deref for MetricNameBuf {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for MetricNameBuf {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// A pattern matching multiple metric names, using `*` as wildcard (e.g. `cpu.*`)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash, Debug)]
+pub struct MetricGlob<'a>(&'a str);
+
+impl<'a> MetricGlob<'a> {
+    pub(crate) fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Returns the part of the pattern before the first wildcard.
+    pub(crate) fn prefix(&self) -> &'a str {
+        self.0.split('*').next().unwrap_or_default()
+    }
+
+    /// Returns `true` if the metric name matches the pattern.
+    #[must_use]
+    pub fn matches(&self, name: &str) -> bool {
+        let mut parts = self.0.split('*');
+
+        // NOTE: split always returns at least one item
+        let first = parts.next().unwrap_or_default();
+
+        let Some(mut rest) = name.strip_prefix(first) else {
+            return false;
+        };
+
+        let mut parts = parts.peekable();
+
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                return rest.ends_with(part);
+            }
+
+            match rest.split_once(part) {
+                Some((_, after)) => rest = after,
+                None => return false,
+            }
+        }
+
+        // NOTE: Pattern without wildcard
+        rest.is_empty()
+    }
+}
+
+impl std::fmt::Display for MetricGlob<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for MetricGlob<'a> {
+    type Error = MetricNameError;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        match value
+            .char_indices()
+            .find(|(_, c)| *c != '*' && !is_valid_char(*c))
+        {
+            Some((position, char)) => Err(MetricNameError { char, position }),
+            None => Ok(Self(value)),
+        }
+    }
+}
+
+/// The metric(s) an aggregation query reads from
+#[derive(Clone, Copy, Debug)]
+pub enum MetricSelector<'a> {
+    /// A single metric
+    Name(MetricName<'a>),
+
+    /// All metrics matching a pattern
+    Glob(MetricGlob<'a>),
+}
+
+impl<'a> From<MetricName<'a>> for MetricSelector<'a> {
+    fn from(value: MetricName<'a>) -> Self {
+        Self::Name(value)
+    }
+}
+
+impl<'a> From<&'a MetricNameBuf> for MetricSelector<'a> {
+    fn from(value: &'a MetricNameBuf) -> Self {
+        Self::Name(value.as_metric_name())
+    }
+}
+
+impl<'a> From<MetricGlob<'a>> for MetricSelector<'a> {
+    fn from(value: MetricGlob<'a>) -> Self {
+        Self::Glob(value)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn metric_name_valid() {
+        assert!(MetricName::try_from("cpu.total").is_ok());
+        assert!(MetricName::try_from("CPU_Total.p99").is_ok());
+        assert!(MetricNameBuf::try_from(String::from("http2.requests")).is_ok());
+    }
+
+    #[test_log::test]
+    fn metric_name_invalid() {
+        assert_eq!(
+            Err(MetricNameError {
+                char: '-',
+                position: 3,
+            }),
+            MetricName::try_from("cpu-total"),
+        );
+        assert_eq!(
+            Err(MetricNameError {
+                char: '#',
+                position: 4,
+            }),
+            MetricNameBuf::try_from("cpu.#"),
+        );
+    }
+
+    #[test_log::test]
+    fn metric_glob() {
+        let glob = MetricGlob::try_from("cpu.*").unwrap();
+        assert_eq!("cpu.", glob.prefix());
+        assert!(glob.matches("cpu.user"));
+        assert!(glob.matches("cpu."));
+        assert!(!glob.matches("cpu"));
+        assert!(!glob.matches("mem.used"));
+
+        let glob = MetricGlob::try_from("http.*.duration").unwrap();
+        assert!(glob.matches("http.server.duration"));
+        assert!(!glob.matches("http.server.requests"));
+
+        let glob = MetricGlob::try_from("cpu.total").unwrap();
+        assert!(glob.matches("cpu.total"));
+        assert!(!glob.matches("cpu.total2"));
+
+        assert!(MetricGlob::try_from("cpu-*").is_err());
+    }
+
+    #[test_log::test]
+    fn metric_name_buf() {
+        let buf = MetricNameBuf::try_from(String::from("cpu.total")).unwrap();
+        let name = buf.as_metric_name();
+        assert_eq!("cpu.total", *name);
+        assert_eq!(buf, MetricNameBuf::from(name));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{MetricName, SeriesId, TagSet};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+/// Timing information of a batch of writes, see [`WriteObserver::on_writes`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WriteStats {
+    /// The amount of writes in the batch
+    pub writes: u64,
+
+    /// The time spent in all writes of the batch
+    pub elapsed: Duration,
+}
+
+impl WriteStats {
+    /// Returns the average time spent per write.
+    #[must_use]
+    pub fn avg_latency(&self) -> Duration {
+        let writes = u32::try_from(self.writes).unwrap_or(u32::MAX).max(1);
+        self.elapsed / writes
+    }
+}
+
+/// Hooks into the write path, so embedding applications can monitor ingestion
+///
+/// Observers are called synchronously on the writing thread, so they should be cheap.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use std::sync::Arc;
+/// use talna::{Database, MetricName, TagSet, WriteObserver, WriteStats};
+///
+/// struct LogObserver;
+///
+/// impl WriteObserver for LogObserver {
+///     fn on_series_created(&self, metric: MetricName, tags: &TagSet, _: u64) {
+///         println!("new series: {metric} {tags:?}");
+///     }
+///
+///     fn on_writes(&self, stats: &WriteStats) {
+///         println!("{} writes, avg latency: {:?}", stats.writes, stats.avg_latency());
+///     }
+/// }
+///
+/// let db = Database::builder()
+///     .write_observer(Arc::new(LogObserver), 1_000)
+///     .open(&folder)?;
+/// #
+/// # Ok::<_, talna::Error>(())
+/// ```
+pub trait WriteObserver: Send + Sync {
+    /// Called after a new series was created.
+    #[allow(unused_variables)]
+    fn on_series_created(&self, metric: MetricName, tags: &TagSet, series_id: SeriesId) {}
+
+    /// Called after every Nth successful write, with the timing of the last N writes.
+    #[allow(unused_variables)]
+    fn on_writes(&self, stats: &WriteStats) {}
+}
+
+/// Accumulates write timings, and calls the observer every N writes
+pub struct ObserverState {
+    pub observer: Arc<dyn WriteObserver>,
+    every: u64,
+    writes: AtomicU64,
+    elapsed_ns: AtomicU64,
+}
+
+impl ObserverState {
+    pub fn new(observer: Arc<dyn WriteObserver>, every: u64) -> Self {
+        Self {
+            observer,
+            every: every.max(1),
+            writes: AtomicU64::new(0),
+            elapsed_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// Runs the write, and records its timing
+    pub fn observe<T>(&self, f: impl FnOnce() -> crate::Result<T>) -> crate::Result<T> {
+        let start = Instant::now();
+        let result = f()?;
+
+        let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed_ns.fetch_add(elapsed, Ordering::Relaxed);
+
+        let writes = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if writes % self.every == 0 {
+            // NOTE: Concurrent writes may be attributed to the next batch, which is fine for monitoring
+            let elapsed = self.elapsed_ns.swap(0, Ordering::Relaxed);
+
+            self.observer.on_writes(&WriteStats {
+                writes: self.every,
+                elapsed: Duration::from_nanos(elapsed),
+            });
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::SeriesId;
+use byteorder::{BigEndian, ReadBytesExt};
+use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition};
+
+const PARTITION_NAME: &str = "orphans";
+
+/// Stores series that were removed while their data points were kept on disk,
+/// so a later GC run can remove the data points, see [`crate::Database::gc_idle_series`]
+///
+/// Series IDs are never reused, so the data points of an orphaned series
+/// can not belong to a series that is created later.
+pub struct OrphanedSeries {
+    partition: TxPartition,
+}
+
+impl OrphanedSeries {
+    pub fn new(keyspace: &TxKeyspace, prefix: &str) -> crate::Result<Self> {
+        let opts = PartitionCreateOptions::default()
+            .block_size(4_096)
+            .compression(CompressionType::Lz4);
+
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        Ok(Self { partition })
+    }
+
+    pub fn insert(&self, series_id: SeriesId, metric: &str) -> crate::Result<()> {
+        self.partition.insert(series_id.to_be_bytes(), metric)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, series_id: SeriesId) -> crate::Result<()> {
+        self.partition.remove(series_id.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the orphaned series with the metric they belonged to.
+    pub fn list(&self) -> crate::Result<Vec<(SeriesId, String)>> {
+        self.partition
+            .inner()
+            .iter()
+            .map(|kv| {
+                let (k, v) = kv?;
+                let series_id = (&k[..]).read_u64::<BigEndian>()?;
+                Ok((series_id, String::from_utf8_lossy(&v).into_owned()))
+            })
+            .collect()
+    }
+}
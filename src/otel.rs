@@ -0,0 +1,318 @@
+use crate::{sanitize, timestamp, Database, MetricName, Stat, Timestamp, Value};
+use opentelemetry_sdk::metrics::{
+    data::{DataPoint, Gauge, Histogram, HistogramDataPoint, ResourceMetrics, Sum},
+    exporter::PushMetricExporter,
+    MetricError, MetricResult, Temporality,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[allow(clippy::cast_precision_loss)]
+fn i64_to_f64(x: i64) -> f64 {
+    x as f64
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn u64_to_f64(x: u64) -> f64 {
+    x as f64
+}
+
+/// OpenTelemetry metrics exporter that persists metrics into a [`Database`]
+///
+/// Gauges and sums are written as raw data points, histograms
+/// are written as pre-aggregated samples (see [`Stat`]).
+///
+/// Resource and data point attributes are mapped to tags, data point attributes
+/// take precedence. Metric names and tag keys are sanitized to be valid talna identifiers
+/// (e.g. `http.server.Duration` is stored as `http.server.duration`, and
+/// `service.name` becomes `service_name`).
+///
+/// Only available using the `otel` feature flag.
+pub struct OtelExporter {
+    db: Database,
+    temporality: Temporality,
+}
+
+impl OtelExporter {
+    /// Creates a new exporter writing into the given database.
+    #[must_use]
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            temporality: Temporality::default(),
+        }
+    }
+
+    /// Sets the temporality of exported sums and histograms.
+    ///
+    /// Delta temporality allows using sum aggregations on counters.
+    ///
+    /// Default = [`Temporality::Cumulative`]
+    #[must_use]
+    pub fn temporality(mut self, temporality: Temporality) -> Self {
+        self.temporality = temporality;
+        self
+    }
+
+    fn to_timestamp(time: Option<SystemTime>) -> Timestamp {
+        time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(timestamp, |duration| duration.as_nanos())
+    }
+
+    fn tags(
+        resource: &[(String, String)],
+        attributes: &[opentelemetry::KeyValue],
+    ) -> Vec<(String, String)> {
+        let mut tags = resource.to_vec();
+
+        for kv in attributes {
+            let key = sanitize::tag_key(kv.key.as_str());
+            let value = sanitize::tag_value(&kv.value.as_str());
+
+            if let Some(tag) = tags.iter_mut().find(|(k, _)| *k == key) {
+                tag.1 = value;
+            } else {
+                tags.push((key, value));
+            }
+        }
+
+        tags
+    }
+
+    fn write_data_points<T: Copy>(
+        &self,
+        metric: MetricName,
+        resource: &[(String, String)],
+        data_points: &[DataPoint<T>],
+        to_f64: impl Fn(T) -> f64,
+    ) -> crate::Result<()> {
+        for dp in data_points {
+            let tags = Self::tags(resource, &dp.attributes);
+            let tags = tags
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+
+            #[allow(clippy::cast_possible_truncation)]
+            let value = to_f64(dp.value) as Value;
+
+            self.db
+                .write_at(metric, Self::to_timestamp(dp.time), value, &tags)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_histogram_data_points<T: Copy + Into<f64>>(
+        &self,
+        metric: MetricName,
+        resource: &[(String, String)],
+        data_points: &[HistogramDataPoint<T>],
+    ) -> crate::Result<()> {
+        #[allow(clippy::cast_possible_truncation)]
+        let to_value = |x: T| x.into() as Value;
+
+        for dp in data_points {
+            if dp.count == 0 {
+                continue;
+            }
+
+            let tags = Self::tags(resource, &dp.attributes);
+            let tags = tags
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+
+            let sum = to_value(dp.sum);
+
+            // NOTE: If min/max are not recorded, the mean is the best approximation
+            #[allow(clippy::cast_precision_loss)]
+            let mean = sum / dp.count as Value;
+
+            let stat = Stat {
+                count: dp.count,
+                sum,
+                min: dp.min.map_or(mean, to_value),
+                max: dp.max.map_or(mean, to_value),
+            };
+
+            self.db
+                .write_stat(metric, Self::to_timestamp(Some(dp.time)), stat, &tags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the given metrics into the database.
+    ///
+    /// Integer and float metrics are supported, other aggregations
+    /// (e.g. exponential histograms) are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn write_metrics(&self, metrics: &ResourceMetrics) -> crate::Result<()> {
+        let resource = metrics
+            .resource
+            .iter()
+            .map(|(k, v)| {
+                (
+                    sanitize::tag_key(k.as_str()),
+                    sanitize::tag_value(&v.as_str()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for metric in metrics.scope_metrics.iter().flat_map(|x| &x.metrics) {
+            let name = sanitize::metric_name(&metric.name);
+
+            let Ok(name) = MetricName::try_from(name.as_str()) else {
+                log::warn!("Skipping OpenTelemetry metric with invalid name {name:?}");
+                continue;
+            };
+
+            let data = metric.data.as_any();
+
+            if let Some(gauge) = data.downcast_ref::<Gauge<f64>>() {
+                self.write_data_points(name, &resource, &gauge.data_points, |x| x)?;
+            } else if let Some(gauge) = data.downcast_ref::<Gauge<i64>>() {
+                self.write_data_points(name, &resource, &gauge.data_points, i64_to_f64)?;
+            } else if let Some(gauge) = data.downcast_ref::<Gauge<u64>>() {
+                self.write_data_points(name, &resource, &gauge.data_points, u64_to_f64)?;
+            } else if let Some(sum) = data.downcast_ref::<Sum<f64>>() {
+                self.write_data_points(name, &resource, &sum.data_points, |x| x)?;
+            } else if let Some(sum) = data.downcast_ref::<Sum<i64>>() {
+                self.write_data_points(name, &resource, &sum.data_points, i64_to_f64)?;
+            } else if let Some(sum) = data.downcast_ref::<Sum<u64>>() {
+                self.write_data_points(name, &resource, &sum.data_points, u64_to_f64)?;
+            } else if let Some(histogram) = data.downcast_ref::<Histogram<f64>>() {
+                self.write_histogram_data_points(name, &resource, &histogram.data_points)?;
+            } else {
+                log::debug!("Skipping unsupported OpenTelemetry aggregation of metric {name}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl PushMetricExporter for OtelExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> MetricResult<()> {
+        self.write_metrics(metrics)
+            .map_err(|e| MetricError::Other(e.to_string()))
+    }
+
+    async fn force_flush(&self) -> MetricResult<()> {
+        self.db
+            .flush(false)
+            .map_err(|e| MetricError::Other(e.to_string()))
+    }
+
+    fn shutdown(&self) -> MetricResult<()> {
+        self.db
+            .flush(true)
+            .map_err(|e| MetricError::Other(e.to_string()))
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use opentelemetry::{InstrumentationScope, KeyValue};
+    use opentelemetry_sdk::{
+        metrics::data::{Metric, ScopeMetrics},
+        Resource,
+    };
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn otel_write_metrics() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let exporter = OtelExporter::new(db.clone());
+
+        let gauge = Gauge {
+            data_points: vec![
+                DataPoint {
+                    attributes: vec![KeyValue::new("host", "h-1")],
+                    start_time: None,
+                    time: Some(UNIX_EPOCH + std::time::Duration::from_nanos(1)),
+                    value: 4.0,
+                    exemplars: vec![],
+                },
+                DataPoint {
+                    attributes: vec![KeyValue::new("host", "h-1")],
+                    start_time: None,
+                    time: Some(UNIX_EPOCH + std::time::Duration::from_nanos(2)),
+                    value: 6.0,
+                    exemplars: vec![],
+                },
+            ],
+        };
+
+        let histogram = Histogram {
+            data_points: vec![HistogramDataPoint {
+                attributes: vec![KeyValue::new("host", "h-1")],
+                start_time: UNIX_EPOCH,
+                time: UNIX_EPOCH + std::time::Duration::from_nanos(1),
+                count: 4,
+                bounds: vec![],
+                bucket_counts: vec![],
+                min: Some(1.0),
+                max: Some(10.0),
+                sum: 20.0,
+                exemplars: vec![],
+            }],
+            temporality: Temporality::Delta,
+        };
+
+        let metrics = ResourceMetrics {
+            resource: Resource::new([KeyValue::new("service.name", "talna")]),
+            scope_metrics: vec![ScopeMetrics {
+                scope: InstrumentationScope::builder("test").build(),
+                metrics: vec![
+                    Metric {
+                        name: "CPU.Usage".into(),
+                        description: "".into(),
+                        unit: "".into(),
+                        data: Box::new(gauge),
+                    },
+                    Metric {
+                        name: "http.duration".into(),
+                        description: "".into(),
+                        unit: "".into(),
+                        data: Box::new(histogram),
+                    },
+                ],
+            }],
+        };
+
+        exporter.write_metrics(&metrics)?;
+
+        let metric = MetricName::try_from("cpu.usage").unwrap();
+        let mut buckets = db
+            .avg(metric, "host")
+            .filter("service_name:talna")
+            .build()?
+            .collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(5.0, bucket.value);
+
+        let metric = MetricName::try_from("http.duration").unwrap();
+
+        let mut buckets = db.count(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(4.0, bucket.value);
+
+        let mut buckets = db.max(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(10.0, bucket.value);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{
+    agg::{Aggregation, Bucket, GroupedAggregation},
+    db::StreamItem,
+};
+use std::{fmt::Write as _, io::Write};
+
+/// Appends the string as JSON string literal
+pub fn json_string(buf: &mut String, s: &str) {
+    buf.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+
+    buf.push('"');
+}
+
+/// Appends the fields of the bucket as JSON object members (without braces)
+///
+/// Values that are not finite (NaN, infinity) are written as `null`, because JSON can not represent them.
+pub fn json_bucket_fields(buf: &mut String, bucket: &Bucket) {
+    let _ = write!(
+        buf,
+        r#""start":{},"end":{},"len":{},"sum":"#,
+        bucket.start, bucket.end, bucket.len,
+    );
+
+    if bucket.sum.is_finite() {
+        let _ = write!(buf, "{},\"value\":", bucket.sum);
+    } else {
+        buf.push_str("null,\"value\":");
+    }
+
+    if bucket.value.is_finite() {
+        let _ = write!(buf, "{}", bucket.value);
+    } else {
+        buf.push_str("null");
+    }
+}
+
+/// Appends the string as CSV field, quoting it if needed
+fn csv_field(buf: &mut String, s: &str) {
+    if s.contains([',', '"', '\n', '\r']) {
+        buf.push('"');
+        buf.push_str(&s.replace('"', "\"\""));
+        buf.push('"');
+    } else {
+        buf.push_str(s);
+    }
+}
+
+impl<A, I> GroupedAggregation<'_, A, I>
+where
+    A: Aggregation,
+    I: Iterator<Item = crate::Result<StreamItem>>,
+{
+    /// Consumes all groups, and writes the buckets as CSV (with header row),
+    /// returning the amount of written buckets.
+    ///
+    /// The columns are `group,start,end,value,len,sum`, rows are ordered by group.
+    ///
+    /// Unlike [`GroupedAggregation::collect`], buckets are written while they are aggregated,
+    /// so large results can be exported with bounded memory. Only queries using `having` or
+    /// `downsample_lttb` read all buckets of a group before writing it.
+    ///
+    /// The query cache is bypassed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use talna::{Database, MetricName, tagset};
+    ///
+    /// let db = Database::builder().open(&folder)?;
+    /// let metric_name = MetricName::try_from("cpu.total").unwrap();
+    /// db.write_at(metric_name, 0, 4.0, tagset!("host" => "h-1"))?;
+    ///
+    /// let mut out = vec![];
+    /// let count = db.avg(metric_name, "host").build()?.write_csv(&mut out)?;
+    ///
+    /// assert_eq!(1, count);
+    /// assert_eq!(
+    ///     "group,start,end,value,len,sum\nh-1,0,0,4,1,4\n",
+    ///     String::from_utf8_lossy(&out),
+    /// );
+    /// #
+    /// # Ok::<(), talna::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    pub fn write_csv<W: Write>(self, writer: &mut W) -> crate::Result<u64> {
+        writer.write_all(b"group,start,end,value,len,sum\n")?;
+
+        let mut line = String::new();
+
+        self.for_each_bucket(|group, bucket| {
+            line.clear();
+            csv_field(&mut line, group);
+
+            let _ = writeln!(
+                line,
+                ",{},{},{},{},{}",
+                bucket.start, bucket.end, bucket.value, bucket.len, bucket.sum,
+            );
+
+            writer.write_all(line.as_bytes())
+        })
+    }
+
+    /// Consumes all groups, and writes one JSON object per bucket and line,
+    /// returning the amount of written buckets.
+    ///
+    /// Each object has the fields `group`, `start`, `end`, `len`, `sum` and `value`,
+    /// values that are not finite are written as `null`. Lines are ordered by group.
+    ///
+    /// Buckets are written while they are aggregated, see [`GroupedAggregation::write_csv`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error occurred.
+    pub fn write_json_lines<W: Write>(self, writer: &mut W) -> crate::Result<u64> {
+        let mut line = String::new();
+
+        self.for_each_bucket(|group, bucket| {
+            line.clear();
+            line.push_str(r#"{"group":"#);
+            json_string(&mut line, group);
+            line.push(',');
+            json_bucket_fields(&mut line, bucket);
+            line.push_str("}\n");
+
+            writer.write_all(line.as_bytes())
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{tagset, Database, MetricName};
+
+    #[test_log::test]
+    fn csv_field_quoting() {
+        let mut buf = String::new();
+        csv_field(&mut buf, "h-1");
+        buf.push(',');
+        csv_field(&mut buf, "a,b");
+        buf.push(',');
+        csv_field(&mut buf, "say \"hi\"");
+        assert_eq!(r#"h-1,"a,b","say ""hi""""#, buf);
+    }
+
+    #[test_log::test]
+    fn write_csv_json_lines() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        for ts in 0..4 {
+            db.write_at(metric_name, ts, 1.0, tagset!("host" => "h,2"))?;
+            db.write_at(metric_name, ts, 2.0, tagset!("host" => "h-1"))?;
+        }
+
+        let query = || db.sum(metric_name, "host").granularity(1);
+
+        let mut out = vec![];
+        assert_eq!(4, query().build()?.write_csv(&mut out)?);
+        assert_eq!(
+            "group,start,end,value,len,sum\n\
+             \"h,2\",2,3,2,2,2\n\
+             \"h,2\",0,1,2,2,2\n\
+             h-1,2,3,4,2,4\n\
+             h-1,0,1,4,2,4\n",
+            String::from_utf8_lossy(&out),
+        );
+
+        let mut out = vec![];
+        assert_eq!(4, query().build()?.write_json_lines(&mut out)?);
+        let out = String::from_utf8_lossy(&out);
+        assert_eq!(4, out.lines().count());
+        assert_eq!(
+            Some(r#"{"group":"h-1","start":0,"end":1,"len":2,"sum":4,"value":4}"#),
+            out.lines().last(),
+        );
+
+        // NOTE: Groups filtered by `having` are not written
+        let mut out = vec![];
+        let count = query()
+            .having(|buckets| buckets.iter().any(|bucket| bucket.value > 3.0))
+            .build()?
+            .write_json_lines(&mut out)?;
+        assert_eq!(2, count);
+        assert!(!String::from_utf8_lossy(&out).contains("h,2"));
+
+        Ok(())
+    }
+}
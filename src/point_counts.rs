@@ -0,0 +1,224 @@
+use crate::smap::META_PARTITION_NAME;
+use crate::SeriesId;
+use byteorder::{BigEndian, ReadBytesExt};
+use fjall::{Partition, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex, PoisonError, RwLock,
+};
+
+const KEY_PREFIX: &str = "point_count#";
+
+/// Set while the persisted counts include every counted write
+///
+/// Removed (through the journal) before the first count after persisting, so after a crash,
+/// the counts are determined again instead of loading counts that drifted from the data.
+const CLEAN_KEY: &str = "point_counts_clean";
+
+/// Approximate amount of data points per metric
+///
+/// Counts are kept in memory, and only persisted when flushing.
+/// After an unclean shutdown, the data points are counted again when opening the database.
+pub struct PointCounts {
+    meta: TxPartition,
+    counts: RwLock<crate::HashMap<String, AtomicU64>>,
+
+    /// Mirrors the clean marker in the meta partition
+    clean: AtomicBool,
+
+    /// Serializes removing the clean marker
+    clean_lock: Mutex<()>,
+}
+
+impl PointCounts {
+    pub fn new(
+        keyspace: &TxKeyspace,
+        prefix: &str,
+        smap: &TxPartition,
+        data: &Partition,
+    ) -> crate::Result<Self> {
+        let meta = keyspace.open_partition(
+            &format!("{prefix}{META_PARTITION_NAME}"),
+            PartitionCreateOptions::default(),
+        )?;
+
+        let mut counts = crate::HashMap::default();
+
+        if meta.get(CLEAN_KEY)?.is_some() {
+            for kv in keyspace.read_tx().prefix(&meta, KEY_PREFIX) {
+                let (key, value) = kv?;
+
+                let metric =
+                    String::from_utf8_lossy(key.get(KEY_PREFIX.len()..).unwrap_or_default())
+                        .into_owned();
+                let count = (&value[..]).read_u64::<BigEndian>()?;
+
+                counts.insert(metric, AtomicU64::new(count));
+            }
+        } else {
+            // NOTE: Counts may have drifted after a crash (or not exist yet), so count
+            // the data points of every series
+            log::info!("Counting data points of existing series");
+
+            let snapshot = data.snapshot();
+
+            for kv in keyspace.read_tx().iter(smap) {
+                let (series_key, series_id) = kv?;
+                let series_id: SeriesId = (&series_id[..]).read_u64::<BigEndian>()?;
+
+                let series_key = String::from_utf8_lossy(&series_key);
+                let metric = series_key
+                    .split_once('#')
+                    .map_or(&*series_key, |(metric, _)| metric);
+
+                let mut count = 0;
+                for kv in snapshot.prefix(series_id.to_be_bytes()) {
+                    kv?;
+                    count += 1;
+                }
+
+                counts
+                    .entry(metric.to_string())
+                    .or_insert_with(|| AtomicU64::new(0))
+                    .fetch_add(count, Ordering::Relaxed);
+            }
+        }
+
+        let point_counts = Self {
+            meta,
+            counts: RwLock::new(counts),
+            clean: AtomicBool::new(false),
+            clean_lock: Mutex::default(),
+        };
+        point_counts.persist()?;
+
+        Ok(point_counts)
+    }
+
+    /// Removes the clean marker, before the first count after persisting.
+    fn mark_dirty(&self) {
+        if !self.clean.load(Ordering::Acquire) {
+            return;
+        }
+
+        let _lock = self
+            .clean_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if self.clean.load(Ordering::Acquire) {
+            // NOTE: Counts are approximate, so a failed write only costs accuracy after a crash
+            if let Err(e) = self.meta.inner().remove(CLEAN_KEY) {
+                log::error!("Failed to remove point counts clean marker: {e:?}");
+                return;
+            }
+
+            self.clean.store(false, Ordering::Release);
+        }
+    }
+
+    /// Adds to the count of a metric.
+    pub fn add(&self, metric: &str, n: u64) {
+        let counts = self.counts.read().unwrap_or_else(PoisonError::into_inner);
+        self.mark_dirty();
+
+        if let Some(count) = counts.get(metric) {
+            count.fetch_add(n, Ordering::Relaxed);
+            return;
+        }
+
+        drop(counts);
+
+        let mut counts = self.counts.write().unwrap_or_else(PoisonError::into_inner);
+        self.mark_dirty();
+
+        counts
+            .entry(metric.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(n, Ordering::Relaxed);
+
+        drop(counts);
+    }
+
+    /// Subtracts from the count of a metric.
+    pub fn sub(&self, metric: &str, n: u64) {
+        let counts = self.counts.read().unwrap_or_else(PoisonError::into_inner);
+        self.mark_dirty();
+
+        if let Some(count) = counts.get(metric) {
+            let _ = count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_sub(n))
+            });
+        }
+
+        drop(counts);
+    }
+
+    /// Returns the count of a metric.
+    pub fn get(&self, metric: &str) -> u64 {
+        self.counts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(metric)
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    /// Writes the counts into the meta partition.
+    pub fn persist(&self) -> crate::Result<()> {
+        // NOTE: Blocks writers, so no count can slip in between persisting the counts
+        // and setting the clean marker
+        let counts = self.counts.write().unwrap_or_else(PoisonError::into_inner);
+
+        for (metric, count) in counts.iter() {
+            self.meta.insert(
+                format!("{KEY_PREFIX}{metric}"),
+                count.load(Ordering::Relaxed).to_be_bytes(),
+            )?;
+        }
+
+        self.meta.insert(CLEAN_KEY, [])?;
+        self.clean.store(true, Ordering::Release);
+
+        drop(counts);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn point_counts_unclean_shutdown() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let smap = keyspace.open_partition("smap", PartitionCreateOptions::default())?;
+        let data = keyspace.open_partition("data", PartitionCreateOptions::default())?;
+
+        smap.insert("cpu.total#host:h-1", 0_u64.to_be_bytes())?;
+        data.insert([0, 0, 0, 0, 0, 0, 0, 0, 1], [])?;
+        data.insert([0, 0, 0, 0, 0, 0, 0, 0, 2], [])?;
+
+        let counts = PointCounts::new(&keyspace, "_talna#v1#", &smap, data.inner())?;
+        assert_eq!(2, counts.get("cpu.total"));
+
+        counts.add("cpu.total", 5);
+        counts.persist()?;
+        drop(counts);
+
+        // NOTE: Counts are trusted after a clean shutdown
+        let counts = PointCounts::new(&keyspace, "_talna#v1#", &smap, data.inner())?;
+        assert_eq!(7, counts.get("cpu.total"));
+
+        // NOTE: Counts changed after persisting are not trusted after a crash
+        counts.add("cpu.total", 1);
+        drop(counts);
+
+        let counts = PointCounts::new(&keyspace, "_talna#v1#", &smap, data.inner())?;
+        assert_eq!(2, counts.get("cpu.total"));
+
+        Ok(())
+    }
+}
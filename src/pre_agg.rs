@@ -0,0 +1,305 @@
+use crate::{db::StreamItem, Stat, TagSet, Timestamp, Value};
+use std::sync::{Mutex, PoisonError};
+
+/// Buffered samples of a series in the current window
+#[derive(Clone)]
+pub struct Window {
+    pub metric: String,
+    pub tags: Vec<(String, String)>,
+
+    /// Start of the window (nanosecond timestamp), the timestamp the window is written at
+    pub start: Timestamp,
+
+    pub stat: Stat,
+
+    /// Amount of samples that were already written by a flush
+    ///
+    /// A window that was written before is overwritten (and not counted again) when it is written again.
+    pub written: u64,
+}
+
+/// Data points of a series in a window that is rolled up after the fact,
+/// see [`crate::Database::backfill_rollups`]
+pub struct RollupWindow {
+    /// Start of the window (nanosecond timestamp), the timestamp the rollup is written at
+    pub start: Timestamp,
+
+    pub stat: Stat,
+
+    /// Amount of stored data points (raw or pre-aggregated) in the window
+    pub points: u64,
+
+    /// Set if the window can not be rolled up, because it contains deleted data points,
+    /// or a pre-aggregated sample with a quantile sketch (which would be lost)
+    pub skip: bool,
+}
+
+impl RollupWindow {
+    pub fn new(
+        start: Timestamp,
+        deleted: &[(Timestamp, Timestamp)],
+        resolution: Timestamp,
+    ) -> Self {
+        let end = start.saturating_add(resolution - 1);
+
+        Self {
+            start,
+            stat: Stat {
+                count: 0,
+                sum: 0.0,
+                min: Value::INFINITY,
+                max: Value::NEG_INFINITY,
+            },
+            points: 0,
+            skip: deleted.iter().any(|&(min, max)| min <= end && start <= max),
+        }
+    }
+
+    /// Adds a stored data point to the window.
+    pub fn add(&mut self, item: &StreamItem) {
+        let stat = item.stat.unwrap_or(Stat {
+            count: 1,
+            sum: item.value,
+            min: item.value,
+            max: item.value,
+        });
+
+        self.stat.count += stat.count;
+        self.stat.sum += stat.sum;
+        self.stat.min = self.stat.min.min(stat.min);
+        self.stat.max = self.stat.max.max(stat.max);
+        self.points += 1;
+        self.skip |= item.sketch.is_some();
+    }
+}
+
+/// Outcome of buffering a sample
+pub enum Buffered {
+    /// The sample was added to the current window of its series
+    Added,
+
+    /// The sample is older than the current window of its series, and
+    /// needs to be written directly
+    Late,
+
+    /// The sample started a new window, so the previous window needs to be written
+    Closed(Window),
+}
+
+/// Aggregates samples of high-frequency metrics in memory, see
+/// [`crate::DatabaseBuilder::pre_aggregate`]
+#[derive(Default)]
+pub struct PreAggregation {
+    /// Window size (in nanoseconds) per metric
+    resolutions: crate::HashMap<String, Timestamp>,
+
+    /// Current window per series key
+    windows: Mutex<crate::HashMap<String, Window>>,
+}
+
+impl PreAggregation {
+    pub fn new(resolutions: crate::HashMap<String, Timestamp>) -> Self {
+        Self {
+            resolutions,
+            windows: Mutex::default(),
+        }
+    }
+
+    /// Returns the window size of the metric, if it is pre-aggregated.
+    pub fn resolution(&self, metric: &str) -> Option<Timestamp> {
+        if self.resolutions.is_empty() {
+            return None;
+        }
+
+        self.resolutions.get(metric).copied()
+    }
+
+    /// Adds a sample to the current window of its series.
+    pub fn add(
+        &self,
+        metric: &str,
+        series_key: String,
+        tags: &TagSet,
+        ts: Timestamp,
+        value: Value,
+        resolution: Timestamp,
+    ) -> Buffered {
+        let window_start = ts - ts % resolution.max(1);
+
+        let sample = Stat {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        };
+
+        let new_window = || Window {
+            metric: metric.into(),
+            tags: tags
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect(),
+            start: window_start,
+            stat: sample,
+            written: 0,
+        };
+
+        // NOTE: Windows are only replaced or updated as a whole, so poisoning can be ignored
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(window) = windows.get_mut(&series_key) else {
+            windows.insert(series_key, new_window());
+            return Buffered::Added;
+        };
+
+        let buffered = match window_start.cmp(&window.start) {
+            std::cmp::Ordering::Less => Buffered::Late,
+            std::cmp::Ordering::Equal => {
+                window.stat.count += 1;
+                window.stat.sum += value;
+                window.stat.min = window.stat.min.min(value);
+                window.stat.max = window.stat.max.max(value);
+                Buffered::Added
+            }
+            std::cmp::Ordering::Greater => {
+                Buffered::Closed(std::mem::replace(window, new_window()))
+            }
+        };
+        drop(windows);
+
+        buffered
+    }
+
+    /// Returns the approximate size of the buffered windows in bytes.
+    pub fn size(&self) -> u64 {
+        let windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let size = windows
+            .iter()
+            .map(|(series_key, window)| {
+                std::mem::size_of::<(String, Window)>()
+                    + series_key.len()
+                    + window.metric.len()
+                    + window
+                        .tags
+                        .iter()
+                        .map(|(k, v)| std::mem::size_of::<(String, String)>() + k.len() + v.len())
+                        .sum::<usize>()
+            })
+            .sum::<usize>();
+        drop(windows);
+
+        size as u64
+    }
+
+    /// Returns the buffered windows that received samples since they were last written,
+    /// marking them as written.
+    ///
+    /// The windows stay buffered, so samples that arrive afterwards are added to them,
+    /// instead of starting a new window that would overwrite the written one.
+    pub fn flush(&self) -> Vec<Window> {
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let flushed = windows
+            .values_mut()
+            .filter(|window| window.written < window.stat.count)
+            .map(|window| {
+                let flushed = window.clone();
+                window.written = window.stat.count;
+                flushed
+            })
+            .collect();
+        drop(windows);
+
+        flushed
+    }
+
+    /// Removes & returns all buffered windows.
+    pub fn drain(&self) -> Vec<Window> {
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+        windows.drain().map(|(_, window)| window).collect()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    #[allow(clippy::indexing_slicing)]
+    fn pre_aggregation_windows() {
+        let mut resolutions = crate::HashMap::default();
+        resolutions.insert("cpu".to_string(), 10);
+
+        let pre_agg = PreAggregation::new(resolutions);
+        assert_eq!(Some(10), pre_agg.resolution("cpu"));
+        assert_eq!(None, pre_agg.resolution("mem"));
+
+        let tags: &TagSet = &[("host", "h-1")];
+
+        for (ts, value) in [(10, 4.0), (15, 2.0), (19, 6.0)] {
+            assert!(matches!(
+                pre_agg.add("cpu", "a".into(), tags, ts, value, 10),
+                Buffered::Added
+            ));
+        }
+
+        assert!(matches!(
+            pre_agg.add("cpu", "a".into(), tags, 5, 1.0, 10),
+            Buffered::Late
+        ));
+
+        let Buffered::Closed(window) = pre_agg.add("cpu", "a".into(), tags, 20, 1.0, 10) else {
+            panic!("window should be closed");
+        };
+        assert_eq!(10, window.start);
+        assert_eq!(vec![("host".to_string(), "h-1".to_string())], window.tags);
+        assert_eq!(
+            Stat {
+                count: 3,
+                sum: 12.0,
+                min: 2.0,
+                max: 6.0
+            },
+            window.stat
+        );
+
+        pre_agg.add("cpu", "b".into(), tags, 25, 1.0, 10);
+
+        let mut windows = pre_agg.drain();
+        windows.sort_by_key(|window| window.start);
+        assert_eq!(2, windows.len());
+        assert_eq!(1, windows[0].stat.count);
+        assert!(pre_agg.drain().is_empty());
+    }
+
+    #[test_log::test]
+    #[allow(clippy::indexing_slicing)]
+    fn pre_agg_flush_keeps_windows() {
+        let pre_agg = PreAggregation::default();
+        let tags = &[("host", "h-1")];
+
+        pre_agg.add("cpu", "a".into(), tags, 1, 1.0, 10);
+        pre_agg.add("cpu", "a".into(), tags, 2, 1.0, 10);
+
+        let flushed = pre_agg.flush();
+        assert_eq!(1, flushed.len());
+        assert_eq!(0, flushed[0].written);
+        assert_eq!(2, flushed[0].stat.count);
+
+        // NOTE: Unchanged windows are not flushed again
+        assert!(pre_agg.flush().is_empty());
+
+        assert!(matches!(
+            pre_agg.add("cpu", "a".into(), tags, 3, 1.0, 10),
+            Buffered::Added
+        ));
+
+        let Buffered::Closed(window) = pre_agg.add("cpu", "a".into(), tags, 10, 1.0, 10) else {
+            panic!("window should be closed");
+        };
+        assert_eq!(2, window.written);
+        assert_eq!(3, window.stat.count);
+    }
+}
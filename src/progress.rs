@@ -0,0 +1,32 @@
+use std::ops::ControlFlow;
+
+/// Progress of a long-running operation, e.g. [`crate::Database::compact_tombstones_with_progress`]
+///
+/// Progress callbacks return [`ControlFlow::Break`] to abort the operation
+/// after the current work item. Work items that were already processed are kept.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Amount of work items (e.g. series or lines) that were processed
+    pub done: u64,
+
+    /// Amount of work items in total, or `None` if unknown (e.g. when importing from a stream)
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// Returns the processed fraction, between `0.0` and `1.0`, or `None` if the total is unknown.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            None => None,
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64).min(1.0)),
+        }
+    }
+}
+
+/// Progress callback that never aborts, used by the operations without progress reporting
+pub fn no_progress(_: Progress) -> ControlFlow<()> {
+    ControlFlow::Continue(())
+}
@@ -1,29 +1,129 @@
+use crate::query::grammar::{parse_strict, GrammarVersion, SyntaxError};
 use crate::query::lexer::{self, tokenize_filter_query};
-use crate::smap::SeriesMapping;
-use crate::{tag_index::TagIndex, SeriesId};
+use crate::tag_index::{Postings, TagIndex};
+use crate::SeriesId;
+use std::borrow::Cow;
 use std::collections::VecDeque;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Tag<'a> {
-    pub key: &'a str,
-    pub value: &'a str,
+    pub key: Cow<'a, str>,
+    pub value: Cow<'a, str>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl<'a> Tag<'a> {
+    fn new(key: &'a str, value: &'a str) -> Self {
+        Self {
+            key: Cow::Borrowed(key),
+            value: Cow::Borrowed(value),
+        }
+    }
+
+    fn into_owned(self) -> Tag<'static> {
+        Tag {
+            key: Cow::Owned(self.key.into_owned()),
+            value: Cow::Owned(self.value.into_owned()),
+        }
+    }
+}
+
+/// Operator of a range term (`key>=value`, `key<value`, ...)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Lte => "<=",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+        }
+    }
+
+    /// Splits a range term into its tag key, operator and bound
+    pub(crate) fn split(term: &str) -> Option<(&str, Self, &str)> {
+        let idx = term.find(['<', '>'])?;
+        let (key, rest) = term.split_at(idx);
+
+        let (op, bound) = if let Some(bound) = rest.strip_prefix("<=") {
+            (Self::Lte, bound)
+        } else if let Some(bound) = rest.strip_prefix(">=") {
+            (Self::Gte, bound)
+        } else if let Some(bound) = rest.strip_prefix('<') {
+            (Self::Lt, bound)
+        } else {
+            (Self::Gt, rest.strip_prefix('>')?)
+        };
+
+        Some((key, op, bound))
+    }
+
+    /// Returns `true` if the tag value compares to the bound as required.
+    ///
+    /// If the bound is a number, tag values are compared numerically (so tag values
+    /// that are not numbers never match), otherwise they are compared lexicographically.
+    #[must_use]
+    pub fn matches(self, value: &str, bound: &str) -> bool {
+        let ordering = match bound.parse::<f64>() {
+            Ok(bound) => {
+                let Some(ordering) = value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|value| value.partial_cmp(&bound))
+                else {
+                    return false;
+                };
+                ordering
+            }
+            Err(_) => value.cmp(bound),
+        };
+
+        match self {
+            Self::Lt => ordering.is_lt(),
+            Self::Lte => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Gte => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Node<'a> {
     And(Vec<Self>),
     Or(Vec<Self>),
     Eq(Tag<'a>),
     Wildcard(Tag<'a>),
+
+    /// Series whose tag value compares to the given bound (`key>=value`, `key<value`, ...)
+    Range(Tag<'a>, Comparison),
     Not(Box<Self>),
     AllStar,
+
+    /// Series that have the tag key, regardless of its value (`has:key`)
+    Has(Cow<'a, str>),
+
+    /// Series that do not have the tag key (`missing:key`)
+    Missing(Cow<'a, str>),
 }
 
-impl<'a> std::fmt::Display for Node<'a> {
+/// Tag key of the `has:<key>` predicate, so it can not be used as a regular tag key in filters
+pub(crate) const HAS_KEY: &str = "has";
+
+/// Tag key of the `missing:<key>` predicate, so it can not be used as a regular tag key in filters
+pub(crate) const MISSING_KEY: &str = "missing";
+
+impl std::fmt::Display for Node<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Node::Eq(leaf) => write!(f, "{}:{}", leaf.key, leaf.value),
             Node::Wildcard(leaf) => write!(f, "{}:{}*", leaf.key, leaf.value),
+            Node::Range(leaf, op) => write!(f, "{}{}{}", leaf.key, op.symbol(), leaf.value),
             Node::And(nodes) => write!(
                 f,
                 "({})",
@@ -43,7 +143,9 @@ impl<'a> std::fmt::Display for Node<'a> {
                     .join(" OR ")
             ),
             Node::AllStar => write!(f, "*"),
-            Node::Not(node) => write!(f, "!({node})",),
+            Node::Not(node) => write!(f, "!({node})"),
+            Node::Has(key) => write!(f, "{HAS_KEY}:{key}"),
+            Node::Missing(key) => write!(f, "{MISSING_KEY}:{key}"),
         }
     }
 }
@@ -63,7 +165,7 @@ pub fn intersection(vecs: &[Vec<SeriesId>]) -> Vec<SeriesId> {
     let mut result = Vec::new();
 
     'outer: for &elem in first_vec {
-        for vec in &vecs[1..] {
+        for vec in vecs.iter().skip(1) {
             if !vec.contains(&elem) {
                 continue 'outer;
             }
@@ -75,6 +177,56 @@ pub fn intersection(vecs: &[Vec<SeriesId>]) -> Vec<SeriesId> {
     result
 }
 
+/// Intersects two ascending lists of series IDs
+#[must_use]
+pub fn intersect_sorted(a: &[SeriesId], b: &[SeriesId]) -> Vec<SeriesId> {
+    let mut result = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+
+    while let (Some(&x), Some(&y)) = (a.get(i), b.get(j)) {
+        match x.cmp(&y) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                result.push(x);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Keeps the (ascending) series IDs that are contained in the postings list,
+/// or, if `keep` is false, the ones that are not
+///
+/// The postings list is streamed, and not read any further once all IDs have been found.
+fn retain_postings(ids: &mut Vec<SeriesId>, postings: Postings, keep: bool) {
+    let mut found = vec![false; ids.len()];
+    let mut remaining = ids.len();
+
+    for id in postings {
+        if remaining == 0 {
+            break;
+        }
+
+        if let Some(found) = ids
+            .binary_search(&id)
+            .ok()
+            .and_then(|idx| found.get_mut(idx))
+        {
+            if !*found {
+                *found = true;
+                remaining -= 1;
+            }
+        }
+    }
+
+    let mut found = found.into_iter();
+    ids.retain(|_| found.next() == Some(keep));
+}
+
 #[must_use]
 pub fn union(vecs: &[Vec<SeriesId>]) -> Vec<SeriesId> {
     let mut result = vec![];
@@ -89,48 +241,152 @@ pub fn union(vecs: &[Vec<SeriesId>]) -> Vec<SeriesId> {
     result
 }
 
-impl<'a> Node<'a> {
+impl Node<'_> {
+    /// Copies all borrowed tags, so the node does not borrow the filter expression anymore.
+    #[must_use]
+    pub fn into_owned(self) -> Node<'static> {
+        match self {
+            Node::And(nodes) => Node::And(nodes.into_iter().map(Node::into_owned).collect()),
+            Node::Or(nodes) => Node::Or(nodes.into_iter().map(Node::into_owned).collect()),
+            Node::Eq(leaf) => Node::Eq(leaf.into_owned()),
+            Node::Wildcard(leaf) => Node::Wildcard(leaf.into_owned()),
+            Node::Range(leaf, op) => Node::Range(leaf.into_owned(), op),
+            Node::Not(node) => Node::Not(Box::new(node.into_owned())),
+            Node::AllStar => Node::AllStar,
+            Node::Has(key) => Node::Has(Cow::Owned(key.into_owned())),
+            Node::Missing(key) => Node::Missing(Cow::Owned(key.into_owned())),
+        }
+    }
+
+    /// Returns the postings list of single-term nodes, so it can be streamed.
+    fn postings(&self, tag_index: &TagIndex, metric_name: &str) -> crate::Result<Option<Postings>> {
+        match self {
+            Node::AllStar => tag_index.iter_eq(metric_name).map(Some),
+            Node::Eq(leaf) => tag_index
+                .iter_eq(&TagIndex::format_key(metric_name, &leaf.key, &leaf.value))
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Keeps the (ascending) series IDs that match the node,
+    /// or, if `keep` is false, the ones that do not match.
+    fn retain_matching(
+        &self,
+        ids: &mut Vec<SeriesId>,
+        tag_index: &TagIndex,
+        metric_name: &str,
+        keep: bool,
+    ) -> crate::Result<()> {
+        if let Some(postings) = self.postings(tag_index, metric_name)? {
+            retain_postings(ids, postings, keep);
+            return Ok(());
+        }
+
+        let mut other = self.evaluate(tag_index, metric_name)?;
+        other.sort_unstable();
+
+        if keep {
+            *ids = intersect_sorted(ids, &other);
+        } else {
+            ids.retain(|id| other.binary_search(id).is_err());
+        }
+
+        Ok(())
+    }
+
     // TODO: 1.0.0 unit test and add benchmark case
     pub fn evaluate(
         &self,
-        smap: &SeriesMapping,
         tag_index: &TagIndex,
         metric_name: &str,
     ) -> crate::Result<Vec<SeriesId>> {
         match self {
             Node::AllStar => tag_index.query_eq(metric_name),
             Node::Eq(leaf) => {
-                tag_index.query_eq(&TagIndex::format_key(metric_name, leaf.key, leaf.value))
+                tag_index.query_eq(&TagIndex::format_key(metric_name, &leaf.key, &leaf.value))
             }
             Node::Wildcard(leaf) => {
-                tag_index.query_prefix(&TagIndex::format_key(metric_name, leaf.key, leaf.value))
+                tag_index.query_prefix(&TagIndex::format_key(metric_name, &leaf.key, &leaf.value))
+            }
+            Node::Range(leaf, op) => tag_index
+                .query_matching(&TagIndex::format_key(metric_name, &leaf.key, ""), |value| {
+                    op.matches(value, &leaf.value)
+                }),
+            Node::Has(key) => tag_index.query_prefix(&TagIndex::format_key(metric_name, key, "")),
+            Node::Missing(key) => {
+                let has = tag_index.query_prefix(&TagIndex::format_key(metric_name, key, ""))?;
+
+                let mut ids = tag_index.query_eq(metric_name)?;
+                ids.retain(|id| has.binary_search(id).is_err());
+                Ok(ids)
             }
             Node::And(children) => {
-                // TODO: evaluate lazily...
-                let ids = children
-                    .iter()
-                    .map(|c| Self::evaluate(c, smap, tag_index, metric_name))
-                    .collect::<crate::Result<Vec<_>>>()?;
+                // NOTE: Children are evaluated in order, and the query planner puts the most
+                // selective term first, so once the intersection is empty, the remaining
+                // (larger) postings lists do not need to be read at all
+                let mut ids: Option<Vec<SeriesId>> = None;
+
+                for child in children {
+                    let next = match (ids, child) {
+                        // NOTE: Negations only remove series from the intersection,
+                        // instead of listing all other series of the metric
+                        (Some(mut ids), Node::Missing(key)) => {
+                            let excluded = tag_index.query_prefix(&TagIndex::format_key(
+                                metric_name,
+                                key,
+                                "",
+                            ))?;
+
+                            ids.retain(|id| excluded.binary_search(id).is_err());
+                            ids
+                        }
+                        (Some(mut ids), Node::Not(node)) => {
+                            node.retain_matching(&mut ids, tag_index, metric_name, false)?;
+                            ids
+                        }
+                        (Some(mut ids), child) => {
+                            child.retain_matching(&mut ids, tag_index, metric_name, true)?;
+                            ids
+                        }
+                        (None, child) => {
+                            let mut ids = child.evaluate(tag_index, metric_name)?;
+                            ids.sort_unstable();
+                            ids
+                        }
+                    };
 
-                Ok(intersection(&ids))
+                    if next.is_empty() {
+                        return Ok(next);
+                    }
+
+                    ids = Some(next);
+                }
+
+                Ok(ids.unwrap_or_default())
             }
             Node::Or(children) => {
-                // TODO: evaluate lazily...
-                let ids = children
-                    .iter()
-                    .map(|c| Self::evaluate(c, smap, tag_index, metric_name))
-                    .collect::<crate::Result<Vec<_>>>()?;
+                let mut ids = vec![];
+
+                for child in children {
+                    match child.postings(tag_index, metric_name)? {
+                        Some(postings) => ids.extend(postings),
+                        None => ids.extend(child.evaluate(tag_index, metric_name)?),
+                    }
+                }
+
+                ids.sort_unstable();
+                ids.dedup();
 
-                Ok(union(&ids))
+                Ok(ids)
             }
             Node::Not(node) => {
-                let mut ids = smap.list_all()?;
-
-                for id in node.evaluate(smap, tag_index, metric_name)? {
-                    ids.remove(&id);
-                }
+                // NOTE: Only negate within the series of the metric, not all series
+                let mut excluded = node.evaluate(tag_index, metric_name)?;
+                excluded.sort_unstable();
 
-                let mut ids = ids.into_iter().collect::<Vec<_>>();
+                let mut ids = tag_index.query_eq(metric_name)?;
+                ids.retain(|id| excluded.binary_search(id).is_err());
                 ids.sort_unstable();
 
                 Ok(ids)
@@ -143,6 +399,9 @@ impl<'a> Node<'a> {
 pub enum Item<'a> {
     Wildcard((&'a str, &'a str)),
     Identifier((&'a str, &'a str)),
+    Range((&'a str, Comparison, &'a str)),
+    Has(&'a str),
+    Missing(&'a str),
     And,
     Or,
     Not,
@@ -150,63 +409,118 @@ pub enum Item<'a> {
     ParanClose,
 }
 
+/// Returns the operand of a `key:value` term, which may be a tag existence predicate
+fn tag_item<'a>(key: &'a str, value: &'a str) -> Item<'a> {
+    match key {
+        HAS_KEY => Item::Has(value),
+        MISSING_KEY => Item::Missing(value),
+        _ => Item::Identifier((key, value)),
+    }
+}
+
+/// Pushes the operands of a `key:[a, b, ...]` set, which are OR'ed
+fn push_set<'a>(output_queue: &mut VecDeque<Item<'a>>, key: &'a str, values: &'a str) {
+    let mut len = 0;
+
+    for value in values.split(',').map(str::trim) {
+        output_queue.push_back(tag_item(key, value));
+        len += 1;
+    }
+
+    // NOTE: OR pairwise like `a OR b OR c` (which is right-associative),
+    // so the set prints as an equivalent expression
+    for _ in 1..len {
+        output_queue.push_back(Item::Or);
+    }
+}
+
+/// Pushes an AND operator, popping operators of higher or equal precedence
+fn push_and<'a>(op_stack: &mut VecDeque<Item<'a>>, output_queue: &mut VecDeque<Item<'a>>) {
+    while let Some(top) = op_stack.back() {
+        // And has higher precedence than Or but lower than Not
+        if !matches!(top, Item::And | Item::Not) {
+            break;
+        }
+
+        output_queue.extend(op_stack.pop_back());
+    }
+
+    op_stack.push_back(Item::And);
+}
+
 #[doc(hidden)]
-pub fn parse_filter_query(s: &str) -> Result<Node, crate::Error> {
+pub fn parse_filter_query(s: &str) -> Result<Node<'_>, crate::Error> {
     if s.trim() == "*" {
         return Ok(Node::AllStar);
     }
 
     let mut output_queue = VecDeque::new();
     let mut op_stack = VecDeque::new();
+    let mut ends_operand = false;
 
     for tok in tokenize_filter_query(s) {
         let Ok(tok) = tok else {
             return Err(crate::Error::InvalidQuery);
         };
 
+        // NOTE: Adjacent terms are implicitly AND'ed (e.g. `env:prod service:db`)
+        if ends_operand && tok.starts_operand() {
+            push_and(&mut op_stack, &mut output_queue);
+        }
+        ends_operand = tok.ends_operand();
+
         match tok {
             lexer::Token::Identifier(id) => {
                 let mut splits = id.split(':');
-                let k = splits.next().expect("should be valid identifier");
-                let v = splits.next().expect("should be valid identifier");
-                output_queue.push_back(Item::Identifier((k, v)));
+                let (Some(k), Some(v)) = (splits.next(), splits.next()) else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                output_queue.push_back(tag_item(k, v));
+            }
+            lexer::Token::NotEq(id) => {
+                // NOTE: `key!=value` is sugar for `!key:value`
+                let Some((k, v)) = id.split_once("!=") else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                output_queue.push_back(tag_item(k, v));
+                output_queue.push_back(Item::Not);
+            }
+            lexer::Token::Range(id) => {
+                let Some(range) = Comparison::split(id) else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                output_queue.push_back(Item::Range(range));
+            }
+            lexer::Token::Set(id) => {
+                let Some((k, v)) = id.split_once(':') else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                let v = v.trim_start_matches('[').trim_end_matches(']');
+                push_set(&mut output_queue, k, v);
+            }
+            lexer::Token::NotSet(id) => {
+                // NOTE: `key:![a, b]` is sugar for `!(key:a OR key:b)`
+                let Some((k, v)) = id.split_once(':') else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                let v = v.trim_start_matches("![").trim_end_matches(']');
+                push_set(&mut output_queue, k, v);
+                output_queue.push_back(Item::Not);
             }
             lexer::Token::Wildcard(id) => {
                 let mut splits = id.split(':');
-                let k = splits.next().expect("should be valid identifier");
-                let v = splits
-                    .next()
-                    .expect("should be valid identifier")
-                    .trim_end_matches("*");
-                output_queue.push_back(Item::Wildcard((k, v)));
+                let (Some(k), Some(v)) = (splits.next(), splits.next()) else {
+                    return Err(crate::Error::InvalidQuery);
+                };
+                output_queue.push_back(Item::Wildcard((k, v.trim_end_matches('*'))));
             }
             lexer::Token::And => {
-                loop {
-                    let Some(top) = op_stack.back() else {
-                        break;
-                    };
-
-                    // And has higher precedence than Or but lower than Not
-                    if matches!(top, Item::And | Item::Not) {
-                        output_queue.push_back(op_stack.pop_back().expect("top should exist"));
-                    } else {
-                        break;
-                    }
-                }
-                op_stack.push_back(Item::And);
+                push_and(&mut op_stack, &mut output_queue);
             }
             lexer::Token::Or => {
-                loop {
-                    let Some(top) = op_stack.back() else {
-                        break;
-                    };
-
-                    // Or has lower precedence, so we pop And and Not operators
-                    if matches!(top, Item::And | Item::Not) {
-                        output_queue.push_back(op_stack.pop_back().expect("top should exist"));
-                    } else {
-                        break;
-                    }
+                // Or has lower precedence, so we pop And and Not operators
+                while let Some(Item::And | Item::Not) = op_stack.back() {
+                    output_queue.extend(op_stack.pop_back());
                 }
 
                 op_stack.push_back(Item::Or);
@@ -218,16 +532,12 @@ pub fn parse_filter_query(s: &str) -> Result<Node, crate::Error> {
                 op_stack.push_back(Item::ParanOpen);
             }
             lexer::Token::ParanClose => {
-                loop {
-                    let Some(top) = op_stack.back() else {
-                        break;
-                    };
-
+                while let Some(top) = op_stack.back() {
                     if matches!(top, Item::ParanOpen) {
                         break;
                     }
 
-                    output_queue.push_back(op_stack.pop_back().expect("top should exist"));
+                    output_queue.extend(op_stack.pop_back());
                 }
 
                 let Some(top) = op_stack.pop_back() else {
@@ -248,15 +558,29 @@ pub fn parse_filter_query(s: &str) -> Result<Node, crate::Error> {
         output_queue.push_back(top);
     }
 
+    build_tree(output_queue)
+}
+
+/// Builds the node tree from the operands & operators in postfix order
+fn build_tree(output_queue: VecDeque<Item<'_>>) -> Result<Node<'_>, crate::Error> {
     let mut buf: Vec<Node> = Vec::new();
 
     for item in output_queue {
         match item {
             Item::Identifier((key, value)) => {
-                buf.push(Node::Eq(Tag { key, value }));
+                buf.push(Node::Eq(Tag::new(key, value)));
             }
             Item::Wildcard((key, value)) => {
-                buf.push(Node::Wildcard(Tag { key, value }));
+                buf.push(Node::Wildcard(Tag::new(key, value)));
+            }
+            Item::Range((key, op, value)) => {
+                buf.push(Node::Range(Tag::new(key, value), op));
+            }
+            Item::Has(key) => {
+                buf.push(Node::Has(Cow::Borrowed(key)));
+            }
+            Item::Missing(key) => {
+                buf.push(Node::Missing(Cow::Borrowed(key)));
             }
             Item::And => {
                 let Some(b) = buf.pop() else {
@@ -282,14 +606,59 @@ pub fn parse_filter_query(s: &str) -> Result<Node, crate::Error> {
                 };
                 buf.push(Node::Not(Box::new(a)));
             }
-            Item::ParanOpen => return Err(crate::Error::InvalidQuery),
-            Item::ParanClose => return Err(crate::Error::InvalidQuery),
+            Item::ParanOpen | Item::ParanClose => return Err(crate::Error::InvalidQuery),
         }
     }
 
-    debug_assert_eq!(1, buf.len());
+    // NOTE: Empty expressions (e.g. `()`) produce no node
+    let (Some(node), true) = (buf.pop(), buf.is_empty()) else {
+        return Err(crate::Error::InvalidQuery);
+    };
+
+    Ok(node)
+}
+
+/// A parsed filter expression, see [`crate::Database::parse_filter`]
+///
+/// Can be passed to many queries using [`AggregationBuilder::filter_compiled`](crate::AggregationBuilder::filter_compiled),
+/// so the expression is only parsed once.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: String,
+    pub(crate) node: Node<'static>,
+}
+
+impl Filter {
+    pub(crate) fn new(expr: &str, node: Node<'static>) -> Self {
+        Self {
+            expr: expr.into(),
+            node,
+        }
+    }
+
+    /// Parses a filter expression, only accepting constructs of the given grammar version,
+    /// and rejecting ambiguous expressions (e.g. `a AND b OR c`).
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SyntaxError`] if the filter expression is invalid, ambiguous,
+    /// or uses constructs of a newer grammar version.
+    pub fn parse_strict(expr: &str, version: GrammarVersion) -> Result<Self, SyntaxError> {
+        let node = parse_strict(expr, version)?.into_owned();
+        Ok(Self::new(expr, node))
+    }
+
+    /// Returns the filter expression.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.expr
+    }
+}
 
-    Ok(buf.pop().expect("queue should not be empty"))
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
 }
 
 #[cfg(test)]
@@ -301,8 +670,8 @@ mod tests {
     fn test_parse_filter_query_1() {
         assert_eq!(
             Node::Eq(Tag {
-                key: "hello",
-                value: "world"
+                key: "hello".into(),
+                value: "world".into()
             }),
             parse_filter_query("hello:world").unwrap()
         );
@@ -312,8 +681,8 @@ mod tests {
     fn test_parse_filter_query_2() {
         assert_eq!(
             Node::Not(Box::new(Node::Eq(Tag {
-                key: "hello",
-                value: "world"
+                key: "hello".into(),
+                value: "world".into()
             }))),
             parse_filter_query("!hello:world").unwrap()
         );
@@ -324,12 +693,12 @@ mod tests {
         assert_eq!(
             Node::Not(Box::new(Node::Or(vec![
                 Node::Eq(Tag {
-                    key: "hello",
-                    value: "world"
+                    key: "hello".into(),
+                    value: "world".into()
                 }),
                 Node::Eq(Tag {
-                    key: "hallo",
-                    value: "welt"
+                    key: "hallo".into(),
+                    value: "welt".into()
                 }),
             ]))),
             parse_filter_query("!(hello:world OR hallo:welt)").unwrap()
@@ -340,13 +709,331 @@ mod tests {
     fn test_parse_filter_query_wildcard_1() {
         assert_eq!(
             Node::Wildcard(Tag {
-                key: "service",
-                value: "db-"
+                key: "service".into(),
+                value: "db-".into()
             }),
             parse_filter_query("service:db-*").unwrap()
         );
     }
 
+    #[test_log::test]
+    fn test_parse_filter_query_unicode() {
+        assert_eq!(
+            Node::Eq(Tag {
+                key: "city".into(),
+                value: "münchen".into()
+            }),
+            parse_filter_query("city:münchen").unwrap()
+        );
+        assert_eq!(
+            Node::Wildcard(Tag {
+                key: "région".into(),
+                value: "île-de-".into()
+            }),
+            parse_filter_query("région:île-de-*").unwrap()
+        );
+        assert_eq!(
+            Node::Eq(Tag {
+                key: "都市".into(),
+                value: "東京".into()
+            }),
+            parse_filter_query("都市:東京").unwrap()
+        );
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_implicit_and() {
+        assert_eq!(
+            parse_filter_query("env:prod AND service:db").unwrap(),
+            parse_filter_query("env:prod service:db").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("env:prod AND service:db AND host:h-*").unwrap(),
+            parse_filter_query("env:prod service:db host:h-*").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("env:prod AND !service:db").unwrap(),
+            parse_filter_query("env:prod !service:db").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("(env:prod OR env:dev) AND service:db").unwrap(),
+            parse_filter_query("(env:prod OR env:dev) service:db").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("env:prod AND (service:db OR service:web)").unwrap(),
+            parse_filter_query("env:prod (service:db OR service:web)").unwrap(),
+        );
+
+        // NOTE: Implicit AND binds like AND, so it binds tighter than OR
+        assert_eq!(
+            parse_filter_query("env:prod OR (env:dev AND service:db)").unwrap(),
+            parse_filter_query("env:prod OR env:dev service:db").unwrap(),
+        );
+
+        assert!(parse_filter_query("env:prod AND").is_err());
+        assert!(parse_filter_query("env:prod OR OR service:db").is_err());
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_has_missing() {
+        assert_eq!(
+            Node::Has("host".into()),
+            parse_filter_query("has:host").unwrap()
+        );
+        assert_eq!(
+            Node::And(vec![
+                Node::Eq(Tag {
+                    key: "env".into(),
+                    value: "prod".into()
+                }),
+                Node::Missing("host".into()),
+            ]),
+            parse_filter_query("env:prod missing:host").unwrap()
+        );
+        assert_eq!("missing:host", Node::Missing("host".into()).to_string());
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_not_shorthand() {
+        assert_eq!(
+            parse_filter_query("!env:prod").unwrap(),
+            parse_filter_query("env!=prod").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("host:h-1 OR host:h-2 OR host:h-3").unwrap(),
+            parse_filter_query("host:[h-1, h-2,h-3]").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("!(host:h-1 OR host:h-2)").unwrap(),
+            parse_filter_query("host:![h-1,h-2]").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("service:db AND !env:dev AND !(host:h-1 OR host:h-2)").unwrap(),
+            parse_filter_query("service:db env!=dev host:![h-1, h-2]").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("!(has:host)").unwrap(),
+            parse_filter_query("has!=host").unwrap(),
+        );
+
+        assert!(parse_filter_query("host:![]").is_err());
+        assert!(parse_filter_query("host:![h-1,]").is_err());
+        assert!(parse_filter_query("env!=").is_err());
+    }
+
+    fn eq(key: &'static str, value: &'static str) -> Node<'static> {
+        Node::Eq(Tag {
+            key: key.into(),
+            value: value.into(),
+        })
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_set_precedence() {
+        let expected = Node::Or(vec![
+            Node::And(vec![
+                eq("env", "prod"),
+                Node::Or(vec![eq("service", "db"), eq("service", "cache")]),
+            ]),
+            Node::Or(vec![eq("region", "eu"), eq("region", "us")]),
+        ]);
+
+        for filter in [
+            "(env:prod AND service:[db, cache]) OR region:[eu, us]",
+            "env:prod AND service:[db, cache] OR region:[eu, us]",
+            "env:prod service:[db,cache] OR region:[eu,us]",
+            "(env:prod AND (service:db OR service:cache)) OR (region:eu OR region:us)",
+        ] {
+            assert_eq!(expected, parse_filter_query(filter).unwrap(), "{filter}");
+        }
+
+        // NOTE: Sets bind tighter than AND, unlike spelled out ORs
+        assert_ne!(
+            parse_filter_query("env:prod AND service:[db, cache]").unwrap(),
+            parse_filter_query("env:prod AND service:db OR service:cache").unwrap(),
+        );
+
+        assert_eq!(
+            eq("region", "eu"),
+            parse_filter_query("region:[eu]").unwrap()
+        );
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_range() {
+        let range = |key: &'static str, op, value: &'static str| {
+            Node::Range(
+                Tag {
+                    key: key.into(),
+                    value: value.into(),
+                },
+                op,
+            )
+        };
+
+        assert_eq!(
+            range("status", Comparison::Gte, "400"),
+            parse_filter_query("status>=400").unwrap()
+        );
+        assert_eq!(
+            Node::And(vec![
+                range("status", Comparison::Gte, "400"),
+                range("status", Comparison::Lt, "500"),
+            ]),
+            parse_filter_query("status>=400 status<500").unwrap()
+        );
+        assert_eq!(
+            Node::Or(vec![
+                range("temp", Comparison::Lte, "-10.5"),
+                range("temp", Comparison::Gt, "30"),
+            ]),
+            parse_filter_query("temp<=-10.5 OR temp>30").unwrap()
+        );
+        assert_eq!(
+            "status>=400",
+            range("status", Comparison::Gte, "400").to_string()
+        );
+
+        assert!(parse_filter_query("status>=").is_err());
+        assert!(parse_filter_query(">=400").is_err());
+        assert!(parse_filter_query("status>>400").is_err());
+    }
+
+    #[test_log::test]
+    fn test_comparison() {
+        // NOTE: Numeric bounds compare numerically
+        assert!(Comparison::Lt.matches("99", "400"));
+        assert!(Comparison::Gte.matches("400", "400"));
+        assert!(Comparison::Gte.matches("1e3", "400"));
+        assert!(!Comparison::Gt.matches("400.0", "400"));
+        assert!(!Comparison::Gte.matches("n/a", "400"));
+
+        // NOTE: Other bounds compare lexicographically
+        assert!(Comparison::Gte.matches("2024-06", "2024-01"));
+        assert!(Comparison::Lt.matches("2023-12-31", "2024-01"));
+        assert!(Comparison::Lte.matches("v1", "v1"));
+        assert!(!Comparison::Lt.matches("v10", "v1"));
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_keywords() {
+        // NOTE: Keywords followed by tag characters are tags
+        assert_eq!(
+            Node::And(vec![eq("a", "1"), eq("ANDROID", "1")]),
+            parse_filter_query("a:1 ANDROID:1").unwrap(),
+        );
+        assert_eq!(
+            Node::And(vec![eq("a", "1"), eq("OR", "1")]),
+            parse_filter_query("a:1 OR:1").unwrap(),
+        );
+        assert_eq!(
+            parse_filter_query("a:1 AND (b:2 OR c:3)").unwrap(),
+            parse_filter_query("a:1 AND(b:2 OR c:3)").unwrap(),
+        );
+    }
+
+    #[test_log::test]
+    fn test_parse_filter_query_empty() {
+        assert!(parse_filter_query("").is_err());
+        assert!(parse_filter_query("  ").is_err());
+        assert!(parse_filter_query("()").is_err());
+        assert!(parse_filter_query("(()) env:prod").is_err());
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn tag(wildcard: bool) -> impl Strategy<Value = Tag<'static>> {
+            let value = if wildcard {
+                "[a-zA-Z0-9_.-]{0,8}"
+            } else {
+                "[a-zA-Z0-9_.-]{1,8}"
+            };
+
+            (key(), value).prop_map(|(key, value)| Tag {
+                key: key.into(),
+                value: value.into(),
+            })
+        }
+
+        fn key() -> impl Strategy<Value = String> {
+            "[a-zA-Z0-9_-]{1,8}"
+                .prop_filter("reserved key", |key| key != HAS_KEY && key != MISSING_KEY)
+        }
+
+        fn comparison() -> impl Strategy<Value = Comparison> {
+            prop_oneof![
+                Just(Comparison::Lt),
+                Just(Comparison::Lte),
+                Just(Comparison::Gt),
+                Just(Comparison::Gte),
+            ]
+        }
+
+        fn node() -> impl Strategy<Value = Node<'static>> {
+            let leaf = prop_oneof![
+                tag(false).prop_map(Node::Eq),
+                tag(true).prop_map(Node::Wildcard),
+                (tag(false), comparison()).prop_map(|(tag, op)| Node::Range(tag, op)),
+                key().prop_map(|key| Node::Has(key.into())),
+                key().prop_map(|key| Node::Missing(key.into())),
+            ];
+
+            leaf.prop_recursive(6, 64, 2, |inner| {
+                prop_oneof![
+                    (inner.clone(), inner.clone()).prop_map(|(a, b)| Node::And(vec![a, b])),
+                    (inner.clone(), inner.clone()).prop_map(|(a, b)| Node::Or(vec![a, b])),
+                    inner.prop_map(|node| Node::Not(Box::new(node))),
+                ]
+            })
+        }
+
+        /// Random sequences of valid tokens, which are mostly invalid expressions
+        fn tokens() -> impl Strategy<Value = String> {
+            let token = prop_oneof![
+                Just("!"),
+                Just("AND"),
+                Just("OR"),
+                Just("("),
+                Just(")"),
+                Just("*"),
+                Just("env:prod"),
+                Just("host:h-*"),
+                Just("has:host"),
+                Just("missing:host"),
+                Just("env!=prod"),
+                Just("host:[h-1, h-2, h-3]"),
+                Just("host:![h-1,h-2]"),
+                Just("status>=400"),
+                Just("status<500"),
+            ];
+
+            prop::collection::vec(token, 0..16).prop_map(|tokens| tokens.join(" "))
+        }
+
+        proptest! {
+            #[test]
+            fn display_roundtrip(node in node()) {
+                let parsed = parse_filter_query(&node.to_string()).unwrap().into_owned();
+                prop_assert_eq!(node, parsed);
+            }
+
+            #[test]
+            fn never_panics(s in "\\PC{0,64}") {
+                let _ = parse_filter_query(&s);
+            }
+
+            #[test]
+            fn tokens_never_panic(s in tokens()) {
+                if let Ok(node) = parse_filter_query(&s) {
+                    let reparsed = parse_filter_query(&node.to_string()).unwrap().into_owned();
+                    prop_assert_eq!(node, reparsed);
+                }
+            }
+        }
+    }
+
     #[test_log::test]
     fn test_intersection() {
         assert_eq!(
@@ -355,6 +1042,99 @@ mod tests {
         );
     }
 
+    #[test_log::test]
+    fn test_intersect_sorted() {
+        assert_eq!([3, 5], *intersect_sorted(&[1, 3, 5, 7], &[2, 3, 4, 5]));
+        assert!(intersect_sorted(&[1, 2], &[3, 4]).is_empty());
+        assert!(intersect_sorted(&[], &[3, 4]).is_empty());
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_evaluate_and() -> crate::Result<()> {
+        use crate::MetricName;
+
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+        let metric = MetricName::try_from("cpu.total").unwrap();
+
+        let mut tx = keyspace.write_tx();
+        for series_id in 0..100 {
+            let host = format!("h-{series_id}");
+            let env = if series_id % 2 == 0 { "prod" } else { "dev" };
+            let tags = crate::tagset!("env" => env, "host" => host.as_str());
+            tag_index.index(&mut tx, metric, tags, series_id)?;
+        }
+        tx.commit()?;
+
+        let evaluate = |filter: &str| -> crate::Result<Vec<SeriesId>> {
+            parse_filter_query(filter)
+                .unwrap()
+                .evaluate(&tag_index, "cpu.total")
+        };
+
+        assert_eq!(vec![4], evaluate("host:h-4 AND env:prod")?);
+        assert_eq!(vec![4], evaluate("env:prod AND host:h-4")?);
+        assert!(evaluate("host:h-5 AND env:prod")?.is_empty());
+        assert!(evaluate("host:h-404 AND env:prod AND env:dev")?.is_empty());
+        assert!(evaluate("host:h-4 AND !env:prod")?.is_empty());
+        assert_eq!(vec![5], evaluate("host:h-5 AND env!=prod")?);
+        assert_eq!(vec![5], evaluate("host:h-5 AND missing:region")?);
+        assert_eq!(
+            vec![1, 3],
+            evaluate("host:[h-0, h-1, h-2, h-3] AND !env:prod")?
+        );
+        assert_eq!(50, evaluate("!env:prod AND has:host")?.len());
+        assert_eq!(vec![1, 2], evaluate("host:h-2 OR host:h-1 OR host:h-404")?);
+        assert_eq!(
+            vec![2, 3],
+            evaluate("(host:h-2 OR host:h-3) AND (env:prod OR host:h-3)")?
+        );
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_evaluate_range() -> crate::Result<()> {
+        use crate::MetricName;
+
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+        let metric = MetricName::try_from("http.latency").unwrap();
+
+        let mut tx = keyspace.write_tx();
+        for (series_id, status) in [200, 201, 301, 404, 418, 500, 503].into_iter().enumerate() {
+            let status = status.to_string();
+            let tags = crate::tagset!("status" => status.as_str());
+            tag_index.index(&mut tx, metric, tags, series_id as SeriesId)?;
+        }
+        tag_index.index(&mut tx, metric, crate::tagset!("status" => "n-a"), 7)?;
+        tx.commit()?;
+
+        let evaluate = |filter: &str| -> crate::Result<Vec<SeriesId>> {
+            parse_filter_query(filter)
+                .unwrap()
+                .evaluate(&tag_index, "http.latency")
+        };
+
+        assert_eq!(vec![3, 4], evaluate("status>=400 status<500")?);
+        assert_eq!(vec![5, 6], evaluate("status>499")?);
+        assert_eq!(vec![0, 1, 2], evaluate("status<=301")?);
+        assert_eq!(
+            vec![0, 1, 5, 6],
+            evaluate("status:[200, 201] OR status>=500")?
+        );
+        assert_eq!(vec![2, 3, 4, 7], evaluate("!status>=500 !status<300")?);
+        assert_eq!(vec![7], evaluate("status>=m")?);
+
+        Ok(())
+    }
+
     #[test_log::test]
     fn test_union() {
         assert_eq!(
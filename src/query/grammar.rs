@@ -0,0 +1,271 @@
+use super::filter::{parse_filter_query, Comparison, Node, HAS_KEY, MISSING_KEY};
+use super::lexer::{tokenize_filter_query, Token};
+
+/// Version of the filter query language
+///
+/// New operators are only added in new versions, so applications can pin the language
+/// their filters are written in, see [`crate::DatabaseBuilder::filter_grammar`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+pub enum GrammarVersion {
+    /// `key:value` tags, `key:prefix*` wildcards, `AND`, `OR`, `!` and parentheses
+    V1,
+
+    /// Adds implicit AND (`env:prod service:db`), `key!=value`, sets (`key:[a, b]`, `key:![a, b]`)
+    /// and tag existence predicates (`has:key`, `missing:key`)
+    V2,
+
+    /// Adds ranges (`key>=value`, `key<value`, ...)
+    V3,
+}
+
+impl GrammarVersion {
+    /// The grammar accepted by [`crate::Database`] by default
+    pub const LATEST: Self = Self::V3;
+}
+
+/// Keywords of the filter query language
+///
+/// `has` and `missing` can not be used as tag keys, because `has:key` and `missing:key`
+/// are tag existence predicates.
+pub const RESERVED_KEYWORDS: &[&str] = &["AND", "OR", HAS_KEY, MISSING_KEY];
+
+/// Reason a filter expression was rejected by [`parse_strict`]
+///
+/// The variants are stable across talna versions, so they can be shown to users
+/// or matched on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SyntaxError {
+    /// The expression contains invalid tokens, unbalanced parentheses or missing operands.
+    Invalid,
+
+    /// The construct was introduced after the pinned grammar version.
+    Unsupported {
+        /// The construct, e.g. `!=`
+        construct: &'static str,
+
+        /// The first grammar version supporting the construct
+        since: GrammarVersion,
+    },
+
+    /// A reserved keyword is used as tag key in a way that has no defined meaning
+    /// (e.g. `has:ho*`).
+    ReservedKeyword(String),
+
+    /// AND and OR are mixed without parentheses (e.g. `a AND b OR c`).
+    MixedOperators,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "invalid filter expression"),
+            Self::Unsupported { construct, since } => {
+                write!(f, "{construct} requires filter grammar {since:?}")
+            }
+            Self::ReservedKeyword(key) => write!(f, "{key:?} is a reserved keyword"),
+            Self::MixedOperators => write!(f, "AND and OR need to be separated by parentheses"),
+        }
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+impl From<SyntaxError> for crate::Error {
+    fn from(_: SyntaxError) -> Self {
+        Self::InvalidQuery
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Operator {
+    And,
+    Or,
+}
+
+/// Remembers the operator of the current nesting level, rejecting a different one
+fn use_operator(scopes: &mut [Option<Operator>], op: Operator) -> Result<(), SyntaxError> {
+    let Some(scope) = scopes.last_mut() else {
+        return Err(SyntaxError::Invalid);
+    };
+
+    match scope {
+        Some(prev) if *prev != op => Err(SyntaxError::MixedOperators),
+        _ => {
+            *scope = Some(op);
+            Ok(())
+        }
+    }
+}
+
+fn require(
+    version: GrammarVersion,
+    construct: &'static str,
+    since: GrammarVersion,
+) -> Result<(), SyntaxError> {
+    if version < since {
+        Err(SyntaxError::Unsupported { construct, since })
+    } else {
+        Ok(())
+    }
+}
+
+/// Parses a filter expression, only accepting constructs of the given grammar version,
+/// and rejecting ambiguous expressions that [`parse_filter_query`] would accept,
+/// e.g. `a AND b OR c`.
+///
+/// # Errors
+///
+/// Returns error if the filter expression is invalid, ambiguous, or uses constructs
+/// of a newer grammar version.
+pub fn parse_strict(s: &str, version: GrammarVersion) -> Result<Node<'_>, SyntaxError> {
+    if s.trim() == "*" {
+        return Ok(Node::AllStar);
+    }
+
+    let mut scopes = vec![None];
+    let mut ends_operand = false;
+
+    for tok in tokenize_filter_query(s) {
+        let Ok(tok) = tok else {
+            return Err(SyntaxError::Invalid);
+        };
+
+        if ends_operand && tok.starts_operand() {
+            require(version, "implicit AND", GrammarVersion::V2)?;
+            use_operator(&mut scopes, Operator::And)?;
+        }
+        ends_operand = tok.ends_operand();
+
+        match tok {
+            Token::And => use_operator(&mut scopes, Operator::And)?,
+            Token::Or => use_operator(&mut scopes, Operator::Or)?,
+            Token::ParanOpen => scopes.push(None),
+            Token::ParanClose => {
+                scopes.pop();
+
+                if scopes.is_empty() {
+                    return Err(SyntaxError::Invalid);
+                }
+            }
+            Token::NotEq(_) => require(version, "!=", GrammarVersion::V2)?,
+            Token::Set(_) | Token::NotSet(_) => require(version, "set", GrammarVersion::V2)?,
+            Token::Identifier(id) => match id.split_once(':') {
+                Some((HAS_KEY, _)) => require(version, "has:", GrammarVersion::V2)?,
+                Some((MISSING_KEY, _)) => require(version, "missing:", GrammarVersion::V2)?,
+                _ => {}
+            },
+            Token::Wildcard(id) => {
+                if let Some((key @ (HAS_KEY | MISSING_KEY), _)) = id.split_once(':') {
+                    return Err(SyntaxError::ReservedKeyword(key.into()));
+                }
+            }
+            Token::Range(id) => {
+                require(version, "range", GrammarVersion::V3)?;
+
+                if let Some((key @ (HAS_KEY | MISSING_KEY), _, _)) = Comparison::split(id) {
+                    return Err(SyntaxError::ReservedKeyword(key.into()));
+                }
+            }
+            Token::Not => {}
+        }
+    }
+
+    parse_filter_query(s).map_err(|_| SyntaxError::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn parse_strict_accepts() {
+        for filter in [
+            "*",
+            "env:prod",
+            "env:prod AND service:db AND host:h-1",
+            "env:prod OR env:dev",
+            "env:prod AND (service:db OR service:ui)",
+            "(env:prod AND service:db) OR host:h-1",
+            "!env:prod AND host:h-*",
+        ] {
+            assert_eq!(
+                parse_filter_query(filter).ok(),
+                parse_strict(filter, GrammarVersion::V1).ok(),
+                "{filter}"
+            );
+        }
+
+        for filter in [
+            "env:prod service:db",
+            "env!=prod",
+            "env:[prod, dev]",
+            "env:![prod, dev]",
+            "has:host AND missing:container",
+            "status>=400 status<500",
+        ] {
+            assert_eq!(
+                parse_filter_query(filter).ok(),
+                parse_strict(filter, GrammarVersion::LATEST).ok(),
+                "{filter}"
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn parse_strict_rejects() {
+        assert_eq!(
+            Err(SyntaxError::MixedOperators),
+            parse_strict("a:1 AND b:2 OR c:3", GrammarVersion::LATEST)
+        );
+        assert_eq!(
+            Err(SyntaxError::MixedOperators),
+            parse_strict("a:1 b:2 OR c:3", GrammarVersion::LATEST)
+        );
+        assert_eq!(
+            Err(SyntaxError::ReservedKeyword("has".into())),
+            parse_strict("has:ho*", GrammarVersion::LATEST)
+        );
+        assert_eq!(
+            Err(SyntaxError::Unsupported {
+                construct: "!=",
+                since: GrammarVersion::V2
+            }),
+            parse_strict("env!=prod", GrammarVersion::V1)
+        );
+        assert_eq!(
+            Err(SyntaxError::Unsupported {
+                construct: "has:",
+                since: GrammarVersion::V2
+            }),
+            parse_strict("has:host", GrammarVersion::V1)
+        );
+        assert_eq!(
+            Err(SyntaxError::Unsupported {
+                construct: "range",
+                since: GrammarVersion::V3
+            }),
+            parse_strict("status>=400", GrammarVersion::V2)
+        );
+        assert_eq!(
+            Err(SyntaxError::ReservedKeyword("has".into())),
+            parse_strict("has>=1", GrammarVersion::LATEST)
+        );
+        assert_eq!(
+            Err(SyntaxError::Unsupported {
+                construct: "implicit AND",
+                since: GrammarVersion::V2
+            }),
+            parse_strict("env:prod service:db", GrammarVersion::V1)
+        );
+
+        for filter in ["", "env:prod)", "(env:prod", "env:prod AND", "env"] {
+            assert_eq!(
+                Err(SyntaxError::Invalid),
+                parse_strict(filter, GrammarVersion::LATEST),
+                "{filter}"
+            );
+        }
+    }
+}
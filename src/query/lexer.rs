@@ -18,18 +18,58 @@ pub enum Token<'a> {
     #[token(")")]
     ParanClose,
 
-    #[regex("[a-zA-Z_-]+:[a-zA-Z0-9_\\-.]*\\*")]
+    #[regex("[\\p{L}\\p{N}_-]+:[\\p{L}\\p{N}_\\-.]*\\*")]
     Wildcard(&'a str),
 
-    #[regex("[a-zA-Z_-]+:[a-zA-Z0-9_\\-.]+")]
+    #[regex("[\\p{L}\\p{N}_-]+:[\\p{L}\\p{N}_\\-.]+")]
     Identifier(&'a str),
+
+    #[regex("[\\p{L}\\p{N}_-]+!=[\\p{L}\\p{N}_\\-.]+")]
+    NotEq(&'a str),
+
+    #[regex("[\\p{L}\\p{N}_-]+(<|<=|>|>=)[\\p{L}\\p{N}_\\-.]+")]
+    Range(&'a str),
+
+    #[regex("[\\p{L}\\p{N}_-]+:\\[ *[\\p{L}\\p{N}_\\-.]+( *, *[\\p{L}\\p{N}_\\-.]+)* *\\]")]
+    Set(&'a str),
+
+    #[regex("[\\p{L}\\p{N}_-]+:!\\[ *[\\p{L}\\p{N}_\\-.]+( *, *[\\p{L}\\p{N}_\\-.]+)* *\\]")]
+    NotSet(&'a str),
 }
 
-// TODO: 1.0.0 replace with nom parser
+impl Token<'_> {
+    /// Returns `true` if the token can be the start of an operand.
+    #[must_use]
+    pub fn starts_operand(&self) -> bool {
+        matches!(
+            self,
+            Self::Identifier(_)
+                | Self::Wildcard(_)
+                | Self::NotEq(_)
+                | Self::Range(_)
+                | Self::Set(_)
+                | Self::NotSet(_)
+                | Self::Not
+                | Self::ParanOpen
+        )
+    }
 
-// TODO: 1.0.0 TagSet values should probably also be allowed to be integers
-// so we can something like: give me the AVG response time of all 4xx HTTP responses
+    /// Returns `true` if the token can be the end of an operand.
+    #[must_use]
+    pub fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            Self::Identifier(_)
+                | Self::Wildcard(_)
+                | Self::NotEq(_)
+                | Self::Range(_)
+                | Self::Set(_)
+                | Self::NotSet(_)
+                | Self::ParanClose
+        )
+    }
+}
 
-pub fn tokenize_filter_query(s: &str) -> impl Iterator<Item = Result<Token, ()>> + '_ {
+pub fn tokenize_filter_query(s: &str) -> impl Iterator<Item = Result<Token<'_>, ()>> + '_ {
     Token::lexer(s)
 }
@@ -1,3 +1,4 @@
 pub mod filter;
+pub mod grammar;
 pub mod lexer;
-// mod parser;
+pub mod planner;
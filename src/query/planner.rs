@@ -0,0 +1,206 @@
+use super::filter::Node;
+
+/// Statistics of the tag index, see [`QueryPlanner`]
+pub trait IndexStatistics {
+    /// Returns the amount of series of the metric.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    fn series_count(&self, metric: &str) -> crate::Result<u64>;
+
+    /// Returns the amount of series of the metric that have the given tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    fn tag_count(&self, metric: &str, key: &str, value: &str) -> crate::Result<u64>;
+}
+
+/// Decides how a filter is evaluated, see [`crate::DatabaseBuilder::query_planner`]
+///
+/// Planners can rewrite the filter AST into an equivalent AST that is cheaper to
+/// evaluate, e.g. by reordering the children of AND nodes, so the most selective
+/// term is evaluated first.
+pub trait QueryPlanner: Send + Sync {
+    /// Returns the filter to evaluate for the given metric.
+    ///
+    /// The returned filter needs to match the same series as the given filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    fn plan<'a>(
+        &self,
+        metric: &str,
+        filter: &Node<'a>,
+        stats: &dyn IndexStatistics,
+    ) -> crate::Result<Node<'a>>;
+}
+
+/// Evaluates filters as written
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NaivePlanner;
+
+impl QueryPlanner for NaivePlanner {
+    fn plan<'a>(
+        &self,
+        _: &str,
+        filter: &Node<'a>,
+        _: &dyn IndexStatistics,
+    ) -> crate::Result<Node<'a>> {
+        Ok(filter.clone())
+    }
+}
+
+/// Returns the children of nested AND nodes (e.g. `(a AND b) AND c`) as one list,
+/// so all of them can be reordered
+fn flatten_and<'n, 'a>(children: &'n [Node<'a>]) -> Vec<&'n Node<'a>> {
+    let mut flat = Vec::with_capacity(children.len());
+
+    for child in children {
+        match child {
+            Node::And(nested) => flat.extend(flatten_and(nested)),
+            child => flat.push(child),
+        }
+    }
+
+    flat
+}
+
+/// Orders the children of AND nodes by their estimated amount of matching series,
+/// so the most selective term is evaluated first (default)
+///
+/// Exact tags are estimated using the length of their postings list. Wildcards and negations
+/// can not be estimated without scanning the index, so they are assumed to match all series
+/// of the metric, and are evaluated last.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SelectivityPlanner;
+
+impl SelectivityPlanner {
+    /// Returns the planned node and its estimated amount of matching series
+    fn plan_node<'a>(
+        metric: &str,
+        node: &Node<'a>,
+        stats: &dyn IndexStatistics,
+        series_count: u64,
+    ) -> crate::Result<(Node<'a>, u64)> {
+        Ok(match node {
+            Node::Eq(leaf) => (
+                node.clone(),
+                stats.tag_count(metric, &leaf.key, &leaf.value)?,
+            ),
+            Node::AllStar
+            | Node::Wildcard(_)
+            | Node::Range(..)
+            | Node::Has(_)
+            | Node::Missing(_) => (node.clone(), series_count),
+            Node::Not(child) => {
+                let (child, _) = Self::plan_node(metric, child, stats, series_count)?;
+                (Node::Not(Box::new(child)), series_count)
+            }
+            Node::And(children) => {
+                let mut children = flatten_and(children)
+                    .into_iter()
+                    .map(|child| Self::plan_node(metric, child, stats, series_count))
+                    .collect::<crate::Result<Vec<_>>>()?;
+
+                // NOTE: Stable, so equally selective terms keep their order
+                children.sort_by_key(|(_, estimate)| *estimate);
+
+                let estimate = children.first().map_or(0, |(_, estimate)| *estimate);
+                let children = children.into_iter().map(|(child, _)| child).collect();
+
+                (Node::And(children), estimate)
+            }
+            Node::Or(children) => {
+                let children = children
+                    .iter()
+                    .map(|child| Self::plan_node(metric, child, stats, series_count))
+                    .collect::<crate::Result<Vec<_>>>()?;
+
+                let estimate = children
+                    .iter()
+                    .map(|(_, estimate)| *estimate)
+                    .sum::<u64>()
+                    .min(series_count);
+                let children = children.into_iter().map(|(child, _)| child).collect();
+
+                (Node::Or(children), estimate)
+            }
+        })
+    }
+}
+
+impl QueryPlanner for SelectivityPlanner {
+    fn plan<'a>(
+        &self,
+        metric: &str,
+        filter: &Node<'a>,
+        stats: &dyn IndexStatistics,
+    ) -> crate::Result<Node<'a>> {
+        // NOTE: Nothing to reorder
+        if !matches!(filter, Node::And(_) | Node::Or(_) | Node::Not(_)) {
+            return Ok(filter.clone());
+        }
+
+        let series_count = stats.series_count(metric)?;
+        let (node, _) = Self::plan_node(metric, filter, stats, series_count)?;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::query::filter::parse_filter_query;
+
+    struct Stats;
+
+    impl IndexStatistics for Stats {
+        fn series_count(&self, _: &str) -> crate::Result<u64> {
+            Ok(1_000)
+        }
+
+        fn tag_count(&self, _: &str, key: &str, _: &str) -> crate::Result<u64> {
+            Ok(match key {
+                "env" => 500,
+                "service" => 50,
+                "host" => 1,
+                _ => 0,
+            })
+        }
+    }
+
+    fn plan(filter: &str) -> String {
+        SelectivityPlanner
+            .plan("cpu", &parse_filter_query(filter).unwrap(), &Stats)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test_log::test]
+    fn selectivity_planner_order() {
+        assert_eq!("env:prod", plan("env:prod"));
+        assert_eq!(
+            "(host:h-1 AND service:db AND env:prod)",
+            plan("env:prod AND service:db AND host:h-1")
+        );
+        assert_eq!(
+            "(host:h-1 AND (env:prod OR env:dev))",
+            plan("(env:prod OR env:dev) AND host:h-1")
+        );
+        assert_eq!("(service:db AND host:h-*)", plan("host:h-* AND service:db"));
+        assert_eq!(
+            "!((host:h-1 AND env:prod))",
+            plan("!(env:prod AND host:h-1)")
+        );
+    }
+
+    #[test_log::test]
+    fn naive_planner_keeps_order() {
+        let filter = parse_filter_query("env:prod AND host:h-1").unwrap();
+        assert_eq!(filter, NaivePlanner.plan("cpu", &filter, &Stats).unwrap());
+    }
+}
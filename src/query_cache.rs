@@ -0,0 +1,220 @@
+use crate::{Bucket, Timestamp};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Collected query result, mapping each group to its buckets
+pub type QueryResult = crate::HashMap<String, Vec<Bucket>>;
+
+/// Time bound of a cached query
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum TimeBound {
+    Unbounded,
+    Absolute(Timestamp),
+
+    /// Window relative to the current time, so repeated dashboard queries share a cache entry
+    Relative(u128),
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct QueryCacheKey {
+    pub metric: String,
+    pub filter: String,
+    pub group_by: String,
+    pub group_by_tags: Option<Vec<String>>,
+    pub split_by_metric: bool,
+
+    // NOTE: Aggregations are not required to be 'static, so we cannot use TypeId
+    pub aggregation: &'static str,
+
+    pub start: TimeBound,
+    pub end: TimeBound,
+    pub granularity: Timestamp,
+
+    // NOTE: f64 bits, because f64 is not Eq
+    pub scale: u64,
+    pub offset: u64,
+
+    pub max_points: Option<usize>,
+    pub max_scanned_points: Option<u64>,
+
+    pub granularity_months: Option<u32>,
+    pub time_zone: Option<String>,
+
+    // NOTE: Debug representation, because f64 is not Eq
+    pub value_filter: Option<String>,
+
+    pub sample_rate: Option<u64>,
+
+    pub missing_tag: crate::MissingTagPolicy,
+
+    pub include_deleted: bool,
+
+    pub max_groups: Option<usize>,
+    pub groups_after: Option<String>,
+}
+
+/// A ticket for storing a query result, taken when the query is started
+///
+/// If the metric is written to before the result is stored, the result is discarded.
+pub struct CacheTicket<'a> {
+    pub(crate) cache: &'a QueryCache,
+    pub(crate) key: QueryCacheKey,
+    pub(crate) generation: u64,
+}
+
+struct Entry {
+    generation: u64,
+    inserted_at: Instant,
+    result: Arc<QueryResult>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Write generation per metric, bumped on every write
+    generations: crate::HashMap<String, u64>,
+
+    entries: crate::HashMap<QueryCacheKey, Entry>,
+}
+
+/// Cache of collected query results
+///
+/// Entries expire after a TTL, and are invalidated when their metric is written to.
+pub struct QueryCache {
+    capacity: usize,
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // NOTE: The cache is never left in an inconsistent state, so poisoning can be ignored
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the approximate size of the cached results in bytes.
+    pub fn size(&self) -> u64 {
+        let inner = self.lock();
+
+        let size = inner
+            .entries
+            .iter()
+            .map(|(key, entry)| {
+                let result = entry
+                    .result
+                    .iter()
+                    .map(|(group, buckets)| {
+                        group.len()
+                            + std::mem::size_of::<String>()
+                            + std::mem::size_of_val(buckets.as_slice())
+                    })
+                    .sum::<usize>();
+
+                std::mem::size_of::<(QueryCacheKey, Entry)>()
+                    + key.metric.len()
+                    + key.filter.len()
+                    + key.group_by.len()
+                    + result
+            })
+            .sum::<usize>();
+        drop(inner);
+
+        size as u64
+    }
+
+    pub fn ticket(&self, key: QueryCacheKey) -> CacheTicket<'_> {
+        let generation = self
+            .lock()
+            .generations
+            .get(&key.metric)
+            .copied()
+            .unwrap_or_default();
+
+        CacheTicket {
+            cache: self,
+            key,
+            generation,
+        }
+    }
+
+    pub fn get(&self, ticket: &CacheTicket) -> Option<Arc<QueryResult>> {
+        let inner = self.lock();
+
+        inner
+            .entries
+            .get(&ticket.key)
+            .filter(|entry| entry.generation == ticket.generation)
+            .filter(|entry| entry.inserted_at.elapsed() < self.ttl)
+            .map(|entry| entry.result.clone())
+    }
+
+    pub fn insert(&self, ticket: CacheTicket, result: Arc<QueryResult>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.lock();
+
+        let generation = inner
+            .generations
+            .get(&ticket.key.metric)
+            .copied()
+            .unwrap_or_default();
+
+        if generation != ticket.generation {
+            // NOTE: Metric was written to while the query was running
+            return;
+        }
+
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&ticket.key) {
+            let Inner {
+                generations,
+                entries,
+            } = &mut *inner;
+
+            // NOTE: Remove stale & expired entries first
+            entries.retain(|key, entry| {
+                generations.get(&key.metric).copied().unwrap_or_default() == entry.generation
+                    && entry.inserted_at.elapsed() < self.ttl
+            });
+
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted_at)
+                    .map(|(key, _)| key.clone());
+
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        inner.entries.insert(
+            ticket.key,
+            Entry {
+                generation,
+                inserted_at: Instant::now(),
+                result,
+            },
+        );
+    }
+
+    /// Invalidates all cached results of the given metric.
+    pub fn invalidate(&self, metric: &str) {
+        let mut inner = self.lock();
+
+        if let Some(generation) = inner.generations.get_mut(metric) {
+            *generation += 1;
+        } else {
+            inner.generations.insert(metric.to_string(), 1);
+        }
+    }
+}
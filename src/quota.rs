@@ -0,0 +1,48 @@
+use fjall::TxKeyspace;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Amount of writes between two checks of the disk space usage
+const CHECK_INTERVAL: u64 = 256;
+
+/// Maximum on-disk size of the database
+pub struct Quota {
+    max_bytes: u64,
+    writes: AtomicU64,
+    exceeded: AtomicBool,
+}
+
+impl Quota {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            writes: AtomicU64::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns an error if the disk space usage exceeds the quota.
+    ///
+    /// The usage is re-checked periodically, so writes are accepted
+    /// again once the usage drops (e.g. after compactions).
+    pub fn check(&self, keyspace: &TxKeyspace) -> crate::Result<()> {
+        if self.writes.fetch_add(1, Ordering::Relaxed) % CHECK_INTERVAL == 0 {
+            let disk_space = keyspace.disk_space();
+
+            if disk_space > self.max_bytes {
+                log::warn!(
+                    "Disk space usage ({disk_space} bytes) exceeds quota ({} bytes), rejecting writes",
+                    self.max_bytes,
+                );
+            }
+
+            self.exceeded
+                .store(disk_space > self.max_bytes, Ordering::Relaxed);
+        }
+
+        if self.exceeded.load(Ordering::Relaxed) {
+            return Err(crate::Error::QuotaExceeded);
+        }
+
+        Ok(())
+    }
+}
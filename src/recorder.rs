@@ -0,0 +1,242 @@
+use crate::{sanitize, Database, MetricName, Value};
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata,
+    SharedString, Unit,
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, PoisonError, RwLock,
+};
+
+/// A registered metric handle, writing into the database
+struct Handle {
+    db: Database,
+    metric: String,
+    tags: Vec<(String, String)>,
+
+    /// Running total (counters) or bits of the current value (gauges)
+    state: AtomicU64,
+}
+
+impl Handle {
+    fn new(db: Database, key: &Key) -> Self {
+        let tags = key
+            .labels()
+            .map(|label| {
+                (
+                    sanitize::tag_key(label.key()),
+                    sanitize::tag_value(label.value()),
+                )
+            })
+            .collect();
+
+        Self {
+            db,
+            metric: sanitize::metric_name(key.name()),
+            tags,
+            state: AtomicU64::new(0),
+        }
+    }
+
+    fn write(&self, value: f64) {
+        let Ok(metric) = MetricName::try_from(self.metric.as_str()) else {
+            log::warn!("Skipping metric with invalid name {:?}", self.metric);
+            return;
+        };
+
+        let tags = self
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+
+        #[allow(clippy::cast_possible_truncation)]
+        let value = value as Value;
+
+        if let Err(e) = self.db.write(metric, value, &tags) {
+            log::warn!("Failed to write metric {metric}: {e:?}");
+        }
+    }
+
+    fn update_gauge(&self, f: impl Fn(f64) -> f64) {
+        let mut value = 0.0;
+
+        // NOTE: fetch_update only fails if the closure returns None
+        let _ = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |bits| {
+                value = f(f64::from_bits(bits));
+                Some(value.to_bits())
+            });
+
+        self.write(value);
+    }
+}
+
+impl CounterFn for Handle {
+    fn increment(&self, value: u64) {
+        self.state.fetch_add(value, Ordering::AcqRel);
+        self.write(u64_to_f64(value));
+    }
+
+    fn absolute(&self, value: u64) {
+        let prev = self.state.fetch_max(value, Ordering::AcqRel);
+
+        if value > prev {
+            self.write(u64_to_f64(value - prev));
+        }
+    }
+}
+
+impl GaugeFn for Handle {
+    fn increment(&self, value: f64) {
+        self.update_gauge(|x| x + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update_gauge(|x| x - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.update_gauge(|_| value);
+    }
+}
+
+impl HistogramFn for Handle {
+    fn record(&self, value: f64) {
+        self.write(value);
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn u64_to_f64(x: u64) -> f64 {
+    x as f64
+}
+
+type Registry = RwLock<crate::HashMap<Key, Arc<Handle>>>;
+
+/// Recorder for the [`metrics`] crate that persists metrics into a [`Database`]
+///
+/// - Counter increments are written as data points, so the `sum` aggregation
+///   returns the amount of increments per bucket
+/// - Every gauge change writes the new value of the gauge
+/// - Every histogram sample is written as a data point
+///
+/// Labels are mapped to tags. Metric names and label keys are sanitized to be valid talna identifiers
+/// (e.g. `http_requests.Total` is stored as `http_requests.total`).
+///
+/// Only available using the `metrics` feature flag.
+///
+/// ```no_run
+/// # let path = std::path::Path::new(".testy");
+/// let db = talna::Database::builder().open(path)?;
+///
+/// metrics::set_global_recorder(talna::MetricsRecorder::new(db.clone()))
+///     .expect("recorder should not be installed yet");
+///
+/// metrics::counter!("http.requests", "host" => "h-1").increment(1);
+/// #
+/// # Ok::<_, talna::Error>(())
+/// ```
+pub struct MetricsRecorder {
+    db: Database,
+    counters: Registry,
+    gauges: Registry,
+    histograms: Registry,
+}
+
+impl MetricsRecorder {
+    /// Creates a new recorder writing into the given database.
+    #[must_use]
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            counters: Registry::default(),
+            gauges: Registry::default(),
+            histograms: Registry::default(),
+        }
+    }
+
+    fn register(&self, registry: &Registry, key: &Key) -> Arc<Handle> {
+        // NOTE: Handles never leave the registry in an inconsistent state, so poisoning can be ignored
+        let handle = registry
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned();
+
+        if let Some(handle) = handle {
+            return handle;
+        }
+
+        registry
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Handle::new(self.db.clone(), key)))
+            .clone()
+    }
+}
+
+impl metrics::Recorder for MetricsRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.register(&self.counters, key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.register(&self.gauges, key))
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.register(&self.histograms, key))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn metrics_recorder() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let recorder = MetricsRecorder::new(db.clone());
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("HTTP.Requests", "host" => "h-1").increment(2);
+            metrics::counter!("HTTP.Requests", "host" => "h-1").increment(3);
+            metrics::counter!("HTTP.Requests", "host" => "h-1").absolute(10);
+
+            metrics::gauge!("queue.len", "host" => "h-1").set(4.0);
+            metrics::gauge!("queue.len", "host" => "h-1").increment(2.0);
+
+            metrics::histogram!("http.duration", "host" => "h-1").record(1.0);
+            metrics::histogram!("http.duration", "host" => "h-1").record(5.0);
+        });
+
+        let metric = MetricName::try_from("http.requests").unwrap();
+        let mut buckets = db.sum(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(10.0, bucket.value);
+
+        let metric = MetricName::try_from("queue.len").unwrap();
+        let mut buckets = db.max(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(6.0, bucket.value);
+
+        let metric = MetricName::try_from("http.duration").unwrap();
+        let mut buckets = db.avg(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(3.0, bucket.value);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Sanitization of metric names and tags coming from external instrumentation libraries
+
+use crate::metric_name::is_valid_char;
+
+/// Lowercases the name and replaces characters that are not allowed in a metric name
+/// (e.g. `http.server.Duration` -> `http.server.duration`)
+pub fn metric_name(name: &str) -> String {
+    name.chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(|c| if is_valid_char(c) { c } else { '_' })
+        .collect()
+}
+
+/// Replaces characters that cannot be used in filter queries (e.g. `service.name` -> `service_name`)
+pub fn tag_key(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Replaces characters that are used as separators in series keys
+pub fn tag_value(s: &str) -> String {
+    s.replace([';', '#'], "_")
+}
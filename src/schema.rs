@@ -0,0 +1,173 @@
+use crate::{MetricName, TagSet};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+/// What happens to writes whose tag keys do not match the declared tag keys
+/// of their metric, see [`crate::Database::declare_metric`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum SchemaPolicy {
+    /// The write is accepted, and a warning is logged (once per distinct mismatch)
+    #[default]
+    Warn,
+
+    /// The write fails with [`crate::Error::SchemaViolation`]
+    Error,
+}
+
+/// Declared tag keys per metric
+///
+/// Declarations are not persisted, applications are expected to declare
+/// their metrics on startup.
+pub struct Schemas {
+    policy: SchemaPolicy,
+    keys: RwLock<crate::HashMap<String, Box<[String]>>>,
+
+    /// Mismatches that were already logged, so repeated writes do not flood the log
+    warned: Mutex<rustc_hash::FxHashSet<String>>,
+}
+
+impl Schemas {
+    pub fn new(policy: SchemaPolicy) -> Self {
+        Self {
+            policy,
+            keys: RwLock::default(),
+            warned: Mutex::default(),
+        }
+    }
+
+    pub fn declare(&self, metric: MetricName, keys: &[&str]) {
+        let mut keys = keys.iter().map(|&key| key.to_owned()).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+
+        self.keys
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(metric.to_string(), keys.into());
+    }
+
+    pub fn declared_keys(&self, metric: &str) -> Option<Vec<String>> {
+        self.keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(metric)
+            .map(|keys| keys.to_vec())
+    }
+
+    /// Compares the tag keys of a write to the declared keys of its metric.
+    ///
+    /// Keys of default tags are treated as present, and are never unexpected.
+    pub fn check(
+        &self,
+        metric: MetricName,
+        tags: &TagSet,
+        default_tags: &[(String, String)],
+    ) -> crate::Result<()> {
+        let declared = self.keys.read().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(keys) = declared.get(*metric) else {
+            return Ok(());
+        };
+
+        let is_default = |key: &str| default_tags.iter().any(|(k, _)| k == key);
+
+        let missing = keys
+            .iter()
+            .filter(|&key| !is_default(key) && !tags.iter().any(|(k, _)| k == key))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut unexpected = tags
+            .iter()
+            .filter(|(key, _)| {
+                !is_default(key) && keys.binary_search_by(|k| k.as_str().cmp(key)).is_err()
+            })
+            .map(|(key, _)| (*key).to_owned())
+            .collect::<Vec<_>>();
+
+        drop(declared);
+
+        if missing.is_empty() && unexpected.is_empty() {
+            return Ok(());
+        }
+
+        unexpected.sort();
+
+        match self.policy {
+            SchemaPolicy::Error => Err(crate::Error::SchemaViolation {
+                metric: metric.to_string(),
+                missing,
+                unexpected,
+            }),
+            SchemaPolicy::Warn => {
+                let mismatch = format!("{metric}:{missing:?}:{unexpected:?}");
+
+                let mut warned = self.warned.lock().unwrap_or_else(PoisonError::into_inner);
+
+                if warned.insert(mismatch) {
+                    log::warn!(
+                        "Write to metric {metric:?} does not match its declared tag keys (missing: {missing:?}, unexpected: {unexpected:?})",
+                    );
+                }
+
+                drop(warned);
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::tagset;
+
+    #[test_log::test]
+    fn schema_check() {
+        let metric = MetricName::try_from("cpu.total").unwrap();
+
+        let schemas = Schemas::new(SchemaPolicy::Error);
+        schemas.declare(metric, &["service", "env", "host", "env"]);
+
+        assert_eq!(
+            Some(vec!["env".into(), "host".into(), "service".into()]),
+            schemas.declared_keys(&metric)
+        );
+
+        assert!(schemas
+            .check(
+                metric,
+                tagset!("env" => "prod", "service" => "db", "host" => "h-1"),
+                &[],
+            )
+            .is_ok());
+
+        // NOTE: Default tags count as present
+        assert!(schemas
+            .check(
+                metric,
+                tagset!("env" => "prod", "service" => "db"),
+                &[("host".into(), "h-1".into())],
+            )
+            .is_ok());
+
+        assert!(matches!(
+            schemas.check(
+                metric,
+                tagset!("env" => "prod", "serivce" => "db", "host" => "h-1"),
+                &[],
+            ),
+            Err(crate::Error::SchemaViolation { missing, unexpected, .. })
+                if missing == ["service"] && unexpected == ["serivce"],
+        ));
+
+        // NOTE: Undeclared metrics are not checked
+        let other = MetricName::try_from("mem.free").unwrap();
+        assert!(schemas.check(other, tagset!("a" => "b"), &[]).is_ok());
+
+        let schemas = Schemas::new(SchemaPolicy::Warn);
+        schemas.declare(metric, &["env"]);
+        assert!(schemas.check(metric, tagset!("host" => "h-1"), &[]).is_ok());
+    }
+}
@@ -0,0 +1,340 @@
+use crate::smap::META_PARTITION_NAME;
+use crate::storage::StoragePartition;
+use crate::{SeriesId, Timestamp};
+use byteorder::{BigEndian, ReadBytesExt};
+use fjall::{Partition, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::ops::Bound;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, PoisonError, RwLock,
+};
+
+const KEY_PREFIX: &str = "series_bounds#";
+
+/// Set while the persisted bounds include every written data point
+///
+/// Removed (through the journal) before the first write after persisting, so after a crash,
+/// writes that are recovered from the journal can not be missing from the persisted bounds.
+const CLEAN_KEY: &str = "series_bounds_clean";
+
+struct Entry {
+    min: Timestamp,
+    max: Timestamp,
+
+    /// Changed since the bounds were last persisted
+    dirty: bool,
+}
+
+/// Oldest & newest timestamp per series, so queries can skip series
+/// without data points in their time bounds
+///
+/// Bounds only ever widen (removing data points does not shrink them), so they may
+/// include time ranges without data points, but never exclude a data point.
+pub struct SeriesBounds {
+    // NOTE: Not transactional, so bounds can be updated while a write transaction is open
+    meta: Partition,
+    bounds: RwLock<crate::HashMap<SeriesId, Mutex<Entry>>>,
+
+    /// Mirrors the clean marker in the meta partition
+    clean: AtomicBool,
+
+    /// Serializes removing the clean marker
+    clean_lock: Mutex<()>,
+}
+
+impl SeriesBounds {
+    pub fn new(
+        keyspace: &TxKeyspace,
+        prefix: &str,
+        smap: &TxPartition,
+        data: &Partition,
+        cold: Option<&dyn StoragePartition>,
+    ) -> crate::Result<Self> {
+        let meta = keyspace
+            .open_partition(
+                &format!("{prefix}{META_PARTITION_NAME}"),
+                PartitionCreateOptions::default(),
+            )?
+            .inner()
+            .clone();
+
+        let mut bounds = crate::HashMap::default();
+
+        if meta.get(CLEAN_KEY)?.is_some() {
+            for kv in meta.prefix(KEY_PREFIX) {
+                let (key, value) = kv?;
+
+                let mut key = key.get(KEY_PREFIX.len()..).unwrap_or_default();
+                let series_id = key.read_u64::<BigEndian>()?;
+
+                let mut value = &value[..];
+                let min = value.read_u128::<BigEndian>()?;
+                let max = value.read_u128::<BigEndian>()?;
+
+                bounds.insert(
+                    series_id,
+                    Mutex::new(Entry {
+                        min,
+                        max,
+                        dirty: false,
+                    }),
+                );
+            }
+        } else {
+            // NOTE: Bounds may be stale after a crash (or not exist yet), so look up
+            // the newest & oldest data point of every series
+            log::info!("Determining time bounds of existing series");
+
+            let snapshot = data.snapshot();
+            let cold = cold.map(StoragePartition::snapshot);
+
+            // NOTE: Keys contain the inverted timestamp, so the first key is the newest
+            let ts = |key: &[u8]| -> crate::Result<Timestamp> {
+                let mut ts = key
+                    .get(std::mem::size_of::<SeriesId>()..)
+                    .unwrap_or_default();
+                Ok(!ts.read_u128::<BigEndian>()?)
+            };
+
+            for kv in keyspace.read_tx().iter(smap) {
+                let (_, series_id) = kv?;
+                let series_id: SeriesId = (&series_id[..]).read_u64::<BigEndian>()?;
+                let prefix = series_id.to_be_bytes();
+
+                let mut hot = snapshot.prefix(prefix);
+
+                let mut max = hot.next().transpose()?.map(|(k, _)| ts(&k)).transpose()?;
+                let mut min = hot
+                    .next_back()
+                    .transpose()?
+                    .map(|(k, _)| ts(&k))
+                    .transpose()?;
+                min = min.or(max);
+
+                // NOTE: Finding the oldest data point of the cold tier would need a full scan,
+                // so the bounds are widened to include every older data point instead
+                if let Some(cold) = &cold {
+                    if let Some((k, _)) = cold.prefix(&prefix).next().transpose()? {
+                        max = max.max(Some(ts(&k)?));
+                        min = Some(0);
+                    }
+                }
+
+                if let (Some(min), Some(max)) = (min, max) {
+                    bounds.insert(
+                        series_id,
+                        Mutex::new(Entry {
+                            min,
+                            max,
+                            dirty: true,
+                        }),
+                    );
+                }
+            }
+        }
+
+        let series_bounds = Self {
+            meta,
+            bounds: RwLock::new(bounds),
+            clean: AtomicBool::new(false),
+            clean_lock: Mutex::default(),
+        };
+        series_bounds.persist()?;
+
+        Ok(series_bounds)
+    }
+
+    /// Removes the clean marker, before the first write after persisting.
+    fn mark_dirty(&self) -> crate::Result<()> {
+        if !self.clean.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let _lock = self
+            .clean_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if self.clean.load(Ordering::Acquire) {
+            self.meta.remove(CLEAN_KEY)?;
+            self.clean.store(false, Ordering::Release);
+        }
+
+        Ok(())
+    }
+
+    /// Widens the bounds of a series to include the timestamp.
+    ///
+    /// Needs to be called before the data point is written.
+    pub fn extend(&self, series_id: SeriesId, ts: Timestamp) -> crate::Result<()> {
+        let bounds = self.bounds.read().unwrap_or_else(PoisonError::into_inner);
+        self.mark_dirty()?;
+
+        if let Some(entry) = bounds.get(&series_id) {
+            let mut entry = entry.lock().unwrap_or_else(PoisonError::into_inner);
+
+            if ts < entry.min || ts > entry.max {
+                entry.min = entry.min.min(ts);
+                entry.max = entry.max.max(ts);
+                entry.dirty = true;
+            }
+
+            drop(entry);
+
+            return Ok(());
+        }
+
+        drop(bounds);
+
+        let mut bounds = self.bounds.write().unwrap_or_else(PoisonError::into_inner);
+        self.mark_dirty()?;
+
+        let entry = bounds.entry(series_id).or_insert_with(|| {
+            Mutex::new(Entry {
+                min: ts,
+                max: ts,
+                dirty: true,
+            })
+        });
+
+        let entry = entry.get_mut().unwrap_or_else(PoisonError::into_inner);
+        entry.min = entry.min.min(ts);
+        entry.max = entry.max.max(ts);
+        entry.dirty = true;
+
+        drop(bounds);
+
+        Ok(())
+    }
+
+    /// Returns the oldest & newest timestamp of a series, if known.
+    pub fn get(&self, series_id: SeriesId) -> Option<(Timestamp, Timestamp)> {
+        let bounds = self.bounds.read().unwrap_or_else(PoisonError::into_inner);
+
+        bounds.get(&series_id).map(|entry| {
+            let entry = entry.lock().unwrap_or_else(PoisonError::into_inner);
+            (entry.min, entry.max)
+        })
+    }
+
+    /// Returns `false` if the series can not have data points in the time range.
+    pub fn overlaps(
+        &self,
+        series_id: SeriesId,
+        (start, end): (Bound<Timestamp>, Bound<Timestamp>),
+    ) -> bool {
+        // NOTE: Unknown series are never skipped
+        let Some((min, max)) = self.get(series_id) else {
+            return true;
+        };
+
+        let after_start = match start {
+            Bound::Included(start) => max >= start,
+            Bound::Excluded(start) => max > start,
+            Bound::Unbounded => true,
+        };
+
+        let before_end = match end {
+            Bound::Included(end) => min <= end,
+            Bound::Excluded(end) => min < end,
+            Bound::Unbounded => true,
+        };
+
+        after_start && before_end
+    }
+
+    /// Forgets the bounds of a removed series.
+    pub fn remove(&self, series_id: SeriesId) -> crate::Result<()> {
+        self.bounds
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&series_id);
+
+        let mut key = KEY_PREFIX.as_bytes().to_vec();
+        key.extend_from_slice(&series_id.to_be_bytes());
+        self.meta.remove(key)?;
+
+        Ok(())
+    }
+
+    /// Writes the changed bounds into the meta partition.
+    pub fn persist(&self) -> crate::Result<()> {
+        // NOTE: Blocks writers, so no write can slip in between persisting the bounds
+        // and setting the clean marker
+        let bounds = self.bounds.write().unwrap_or_else(PoisonError::into_inner);
+
+        for (series_id, entry) in bounds.iter() {
+            let mut entry = entry.lock().unwrap_or_else(PoisonError::into_inner);
+
+            if !entry.dirty {
+                continue;
+            }
+
+            let mut key = KEY_PREFIX.as_bytes().to_vec();
+            key.extend_from_slice(&series_id.to_be_bytes());
+
+            let mut value = Vec::with_capacity(2 * std::mem::size_of::<Timestamp>());
+            value.extend_from_slice(&entry.min.to_be_bytes());
+            value.extend_from_slice(&entry.max.to_be_bytes());
+
+            self.meta.insert(key, value)?;
+            entry.dirty = false;
+        }
+
+        self.meta.insert(CLEAN_KEY, [])?;
+        self.clean.store(true, Ordering::Release);
+
+        drop(bounds);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn series_bounds_overlaps() -> crate::Result<()> {
+        use Bound::{Excluded, Included, Unbounded};
+
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let smap = keyspace.open_partition("smap", PartitionCreateOptions::default())?;
+        let data = keyspace.open_partition("data", PartitionCreateOptions::default())?;
+
+        let bounds = SeriesBounds::new(&keyspace, "_talna#v1#", &smap, data.inner(), None)?;
+
+        bounds.extend(0, 20)?;
+        bounds.extend(0, 10)?;
+        bounds.extend(0, 15)?;
+        assert_eq!(Some((10, 20)), bounds.get(0));
+
+        assert!(bounds.overlaps(0, (Unbounded, Unbounded)));
+        assert!(bounds.overlaps(0, (Included(20), Unbounded)));
+        assert!(!bounds.overlaps(0, (Excluded(20), Unbounded)));
+        assert!(bounds.overlaps(0, (Unbounded, Included(10))));
+        assert!(!bounds.overlaps(0, (Unbounded, Excluded(10))));
+        assert!(!bounds.overlaps(0, (Included(21), Included(30))));
+        assert!(bounds.overlaps(0, (Included(12), Included(13))));
+
+        // NOTE: Unknown series are not skipped
+        assert!(bounds.overlaps(1, (Included(21), Included(30))));
+
+        bounds.persist()?;
+        drop(bounds);
+
+        let bounds = SeriesBounds::new(&keyspace, "_talna#v1#", &smap, data.inner(), None)?;
+        assert_eq!(Some((10, 20)), bounds.get(0));
+
+        // NOTE: Bounds written after persisting are not trusted after a crash
+        bounds.extend(0, 30)?;
+        drop(bounds);
+
+        let bounds = SeriesBounds::new(&keyspace, "_talna#v1#", &smap, data.inner(), None)?;
+        assert_eq!(None, bounds.get(0));
+
+        Ok(())
+    }
+}
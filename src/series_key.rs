@@ -18,8 +18,12 @@ impl SeriesKey {
 
     #[doc(hidden)]
     pub fn join_tags(buf: &mut String, tags: &TagSet) {
-        let mut tags = tags.iter().collect::<Vec<_>>();
-        tags.sort();
+        // NOTE: Tags are usually passed pre-sorted (e.g. TagSetBuf), so avoid the allocation
+        if !crate::tagset::is_sorted(tags) {
+            let mut tags = tags.to_vec();
+            tags.sort_unstable();
+            return Self::join_tags(buf, &tags);
+        }
 
         for (idx, (key, value)) in tags.iter().enumerate() {
             if idx > 0 {
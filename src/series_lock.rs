@@ -0,0 +1,65 @@
+use crate::SeriesId;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Amount of locks series are spread over
+const STRIPES: usize = 64;
+
+/// Striped locks that serialize writes to a series with its removal
+///
+/// Writes into an existing series take a shared lock, so they can run concurrently.
+/// Removing (or moving) a series takes the exclusive lock, so no write can sneak in
+/// between deciding to remove the series and removing it.
+///
+/// Series are spread over a fixed amount of locks, so unrelated series may share a lock.
+pub struct SeriesLocks {
+    stripes: Box<[RwLock<()>]>,
+}
+
+impl SeriesLocks {
+    pub fn new() -> Self {
+        Self {
+            stripes: (0..STRIPES).map(|_| RwLock::new(())).collect(),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn index(series_id: SeriesId) -> usize {
+        (series_id % STRIPES as u64) as usize
+    }
+
+    fn stripe(&self, series_id: SeriesId) -> &RwLock<()> {
+        #[allow(clippy::indexing_slicing)]
+        &self.stripes[Self::index(series_id)]
+    }
+
+    /// Locks the series for writing data points.
+    pub fn write_points(&self, series_id: SeriesId) -> RwLockReadGuard<'_, ()> {
+        self.stripe(series_id)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the series for removing it, blocking writes into it.
+    pub fn remove(&self, series_id: SeriesId) -> RwLockWriteGuard<'_, ()> {
+        self.stripe(series_id)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks two series for moving data points from one into the other, blocking writes into both.
+    ///
+    /// The locks are taken in a fixed order, so two concurrent moves can not deadlock.
+    pub fn remove_pair(&self, a: SeriesId, b: SeriesId) -> Vec<RwLockWriteGuard<'_, ()>> {
+        let (first, second) = if Self::index(a) <= Self::index(b) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        if Self::index(first) == Self::index(second) {
+            return vec![self.remove(first)];
+        }
+
+        vec![self.remove(first), self.remove(second)]
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    db::{SeriesReader, StreamItem},
+    SeriesId,
+};
+use std::collections::BTreeMap;
+
+/// Raw data points of a single series, ordered from newest to oldest,
+/// see [`crate::Database::series_streams`]
+///
+/// Unlike aggregations, series are not merged into groups, so applications can
+/// implement custom joins between series (e.g. divide each host's metric A by its metric B).
+pub struct SeriesStream {
+    /// ID of the series
+    pub series_id: SeriesId,
+
+    /// Metric the series was written to
+    ///
+    /// May be an aliased metric, see [`crate::Database::alias_metric`].
+    pub metric: String,
+
+    /// Tags of the series
+    pub tags: BTreeMap<String, String>,
+
+    pub(crate) reader: SeriesReader,
+}
+
+impl std::fmt::Debug for SeriesStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeriesStream")
+            .field("series_id", &self.series_id)
+            .field("metric", &self.metric)
+            .field("tags", &self.tags)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Iterator for SeriesStream {
+    type Item = crate::Result<StreamItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next()
+    }
+}
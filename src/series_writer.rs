@@ -0,0 +1,111 @@
+use crate::{
+    time::timestamp, Database, MetricNameBuf, SeriesId, Stat, Timestamp, Value, ValueEncoding,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Writer for a single series, see [`Database::writer`]
+///
+/// The series ID is resolved once, so writes skip formatting and looking up the series key.
+///
+/// If the series is removed (see [`Database::retag`] & [`Database::gc_idle_series`]),
+/// writes return [`crate::Error::SeriesRemoved`], and the writer needs to be recreated.
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use talna::{Database, MetricName, tagset};
+///
+/// let db = Database::builder().open(&folder)?;
+///
+/// let metric_name = MetricName::try_from("cpu.total").unwrap();
+/// let writer = db.writer(metric_name, tagset!("host" => "h-1"))?;
+///
+/// writer.write(25.42)?;
+/// writer.write(42.42)?;
+/// #
+/// # Ok::<_, talna::Error>(())
+/// ```
+pub struct SeriesWriter {
+    pub(crate) db: Database,
+    pub(crate) metric: MetricNameBuf,
+    pub(crate) series_id: SeriesId,
+    pub(crate) encoding: ValueEncoding,
+
+    /// Amount of series removals at the time the series was last known to exist
+    pub(crate) checked_removals: AtomicU64,
+}
+
+impl Clone for SeriesWriter {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            metric: self.metric.clone(),
+            series_id: self.series_id,
+            encoding: self.encoding,
+            checked_removals: AtomicU64::new(self.checked_removals.load(Ordering::SeqCst)),
+        }
+    }
+}
+
+impl SeriesWriter {
+    /// Makes sure the series was not removed since it was last checked.
+    fn check_series(&self) -> crate::Result<()> {
+        let removals = self.db.series_removals();
+
+        if removals == self.checked_removals.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if !self.db.series_exists(self.series_id)? {
+            return Err(crate::Error::SeriesRemoved(self.series_id));
+        }
+
+        self.checked_removals.store(removals, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Writes a data point with the current time.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, the timestamp is out of range, or the series was removed.
+    pub fn write(&self, value: Value) -> crate::Result<()> {
+        self.write_at(timestamp(), value)
+    }
+
+    /// Writes a data point with the given timestamp.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, the timestamp is out of range, or the series was removed.
+    pub fn write_at(&self, ts: Timestamp, value: Value) -> crate::Result<()> {
+        self.db.check_timestamp(ts)?;
+        self.db.run_write(|| {
+            let _lock = self.db.lock_series(self.series_id);
+            self.check_series()?;
+            self.db
+                .insert_data_point(self.series_id, ts, self.encoding.encode(value))
+        })?;
+        self.db.invalidate_query_cache(self.metric.as_metric_name());
+        self.db.count_points(self.metric.as_metric_name(), 1);
+        Ok(())
+    }
+
+    /// Writes a pre-aggregated sample with the given timestamp.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred, the timestamp is out of range, or the series was removed.
+    pub fn write_stat(&self, ts: Timestamp, stat: Stat) -> crate::Result<()> {
+        self.db.check_timestamp(ts)?;
+        self.db.run_write(|| {
+            let _lock = self.db.lock_series(self.series_id);
+            self.check_series()?;
+            self.db
+                .insert_data_point(self.series_id, ts, stat.serialize())
+        })?;
+        self.db.invalidate_query_cache(self.metric.as_metric_name());
+        self.db.count_points(self.metric.as_metric_name(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+use crate::{
+    agg::Builder,
+    line_protocol::Line,
+    output::{json_bucket_fields, json_string},
+    timestamp, Aggregation, Bucket, Database, MetricGlob, MetricName, Timestamp,
+};
+use std::net::{SocketAddr, ToSocketAddrs};
+use tiny_http::{Header, Method, Request, Response};
+
+/// Minimal embedded HTTP API
+///
+/// - `POST /write` ingests data points in line protocol, one per line:
+///   `<metric>[,<key>=<value>...] <value> [<timestamp>]`
+///
+///   The whole body is validated first, so an invalid line rejects the request
+///   without writing any data point. If writing fails afterwards (e.g. because the
+///   quota is exceeded), the error message contains the amount of lines that were written.
+///
+/// - `GET /query` runs an aggregation and returns its buckets as JSON, mapping
+///   each group to a list of buckets.
+///   Parameters: `metric`, `group_by`,
+///   `agg` (`avg`, `sum`, `min`, `max`, `count`, `distinct`, `active_series`; default: `avg`),
+///   `filter` (default: `*`), `start`, `end` and `granularity` (in nanoseconds)
+///
+/// - `GET /metrics` lists the metrics matching the `glob` parameter (default: `*`) as JSON,
+///   mapping each metric to its unit & description (or `null`)
+///
+/// Only available using the `server` feature flag.
+pub struct Server {
+    http: tiny_http::Server,
+    db: Database,
+}
+
+/// Response status + body
+type Reply = (u16, String);
+
+impl Server {
+    /// Binds the HTTP server to the given address.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the address could not be bound.
+    pub fn bind<A: ToSocketAddrs>(db: Database, addr: A) -> crate::Result<Self> {
+        let http = tiny_http::Server::http(addr).map_err(std::io::Error::other)?;
+        Ok(Self { http, db })
+    }
+
+    /// Returns the address the server is bound to.
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Handles incoming requests, blocking the current thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred while accepting requests.
+    pub fn run(&self) -> crate::Result<()> {
+        loop {
+            let request = self.http.recv()?;
+            self.handle(request);
+        }
+    }
+
+    fn handle(&self, mut request: Request) {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        let (status, body) = match (request.method(), path) {
+            (Method::Post, "/write") => {
+                let mut body = String::new();
+
+                match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) => self.write(&body),
+                    Err(e) => (400, e.to_string()),
+                }
+            }
+            (Method::Get, "/query") => self.query(query),
+            (Method::Get, "/metrics") => self.metrics(query),
+            _ => (404, "not found".into()),
+        };
+
+        let mut response = Response::from_string(body).with_status_code(status);
+
+        if status == 200 {
+            if let Ok(header) = Header::from_bytes("Content-Type", "application/json") {
+                response = response.with_header(header);
+            }
+        }
+
+        if let Err(e) = request.respond(response) {
+            log::warn!("Failed to send HTTP response: {e:?}");
+        }
+    }
+
+    fn write(&self, body: &str) -> Reply {
+        let mut lines = vec![];
+
+        for (idx, line) in body.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let line = match Line::parse(line) {
+                Ok(line) => line,
+                Err(e) => return (400, format!("line {}: {e}", idx + 1)),
+            };
+
+            let ts = line.ts.unwrap_or_else(timestamp);
+
+            if let Err(e) = self.db.validate_write(line.metric, ts, &line.tags) {
+                return (400, format!("line {}: {e}", idx + 1));
+            }
+
+            lines.push((line, ts));
+        }
+
+        for (written, (line, ts)) in lines.iter().enumerate() {
+            if let Err(e) = self.db.write_at(line.metric, *ts, line.value, &line.tags) {
+                let status = if matches!(e, crate::Error::QuotaExceeded) {
+                    507
+                } else {
+                    500
+                };
+
+                return (
+                    status,
+                    format!("{e} ({written} of {} lines written)", lines.len()),
+                );
+            }
+        }
+
+        (204, String::new())
+    }
+
+    fn query(&self, query: &str) -> Reply {
+        let params = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k, percent_decode(v)))
+            .collect::<crate::HashMap<_, _>>();
+
+        let param = |key: &str| params.get(key).map(String::as_str);
+
+        let parse_ts = |key: &str| {
+            param(key)
+                .map(str::parse::<Timestamp>)
+                .transpose()
+                .map_err(|_| (400, format!("invalid {key}")))
+        };
+
+        let (start, end, granularity) =
+            match (parse_ts("start"), parse_ts("end"), parse_ts("granularity")) {
+                (Ok(start), Ok(end), Ok(granularity)) => (start, end, granularity),
+                (Err(reply), _, _) | (_, Err(reply), _) | (_, _, Err(reply)) => return reply,
+            };
+
+        let Some(metric) = param("metric") else {
+            return (400, "missing metric".into());
+        };
+        let Ok(metric) = MetricName::try_from(metric) else {
+            return (400, "invalid metric".into());
+        };
+
+        let Some(group_by) = param("group_by") else {
+            return (400, "missing group_by".into());
+        };
+
+        let filter = param("filter").unwrap_or("*");
+
+        let query = Query {
+            filter,
+            start,
+            end,
+            granularity,
+        };
+
+        let result = match param("agg").unwrap_or("avg") {
+            "avg" => query.run(self.db.avg(metric, group_by)),
+            "sum" => query.run(self.db.sum(metric, group_by)),
+            "min" => query.run(self.db.min(metric, group_by)),
+            "max" => query.run(self.db.max(metric, group_by)),
+            "count" => query.run(self.db.count(metric, group_by)),
+            "distinct" => query.run(self.db.distinct(metric, group_by)),
+            "active_series" => query.run(self.db.active_series(metric, group_by)),
+            agg => return (400, format!("invalid agg {agg:?}")),
+        };
+
+        match result {
+            Ok(groups) => (200, groups_to_json(groups)),
+            Err(crate::Error::InvalidQuery) => (400, "invalid filter".into()),
+            Err(crate::Error::Timeout) => (504, "query timed out".into()),
+            Err(e) => (500, e.to_string()),
+        }
+    }
+
+    fn metrics(&self, query: &str) -> Reply {
+        let glob = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "glob")
+            .map_or_else(|| "*".into(), |(_, v)| percent_decode(v));
+
+        let Ok(glob) = MetricGlob::try_from(glob.as_str()) else {
+            return (400, "invalid glob".into());
+        };
+
+        let metrics = match self.db.list_metrics(glob) {
+            Ok(metrics) => metrics,
+            Err(e) => return (500, e.to_string()),
+        };
+
+        let mut buf = String::from("{");
+
+        for (idx, metric) in metrics.iter().enumerate() {
+            if idx > 0 {
+                buf.push(',');
+            }
+
+            json_string(&mut buf, metric);
+            buf.push(':');
+
+            let metadata = MetricName::try_from(metric.as_str())
+                .ok()
+                .and_then(|metric| self.db.metric_metadata(metric));
+
+            if let Some(metadata) = metadata {
+                buf.push_str(r#"{"unit":"#);
+                json_string(&mut buf, &metadata.unit);
+                buf.push_str(r#","description":"#);
+                json_string(&mut buf, &metadata.description);
+                buf.push('}');
+            } else {
+                buf.push_str("null");
+            }
+        }
+
+        buf.push('}');
+
+        (200, buf)
+    }
+}
+
+struct Query<'a> {
+    filter: &'a str,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+    granularity: Option<Timestamp>,
+}
+
+impl<'a> Query<'a> {
+    fn run<A: Aggregation>(
+        &self,
+        mut builder: Builder<'a, A>,
+    ) -> crate::Result<crate::HashMap<String, Vec<Bucket>>> {
+        builder = builder.filter(self.filter);
+
+        if let Some(ts) = self.start {
+            builder = builder.start(ts);
+        }
+        if let Some(ts) = self.end {
+            builder = builder.end(ts);
+        }
+        if let Some(granularity) = self.granularity {
+            builder = builder.granularity(granularity);
+        }
+
+        builder.build()?.collect()
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next(), iter.next()];
+
+                let decoded = if let [Some(hi), Some(lo)] = hex {
+                    std::str::from_utf8(&[hi, lo])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                } else {
+                    None
+                };
+
+                if let Some(b) = decoded {
+                    bytes.push(b);
+                } else {
+                    bytes.push(b'%');
+                    bytes.extend(hex.into_iter().flatten());
+                }
+            }
+            b => bytes.push(b),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn groups_to_json(groups: crate::HashMap<String, Vec<Bucket>>) -> String {
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut buf = String::from("{");
+
+    for (idx, (group, buckets)) in groups.iter().enumerate() {
+        if idx > 0 {
+            buf.push(',');
+        }
+
+        json_string(&mut buf, group);
+        buf.push_str(":[");
+
+        for (idx, bucket) in buckets.iter().enumerate() {
+            if idx > 0 {
+                buf.push(',');
+            }
+
+            buf.push('{');
+            json_bucket_fields(&mut buf, bucket);
+            buf.push('}');
+        }
+
+        buf.push(']');
+    }
+
+    buf.push('}');
+    buf
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn request(addr: SocketAddr, req: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(req.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test_log::test]
+    fn server_write_query() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let server = Arc::new(Server::bind(db.clone(), "127.0.0.1:0")?);
+        let addr = server.local_addr().unwrap();
+
+        std::thread::spawn({
+            let server = server.clone();
+            move || server.run()
+        });
+
+        let body = "cpu.total,env=prod,host=h-1 4 1\ncpu.total,env=prod,host=h-1 6 2\ncpu.total,env=dev,host=h-2 1 3\n";
+        let response = request(
+            addr,
+            &format!(
+                "POST /write HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len(),
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.0 204"), "{response}");
+
+        let response = request(
+            addr,
+            "GET /query?metric=cpu.total&group_by=host&agg=avg&filter=env%3Aprod HTTP/1.0\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.0 200"), "{response}");
+        assert!(
+            response.ends_with(r#"{"h-1":[{"start":1,"end":2,"len":2,"sum":10,"value":5}]}"#),
+            "{response}"
+        );
+
+        server.db.describe_metric(
+            MetricName::try_from("cpu.total").unwrap(),
+            "%",
+            "CPU \"total\"",
+        )?;
+        let response = request(addr, "GET /metrics?glob=cpu.* HTTP/1.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.0 200"), "{response}");
+        assert!(
+            response.ends_with(r#"{"cpu.total":{"unit":"%","description":"CPU \"total\""}}"#),
+            "{response}"
+        );
+
+        let response = request(addr, "GET /query?metric=cpu.total HTTP/1.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.0 400"), "{response}");
+
+        let response = request(
+            addr,
+            "POST /write HTTP/1.0\r\nContent-Length: 9\r\n\r\ncpu.total",
+        );
+        assert!(response.starts_with("HTTP/1.0 400"), "{response}");
+
+        // NOTE: Lines before an invalid line are not written either
+        let body = "mem.used,host=h-1 1.0 10\nmem.used,host=h;1 1.0 10";
+        let response = request(
+            addr,
+            &format!(
+                "POST /write HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.0 400"), "{response}");
+        assert!(response.contains("line 2"), "{response}");
+        assert_eq!(2, db.series_count()?);
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn server_percent_decode() {
+        assert_eq!("env:prod AND x", percent_decode("env%3Aprod+AND%20x"));
+        assert_eq!("100%", percent_decode("100%"));
+        assert_eq!("%zz", percent_decode("%zz"));
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{Stat, Value};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Relative accuracy of quantiles returned by a sketch
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Maximum amount of bins per sign, the lowest bins are collapsed beyond that
+const MAX_BINS: usize = 2_048;
+
+/// Serialization format version
+const VERSION: u8 = 1;
+
+/// A mergeable quantile sketch (`DDSketch`) of a rolled-up bucket
+///
+/// Values are counted in logarithmically sized bins, so quantiles have a
+/// relative error of at most 1%, regardless of the amount of values.
+/// Count, sum, minimum & maximum are tracked exactly.
+///
+/// Sketches are written using [`crate::Database::write_sketch`], so quantile
+/// aggregations stay accurate over downsampled data.
+///
+/// # Examples
+///
+/// ```
+/// use talna::QuantileSketch;
+///
+/// let mut sketch = QuantileSketch::default();
+///
+/// for x in 1..=100 {
+///     sketch.insert(x as talna::Value);
+/// }
+///
+/// let p95 = sketch.quantile(0.95).unwrap();
+/// assert!((p95 - 95.0).abs() <= 95.0 * 0.01);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantileSketch {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero_count: u64,
+
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl QuantileSketch {
+    fn gamma_ln() -> f64 {
+        (2.0 * RELATIVE_ACCURACY / (1.0 - RELATIVE_ACCURACY)).ln_1p()
+    }
+
+    /// Smallest magnitude that gets its own bin, smaller values are counted as zero
+    fn min_indexable() -> f64 {
+        f64::MIN_POSITIVE * (1.0 + 2.0 * RELATIVE_ACCURACY / (1.0 - RELATIVE_ACCURACY))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn index(x: f64) -> i32 {
+        (x.ln() / Self::gamma_ln()).ceil() as i32
+    }
+
+    fn bin_value(index: i32) -> f64 {
+        let gamma = Self::gamma_ln().exp();
+        2.0 * (f64::from(index) * Self::gamma_ln()).exp() / (gamma + 1.0)
+    }
+
+    /// Merges the lowest bins, if there are too many
+    fn collapse(bins: &mut BTreeMap<i32, u64>) {
+        while bins.len() > MAX_BINS {
+            if let (Some((_, lowest)), Some(mut second)) = (bins.pop_first(), bins.first_entry()) {
+                *second.get_mut() += lowest;
+            }
+        }
+    }
+
+    /// Adds a value to the sketch.
+    pub fn insert(&mut self, value: Value) {
+        self.insert_n(value, 1);
+    }
+
+    /// Adds a value that occurred `n` times to the sketch.
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::useless_conversion)]
+    pub fn insert_n(&mut self, value: Value, n: u64) {
+        let x = f64::from(value);
+
+        if n == 0 || x.is_nan() {
+            return;
+        }
+
+        if x > Self::min_indexable() {
+            *self.positive.entry(Self::index(x)).or_default() += n;
+            Self::collapse(&mut self.positive);
+        } else if x < -Self::min_indexable() {
+            *self.negative.entry(Self::index(-x)).or_default() += n;
+            Self::collapse(&mut self.negative);
+        } else {
+            self.zero_count += n;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let sum = x * n as f64;
+
+        if self.count == 0 {
+            (self.min, self.max) = (x, x);
+        } else {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+        }
+
+        self.count += n;
+        self.sum += sum;
+    }
+
+    /// Adds the data points of a pre-aggregated sample to the sketch.
+    ///
+    /// Only the minimum, maximum & sum of the sample are known, so
+    /// its remaining data points are approximated by their mean.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn insert_stat(&mut self, stat: &Stat) {
+        match stat.count {
+            0 => {}
+            1 => self.insert(stat.sum),
+            count => {
+                let rest = count - 2;
+                self.insert(stat.min);
+                self.insert(stat.max);
+
+                if rest > 0 {
+                    let mean = (stat.sum - stat.min - stat.max) / rest as Value;
+                    self.insert_n(mean, rest);
+                }
+            }
+        }
+    }
+
+    /// Merges another sketch into this sketch.
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+
+        for (&index, &n) in &other.positive {
+            *self.positive.entry(index).or_default() += n;
+        }
+        for (&index, &n) in &other.negative {
+            *self.negative.entry(index).or_default() += n;
+        }
+        Self::collapse(&mut self.positive);
+        Self::collapse(&mut self.negative);
+
+        if self.count == 0 {
+            (self.min, self.max) = (other.min, other.max);
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    /// Returns the amount of values in the sketch.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns `true` if the sketch contains no values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the approximate quantile (nearest rank), `q` is clamped to `[0.0, 1.0]`.
+    ///
+    /// Returns `None` if the sketch is empty.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn quantile(&self, q: f64) -> Option<Value> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+
+        // NOTE: Negative bins are ordered by magnitude, so the lowest values come last
+        let bins = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(&index, &n)| (-Self::bin_value(index), n))
+            .chain(std::iter::once((0.0, self.zero_count)))
+            .chain(
+                self.positive
+                    .iter()
+                    .map(|(&index, &n)| (Self::bin_value(index), n)),
+            );
+
+        let mut seen = 0;
+        let mut value = self.max;
+
+        for (bin_value, n) in bins {
+            seen += n;
+
+            if seen >= rank {
+                value = bin_value;
+                break;
+            }
+        }
+
+        Some(value.clamp(self.min, self.max) as Value)
+    }
+
+    /// Returns the exact count, sum, minimum & maximum of the sketch.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn stat(&self) -> Stat {
+        Stat {
+            count: self.count,
+            sum: self.sum as Value,
+            min: self.min as Value,
+            max: self.max as Value,
+        }
+    }
+
+    fn serialize_bins(bytes: &mut Vec<u8>, bins: &BTreeMap<i32, u64>) {
+        // NOTE: Bins are collapsed to MAX_BINS, so the length fits
+        #[allow(clippy::cast_possible_truncation)]
+        let _ = bytes.write_u16::<BigEndian>(bins.len() as u16);
+
+        for (&index, &n) in bins {
+            let _ = bytes.write_i32::<BigEndian>(index);
+            let _ = bytes.write_u64::<BigEndian>(n);
+        }
+    }
+
+    fn deserialize_bins<R: Read>(reader: &mut R) -> std::io::Result<BTreeMap<i32, u64>> {
+        let len = reader.read_u16::<BigEndian>()?;
+        let mut bins = BTreeMap::new();
+
+        for _ in 0..len {
+            let index = reader.read_i32::<BigEndian>()?;
+            let n = reader.read_u64::<BigEndian>()?;
+            bins.insert(index, n);
+        }
+
+        Ok(bins)
+    }
+
+    /// Serializes the bins of the sketch, the statistics are stored by the [`Stat`] it is written with
+    pub(crate) fn serialize_into(&self, bytes: &mut Vec<u8>) {
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.zero_count.to_be_bytes());
+        Self::serialize_bins(bytes, &self.positive);
+        Self::serialize_bins(bytes, &self.negative);
+    }
+
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(clippy::useless_conversion)]
+    pub(crate) fn deserialize<R: Read>(reader: &mut R, stat: &Stat) -> std::io::Result<Self> {
+        let version = reader.read_u8()?;
+
+        if version != VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown sketch version {version}"),
+            ));
+        }
+
+        let zero_count = reader.read_u64::<BigEndian>()?;
+        let positive = Self::deserialize_bins(reader)?;
+        let negative = Self::deserialize_bins(reader)?;
+
+        Ok(Self {
+            positive,
+            negative,
+            zero_count,
+            count: stat.count,
+            sum: f64::from(stat.sum),
+            min: f64::from(stat.min),
+            max: f64::from(stat.max),
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    // NOTE: Value is f64 when using the `high_precision` feature
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_lossless,
+        clippy::float_cmp
+    )]
+    fn sketch_quantile_accuracy() {
+        let mut sketch = QuantileSketch::default();
+
+        for x in 1..=10_000 {
+            sketch.insert(x as Value);
+        }
+
+        for q in [0.0, 0.25, 0.5, 0.9, 0.95, 0.99, 1.0] {
+            let expected = ((q * 10_000.0) as Value).max(1.0);
+            let actual = sketch.quantile(q).unwrap();
+            assert!(
+                (actual - expected).abs() <= expected * 0.01,
+                "q={q}: {actual} != {expected}",
+            );
+        }
+
+        assert_eq!(10_000, sketch.count());
+        assert_eq!(1.0, sketch.stat().min);
+        assert_eq!(10_000.0, sketch.stat().max);
+    }
+
+    #[test_log::test]
+    fn sketch_negative_and_zero() {
+        let mut sketch = QuantileSketch::default();
+
+        for x in [-100.0, -10.0, 0.0, 10.0, 100.0] {
+            sketch.insert(x);
+        }
+
+        assert_eq!(Some(-100.0), sketch.quantile(0.0));
+        assert_eq!(Some(0.0), sketch.quantile(0.5));
+        assert_eq!(Some(100.0), sketch.quantile(1.0));
+
+        let ten = sketch.quantile(0.8).unwrap();
+        assert!((ten - 10.0).abs() <= 0.1);
+
+        let minus_ten = sketch.quantile(0.4).unwrap();
+        assert!((minus_ten + 10.0).abs() <= 0.1);
+    }
+
+    #[test_log::test]
+    fn sketch_merge() {
+        let mut a = QuantileSketch::default();
+        let mut b = QuantileSketch::default();
+
+        a.insert_n(1.0, 90);
+        b.insert_n(1_000.0, 10);
+        a.merge(&b);
+
+        assert_eq!(100, a.count());
+        assert_eq!(Some(1.0), a.quantile(0.9));
+        assert_eq!(Some(1_000.0), a.quantile(0.91));
+    }
+
+    #[test_log::test]
+    fn sketch_roundtrip() {
+        let mut sketch = QuantileSketch::default();
+
+        for x in [-3.5, 0.0, 1.0, 2.0, 2.0, 500.0] {
+            sketch.insert(x);
+        }
+
+        let stat = sketch.stat();
+
+        let mut bytes = vec![];
+        sketch.serialize_into(&mut bytes);
+
+        assert_eq!(
+            sketch,
+            QuantileSketch::deserialize(&mut &bytes[..], &stat).unwrap()
+        );
+    }
+
+    #[test_log::test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn sketch_collapse() {
+        let mut sketch = QuantileSketch::default();
+
+        let values = std::iter::successors(Some(1e-30_f64), |x| Some(x * 1.01));
+        for x in values.take_while(|&x| x < 1e30) {
+            sketch.insert(x as Value);
+        }
+
+        assert!(sketch.positive.len() <= MAX_BINS);
+        let max = sketch.stat().max;
+        assert!((sketch.quantile(1.0).unwrap() - max).abs() <= max * 0.01);
+    }
+}
@@ -1,39 +1,159 @@
 use crate::SeriesId;
 use byteorder::{BigEndian, ReadBytesExt};
 use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition, WriteTransaction};
-use std::collections::HashSet;
+use quick_cache::{sync::Cache, Weighter};
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-const PARTITION_NAME: &str = "_talna#v1#smap";
+const PARTITION_NAME: &str = "smap";
+pub const META_PARTITION_NAME: &str = "meta";
+
+const NEXT_SERIES_ID_KEY: &str = "next_series_id";
+
+/// Weighs series keys by their approximate heap size
+#[derive(Clone)]
+struct SeriesKeyWeighter;
+
+impl Weighter<String, SeriesId> for SeriesKeyWeighter {
+    fn weight(&self, series_key: &String, _: &SeriesId) -> u64 {
+        (series_key.len() + std::mem::size_of::<String>() + std::mem::size_of::<SeriesId>()) as u64
+    }
+}
 
 pub struct SeriesMapping {
     keyspace: TxKeyspace,
     pub(crate) partition: TxPartition,
+
+    /// Holds the next series ID to allocate
+    ///
+    /// Series can be removed, so the amount of series can not be used as the next ID.
+    meta: TxPartition,
+
+    /// Recently used series keys, so the write path does not need to
+    /// look up the series ID in the partition
+    ///
+    /// Series are removed by `retag` and `gc_idle_series`, after which their entries are stale,
+    /// so every removal needs to be followed by [`SeriesMapping::invalidate`].
+    cache: Cache<String, SeriesId, SeriesKeyWeighter>,
+
+    /// Counts the invalidations, so a lookup that raced with a removal
+    /// does not cache the removed series ID again
+    invalidations: AtomicU64,
 }
 
 impl SeriesMapping {
-    pub fn new(keyspace: &TxKeyspace) -> crate::Result<Self> {
+    pub fn new(
+        keyspace: &TxKeyspace,
+        prefix: &str,
+        cache_capacity_bytes: u64,
+    ) -> crate::Result<Self> {
         let opts = PartitionCreateOptions::default()
             .block_size(4_096)
             .compression(CompressionType::Lz4)
             .max_memtable_size(4_000_000);
 
-        let partition = keyspace.open_partition(PARTITION_NAME, opts)?;
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+        let meta = keyspace.open_partition(
+            &format!("{prefix}{META_PARTITION_NAME}"),
+            PartitionCreateOptions::default(),
+        )?;
+
+        // NOTE: Databases created before the counter existed allocated IDs sequentially
+        if meta.get(NEXT_SERIES_ID_KEY)?.is_none() {
+            let next_series_id = partition.inner().len()? as SeriesId;
+            meta.insert(NEXT_SERIES_ID_KEY, next_series_id.to_be_bytes())?;
+        }
+
+        // NOTE: Assume ~64 bytes per series key to estimate the amount of items
+        let estimated_items = usize::try_from(cache_capacity_bytes / 64).unwrap_or(usize::MAX);
 
         Ok(Self {
             keyspace: keyspace.clone(),
             partition,
+            meta,
+            cache: Cache::with_weighter(estimated_items, cache_capacity_bytes, SeriesKeyWeighter),
+            invalidations: AtomicU64::new(0),
         })
     }
 
+    /// Returns the approximate size of the cached series keys in bytes.
+    pub fn cache_size(&self) -> u64 {
+        self.cache.weight()
+    }
+
+    /// Allocates a new series ID.
+    pub fn next_series_id(&self, tx: &mut WriteTransaction) -> crate::Result<SeriesId> {
+        let series_id = match tx.get(&self.meta, NEXT_SERIES_ID_KEY)? {
+            Some(bytes) => (&bytes[..]).read_u64::<BigEndian>()?,
+            None => 0,
+        };
+
+        tx.insert(
+            &self.meta,
+            NEXT_SERIES_ID_KEY,
+            (series_id + 1).to_be_bytes(),
+        );
+
+        Ok(series_id)
+    }
+
     pub fn insert(&self, tx: &mut WriteTransaction, series_key: &str, series_id: SeriesId) {
         tx.insert(&self.partition, series_key, series_id.to_be_bytes());
     }
 
+    pub fn remove(&self, tx: &mut WriteTransaction, series_key: &str) {
+        tx.remove(&self.partition, series_key);
+    }
+
+    /// Removes a series key from the cache.
+    ///
+    /// Needs to be called after a series was removed.
+    pub fn invalidate(&self, series_key: &str) {
+        self.invalidations.fetch_add(1, Ordering::SeqCst);
+        self.cache.remove(series_key);
+    }
+
+    /// Caches a series ID after its series was created.
+    pub fn cache(&self, series_key: &str, series_id: SeriesId) {
+        self.cache.insert(series_key.to_string(), series_id);
+    }
+
+    pub fn deserialize_series_id(
+        &self,
+        series_key: impl AsRef<[u8]>,
+        mut bytes: &[u8],
+    ) -> crate::Result<SeriesId> {
+        bytes
+            .read_u64::<BigEndian>()
+            .map_err(|_| crate::Error::corruption(&self.partition.inner().name, series_key))
+    }
+
     pub fn get(&self, series_key: &str) -> crate::Result<Option<SeriesId>> {
-        Ok(self.partition.get(series_key)?.map(|bytes| {
-            let mut reader = &bytes[..];
-            reader.read_u64::<BigEndian>().expect("should deserialize")
-        }))
+        if let Some(series_id) = self.cache.get(series_key) {
+            return Ok(Some(series_id));
+        }
+
+        let invalidations = self.invalidations.load(Ordering::SeqCst);
+
+        let series_id = self
+            .partition
+            .get(series_key)?
+            .map(|bytes| self.deserialize_series_id(series_key, &bytes))
+            .transpose()?;
+
+        if let Some(series_id) = series_id {
+            self.cache(series_key, series_id);
+
+            // NOTE: The series may have been removed after it was read, in which case
+            // the entry may be stale, and the invalidation may have run before it was cached
+            if self.invalidations.load(Ordering::SeqCst) != invalidations {
+                self.cache.remove(series_key);
+            }
+        }
+
+        Ok(series_id)
     }
 
     pub fn list_all(&self) -> crate::Result<HashSet<SeriesId>> {
@@ -42,12 +162,63 @@ impl SeriesMapping {
         read_tx
             .iter(&self.partition)
             .map(|kv| match kv {
-                Ok((_, v)) => {
-                    let mut reader = &v[..];
-                    Ok(reader.read_u64::<BigEndian>().expect("should deserialize"))
-                }
+                Ok((k, v)) => self.deserialize_series_id(k, &v),
                 Err(e) => Err(e.into()),
             })
             .collect::<crate::Result<HashSet<_>>>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn smap_cache() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let smap = SeriesMapping::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+
+        assert_eq!(None, smap.get("cpu.total#host:h-1")?);
+
+        let mut tx = keyspace.write_tx();
+        smap.insert(&mut tx, "cpu.total#host:h-1", 7);
+        tx.commit()?;
+
+        // NOTE: Misses are not cached
+        assert_eq!(Some(7), smap.get("cpu.total#host:h-1")?);
+        assert_eq!(Some(7), smap.cache.get("cpu.total#host:h-1"));
+
+        let mut tx = keyspace.write_tx();
+        smap.remove(&mut tx, "cpu.total#host:h-1");
+        tx.commit()?;
+        smap.invalidate("cpu.total#host:h-1");
+
+        assert_eq!(None, smap.get("cpu.total#host:h-1")?);
+        assert_eq!(None, smap.cache.get("cpu.total#host:h-1"));
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn smap_corruption() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let smap = SeriesMapping::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+
+        smap.partition.insert("cpu.total#host:h-1", [0, 1, 2])?;
+
+        assert!(matches!(
+            smap.get("cpu.total#host:h-1"),
+            Err(crate::Error::Corruption { .. })
+        ));
+        assert!(matches!(
+            smap.list_all(),
+            Err(crate::Error::Corruption { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use crate::encoding::STAT_TAG;
+use crate::Value;
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::Read;
+
+/// A pre-aggregated sample
+///
+/// Can be used by agents that already roll up data points locally,
+/// and only periodically ship a summary of the raw data points.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stat {
+    /// The amount of raw data points that were rolled up
+    pub count: u64,
+
+    /// The sum of the raw data points
+    pub sum: Value,
+
+    /// The minimum raw data point
+    pub min: Value,
+
+    /// The maximum raw data point
+    pub max: Value,
+}
+
+impl Stat {
+    /// Size of a serialized stat, including its format tag
+    pub(crate) const SERIALIZED_LEN: usize =
+        1 + std::mem::size_of::<u64>() + 3 * std::mem::size_of::<Value>();
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SERIALIZED_LEN);
+        bytes.push(STAT_TAG);
+        bytes.extend_from_slice(&self.count.to_be_bytes());
+
+        for value in [self.sum, self.min, self.max] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    pub(crate) fn deserialize<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let tag = reader.read_u8()?;
+        if tag != STAT_TAG {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("expected stat, got value format {tag}"),
+            ));
+        }
+
+        let count = reader.read_u64::<BigEndian>()?;
+
+        #[cfg(feature = "high_precision")]
+        let (sum, min, max) = (
+            reader.read_f64::<BigEndian>()?,
+            reader.read_f64::<BigEndian>()?,
+            reader.read_f64::<BigEndian>()?,
+        );
+
+        #[cfg(not(feature = "high_precision"))]
+        let (sum, min, max) = (
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+            reader.read_f32::<BigEndian>()?,
+        );
+
+        Ok(Self {
+            count,
+            sum,
+            min,
+            max,
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn stat_roundtrip() {
+        let stat = Stat {
+            count: 5,
+            sum: 50.0,
+            min: 4.0,
+            max: 20.0,
+        };
+
+        let bytes = stat.serialize();
+        assert_eq!(Stat::SERIALIZED_LEN, bytes.len());
+        assert_eq!(stat, Stat::deserialize(&mut &bytes[..]).unwrap());
+    }
+}
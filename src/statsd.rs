@@ -0,0 +1,266 @@
+use crate::{sanitize, Database, MetricName, Value};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Maximum size of a UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+/// A sample parsed from a `StatsD` line
+///
+/// `<metric>:<value>|<type>[|@<sample rate>][|#<key>:<value>,...]`
+///
+/// e.g. `http.requests:1|c|@0.5|#env:prod,host:h-1`
+#[derive(Debug, PartialEq)]
+struct Sample<'a> {
+    metric: &'a str,
+    value: Value,
+    tags: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Sample<'a> {
+    /// Parses a `StatsD` line.
+    ///
+    /// Returns `Ok(None)` for valid lines of unsupported types (sets).
+    fn parse(line: &'a str) -> Result<Option<Self>, String> {
+        let Some((metric, rest)) = line.split_once(':') else {
+            return Err("missing value".into());
+        };
+
+        if metric.is_empty() {
+            return Err("missing metric name".into());
+        }
+
+        let mut fields = rest.split('|');
+
+        let value = fields.next().unwrap_or_default();
+
+        let Some(kind) = fields.next() else {
+            return Err("missing metric type".into());
+        };
+
+        let mut sample_rate: Value = 1.0;
+        let mut tags = vec![];
+
+        for field in fields {
+            if let Some(rate) = field.strip_prefix('@') {
+                sample_rate = rate
+                    .parse::<Value>()
+                    .ok()
+                    .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+                    .ok_or_else(|| format!("invalid sample rate {rate:?}"))?;
+            } else if let Some(list) = field.strip_prefix('#') {
+                // NOTE: Tags without value can not be queried, so they are skipped
+                tags.extend(
+                    list.split(',')
+                        .filter_map(|tag| tag.split_once(':'))
+                        .filter(|(k, v)| !k.is_empty() && !v.is_empty()),
+                );
+            }
+        }
+
+        if kind == "s" {
+            return Ok(None);
+        }
+
+        // NOTE: Relative gauge updates would require keeping the gauge's state,
+        // and a signed gauge value is always a relative update (`-4` decrements by 4)
+        if kind == "g" && (value.starts_with('+') || value.starts_with('-')) {
+            return Err("relative gauge updates are not supported".into());
+        }
+
+        let value = value
+            .parse::<Value>()
+            .map_err(|_| format!("invalid value {value:?}"))?;
+
+        let value = match kind {
+            // NOTE: Counters are scaled up to compensate for client-side sampling
+            "c" => value / sample_rate,
+            "g" | "ms" | "h" | "d" => value,
+            kind => return Err(format!("invalid metric type {kind:?}")),
+        };
+
+        Ok(Some(Self {
+            metric,
+            value,
+            tags,
+        }))
+    }
+}
+
+/// `StatsD` UDP ingestion listener
+///
+/// Receives `StatsD` lines (including `DogStatsD` tags) and writes them into the database:
+///
+/// - Counters (`c`) are written as data points (scaled by the sample rate), so the `sum`
+///   aggregation returns the total count per bucket
+/// - Gauges (`g`), timers (`ms`), histograms (`h`) and distributions (`d`) are written as-is
+/// - Sets (`s`) and relative gauge updates (signed gauge values, e.g. `+4` or `-4`)
+///   are not supported and skipped
+///
+/// Metric names and tag keys are sanitized to be valid talna identifiers.
+///
+/// Only available using the `statsd` feature flag.
+pub struct StatsdListener {
+    socket: UdpSocket,
+    db: Database,
+}
+
+impl StatsdListener {
+    /// Binds the UDP socket to the given address.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the address could not be bound.
+    pub fn bind<A: ToSocketAddrs>(db: Database, addr: A) -> crate::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Self { socket, db })
+    }
+
+    /// Returns the address the listener is bound to.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn local_addr(&self) -> crate::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Receives a single datagram and writes its samples, blocking the current thread.
+    ///
+    /// Returns the amount of written samples. Invalid lines are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn recv(&self) -> crate::Result<usize> {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let (len, _) = self.socket.recv_from(&mut buf)?;
+
+        let datagram = String::from_utf8_lossy(buf.get(..len).unwrap_or_default());
+        self.write_datagram(&datagram)
+    }
+
+    /// Handles incoming datagrams, blocking the current thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    pub fn run(&self) -> crate::Result<()> {
+        loop {
+            self.recv()?;
+        }
+    }
+
+    fn write_datagram(&self, datagram: &str) -> crate::Result<usize> {
+        let mut written = 0;
+
+        for line in datagram.lines() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let sample = match Sample::parse(line) {
+                Ok(Some(sample)) => sample,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("Skipping invalid StatsD line {line:?}: {e}");
+                    continue;
+                }
+            };
+
+            let metric = sanitize::metric_name(sample.metric);
+
+            let Ok(metric) = MetricName::try_from(metric.as_str()) else {
+                log::warn!("Skipping StatsD line with invalid metric name {metric:?}");
+                continue;
+            };
+
+            let tags = sample
+                .tags
+                .iter()
+                .map(|(k, v)| (sanitize::tag_key(k), sanitize::tag_value(v)))
+                .collect::<Vec<_>>();
+
+            let tags = tags
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>();
+
+            self.db.write(metric, sample.value, &tags)?;
+            written += 1;
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn statsd_parse() {
+        assert_eq!(
+            Some(Sample {
+                metric: "http.requests",
+                value: 2.0,
+                tags: vec![("env", "prod"), ("host", "h-1")],
+            }),
+            Sample::parse("http.requests:1|c|@0.5|#env:prod,host:h-1").unwrap(),
+        );
+
+        assert_eq!(
+            Some(Sample {
+                metric: "queue.len",
+                value: 4.0,
+                tags: vec![],
+            }),
+            Sample::parse("queue.len:4|g").unwrap(),
+        );
+
+        assert_eq!(None, Sample::parse("users.unique:abc|s").unwrap());
+    }
+
+    #[test_log::test]
+    fn statsd_parse_invalid() {
+        assert!(Sample::parse("").is_err());
+        assert!(Sample::parse("http.requests").is_err());
+        assert!(Sample::parse("http.requests:1").is_err());
+        assert!(Sample::parse("http.requests:abc|c").is_err());
+        assert!(Sample::parse("http.requests:1|x").is_err());
+        assert!(Sample::parse("http.requests:1|c|@2").is_err());
+        assert!(Sample::parse("queue.len:+4|g").is_err());
+        assert!(Sample::parse("queue.len:-4|g").is_err());
+    }
+
+    #[test_log::test]
+    #[allow(clippy::float_cmp)]
+    fn statsd_listener() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+
+        let listener = StatsdListener::bind(db.clone(), "127.0.0.1:0")?;
+
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.send_to(
+            b"HTTP.Requests:1|c|#host:h-1\nHTTP.Requests:2|c|@0.5|#host:h-1\ninvalid\nhttp.duration:5|ms|#host:h-1",
+            listener.local_addr()?,
+        )?;
+
+        assert_eq!(3, listener.recv()?);
+
+        let metric = MetricName::try_from("http.requests").unwrap();
+        let mut buckets = db.sum(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(5.0, bucket.value);
+
+        let metric = MetricName::try_from("http.duration").unwrap();
+        let mut buckets = db.max(metric, "host").build()?.collect()?;
+        let bucket = buckets.remove("h-1").unwrap().pop().unwrap();
+        assert_eq!(5.0, bucket.value);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,465 @@
+use fjall::{Keyspace, PartitionCreateOptions, PartitionHandle, Slice, Snapshot};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// A key-value pair read from storage
+pub type KvResult = crate::Result<(Slice, Slice)>;
+
+/// Iterator over the key-value pairs of a [`StorageSnapshot`], in key order
+pub type StorageIter = Box<dyn Iterator<Item = crate::Result<(Slice, Slice)>>>;
+
+/// Storage backend of data points, see [`crate::DatabaseBuilder::cold_tier_storage`]
+///
+/// Backends other than fjall (e.g. an in-memory store or a mock that injects
+/// failures in tests) can be plugged in by implementing this trait.
+///
+/// Only the cold tier is stored in a pluggable backend, the primary partitions
+/// (series, tags, hot data points & metadata) are always stored in fjall.
+pub trait Storage: Send + Sync {
+    /// Opens a partition, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the partition could not be opened.
+    fn open_partition(&self, name: &str) -> crate::Result<Arc<dyn StoragePartition>>;
+
+    /// Durably persists all writes of all partitions.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the writes could not be persisted.
+    fn persist(&self) -> crate::Result<()>;
+}
+
+/// An ordered key-value partition of a [`Storage`]
+pub trait StoragePartition: Send + Sync {
+    /// Inserts a key-value pair, overwriting any previous value.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the write failed.
+    fn insert(&self, key: &[u8], value: &[u8]) -> crate::Result<()>;
+
+    /// Removes a key, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the write failed.
+    fn remove(&self, key: &[u8]) -> crate::Result<()>;
+
+    /// Opens a point-in-time view of the partition, which is not affected by later writes.
+    fn snapshot(&self) -> Box<dyn StorageSnapshot>;
+}
+
+/// A point-in-time view of a [`StoragePartition`]
+pub trait StorageSnapshot: Send + Sync {
+    /// Returns the key-value pairs in the given key range, in ascending key order.
+    fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> StorageIter;
+
+    /// Returns the key-value pairs whose keys start with the given prefix, in ascending key order.
+    fn prefix(&self, prefix: &[u8]) -> StorageIter;
+
+    /// Returns the amount of key-value pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    fn len(&self) -> crate::Result<usize>;
+
+    /// Returns `true` if the snapshot contains no key-value pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    fn is_empty(&self) -> crate::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+/// Storage backed by a fjall keyspace
+pub struct FjallStorage {
+    keyspace: Keyspace,
+    options: PartitionCreateOptions,
+}
+
+impl FjallStorage {
+    pub fn new(keyspace: Keyspace, options: PartitionCreateOptions) -> Self {
+        Self { keyspace, options }
+    }
+}
+
+impl Storage for FjallStorage {
+    fn open_partition(&self, name: &str) -> crate::Result<Arc<dyn StoragePartition>> {
+        let partition = self.keyspace.open_partition(name, self.options.clone())?;
+        Ok(Arc::new(partition))
+    }
+
+    fn persist(&self) -> crate::Result<()> {
+        self.keyspace.persist(fjall::PersistMode::SyncAll)?;
+        Ok(())
+    }
+}
+
+impl StoragePartition for PartitionHandle {
+    fn insert(&self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        Self::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> crate::Result<()> {
+        Self::remove(self, key)?;
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Box<dyn StorageSnapshot> {
+        Box::new(Self::snapshot(self))
+    }
+}
+
+// NOTE: The methods of fjall snapshots are inherent to the dereferenced LSM-tree
+// snapshot, so they are called explicitly to not recurse into the trait methods
+impl StorageSnapshot for Snapshot {
+    fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> StorageIter {
+        Box::new(lsm_snapshot(self).range(range).map(|kv| Ok(kv?)))
+    }
+
+    fn prefix(&self, prefix: &[u8]) -> StorageIter {
+        Box::new(lsm_snapshot(self).prefix(prefix).map(|kv| Ok(kv?)))
+    }
+
+    fn len(&self) -> crate::Result<usize> {
+        Ok(lsm_snapshot(self).len()?)
+    }
+}
+
+fn lsm_snapshot(snapshot: &Snapshot) -> &<Snapshot as std::ops::Deref>::Target {
+    snapshot
+}
+
+/// Storage that keeps all partitions in memory, e.g. for tests
+///
+/// Data is lost when the storage is dropped, so [`Storage::persist`] does nothing.
+#[derive(Clone, Default)]
+pub struct MemoryStorage(Arc<Mutex<HashMap<String, Arc<MemoryPartition>>>>);
+
+impl Storage for MemoryStorage {
+    fn open_partition(&self, name: &str) -> crate::Result<Arc<dyn StoragePartition>> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(name.into())
+            .or_default()
+            .clone())
+    }
+
+    fn persist(&self) -> crate::Result<()> {
+        Ok(())
+    }
+}
+
+/// Key of a version of a key-value pair, versions of the same key are ordered newest first
+type VersionKey = (Vec<u8>, Reverse<u64>);
+
+/// Versioned key-value pairs of a [`MemoryPartition`]
+///
+/// Writes add a new version (`None` if the key was removed), so snapshots read the versions
+/// that existed when they were opened, without copying the tree.
+#[derive(Default)]
+struct Versions {
+    tree: BTreeMap<VersionKey, Option<Slice>>,
+
+    /// Sequence number of the latest write
+    seqno: u64,
+
+    /// Sequence numbers of the open snapshots, with the amount of snapshots
+    snapshots: BTreeMap<u64, usize>,
+}
+
+impl Versions {
+    fn write(&mut self, key: &[u8], value: Option<Slice>) {
+        self.seqno += 1;
+        self.tree.insert((key.into(), Reverse(self.seqno)), value);
+
+        // NOTE: Versions older than the newest version the oldest reader can see
+        // are not visible to any reader anymore
+        let oldest = self.snapshots.keys().next().copied().unwrap_or(self.seqno);
+
+        let stale = self
+            .tree
+            .range::<VersionKey, _>((
+                Bound::Included((key.to_vec(), Reverse(oldest))),
+                Bound::Included((key.to_vec(), Reverse(0))),
+            ))
+            .skip(1)
+            .map(|(version, _)| version.clone())
+            .collect::<Vec<_>>();
+
+        for version in stale {
+            self.tree.remove(&version);
+        }
+
+        // NOTE: Without snapshots, nobody needs to see that the key was removed
+        if value_is_removal(&self.tree, key, self.seqno) && self.snapshots.is_empty() {
+            self.tree.remove(&(key.to_vec(), Reverse(self.seqno)));
+        }
+    }
+
+    /// Returns the first key-value pair after `lower` as of the given sequence number
+    fn next_visible(
+        &self,
+        lower: &Bound<Vec<u8>>,
+        upper: &Bound<Vec<u8>>,
+        seqno: u64,
+    ) -> Option<(Vec<u8>, Slice)> {
+        let mut lower = match lower {
+            Bound::Included(key) => Bound::Included((key.clone(), Reverse(u64::MAX))),
+            Bound::Excluded(key) => Bound::Excluded((key.clone(), Reverse(0))),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        loop {
+            let ((key, _), value) = self
+                .tree
+                .range::<VersionKey, _>((lower.clone(), Bound::Unbounded))
+                .find(|((_, Reverse(version)), _)| *version <= seqno)?;
+
+            let in_range = match upper {
+                Bound::Included(upper) => key <= upper,
+                Bound::Excluded(upper) => key < upper,
+                Bound::Unbounded => true,
+            };
+
+            if !in_range {
+                return None;
+            }
+
+            match value {
+                Some(value) => return Some((key.clone(), value.clone())),
+
+                // NOTE: Removed as of the sequence number, so skip all versions of the key
+                None => lower = Bound::Excluded((key.clone(), Reverse(0))),
+            }
+        }
+    }
+}
+
+fn value_is_removal(tree: &BTreeMap<VersionKey, Option<Slice>>, key: &[u8], seqno: u64) -> bool {
+    matches!(tree.get(&(key.to_vec(), Reverse(seqno))), Some(None))
+}
+
+/// A partition of a [`MemoryStorage`]
+#[derive(Default)]
+struct MemoryPartition(Arc<RwLock<Versions>>);
+
+impl StoragePartition for MemoryPartition {
+    fn insert(&self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(key, Some(Slice::from(value)));
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> crate::Result<()> {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(key, None);
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Box<dyn StorageSnapshot> {
+        let mut versions = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let seqno = versions.seqno;
+        *versions.snapshots.entry(seqno).or_default() += 1;
+        drop(versions);
+
+        Box::new(MemorySnapshot(Arc::new(SnapshotHandle {
+            versions: self.0.clone(),
+            seqno,
+        })))
+    }
+}
+
+/// Registers an open snapshot, so the versions it reads are not pruned
+struct SnapshotHandle {
+    versions: Arc<RwLock<Versions>>,
+    seqno: u64,
+}
+
+impl Drop for SnapshotHandle {
+    fn drop(&mut self) {
+        let mut versions = self
+            .versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(count) = versions.snapshots.get_mut(&self.seqno) {
+            *count -= 1;
+
+            if *count == 0 {
+                versions.snapshots.remove(&self.seqno);
+            }
+        }
+    }
+}
+
+struct MemorySnapshot(Arc<SnapshotHandle>);
+
+impl StorageSnapshot for MemorySnapshot {
+    fn range(&self, (lower, upper): (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> StorageIter {
+        Box::new(MemoryRange {
+            snapshot: self.0.clone(),
+            lower,
+            upper,
+        })
+    }
+
+    fn prefix(&self, prefix: &[u8]) -> StorageIter {
+        self.range((Bound::Included(prefix.into()), prefix_upper_bound(prefix)))
+    }
+
+    fn len(&self) -> crate::Result<usize> {
+        Ok(self.range((Bound::Unbounded, Bound::Unbounded)).count())
+    }
+}
+
+/// Returns the smallest key that is larger than all keys starting with the given prefix
+fn prefix_upper_bound(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Bound::Excluded(upper);
+        }
+    }
+
+    Bound::Unbounded
+}
+
+/// Iterates over a key range of a snapshot, owning the snapshot so the iterator is not borrowed
+struct MemoryRange {
+    snapshot: Arc<SnapshotHandle>,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+}
+
+impl Iterator for MemoryRange {
+    type Item = KvResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self
+            .snapshot
+            .versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next_visible(&self.lower, &self.upper, self.snapshot.seqno)?;
+
+        let item = (Slice::from(key.as_slice()), value);
+        self.lower = Bound::Excluded(key);
+
+        Some(Ok(item))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn keys(iter: StorageIter) -> Vec<Vec<u8>> {
+        iter.map(|kv| kv.unwrap().0.to_vec()).collect()
+    }
+
+    #[test_log::test]
+    fn memory_storage() -> crate::Result<()> {
+        let storage = MemoryStorage::default();
+        let partition = storage.open_partition("data")?;
+
+        for key in [&b"a"[..], b"ab", b"b", b"b\xff", b"c"] {
+            partition.insert(key, b"1")?;
+        }
+
+        let snapshot = partition.snapshot();
+        partition.remove(b"a")?;
+        partition.insert(b"d", b"1")?;
+
+        assert_eq!(5, snapshot.len()?);
+        assert_eq!(5, partition.snapshot().len()?);
+        assert_eq!(
+            vec![b"a".to_vec(), b"ab".to_vec()],
+            keys(snapshot.prefix(b"a"))
+        );
+        assert_eq!(
+            vec![b"ab".to_vec()],
+            keys(partition.snapshot().prefix(b"a"))
+        );
+        assert_eq!(
+            vec![b"b".to_vec(), b"b\xff".to_vec()],
+            keys(snapshot.prefix(b"b"))
+        );
+
+        assert_eq!(
+            vec![b"ab".to_vec(), b"b".to_vec()],
+            keys(snapshot.range((
+                Bound::Excluded(b"a".to_vec()),
+                Bound::Included(b"b".to_vec())
+            )))
+        );
+        assert!(keys(snapshot.range((
+            Bound::Included(b"c".to_vec()),
+            Bound::Excluded(b"a".to_vec())
+        )))
+        .is_empty());
+
+        // NOTE: Partitions are shared by name
+        assert_eq!(5, storage.open_partition("data")?.snapshot().len()?);
+        assert!(storage.open_partition("other")?.snapshot().is_empty()?);
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn memory_storage_prunes_versions() -> crate::Result<()> {
+        let partition = MemoryPartition::default();
+        let versions = || partition.0.read().unwrap().tree.len();
+
+        for _ in 0..10 {
+            partition.insert(b"a", b"1")?;
+        }
+        assert_eq!(1, versions());
+
+        let snapshot = partition.snapshot();
+        partition.insert(b"a", b"2")?;
+        partition.insert(b"a", b"3")?;
+        partition.remove(b"b")?;
+        assert_eq!(4, versions());
+        assert_eq!(
+            vec![(b"a".to_vec(), b"1".to_vec())],
+            snapshot
+                .prefix(b"")
+                .map(|kv| kv.map(|(k, v)| (k.to_vec(), v.to_vec())))
+                .collect::<crate::Result<Vec<_>>>()?,
+        );
+
+        // NOTE: Versions of a key are pruned by its next write once the snapshot is closed
+        drop(snapshot);
+        partition.remove(b"a")?;
+        assert_eq!(1, versions());
+        partition.remove(b"b")?;
+        assert_eq!(0, versions());
+        assert!(partition.snapshot().is_empty()?);
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn prefix_upper_bound_overflow() {
+        assert_eq!(Bound::Excluded(b"b".to_vec()), prefix_upper_bound(b"a\xff"));
+        assert_eq!(Bound::Unbounded, prefix_upper_bound(b"\xff\xff"));
+    }
+}
@@ -0,0 +1,91 @@
+/// Maximum amount of suggestions returned for one input
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Returns the Levenshtein distance (in characters) between two strings.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+
+    // NOTE: Only the previous row of the distance matrix is needed
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row.first().copied().unwrap_or_default();
+
+        if let Some(first) = row.first_mut() {
+            *first = i + 1;
+        }
+
+        for (j, cb) in b.iter().enumerate() {
+            let above = row.get(j + 1).copied().unwrap_or_default();
+            let left = row.get(j).copied().unwrap_or_default();
+
+            let distance = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(left)
+            };
+
+            diagonal = above;
+
+            if let Some(cell) = row.get_mut(j + 1) {
+                *cell = distance;
+            }
+        }
+    }
+
+    row.last().copied().unwrap_or_default()
+}
+
+/// Returns the candidates that are within a typo or two of the input, closest first.
+///
+/// The allowed distance grows with the input length (one edit per three characters,
+/// at least one), so short inputs do not match everything.
+pub fn closest(input: &str, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    let max_distance = (input.chars().count() / 3).max(1);
+
+    let mut matches = candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(input, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+
+    matches.sort();
+    matches.dedup();
+    matches.truncate(MAX_SUGGESTIONS);
+
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn levenshtein_distance() {
+        assert_eq!(0, levenshtein("", ""));
+        assert_eq!(3, levenshtein("", "abc"));
+        assert_eq!(3, levenshtein("abc", ""));
+        assert_eq!(0, levenshtein("service", "service"));
+        assert_eq!(2, levenshtein("serivce", "service"));
+        assert_eq!(1, levenshtein("hst", "host"));
+        assert_eq!(3, levenshtein("kitten", "sitting"));
+        assert_eq!(1, levenshtein("prod", "pröd"));
+    }
+
+    #[test_log::test]
+    fn closest_candidates() {
+        let candidates = ["service", "env", "host", "services", "region"].map(String::from);
+
+        assert_eq!(vec!["service"], closest("serivce", candidates.clone()));
+        assert_eq!(
+            vec!["service", "services"],
+            closest("servic", candidates.clone())
+        );
+        assert_eq!(vec!["host"], closest("hst", candidates.clone()));
+        assert_eq!(vec!["env"], closest("env", candidates.clone()));
+        assert!(closest("zone", candidates).is_empty());
+    }
+}
@@ -1,48 +1,184 @@
+use crate::query::planner::IndexStatistics;
 use crate::{MetricName, SeriesId, TagSet};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt};
 use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition, WriteTransaction};
+use quick_cache::{sync::Cache, Weighter};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+const PARTITION_NAME: &str = "tidx";
+
+/// Weighs postings lists by their approximate heap size
+#[derive(Clone)]
+struct PostingsWeighter;
+
+impl Weighter<String, Arc<Vec<SeriesId>>> for PostingsWeighter {
+    fn weight(&self, term: &String, postings: &Arc<Vec<SeriesId>>) -> u64 {
+        (term.len()
+            + std::mem::size_of::<String>()
+            + postings.len() * std::mem::size_of::<SeriesId>()) as u64
+    }
+}
 
-const PARTITION_NAME: &str = "_talna#v1#tidx";
+/// Lazily decoded postings list, see [`TagIndex::iter_eq`]
+pub struct Postings(PostingsInner);
+
+enum PostingsInner {
+    Cached(Arc<Vec<SeriesId>>, usize),
+
+    /// Serialized postings list (without the length prefix), decoded on demand
+    Raw(fjall::Slice, usize),
+}
+
+impl Postings {
+    fn empty() -> Self {
+        Self(PostingsInner::Cached(Arc::default(), 0))
+    }
+}
+
+impl Iterator for Postings {
+    type Item = SeriesId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            PostingsInner::Cached(postings, pos) => {
+                let id = postings.get(*pos).copied()?;
+                *pos += 1;
+                Some(id)
+            }
+            PostingsInner::Raw(bytes, pos) => {
+                let end = *pos + std::mem::size_of::<SeriesId>();
+                let id = bytes.get(*pos..end)?;
+                *pos = end;
+                <[u8; 8]>::try_from(id).ok().map(SeriesId::from_be_bytes)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.0 {
+            PostingsInner::Cached(postings, pos) => postings.len() - pos,
+            PostingsInner::Raw(bytes, pos) => (bytes.len() - pos) / std::mem::size_of::<SeriesId>(),
+        };
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Postings {}
 
 /// Inverted index, mapping key:value tag pairs to series IDs
 pub struct TagIndex {
     keyspace: TxKeyspace,
     partition: TxPartition,
+
+    /// Cache of deserialized postings lists, so hot queries do not need to
+    /// read & deserialize the same postings lists again
+    cache: Cache<String, Arc<Vec<SeriesId>>, PostingsWeighter>,
+
+    /// Bumped on every invalidation, so a postings list that was read
+    /// before a concurrent update is not kept in the cache
+    epoch: AtomicU64,
 }
 
 impl TagIndex {
-    pub fn new(keyspace: &TxKeyspace) -> crate::Result<Self> {
+    pub fn new(
+        keyspace: &TxKeyspace,
+        prefix: &str,
+        cache_capacity_bytes: u64,
+    ) -> crate::Result<Self> {
         let opts = PartitionCreateOptions::default()
             .block_size(4_096)
             .compression(CompressionType::Lz4)
             .max_memtable_size(8_000_000);
 
-        let partition = keyspace.open_partition(PARTITION_NAME, opts)?;
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        // NOTE: Assume ~256 bytes per postings list to estimate the amount of items
+        let estimated_items = usize::try_from(cache_capacity_bytes / 256).unwrap_or(usize::MAX);
 
         Ok(Self {
             keyspace: keyspace.clone(),
             partition,
+            cache: Cache::with_weighter(estimated_items, cache_capacity_bytes, PostingsWeighter),
+            epoch: AtomicU64::default(),
         })
     }
 
+    /// Returns the approximate size of the cached postings lists in bytes.
+    pub fn cache_size(&self) -> u64 {
+        self.cache.weight()
+    }
+
+    /// Removes the postings lists of a series' metric and tags from the cache.
+    ///
+    /// Needs to be called after the series was (un)indexed.
+    pub fn invalidate<'a>(&self, metric: &str, tags: impl IntoIterator<Item = (&'a str, &'a str)>) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+
+        self.cache.remove(metric);
+
+        for (key, value) in tags {
+            self.cache.remove(&Self::format_key(metric, key, value));
+        }
+    }
+
     // TODO: could probably use varint encoding + delta encoding here
     // or even bitpacking for blocks of 128, and delta varint for remaining
     fn serialize_postings_list(postings: &[SeriesId]) -> Vec<u8> {
-        let mut posting_list = vec![];
+        let mut posting_list =
+            Vec::with_capacity((postings.len() + 1) * std::mem::size_of::<SeriesId>());
 
-        posting_list
-            .write_u64::<BigEndian>(postings.len() as u64)
-            .expect("should serialize");
+        posting_list.extend_from_slice(&(postings.len() as u64).to_be_bytes());
 
         for id in postings {
-            posting_list
-                .write_u64::<BigEndian>(*id)
-                .expect("should serialize");
+            posting_list.extend_from_slice(&id.to_be_bytes());
         }
 
         posting_list
     }
 
+    /// Checks the length prefix of a serialized postings list, without decoding the IDs
+    fn raw_postings(&self, term: impl AsRef<[u8]>, bytes: fjall::Slice) -> crate::Result<Postings> {
+        let corruption = || crate::Error::corruption(&self.partition.inner().name, &term);
+
+        let mut reader = &bytes[..];
+        let len = reader.read_u64::<BigEndian>().map_err(|_| corruption())?;
+
+        let expected = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_mul(std::mem::size_of::<SeriesId>()));
+
+        if expected != Some(reader.len()) {
+            return Err(corruption());
+        }
+
+        Ok(Postings(PostingsInner::Raw(
+            bytes,
+            std::mem::size_of::<u64>(),
+        )))
+    }
+
+    fn deserialize_postings_list(
+        &self,
+        term: impl AsRef<[u8]>,
+        mut reader: &[u8],
+    ) -> crate::Result<Vec<SeriesId>> {
+        let corruption = |_| crate::Error::corruption(&self.partition.inner().name, &term);
+
+        let len = reader.read_u64::<BigEndian>().map_err(corruption)?;
+
+        // NOTE: Do not trust the length prefix for the allocation
+        let mut postings = Vec::with_capacity(reader.len() / std::mem::size_of::<SeriesId>());
+
+        for _ in 0..len {
+            postings.push(reader.read_u64::<BigEndian>().map_err(corruption)?);
+        }
+
+        Ok(postings)
+    }
+
     pub fn index(
         &self,
         tx: &mut WriteTransaction,
@@ -60,36 +196,75 @@ impl TagIndex {
         Ok(())
     }
 
-    fn index_term(
+    /// Removes a series from the postings lists of its metric and tags.
+    pub fn remove(
         &self,
         tx: &mut WriteTransaction,
-        term: &str,
+        metric: &str,
+        tags: &crate::tag_sets::OwnedTagSets,
         series_id: SeriesId,
     ) -> crate::Result<()> {
-        // log::trace!("Indexing {term:?} => {series_id}");
-
-        tx.fetch_update(&self.partition, term, |bytes| match bytes {
-            Some(bytes) => {
-                let mut reader = &bytes[..];
+        self.remove_term(tx, metric, series_id)?;
 
-                let len = reader.read_u64::<BigEndian>().expect("should deserialize");
-                let mut postings = Vec::with_capacity(len as usize);
-
-                for _ in 0..len {
-                    postings.push(reader.read_u64::<BigEndian>().expect("should deserialize"));
-                }
-                postings.push(series_id);
+        for (key, value) in tags {
+            self.remove_term(tx, &Self::format_key(metric, key, value), series_id)?;
+        }
 
-                // log::trace!("posting list {term:?} is now {postings:?}");
+        Ok(())
+    }
 
+    fn remove_term(
+        &self,
+        tx: &mut WriteTransaction,
+        term: &str,
+        series_id: SeriesId,
+    ) -> crate::Result<()> {
+        tx.fetch_update(&self.partition, term, |bytes| {
+            // NOTE: Skip the length prefix
+            let postings = bytes?
+                .chunks_exact(std::mem::size_of::<SeriesId>())
+                .skip(1)
+                .filter_map(|chunk| <[u8; 8]>::try_from(chunk).ok())
+                .map(SeriesId::from_be_bytes)
+                .filter(|id| *id != series_id)
+                .collect::<Vec<_>>();
+
+            // NOTE: Remove empty postings lists, so the term is not listed anymore
+            if postings.is_empty() {
+                None
+            } else {
                 Some(Self::serialize_postings_list(&postings).into())
             }
-            None => Some(Self::serialize_postings_list(&[series_id]).into()),
         })?;
 
         Ok(())
     }
 
+    fn index_term(
+        &self,
+        tx: &mut WriteTransaction,
+        term: &str,
+        series_id: SeriesId,
+    ) -> crate::Result<()> {
+        // log::trace!("Indexing {term:?} => {series_id}");
+
+        let mut postings = match tx.get(&self.partition, term)? {
+            Some(bytes) => self.deserialize_postings_list(term, &bytes)?,
+            None => vec![],
+        };
+        postings.push(series_id);
+
+        // log::trace!("posting list {term:?} is now {postings:?}");
+
+        tx.insert(
+            &self.partition,
+            term,
+            Self::serialize_postings_list(&postings),
+        );
+
+        Ok(())
+    }
+
     pub fn format_key(metric_name: &str, key: &str, value: &str) -> String {
         let mut s = String::with_capacity(metric_name.len() + 1 + key.len() + 1 + value.len());
         s.push_str(metric_name);
@@ -101,42 +276,164 @@ impl TagIndex {
     }
 
     pub fn query_eq(&self, term: &str) -> crate::Result<Vec<SeriesId>> {
+        if let Some(postings) = self.cache.get(term) {
+            return Ok((*postings).clone());
+        }
+
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let postings = self.load(term)?;
+
+        self.cache
+            .insert(term.to_string(), Arc::new(postings.clone()));
+
+        // NOTE: The postings list may have been updated while it was read
+        if self.epoch.load(Ordering::SeqCst) != epoch {
+            self.cache.remove(term);
+        }
+
+        Ok(postings)
+    }
+
+    /// Returns an iterator over a postings list, decoding series IDs as they are consumed.
+    ///
+    /// Unlike [`TagIndex::query_eq`], the postings list is not materialized (or cached), so
+    /// callers can stop early, e.g. when intersecting with a smaller list.
+    pub fn iter_eq(&self, term: &str) -> crate::Result<Postings> {
+        if let Some(postings) = self.cache.get(term) {
+            return Ok(Postings(PostingsInner::Cached(postings, 0)));
+        }
+
+        self.partition.get(term)?.map_or_else(
+            || Ok(Postings::empty()),
+            |bytes| self.raw_postings(term, bytes),
+        )
+    }
+
+    /// Returns the length of a postings list, without deserializing it
+    fn postings_len(&self, term: &str) -> crate::Result<u64> {
+        if let Some(postings) = self.cache.get(term) {
+            return Ok(postings.len() as u64);
+        }
+
+        let Some(bytes) = self.partition.get(term)? else {
+            return Ok(0);
+        };
+
+        let mut reader = &bytes[..];
+        reader
+            .read_u64::<BigEndian>()
+            .map_err(|_| crate::Error::corruption(&self.partition.inner().name, term))
+    }
+
+    fn load(&self, term: &str) -> crate::Result<Vec<SeriesId>> {
         Ok(self
             .partition
             .get(term)?
-            .map(|bytes| {
-                let mut reader = &bytes[..];
+            .map(|bytes| self.deserialize_postings_list(term, &bytes))
+            .transpose()?
+            .unwrap_or_default())
+    }
 
-                let len = reader.read_u64::<BigEndian>().expect("should deserialize");
-                let mut postings = Vec::with_capacity(len as usize);
+    /// Lists all indexed metric names starting with the given prefix
+    pub fn list_metrics(&self, prefix: &str) -> crate::Result<Vec<String>> {
+        let mut metrics = vec![];
 
-                for _ in 0..len {
-                    postings.push(reader.read_u64::<BigEndian>().expect("should deserialize"));
-                }
+        let read_tx = self.keyspace.read_tx();
 
-                postings
-            })
-            .unwrap_or_default())
+        for kv in read_tx.prefix(&self.partition, prefix) {
+            let (k, _) = kv?;
+
+            // NOTE: Metric terms are the only terms without a '#'
+            if !k.contains(&b'#') {
+                metrics.push(String::from_utf8_lossy(&k).into_owned());
+            }
+        }
+
+        Ok(metrics)
     }
 
-    pub fn query_prefix(&self, prefix: &str) -> crate::Result<Vec<SeriesId>> {
+    /// Lists all indexed tags of the metric, in ascending order
+    pub fn list_tags(&self, metric: &str) -> crate::Result<Vec<(String, String)>> {
+        let prefix = format!("{metric}#");
+        let mut tags = vec![];
+
+        let read_tx = self.keyspace.read_tx();
+
+        for kv in read_tx.prefix(&self.partition, &prefix) {
+            let (k, _) = kv?;
+
+            let term = String::from_utf8_lossy(k.get(prefix.len()..).unwrap_or_default());
+
+            if let Some((key, value)) = term.split_once(':') {
+                tags.push((key.to_owned(), value.to_owned()));
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /// Lists all metrics that have a series with the given tag, in ascending order.
+    ///
+    /// Terms are keyed by metric first, so this scans all terms.
+    pub fn metrics_with_tag(&self, key: &str, value: &str) -> crate::Result<Vec<String>> {
+        let tag = format!("{key}:{value}");
+        let mut metrics = vec![];
+
+        let read_tx = self.keyspace.read_tx();
+
+        for k in read_tx.keys(&self.partition) {
+            let k = k?;
+
+            // NOTE: Metric names do not contain '#', so the first '#' ends the metric
+            let mut parts = k.splitn(2, |&b| b == b'#');
+
+            let (Some(metric), Some(term_tag)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+
+            if term_tag == tag.as_bytes() {
+                metrics.push(String::from_utf8_lossy(metric).into_owned());
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    /// Returns the series of all terms starting with the prefix,
+    /// whose remainder (e.g. the tag value) matches the predicate
+    pub fn query_matching(
+        &self,
+        prefix: &str,
+        predicate: impl Fn(&str) -> bool,
+    ) -> crate::Result<Vec<SeriesId>> {
         let mut ids = vec![];
 
         let read_tx = self.keyspace.read_tx();
 
         for kv in read_tx.prefix(&self.partition, prefix) {
-            let (_, v) = kv?;
+            let (k, v) = kv?;
 
-            let mut reader = &v[..];
+            let rest = String::from_utf8_lossy(k.get(prefix.len()..).unwrap_or_default());
 
-            let len = reader.read_u64::<BigEndian>().expect("should deserialize");
-            let mut postings = Vec::with_capacity(len as usize);
-
-            for _ in 0..len {
-                postings.push(reader.read_u64::<BigEndian>().expect("should deserialize"));
+            if predicate(&rest) {
+                ids.extend(self.raw_postings(k, v)?);
             }
+        }
+
+        ids.sort_unstable();
+        ids.dedup();
+
+        Ok(ids)
+    }
+
+    pub fn query_prefix(&self, prefix: &str) -> crate::Result<Vec<SeriesId>> {
+        let mut ids = vec![];
+
+        let read_tx = self.keyspace.read_tx();
 
-            ids.extend(postings);
+        for kv in read_tx.prefix(&self.partition, prefix) {
+            let (k, v) = kv?;
+            ids.extend(self.raw_postings(k, v)?);
         }
 
         ids.sort_unstable();
@@ -146,16 +443,28 @@ impl TagIndex {
     }
 }
 
+impl IndexStatistics for TagIndex {
+    fn series_count(&self, metric: &str) -> crate::Result<u64> {
+        self.postings_len(metric)
+    }
+
+    fn tag_count(&self, metric: &str, key: &str, value: &str) -> crate::Result<u64> {
+        self.postings_len(&Self::format_key(metric, key, value))
+    }
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
     #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
     fn test_tag_index_prefix() -> crate::Result<()> {
         let path = tempfile::tempdir()?;
         let keyspace = fjall::Config::new(&path).open_transactional()?;
-        let tag_index = TagIndex::new(&keyspace)?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
         let metric = MetricName::try_from("cpu.total").unwrap();
 
         let mut tx = keyspace.write_tx();
@@ -208,10 +517,125 @@ mod tests {
     }
 
     #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_tag_index_metrics_with_tag() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+
+        let mut tx = keyspace.write_tx();
+
+        let series: [(&str, &TagSet); 5] = [
+            (
+                "cpu.total",
+                crate::tagset!("host" => "h-1", "env" => "prod"),
+            ),
+            (
+                "cpu.total",
+                crate::tagset!("host" => "h-2", "env" => "prod"),
+            ),
+            ("mem.used", crate::tagset!("host" => "h-1")),
+            ("disk.used", crate::tagset!("host" => "h-10")),
+            ("net.rx", crate::tagset!("hostname" => "h-1")),
+        ];
+
+        for (idx, (metric, tags)) in series.into_iter().enumerate() {
+            let metric = MetricName::try_from(metric).unwrap();
+            tag_index.index(&mut tx, metric, tags, idx as SeriesId)?;
+        }
+
+        tx.commit()?;
+
+        assert_eq!(
+            vec!["cpu.total", "mem.used"],
+            tag_index.metrics_with_tag("host", "h-1")?
+        );
+        assert_eq!(
+            vec!["cpu.total"],
+            tag_index.metrics_with_tag("env", "prod")?
+        );
+        assert!(tag_index.metrics_with_tag("host", "h-3")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_tag_index_cache() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+        let metric = MetricName::try_from("cpu.total").unwrap();
+        let tags = crate::tagset!("env" => "prod");
+
+        let mut tx = keyspace.write_tx();
+        tag_index.index(&mut tx, metric, tags, 0)?;
+        tx.commit()?;
+        tag_index.invalidate(&metric, tags.iter().copied());
+
+        assert_eq!(vec![0], tag_index.query_eq("cpu.total#env:prod")?);
+        assert!(tag_index.cache.get("cpu.total#env:prod").is_some());
+
+        let mut tx = keyspace.write_tx();
+        tag_index.index(&mut tx, metric, tags, 1)?;
+        tx.commit()?;
+
+        // NOTE: Stale until invalidated
+        assert_eq!(vec![0], tag_index.query_eq("cpu.total#env:prod")?);
+
+        tag_index.invalidate(&metric, tags.iter().copied());
+        assert_eq!(vec![0, 1], tag_index.query_eq("cpu.total#env:prod")?);
+        assert_eq!(vec![0, 1], tag_index.query_eq(&metric)?);
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_tag_index_iter_eq() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+        let metric = MetricName::try_from("cpu.total").unwrap();
+        let tags = crate::tagset!("env" => "prod");
+
+        let mut tx = keyspace.write_tx();
+        for series_id in 0..3 {
+            tag_index.index(&mut tx, metric, tags, series_id)?;
+        }
+        tx.commit()?;
+
+        let postings = tag_index.iter_eq("cpu.total#env:prod")?;
+        assert_eq!(3, postings.len());
+        assert_eq!(vec![0, 1, 2], postings.collect::<Vec<_>>());
+        assert!(tag_index.cache.get("cpu.total#env:prod").is_none());
+
+        let mut postings = tag_index.iter_eq("cpu.total#env:prod")?;
+        assert_eq!(Some(0), postings.next());
+        assert_eq!(2, postings.len());
+
+        // NOTE: Cached postings lists are iterated as well
+        tag_index.query_eq("cpu.total#env:prod")?;
+        assert_eq!(
+            vec![0, 1, 2],
+            tag_index.iter_eq("cpu.total#env:prod")?.collect::<Vec<_>>()
+        );
+
+        assert_eq!(0, tag_index.iter_eq("cpu.total#env:dev")?.count());
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
     fn test_tag_index_eq() -> crate::Result<()> {
         let path = tempfile::tempdir()?;
         let keyspace = fjall::Config::new(&path).open_transactional()?;
-        let tag_index = TagIndex::new(&keyspace)?;
+        let tag_index = TagIndex::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
         let metric = MetricName::try_from("cpu.total").unwrap();
 
         let mut tx = keyspace.write_tx();
@@ -1,25 +1,58 @@
 use crate::SeriesId;
 use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition, WriteTransaction};
+use quick_cache::{sync::Cache, Weighter};
+use std::sync::Arc;
 
-const PARTITION_NAME: &str = "_talna#v1#tags";
+const PARTITION_NAME: &str = "tags";
 
 pub type OwnedTagSets = crate::HashMap<String, String>;
 
+/// Weighs tag sets by their approximate heap size
+#[derive(Clone)]
+struct TagSetWeighter;
+
+impl Weighter<SeriesId, Arc<OwnedTagSets>> for TagSetWeighter {
+    fn weight(&self, _: &SeriesId, tags: &Arc<OwnedTagSets>) -> u64 {
+        let size = std::mem::size_of::<SeriesId>()
+            + std::mem::size_of::<OwnedTagSets>()
+            + tags
+                .iter()
+                .map(|(k, v)| k.len() + v.len() + 2 * std::mem::size_of::<String>())
+                .sum::<usize>();
+
+        size as u64
+    }
+}
+
 /// Maps Series IDs to their tags
 pub struct TagSets {
     partition: TxPartition,
+
+    /// Cache of parsed tag sets, so queries do not need to read & parse
+    /// every series' tag set again
+    cache: Cache<SeriesId, Arc<OwnedTagSets>, TagSetWeighter>,
 }
 
 impl TagSets {
-    pub fn new(keyspace: &TxKeyspace) -> crate::Result<Self> {
+    pub fn new(
+        keyspace: &TxKeyspace,
+        prefix: &str,
+        cache_capacity_bytes: u64,
+    ) -> crate::Result<Self> {
         let opts = PartitionCreateOptions::default()
             .block_size(4_096)
             .compression(CompressionType::Lz4)
             .max_memtable_size(8_000_000);
 
-        let partition = keyspace.open_partition(PARTITION_NAME, opts)?;
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        // NOTE: Assume ~100 bytes per tag set to estimate the amount of items
+        let estimated_items = usize::try_from(cache_capacity_bytes / 100).unwrap_or(usize::MAX);
 
-        Ok(Self { partition })
+        Ok(Self {
+            partition,
+            cache: Cache::with_weighter(estimated_items, cache_capacity_bytes, TagSetWeighter),
+        })
     }
 
     pub fn insert(&self, tx: &mut WriteTransaction, series_id: SeriesId, tags: &str) {
@@ -27,30 +60,125 @@ impl TagSets {
         tx.insert(&self.partition, series_id.to_be_bytes(), tags);
     }
 
-    pub fn get(&self, series_id: SeriesId) -> crate::Result<OwnedTagSets> {
+    pub fn remove(&self, tx: &mut WriteTransaction, series_id: SeriesId) {
+        tx.remove(&self.partition, series_id.to_be_bytes());
+    }
+
+    /// Returns the approximate size of the cached tag sets in bytes.
+    pub fn cache_size(&self) -> u64 {
+        self.cache.weight()
+    }
+
+    /// Removes a series' tag set from the cache.
+    ///
+    /// Needs to be called after a series was created, so a tag set that was
+    /// looked up before the series existed is not served from the cache.
+    pub fn invalidate(&self, series_id: SeriesId) {
+        self.cache.remove(&series_id);
+    }
+
+    /// Returns `true` if the series exists.
+    pub fn contains(&self, series_id: SeriesId) -> crate::Result<bool> {
+        Ok(self
+            .partition
+            .inner()
+            .contains_key(series_id.to_be_bytes())?)
+    }
+
+    pub fn get(&self, series_id: SeriesId) -> crate::Result<Arc<OwnedTagSets>> {
+        if let Some(tags) = self.cache.get(&series_id) {
+            return Ok(tags);
+        }
+
+        let tags = Arc::new(self.load(series_id)?);
+        self.cache.insert(series_id, tags.clone());
+
+        Ok(tags)
+    }
+
+    fn load(&self, series_id: SeriesId) -> crate::Result<OwnedTagSets> {
         Ok(self
             .partition
             .get(series_id.to_be_bytes())?
             .filter(|x| !x.is_empty())
             .map(|bytes| {
-                let reader = std::str::from_utf8(&bytes).expect("should be utf-8");
-                parse_key_value_pairs(reader)
+                std::str::from_utf8(&bytes)
+                    .ok()
+                    .and_then(parse_key_value_pairs)
+                    .ok_or_else(|| {
+                        crate::Error::corruption(
+                            &self.partition.inner().name,
+                            series_id.to_be_bytes(),
+                        )
+                    })
             })
+            .transpose()?
             .unwrap_or_default())
     }
 }
 
-fn parse_key_value_pairs(input: &str) -> OwnedTagSets {
+/// Parses a stored tag set, returning `None` if it is malformed.
+fn parse_key_value_pairs(input: &str) -> Option<OwnedTagSets> {
     input
         .split(';')
         .map(|pair| {
-            let mut split = pair.splitn(2, ':');
-
-            if let (Some(key), Some(value)) = (split.next(), split.next()) {
-                (key.to_string(), value.to_string())
-            } else {
-                panic!("Invalid parsed tag: {split:?}");
-            }
+            let (key, value) = pair.split_once(':')?;
+            Some((key.to_string(), value.to_string()))
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn tag_sets_cache_invalidate() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_sets = TagSets::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+
+        assert!(tag_sets.get(0)?.is_empty());
+
+        let mut tx = keyspace.write_tx();
+        tag_sets.insert(&mut tx, 0, "env:prod;host:h-1");
+        tx.commit()?;
+
+        // NOTE: Not invalidated yet, so the cached empty tag set is returned
+        assert!(tag_sets.get(0)?.is_empty());
+
+        tag_sets.invalidate(0);
+
+        let tags = tag_sets.get(0)?;
+        assert_eq!(Some("prod"), tags.get("env").map(String::as_str));
+        assert_eq!(Some("h-1"), tags.get("host").map(String::as_str));
+
+        Ok(())
+    }
+
+    #[test_log::test]
+    // NOTE: The transaction is consumed by `commit`, which the lint does not see
+    #[allow(clippy::significant_drop_tightening)]
+    fn tag_sets_corruption() -> crate::Result<()> {
+        let path = tempfile::tempdir()?;
+        let keyspace = fjall::Config::new(&path).open_transactional()?;
+        let tag_sets = TagSets::new(&keyspace, "_talna#v1#", 1_024 * 1_024)?;
+
+        let mut tx = keyspace.write_tx();
+        tag_sets.insert(&mut tx, 0, "env:prod;host");
+        tx.insert(&tag_sets.partition, 1_u64.to_be_bytes(), [0xff, 0xfe]);
+        tx.commit()?;
+
+        for series_id in [0, 1] {
+            assert!(matches!(
+                tag_sets.get(series_id),
+                Err(crate::Error::Corruption { partition, key })
+                    if partition == "_talna#v1#tags" && key == series_id.to_be_bytes()
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use crate::TagSet;
+
+/// Error returned when validating an invalid tag set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagSetError {
+    /// A tag key is empty, or contains one of `:`, `;`, `#`
+    InvalidKey(String),
+
+    /// A tag value contains one of `;`, `#`
+    InvalidValue(String),
+
+    /// A tag key was used more than once
+    DuplicateKey(String),
+}
+
+impl std::fmt::Display for TagSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidKey(key) => write!(f, "invalid tag key {key:?}"),
+            Self::InvalidValue(value) => write!(f, "invalid tag value {value:?}"),
+            Self::DuplicateKey(key) => write!(f, "duplicate tag key {key:?}"),
+        }
+    }
+}
+
+impl std::error::Error for TagSetError {}
+
+/// Checks that all tags can be stored in a series key unambiguously.
+///
+/// `tags` need to be sorted.
+fn validate_sorted(tags: &TagSet) -> Result<(), TagSetError> {
+    for (key, value) in tags {
+        if key.is_empty() || key.contains([':', ';', '#']) {
+            return Err(TagSetError::InvalidKey((*key).into()));
+        }
+
+        if value.contains([';', '#']) {
+            return Err(TagSetError::InvalidValue((*value).into()));
+        }
+    }
+
+    for pair in tags.windows(2) {
+        if let [(a, _), (b, _)] = pair {
+            if a == b {
+                return Err(TagSetError::DuplicateKey((*a).into()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that all tags can be stored in a series key unambiguously.
+pub fn validate(tags: &TagSet) -> Result<(), TagSetError> {
+    if is_sorted(tags) {
+        validate_sorted(tags)
+    } else {
+        let mut tags = tags.to_vec();
+        tags.sort_unstable();
+        validate_sorted(&tags)
+    }
+}
+
+pub fn is_sorted(tags: &TagSet) -> bool {
+    tags.windows(2).all(|pair| matches!(pair, [a, b] if a <= b))
+}
+
+/// A validated, sorted list of tags
+///
+/// Writing a pre-sorted tag set avoids sorting the tags on every write.
+///
+/// # Examples
+///
+/// ```
+/// use talna::{tagset, TagSet, TagSetBuf};
+///
+/// let tags = TagSetBuf::new([
+///   ("service", "db"),
+///   ("env", "production"),
+/// ])?;
+///
+/// assert_eq!(&[("env", "production"), ("service", "db")], &*tags);
+///
+/// let tags: &TagSet = tagset!(
+///   "env" => "production",
+///   "env" => "staging",
+/// );
+/// assert!(TagSetBuf::try_from(tags).is_err());
+/// #
+/// # Ok::<_, talna::TagSetError>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagSetBuf<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> TagSetBuf<'a> {
+    /// Creates a tag set from a list of tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a tag is invalid, or a tag key is used more than once.
+    pub fn new(tags: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, TagSetError> {
+        let mut tags = tags.into_iter().collect::<Vec<_>>();
+        tags.sort_unstable();
+        validate_sorted(&tags)?;
+        Ok(Self(tags))
+    }
+}
+
+impl<'a> TryFrom<&TagSet<'a>> for TagSetBuf<'a> {
+    type Error = TagSetError;
+
+    fn try_from(value: &TagSet<'a>) -> Result<Self, Self::Error> {
+        Self::new(value.iter().copied())
+    }
+}
+
+impl<'a> std::ops::Deref for TagSetBuf<'a> {
+    type Target = TagSet<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A struct whose fields are the tags of a series
+///
+/// Can be derived using `#[derive(TagSet)]` with the `derive` feature flag.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// # #[cfg(feature = "derive")]
+/// # {
+/// use talna::{Database, MetricName, TagSet, ToTagSet};
+///
+/// #[derive(TagSet)]
+/// struct Labels {
+///     host: String,
+///     env: String,
+/// }
+///
+/// let labels = Labels {
+///     host: "h-1".into(),
+///     env: "prod".into(),
+/// };
+///
+/// assert_eq!(vec![("env", "prod"), ("host", "h-1")], labels.to_tag_set());
+///
+/// let db = Database::builder().open(&folder)?;
+/// db.write(MetricName::try_from("cpu.total").unwrap(), 25.0, &labels.to_tag_set())?;
+/// # }
+/// #
+/// # Ok::<_, talna::Error>(())
+/// ```
+pub trait ToTagSet {
+    /// Returns the tags, sorted by key.
+    fn to_tag_set(&self) -> Vec<(&str, &str)>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tagset;
+
+    #[test_log::test]
+    fn tagset_validate() {
+        assert_eq!(Ok(()), validate(tagset!("host" => "h-1", "env" => "prod")));
+        assert_eq!(Ok(()), validate(tagset!("url" => "http://a")));
+
+        assert_eq!(
+            Err(TagSetError::DuplicateKey("host".into())),
+            validate(tagset!("host" => "h-2", "env" => "prod", "host" => "h-1")),
+        );
+        assert_eq!(
+            Err(TagSetError::InvalidKey("a:b".into())),
+            validate(tagset!("a:b" => "c")),
+        );
+        assert_eq!(
+            Err(TagSetError::InvalidKey(String::new())),
+            validate(tagset!("" => "c")),
+        );
+        assert_eq!(
+            Err(TagSetError::InvalidValue("a;b".into())),
+            validate(tagset!("key" => "a;b")),
+        );
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "derive")]
+    fn tagset_derive() {
+        use crate::ToTagSet;
+
+        #[derive(crate::TagSet)]
+        struct Labels<'a> {
+            service: &'a str,
+            host: String,
+
+            #[tag(name = "env")]
+            environment: String,
+
+            #[tag(skip)]
+            #[allow(dead_code)]
+            comment: String,
+        }
+
+        let labels = Labels {
+            service: "db",
+            host: "h-1".into(),
+            environment: "prod".into(),
+            comment: "ignored".into(),
+        };
+
+        let tags = labels.to_tag_set();
+        assert_eq!(
+            vec![("env", "prod"), ("host", "h-1"), ("service", "db")],
+            tags
+        );
+        assert!(is_sorted(&tags));
+        assert_eq!(Ok(()), validate(&tags));
+    }
+}
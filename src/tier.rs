@@ -0,0 +1,146 @@
+use crate::storage::{FjallStorage, KvResult, Storage, StoragePartition, StorageSnapshot};
+use crate::Timestamp;
+use fjall::{PartitionCreateOptions, Snapshot};
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where the cold tier is stored
+pub enum ColdStorage {
+    /// A fjall keyspace at the given path
+    Path(PathBuf),
+
+    /// A user-provided storage backend
+    Custom(Arc<dyn Storage>),
+}
+
+/// Second storage location that old data points are moved to,
+/// see [`crate::DatabaseBuilder::cold_tier`] & [`crate::DatabaseBuilder::cold_tier_storage`]
+pub struct ColdTier {
+    storage: Arc<dyn Storage>,
+
+    /// Data points older than the tier's age
+    pub(crate) data: Arc<dyn StoragePartition>,
+
+    /// Age (in nanoseconds) after which data points are moved
+    pub(crate) age: Timestamp,
+}
+
+impl ColdTier {
+    pub fn open(location: &ColdStorage, prefix: &str, age: Timestamp) -> crate::Result<Self> {
+        let storage: Arc<dyn Storage> = match location {
+            ColdStorage::Path(path) => {
+                log::info!("Opening cold tier at {path:?}");
+
+                let keyspace = fjall::Config::new(path).open()?;
+
+                // NOTE: Cold data is rarely read, so larger blocks compress better
+                Arc::new(FjallStorage::new(
+                    keyspace,
+                    PartitionCreateOptions::default()
+                        .use_bloom_filters(false)
+                        .manual_journal_persist(true)
+                        .block_size(256_000)
+                        .compression(fjall::CompressionType::Lz4),
+                ))
+            }
+            ColdStorage::Custom(storage) => {
+                log::info!("Opening cold tier using custom storage");
+                storage.clone()
+            }
+        };
+
+        let data = storage.open_partition(&format!("{prefix}data"))?;
+
+        Ok(Self { storage, data, age })
+    }
+
+    /// Syncs moved data points to disk, before they are removed from the hot tier
+    pub fn persist(&self) -> crate::Result<()> {
+        self.storage.persist()
+    }
+}
+
+/// Point-in-time view of the data partition of both tiers
+pub struct DataSnapshot {
+    pub(crate) hot: Snapshot,
+    pub(crate) cold: Option<Box<dyn StorageSnapshot>>,
+}
+
+/// Merges the key-value pairs of both tiers in key order
+///
+/// Data points that are currently being moved may be visible in
+/// both tiers, so duplicate keys are only returned once.
+pub struct MergeTiers<H: Iterator<Item = KvResult>, C: Iterator<Item = KvResult>> {
+    hot: Peekable<H>,
+    cold: Peekable<C>,
+}
+
+impl<H: Iterator<Item = KvResult>, C: Iterator<Item = KvResult>> MergeTiers<H, C> {
+    pub fn new(hot: H, cold: C) -> Self {
+        Self {
+            hot: hot.peekable(),
+            cold: cold.peekable(),
+        }
+    }
+}
+
+impl<H: Iterator<Item = KvResult>, C: Iterator<Item = KvResult>> Iterator for MergeTiers<H, C> {
+    type Item = KvResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        let ordering = match (self.hot.peek(), self.cold.peek()) {
+            (Some(Ok((a, _))), Some(Ok((b, _)))) => a.cmp(b),
+            (Some(_), _) => Less,
+            (None, _) => Greater,
+        };
+
+        match ordering {
+            Less => self.hot.next(),
+            Greater => self.cold.next(),
+            Equal => {
+                self.cold.next();
+                self.hot.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fjall::Slice;
+
+    #[test_log::test]
+    #[allow(clippy::unwrap_used)]
+    fn merge_tiers() {
+        let kv = |k: &str| Ok((Slice::from(k.as_bytes()), Slice::from(&b"hot"[..])));
+        let cold_kv = |k: &str| Ok((Slice::from(k.as_bytes()), Slice::from(&b"cold"[..])));
+
+        let hot = vec![kv("a"), kv("c"), kv("d")];
+        let cold = vec![cold_kv("b"), cold_kv("c"), cold_kv("e")];
+
+        let merged = MergeTiers::new(hot.into_iter(), cold.into_iter())
+            .map(|kv| {
+                let (k, v) = kv.unwrap();
+                (
+                    String::from_utf8(k.to_vec()).unwrap(),
+                    String::from_utf8(v.to_vec()).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("a".into(), "hot".into()),
+                ("b".into(), "cold".into()),
+                ("c".into(), "hot".into()),
+                ("d".into(), "hot".into()),
+                ("e".into(), "cold".into()),
+            ],
+            merged
+        );
+    }
+}
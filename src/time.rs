@@ -2,7 +2,12 @@ use crate::Timestamp;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Returns the current timestamp in nanoseconds.
+///
+/// # Panics
+///
+/// Panics if the system clock is set before the Unix epoch.
 #[must_use]
+#[allow(clippy::expect_used)]
 pub fn timestamp() -> Timestamp {
     let start = SystemTime::now();
     let since_the_epoch = start
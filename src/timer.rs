@@ -0,0 +1,74 @@
+use crate::{Database, MetricName, TagSet, Value};
+use std::time::Instant;
+
+/// Unit of durations in query results, see [`AggregationBuilder::unit`](crate::AggregationBuilder::unit)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DurationUnit {
+    /// Nanoseconds (the unit durations are stored in)
+    Nanos,
+
+    /// Microseconds
+    Micros,
+
+    /// Milliseconds
+    Millis,
+
+    /// Seconds
+    Seconds,
+}
+
+impl DurationUnit {
+    /// Returns the amount of nanoseconds per unit.
+    #[must_use]
+    pub fn nanos(self) -> f64 {
+        match self {
+            Self::Nanos => 1.0,
+            Self::Micros => 1_000.0,
+            Self::Millis => 1_000_000.0,
+            Self::Seconds => 1_000_000_000.0,
+        }
+    }
+}
+
+/// Measures the duration of an operation, see [`Database::timer`]
+///
+/// The duration is written as nanoseconds when the timer is stopped.
+/// Dropping the timer without stopping it does not write anything.
+///
+/// `f32` values have ~7 significant digits, so durations keep sub-microsecond
+/// precision up to ~10 ms, and microsecond precision up to ~10 s.
+/// [`ValueEncoding::Half`](crate::ValueEncoding::Half) can not store durations
+/// above ~65 µs, so it should not be used for timer metrics.
+#[must_use = "the duration is only written when the timer is stopped"]
+pub struct Timer<'a> {
+    db: &'a Database,
+    metric: MetricName<'a>,
+    tags: &'a TagSet<'a>,
+    start: Instant,
+}
+
+impl<'a> Timer<'a> {
+    pub(crate) fn start(db: &'a Database, metric: MetricName<'a>, tags: &'a TagSet<'a>) -> Self {
+        Self {
+            db,
+            metric,
+            tags,
+            start: Instant::now(),
+        }
+    }
+
+    /// Stops the timer, and writes the elapsed time in nanoseconds.
+    ///
+    /// Returns the elapsed time.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an I/O error occurred.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn stop(self) -> crate::Result<std::time::Duration> {
+        let elapsed = self.start.elapsed();
+        self.db
+            .write(self.metric, elapsed.as_nanos() as Value, self.tags)?;
+        Ok(elapsed)
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{SeriesId, Timestamp};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use fjall::{CompressionType, PartitionCreateOptions, TxKeyspace, TxPartition};
+use std::io::Read;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    PoisonError, RwLock,
+};
+
+const PARTITION_NAME: &str = "tomb";
+
+/// Stores the next tombstone ID, so IDs are not reused after tombstones are removed
+const NEXT_ID_KEY: &[u8] = b"next_id";
+
+/// A deleted time range of some series, see [`crate::Database::delete`]
+///
+/// The data points stay on disk (and can be queried using
+/// [`AggregationBuilder::include_deleted`](crate::AggregationBuilder::include_deleted))
+/// until the tombstone is compacted, see [`crate::Database::compact_tombstones`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tombstone {
+    /// ID of the delete, used to undo it
+    pub id: u64,
+
+    /// Metric the data points were deleted from
+    pub metric: String,
+
+    /// Series whose data points were deleted
+    pub series_ids: Vec<SeriesId>,
+
+    /// Minimum deleted timestamp (inclusive)
+    pub start: Timestamp,
+
+    /// Maximum deleted timestamp (inclusive)
+    pub end: Timestamp,
+
+    /// Time of the delete
+    pub deleted_at: Timestamp,
+}
+
+impl Tombstone {
+    /// Returns `true` if the data point is hidden by this tombstone.
+    #[must_use]
+    pub fn covers(&self, series_id: SeriesId, ts: Timestamp) -> bool {
+        (self.start..=self.end).contains(&ts) && self.series_ids.binary_search(&series_id).is_ok()
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            3 * std::mem::size_of::<Timestamp>()
+                + 2
+                + self.metric.len()
+                + self.series_ids.len() * std::mem::size_of::<SeriesId>(),
+        );
+
+        bytes.extend_from_slice(&self.start.to_be_bytes());
+        bytes.extend_from_slice(&self.end.to_be_bytes());
+        bytes.extend_from_slice(&self.deleted_at.to_be_bytes());
+
+        // NOTE: Metric names are short, so the length fits
+        #[allow(clippy::cast_possible_truncation)]
+        let _ = bytes.write_u16::<BigEndian>(self.metric.len() as u16);
+        bytes.extend_from_slice(self.metric.as_bytes());
+
+        for series_id in &self.series_ids {
+            bytes.extend_from_slice(&series_id.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    fn deserialize(id: u64, mut bytes: &[u8]) -> std::io::Result<Self> {
+        let start = bytes.read_u128::<BigEndian>()?;
+        let end = bytes.read_u128::<BigEndian>()?;
+        let deleted_at = bytes.read_u128::<BigEndian>()?;
+
+        let len = bytes.read_u16::<BigEndian>()?;
+        let mut metric = vec![0; usize::from(len)];
+        bytes.read_exact(&mut metric)?;
+
+        let mut series_ids = Vec::with_capacity(bytes.len() / std::mem::size_of::<SeriesId>());
+
+        while !bytes.is_empty() {
+            series_ids.push(bytes.read_u64::<BigEndian>()?);
+        }
+
+        Ok(Self {
+            id,
+            metric: String::from_utf8_lossy(&metric).into_owned(),
+            series_ids,
+            start,
+            end,
+            deleted_at,
+        })
+    }
+}
+
+/// Stores the tombstones of deletes that were not compacted yet
+///
+/// Kept in memory, because every query needs to check them, and
+/// there are only few at a time.
+pub struct Tombstones {
+    partition: TxPartition,
+    list: RwLock<Vec<Tombstone>>,
+    next_id: AtomicU64,
+}
+
+impl Tombstones {
+    pub fn new(keyspace: &TxKeyspace, prefix: &str) -> crate::Result<Self> {
+        let opts = PartitionCreateOptions::default()
+            .block_size(4_096)
+            .compression(CompressionType::Lz4);
+
+        let partition = keyspace.open_partition(&format!("{prefix}{PARTITION_NAME}"), opts)?;
+
+        let mut list: Vec<Tombstone> = vec![];
+        let mut next_id = 0;
+
+        for kv in keyspace.read_tx().iter(&partition) {
+            let (k, v) = kv?;
+
+            if &*k == NEXT_ID_KEY {
+                next_id = next_id.max((&v[..]).read_u64::<BigEndian>()?);
+                continue;
+            }
+
+            let id = (&k[..]).read_u64::<BigEndian>()?;
+            list.push(Tombstone::deserialize(id, &v)?);
+        }
+
+        // NOTE: The next ID is written after the tombstone, so it may be stale after a crash
+        if let Some(tombstone) = list.last() {
+            next_id = next_id.max(tombstone.id + 1);
+        }
+
+        Ok(Self {
+            partition,
+            list: RwLock::new(list),
+            next_id: AtomicU64::new(next_id),
+        })
+    }
+
+    /// Stores a tombstone, returning its ID.
+    pub fn insert(
+        &self,
+        metric: &str,
+        series_ids: &[SeriesId],
+        (start, end): (Timestamp, Timestamp),
+        deleted_at: Timestamp,
+    ) -> crate::Result<u64> {
+        let mut series_ids = series_ids.to_vec();
+        series_ids.sort_unstable();
+        series_ids.dedup();
+
+        let mut list = self.list.write().unwrap_or_else(PoisonError::into_inner);
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let tombstone = Tombstone {
+            id,
+            metric: metric.into(),
+            series_ids,
+            start,
+            end,
+            deleted_at,
+        };
+
+        self.partition
+            .insert(id.to_be_bytes(), tombstone.serialize())?;
+        self.partition.insert(NEXT_ID_KEY, (id + 1).to_be_bytes())?;
+        list.push(tombstone);
+
+        drop(list);
+
+        Ok(id)
+    }
+
+    /// Removes a tombstone, returning it if it existed.
+    pub fn remove(&self, id: u64) -> crate::Result<Option<Tombstone>> {
+        let mut list = self.list.write().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(idx) = list.iter().position(|tombstone| tombstone.id == id) else {
+            return Ok(None);
+        };
+
+        self.partition.remove(id.to_be_bytes())?;
+        let tombstone = list.remove(idx);
+
+        drop(list);
+
+        Ok(Some(tombstone))
+    }
+
+    pub fn list(&self) -> Vec<Tombstone> {
+        // NOTE: The list is never left in an inconsistent state, so poisoning can be ignored
+        self.list
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the deleted time ranges (inclusive) of a series.
+    pub fn ranges(&self, series_id: SeriesId) -> Vec<(Timestamp, Timestamp)> {
+        self.list
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|tombstone| tombstone.series_ids.binary_search(&series_id).is_ok())
+            .map(|tombstone| (tombstone.start, tombstone.end))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn tombstone_roundtrip() -> crate::Result<()> {
+        let tombstone = Tombstone {
+            id: 3,
+            metric: "cpu.total".into(),
+            series_ids: vec![1, 5, 7],
+            start: 10,
+            end: 20,
+            deleted_at: 1_700_000_000_000_000_000,
+        };
+
+        assert_eq!(
+            tombstone,
+            Tombstone::deserialize(3, &tombstone.serialize())?
+        );
+
+        assert!(tombstone.covers(5, 10));
+        assert!(tombstone.covers(5, 20));
+        assert!(!tombstone.covers(5, 21));
+        assert!(!tombstone.covers(2, 15));
+
+        assert!(Tombstone::deserialize(0, &[0, 1, 2]).is_err());
+
+        Ok(())
+    }
+}
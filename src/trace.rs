@@ -0,0 +1,135 @@
+//! Instrumentation using `tracing` spans, which compiles to nothing without the `tracing` feature flag
+
+/// Stands in for [`tracing::span::EnteredSpan`] without the `tracing` feature flag
+#[cfg(not(feature = "tracing"))]
+pub struct SpanGuard;
+
+#[cfg(not(feature = "tracing"))]
+impl SpanGuard {
+    #[allow(clippy::unused_self, clippy::needless_pass_by_value)]
+    pub fn record<V>(&self, _: &str, _: V) -> &Self {
+        self
+    }
+}
+
+/// Only every n-th write is traced, because tracing every write would slow down ingestion
+#[cfg(feature = "tracing")]
+const WRITE_SAMPLE_INTERVAL: u64 = 1_024;
+
+// NOTE: Counted per thread, so writer threads do not contend on a shared counter
+#[cfg(feature = "tracing")]
+thread_local! {
+    static WRITES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// Returns `true` if the current write should be traced.
+#[cfg(feature = "tracing")]
+pub fn sample_write() -> bool {
+    WRITES.with(|writes| {
+        let n = writes.get();
+        writes.set(n.wrapping_add(1));
+        n % WRITE_SAMPLE_INTERVAL == 0
+    })
+}
+
+/// Enters a debug span, see [`tracing::debug_span`]
+///
+/// Fields are only evaluated using the `tracing` feature flag.
+macro_rules! span {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!($($args)*).entered();
+
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::SpanGuard;
+
+        span
+    }};
+}
+
+/// Enters a debug span for a sampled subset of writes, see [`span`]
+macro_rules! write_span {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = if $crate::trace::sample_write() {
+            tracing::debug_span!($($args)*).entered()
+        } else {
+            tracing::Span::none().entered()
+        };
+
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::SpanGuard;
+
+        span
+    }};
+}
+
+#[cfg(all(test, feature = "tracing"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use crate::{tagset, Database, MetricName};
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the names of all created spans
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            spans.push(span.metadata().name());
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test_log::test]
+    fn query_spans() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let db = Database::builder().open(&folder)?;
+        let metric_name = MetricName::try_from("cpu.total").unwrap();
+
+        let recorder = Recorder::default();
+
+        tracing::subscriber::with_default(recorder.clone(), || -> crate::Result<()> {
+            for _ in 0..(2 * super::WRITE_SAMPLE_INTERVAL) {
+                db.write(metric_name, 1.0, tagset!("host" => "h-1"))?;
+            }
+
+            db.avg(metric_name, "host")
+                .filter("host:h-1")
+                .build()?
+                .collect()?;
+
+            Ok(())
+        })?;
+
+        let spans = recorder.0.lock().unwrap().clone();
+
+        assert_eq!(2, spans.iter().filter(|name| **name == "write").count());
+
+        let query = spans
+            .iter()
+            .filter(|name| **name != "write")
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["query", "filter", "collect"], query);
+
+        Ok(())
+    }
+}